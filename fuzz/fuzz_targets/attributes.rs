@@ -0,0 +1,25 @@
+#![no_main]
+use jvm::class_parser::{
+    self,
+    format::{attributes::Tag, constant_pool::Type},
+};
+use libfuzzer_sys::fuzz_target;
+use std::sync::Arc;
+
+// The first byte picks the attribute, the rest is its body.
+fuzz_target!(|data: &[u8]| {
+    let (which, body) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let tag = Tag::KNOWN[*which as usize % Tag::KNOWN.len()];
+
+    // names resolve for small indices, so annotations get past their type
+    let mut cp = vec![Type::Nop];
+    for name in ["LA;", "value", "Code", "LineNumberTable", "StackMapTable"].iter() {
+        cp.push(Type::Utf8 {
+            bytes: Arc::new(name.as_bytes().to_vec()),
+        });
+    }
+    let _ = class_parser::parse_attribute(tag.name(), body, Arc::new(cp));
+});
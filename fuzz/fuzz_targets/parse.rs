@@ -0,0 +1,16 @@
+#![no_main]
+use jvm::class_parser::{self, ParseOptions};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = class_parser::parse(data);
+
+    // small limits, so that small inputs reach them too
+    let options = ParseOptions {
+        max_depth: 2,
+        max_allocation: 4096,
+        exact_attribute_length: false,
+        ..ParseOptions::default()
+    };
+    let _ = class_parser::parse_with(data, &options);
+});
@@ -2,5 +2,6 @@
 pushd "$(dirname "$0")"
 
 javac HelloWorld.java
+cargo run --bin jasm -- --disassemble HelloWorld.class -o HelloWorld.j
 
-popd
\ No newline at end of file
+popd
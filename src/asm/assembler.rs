@@ -0,0 +1,1365 @@
+use super::{
+    raw_index, tokenize, Error, Token, ARRAY_TYPES, CLASS_FLAGS, FIELD_FLAGS, HANDLE_KINDS,
+    INNER_CLASS_FLAGS, METHOD_FLAGS, PARAMETER_FLAGS,
+};
+use crate::class_parser::{
+    self,
+    builder::ConstantPoolBuilder,
+    bytecode::{self, OperandKind},
+    format::{
+        attributes::{
+            AnnotationElementValue, AnnotationEntry, BootstrapMethod, Code, CodeException,
+            ElementValuePair, ElementValueType, EnclosingMethod, InnerClass, LineNumber,
+            LocalVariable, MethodParameter, StackMapFrame, Type as AttributeType,
+            VerificationTypeInfo,
+        },
+        class_file::ClassFile,
+        constant_pool::Type as ConstantType,
+        field_info::FieldInfo,
+        method_info::MethodInfo,
+        version::Version,
+    },
+};
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Assembles the text form produced by [`print`](super::print).
+pub fn assemble(text: &str) -> Result<ClassFile, Error> {
+    let mut lines = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let tokens = tokenize(line, i + 1)?;
+        if !tokens.is_empty() {
+            lines.push(Line { no: i + 1, tokens });
+        }
+    }
+
+    let mut asm = Assembler {
+        lines,
+        pos: 0,
+        line: 0,
+        b: ConstantPoolBuilder::new(),
+    };
+    asm.constant_pool()?;
+    asm.class_file()
+}
+
+#[derive(Clone)]
+struct Line {
+    no: usize,
+    tokens: Vec<Token>,
+}
+
+struct Cursor<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    line: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(line: &'a Line) -> Self {
+        Self {
+            tokens: &line.tokens,
+            pos: 0,
+            line: line.no,
+        }
+    }
+
+    fn err(&self, message: impl Into<String>) -> Error {
+        Error::new(self.line, message)
+    }
+
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_word(&self) -> Option<&'a str> {
+        match self.peek() {
+            Some(Token::Word(w)) => Some(w),
+            _ => None,
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.pos >= self.tokens.len()
+    }
+
+    fn next(&mut self) -> Result<&'a Token, Error> {
+        let t = self
+            .tokens
+            .get(self.pos)
+            .ok_or_else(|| self.err("unexpected end of line"))?;
+        self.pos += 1;
+        Ok(t)
+    }
+
+    fn word(&mut self) -> Result<&'a str, Error> {
+        match self.next()? {
+            Token::Word(w) => Ok(w),
+            Token::Str(_) => Err(self.err("unexpected string")),
+        }
+    }
+
+    fn expect(&mut self, keyword: &str) -> Result<(), Error> {
+        let w = self.word()?;
+        if w == keyword {
+            Ok(())
+        } else {
+            Err(self.err(format!("expected {}, found {}", keyword, w)))
+        }
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, Error> {
+        match self.next()? {
+            Token::Word(w) => Ok(w.as_bytes().to_vec()),
+            Token::Str(s) => Ok(s.clone()),
+        }
+    }
+
+    fn number<T: FromStr>(&mut self) -> Result<T, Error> {
+        let w = self.word()?;
+        w.parse().map_err(|_| self.err(format!("bad number {}", w)))
+    }
+
+    fn end(&self) -> Result<(), Error> {
+        match self.peek() {
+            None => Ok(()),
+            Some(t) => Err(self.err(format!("unexpected {:?}", t))),
+        }
+    }
+
+    // `-` stands for an absent (zero) index
+    fn is_none_marker(&mut self) -> bool {
+        if self.peek_word() == Some("-") {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn flags(&mut self, table: &[(&str, u16)], leave: usize) -> Result<u16, Error> {
+        let mut flags = 0;
+        while self.tokens.len() - self.pos > leave {
+            let w = self.word()?;
+            flags |= match table.iter().find(|(name, _)| *name == w) {
+                Some((_, bit)) => *bit,
+                None => w
+                    .strip_prefix("0x")
+                    .and_then(|h| u16::from_str_radix(h, 16).ok())
+                    .ok_or_else(|| self.err(format!("unknown flag {}", w)))?,
+            };
+        }
+        Ok(flags)
+    }
+
+    fn reference(&mut self) -> Result<Ref, Error> {
+        if let Some(idx) = self.peek_word().and_then(raw_index) {
+            self.pos += 1;
+            return Ok(Ref::Index(idx));
+        }
+        Ok(Ref::Bytes(self.bytes()?))
+    }
+
+    fn name_and_type(&mut self) -> Result<NatRef, Error> {
+        if let Some(idx) = self.peek_word().and_then(raw_index) {
+            self.pos += 1;
+            return Ok(NatRef::Index(idx));
+        }
+        Ok(NatRef::Pair(self.bytes()?, self.bytes()?))
+    }
+
+    fn float_bits(&mut self) -> Result<u32, Error> {
+        let w = self.word()?;
+        match w.strip_prefix("0x") {
+            Some(h) => u32::from_str_radix(h, 16).ok(),
+            None => w.parse::<f32>().ok().map(f32::to_bits),
+        }
+        .ok_or_else(|| self.err(format!("bad float {}", w)))
+    }
+
+    fn double_bits(&mut self) -> Result<u64, Error> {
+        let w = self.word()?;
+        match w.strip_prefix("0x") {
+            Some(h) => u64::from_str_radix(h, 16).ok(),
+            None => w.parse::<f64>().ok().map(f64::to_bits),
+        }
+        .ok_or_else(|| self.err(format!("bad double {}", w)))
+    }
+
+    fn constant(&mut self) -> Result<Const, Error> {
+        let kind = self.word()?;
+        if let Some(idx) = raw_index(kind) {
+            return Ok(Const::Index(idx));
+        }
+        let c = match kind {
+            "Utf8" => Const::Utf8(self.bytes()?),
+            "Int" => Const::Int(self.number()?),
+            "Float" => Const::Float(self.float_bits()?),
+            "Long" => Const::Long(self.number()?),
+            "Double" => Const::Double(self.double_bits()?),
+            "Class" => Const::Class(self.reference()?),
+            "String" => Const::String(self.reference()?),
+            "Field" => Const::Field(self.reference()?, self.name_and_type()?),
+            "Method" => Const::Method(self.reference()?, self.name_and_type()?),
+            "InterfaceMethod" => Const::InterfaceMethod(self.reference()?, self.name_and_type()?),
+            "NameAndType" => Const::NameAndType(self.reference()?, self.reference()?),
+            "MethodHandle" => {
+                let w = self.word()?;
+                let kind = match HANDLE_KINDS.iter().position(|k| !k.is_empty() && *k == w) {
+                    Some(k) => k as u8,
+                    None => w
+                        .parse()
+                        .map_err(|_| self.err(format!("unknown handle kind {}", w)))?,
+                };
+                Const::MethodHandle(kind, Box::new(self.constant()?))
+            }
+            "MethodType" => Const::MethodType(self.reference()?),
+            "InvokeDynamic" => Const::InvokeDynamic(self.number()?, self.name_and_type()?),
+            "Dynamic" => Const::Dynamic(self.number()?, self.name_and_type()?),
+            "Module" => Const::Module(self.reference()?),
+            "Package" => Const::Package(self.reference()?),
+            _ => return Err(self.err(format!("unknown constant kind {}", kind))),
+        };
+        Ok(c)
+    }
+
+    fn label(&mut self, labels: &HashMap<String, usize>) -> Result<usize, Error> {
+        let w = self.word()?;
+        labels
+            .get(w)
+            .copied()
+            .ok_or_else(|| self.err(format!("undefined label {}", w)))
+    }
+}
+
+/// A pool index, or the text of a Utf8 constant (for class references, the
+/// class name) to look up or add.
+enum Ref {
+    Index(u16),
+    Bytes(Vec<u8>),
+}
+
+enum NatRef {
+    Index(u16),
+    Pair(Vec<u8>, Vec<u8>),
+}
+
+enum Const {
+    Index(u16),
+    Utf8(Vec<u8>),
+    Int(i32),
+    Float(u32),
+    Long(i64),
+    Double(u64),
+    Class(Ref),
+    String(Ref),
+    Field(Ref, NatRef),
+    Method(Ref, NatRef),
+    InterfaceMethod(Ref, NatRef),
+    NameAndType(Ref, Ref),
+    MethodHandle(u8, Box<Const>),
+    MethodType(Ref),
+    InvokeDynamic(u16, NatRef),
+    Dynamic(u16, NatRef),
+    Module(Ref),
+    Package(Ref),
+}
+
+impl Const {
+    fn is_wide(&self) -> bool {
+        matches!(self, Const::Long(_) | Const::Double(_))
+    }
+
+    fn is_literal(&self) -> bool {
+        matches!(
+            self,
+            Const::Utf8(_) | Const::Int(_) | Const::Float(_) | Const::Long(_) | Const::Double(_)
+        )
+    }
+}
+
+// instruction pending label resolution
+struct Insn {
+    line: Line,
+    pc: usize,
+    opcode: u8,
+}
+
+struct Assembler {
+    lines: Vec<Line>,
+    pos: usize,
+    line: usize,
+    b: ConstantPoolBuilder,
+}
+
+impl Assembler {
+    fn err(&self, message: impl Into<String>) -> Error {
+        Error::new(self.line, message)
+    }
+
+    fn take_line(&mut self) -> Option<Line> {
+        let line = self.lines.get(self.pos)?.clone();
+        self.pos += 1;
+        self.line = line.no;
+        Some(line)
+    }
+
+    /// Lines up to the matching `.end <name>`.
+    fn block(&mut self, name: &str) -> Result<Vec<Line>, Error> {
+        let start = self.line;
+        let mut lines = vec![];
+        loop {
+            let line = self
+                .take_line()
+                .ok_or_else(|| Error::new(start, format!("missing .end {}", name)))?;
+            if line.tokens.first() == Some(&Token::Word(".end".to_string())) {
+                let mut cur = Cursor::new(&line);
+                cur.word()?;
+                cur.expect(name)?;
+                cur.end()?;
+                return Ok(lines);
+            }
+            lines.push(line);
+        }
+    }
+
+    fn add(&mut self, c: ConstantType) -> Result<u16, Error> {
+        self.b.add(c).map_err(|e| self.err(e.to_string()))
+    }
+
+    fn utf8(&mut self, r: &Ref) -> Result<u16, Error> {
+        match r {
+            Ref::Index(idx) => Ok(*idx),
+            Ref::Bytes(bytes) => {
+                let bytes = bytes.clone();
+                self.b
+                    .utf8_bytes(bytes)
+                    .map_err(|e| self.err(e.to_string()))
+            }
+        }
+    }
+
+    fn class(&mut self, r: &Ref) -> Result<u16, Error> {
+        match r {
+            Ref::Index(idx) => Ok(*idx),
+            Ref::Bytes(_) => {
+                let name_index = self.utf8(r)?;
+                self.add(ConstantType::Class { name_index })
+            }
+        }
+    }
+
+    fn name_and_type(&mut self, r: &NatRef) -> Result<u16, Error> {
+        match r {
+            NatRef::Index(idx) => Ok(*idx),
+            NatRef::Pair(name, desc) => {
+                let name_index = self.utf8(&Ref::Bytes(name.clone()))?;
+                let desc_index = self.utf8(&Ref::Bytes(desc.clone()))?;
+                self.add(ConstantType::NameAndType {
+                    name_index,
+                    desc_index,
+                })
+            }
+        }
+    }
+
+    fn entry(&mut self, c: &Const) -> Result<ConstantType, Error> {
+        let entry = match c {
+            Const::Index(_) => return Err(self.err("expected a constant, found an index")),
+            Const::Utf8(bytes) => ConstantType::Utf8 {
+                bytes: Arc::new(bytes.clone()),
+            },
+            Const::Int(v) => ConstantType::Integer { v: v.to_be_bytes() },
+            Const::Float(bits) => ConstantType::Float {
+                v: bits.to_be_bytes(),
+            },
+            Const::Long(v) => ConstantType::Long { v: v.to_be_bytes() },
+            Const::Double(bits) => ConstantType::Double {
+                v: bits.to_be_bytes(),
+            },
+            Const::Class(name) => ConstantType::Class {
+                name_index: self.utf8(name)?,
+            },
+            Const::String(s) => ConstantType::String {
+                string_index: self.utf8(s)?,
+            },
+            Const::Field(class, nat) => ConstantType::FieldRef {
+                class_index: self.class(class)?,
+                name_and_type_index: self.name_and_type(nat)?,
+            },
+            Const::Method(class, nat) => ConstantType::MethodRef {
+                class_index: self.class(class)?,
+                name_and_type_index: self.name_and_type(nat)?,
+            },
+            Const::InterfaceMethod(class, nat) => ConstantType::InterfaceMethodRef {
+                class_index: self.class(class)?,
+                name_and_type_index: self.name_and_type(nat)?,
+            },
+            Const::NameAndType(name, desc) => ConstantType::NameAndType {
+                name_index: self.utf8(name)?,
+                desc_index: self.utf8(desc)?,
+            },
+            Const::MethodHandle(ref_kind, target) => ConstantType::MethodHandle {
+                ref_kind: *ref_kind,
+                ref_index: self.constant(target)?,
+            },
+            Const::MethodType(desc) => ConstantType::MethodType {
+                desc_index: self.utf8(desc)?,
+            },
+            Const::InvokeDynamic(bootstrap_method_attr_index, nat) => ConstantType::InvokeDynamic {
+                bootstrap_method_attr_index: *bootstrap_method_attr_index,
+                name_and_type_index: self.name_and_type(nat)?,
+            },
+            Const::Dynamic(bootstrap_method_attr_index, nat) => ConstantType::Dynamic {
+                bootstrap_method_attr_index: *bootstrap_method_attr_index,
+                name_and_type_index: self.name_and_type(nat)?,
+            },
+            Const::Module(name) => ConstantType::Module {
+                name_index: self.utf8(name)?,
+            },
+            Const::Package(name) => ConstantType::Package {
+                name_index: self.utf8(name)?,
+            },
+        };
+        Ok(entry)
+    }
+
+    fn constant(&mut self, c: &Const) -> Result<u16, Error> {
+        match c {
+            Const::Index(idx) => Ok(*idx),
+            _ => {
+                let entry = self.entry(c)?;
+                self.add(entry)
+            }
+        }
+    }
+
+    /// Places every `.const` at its slot before anything else is added, so
+    /// symbolic references elsewhere resolve to the listed entries.
+    fn constant_pool(&mut self) -> Result<(), Error> {
+        let mut consts = vec![];
+        for line in self.lines.iter() {
+            if line.tokens.first() != Some(&Token::Word(".const".to_string())) {
+                continue;
+            }
+            let mut cur = Cursor::new(line);
+            cur.word()?;
+            let idx = cur
+                .word()
+                .ok()
+                .and_then(raw_index)
+                .filter(|idx| *idx != 0)
+                .ok_or_else(|| cur.err("expected a slot like [1]"))?;
+            cur.expect("=")?;
+            let c = cur.constant()?;
+            cur.end()?;
+            consts.push((line.no, idx, c));
+        }
+        if consts.is_empty() {
+            return Ok(());
+        }
+
+        let slots = consts
+            .iter()
+            .map(|(_, idx, c)| *idx as usize + if c.is_wide() { 2 } else { 1 })
+            .max()
+            .unwrap_or(1);
+        let mut taken = vec![false; slots];
+        for (line, idx, c) in consts.iter() {
+            let idx = *idx as usize;
+            let wide = c.is_wide();
+            if taken[idx] || (wide && taken[idx + 1]) {
+                return Err(Error::new(*line, format!("slot [{}] is already used", idx)));
+            }
+            taken[idx] = true;
+            if wide {
+                taken[idx + 1] = true;
+            }
+        }
+
+        // literals need nothing else from the pool, so they go in first and
+        // symbolic components of the other entries can find them
+        let mut entries = vec![ConstantType::Nop; slots];
+        let mut refs = vec![];
+        for (line, idx, c) in consts.iter() {
+            if c.is_literal() {
+                self.line = *line;
+                entries[*idx as usize] = self.entry(c)?;
+            } else {
+                refs.push((*line, *idx, c));
+            }
+        }
+        self.b = ConstantPoolBuilder::from_pool(&entries);
+        for (line, idx, c) in refs {
+            self.line = line;
+            let entry = self.entry(c)?;
+            self.b.replace(idx, entry);
+        }
+        Ok(())
+    }
+
+    fn class_file(&mut self) -> Result<ClassFile, Error> {
+        // javac 8 output unless told otherwise
+        let mut version = Version {
+            major: 52,
+            minor: 0,
+        };
+        let mut this_class = None;
+        let mut acc_flags = 0;
+        let mut super_class = 0;
+        let mut interfaces = vec![];
+        let mut fields = vec![];
+        let mut methods = vec![];
+        let mut attrs = vec![];
+
+        while let Some(line) = self.take_line() {
+            let mut cur = Cursor::new(&line);
+            let directive = cur.word()?;
+            match directive {
+                ".version" => {
+                    version.major = cur.number()?;
+                    version.minor = cur.number()?;
+                }
+                ".class" => {
+                    acc_flags = cur.flags(CLASS_FLAGS, 1)?;
+                    this_class = Some(self.class(&cur.reference()?)?);
+                }
+                ".super" => super_class = self.class(&cur.reference()?)?,
+                ".implements" => interfaces.push(self.class(&cur.reference()?)?),
+                ".const" => continue,
+                ".field" => {
+                    let acc_flags = cur.flags(FIELD_FLAGS, 2)?;
+                    let name_index = self.utf8(&cur.reference()?)?;
+                    let desc_index = self.utf8(&cur.reference()?)?;
+                    cur.end()?;
+                    let attrs = self.member_attrs("field")?;
+                    fields.push(FieldInfo {
+                        acc_flags,
+                        name_index,
+                        desc_index,
+                        attrs,
+                    });
+                    continue;
+                }
+                ".method" => {
+                    let acc_flags = cur.flags(METHOD_FLAGS, 2)?;
+                    let name_index = self.utf8(&cur.reference()?)?;
+                    let desc_index = self.utf8(&cur.reference()?)?;
+                    cur.end()?;
+                    let attrs = self.member_attrs("method")?;
+                    methods.push(MethodInfo {
+                        acc_flags,
+                        name_index,
+                        desc_index,
+                        attrs,
+                    });
+                    continue;
+                }
+                ".end" => {
+                    cur.expect("class")?;
+                    cur.end()?;
+                    break;
+                }
+                _ => {
+                    cur.pos = 0;
+                    self.attribute(&mut cur, &mut attrs)?;
+                    continue;
+                }
+            }
+            cur.end()?;
+        }
+
+        let this_class = this_class.ok_or_else(|| Error::new(0, "missing .class"))?;
+        for field in fields.iter_mut() {
+            self.finish_attrs(&mut field.attrs)?;
+        }
+        for method in methods.iter_mut() {
+            self.finish_attrs(&mut method.attrs)?;
+        }
+        self.finish_attrs(&mut attrs)?;
+
+        let b = std::mem::take(&mut self.b);
+        Ok(ClassFile {
+            version,
+            cp: b.build(),
+            acc_flags,
+            this_class,
+            super_class,
+            interfaces,
+            fields,
+            methods,
+            attrs,
+        })
+    }
+
+    // attribute names have to be in the pool for the writer to find them
+    fn finish_attrs(&mut self, attrs: &mut [AttributeType]) -> Result<(), Error> {
+        self.line = 0;
+        for attr in attrs.iter_mut() {
+            if let AttributeType::Code(code) = attr {
+                self.finish_attrs(&mut code.attrs)?;
+            }
+            self.utf8(&Ref::Bytes(attr.name().to_vec()))?;
+        }
+        class_parser::refresh_raw(attrs).map_err(|e| Error::new(0, e.to_string()))
+    }
+
+    fn member_attrs(&mut self, member: &str) -> Result<Vec<AttributeType>, Error> {
+        let start = self.line;
+        let mut attrs = vec![];
+        loop {
+            let line = self
+                .take_line()
+                .ok_or_else(|| Error::new(start, format!("missing .end {}", member)))?;
+            let mut cur = Cursor::new(&line);
+            if cur.peek_word() == Some(".end") {
+                cur.word()?;
+                cur.expect(member)?;
+                cur.end()?;
+                return Ok(attrs);
+            }
+            self.attribute(&mut cur, &mut attrs)?;
+        }
+    }
+
+    fn attribute(&mut self, cur: &mut Cursor, attrs: &mut Vec<AttributeType>) -> Result<(), Error> {
+        let directive = cur.word()?;
+        let attr = match directive {
+            ".constantvalue" => AttributeType::ConstantValue {
+                constant_value_index: self.constant(&cur.constant()?)?,
+            },
+            ".code" => AttributeType::Code(self.code(cur)?),
+            ".exceptions" => {
+                let mut exceptions = vec![];
+                while !cur.is_done() {
+                    exceptions.push(self.class(&cur.reference()?)?);
+                }
+                AttributeType::Exceptions { exceptions }
+            }
+            ".innerclasses" => {
+                let mut classes = vec![];
+                for line in self.block("innerclasses")? {
+                    let mut row = Cursor::new(&line);
+                    let inner_class_info_index = self.optional_class(&mut row)?;
+                    let outer_class_info_index = self.optional_class(&mut row)?;
+                    let inner_name_index = if row.is_none_marker() {
+                        0
+                    } else {
+                        self.utf8(&row.reference()?)?
+                    };
+                    let inner_class_access_flags = row.flags(INNER_CLASS_FLAGS, 0)?;
+                    classes.push(InnerClass {
+                        inner_class_info_index,
+                        outer_class_info_index,
+                        inner_name_index,
+                        inner_class_access_flags,
+                    });
+                }
+                AttributeType::InnerClasses { classes }
+            }
+            ".enclosingmethod" => {
+                let class_index = self.class(&cur.reference()?)?;
+                let method_index = if cur.is_none_marker() {
+                    0
+                } else {
+                    self.name_and_type(&cur.name_and_type()?)?
+                };
+                AttributeType::EnclosingMethod {
+                    em: EnclosingMethod {
+                        class_index,
+                        method_index,
+                    },
+                }
+            }
+            ".synthetic" => AttributeType::Synthetic,
+            ".deprecated" => AttributeType::Deprecated,
+            ".signature" => AttributeType::Signature {
+                signature_index: self.utf8(&cur.reference()?)?,
+            },
+            ".sourcefile" => AttributeType::SourceFile {
+                source_file_index: self.utf8(&cur.reference()?)?,
+            },
+            ".sourcedebugextension" => AttributeType::SourceDebugExtension {
+                debug_extension: Arc::new(cur.bytes()?),
+            },
+            ".annotation" => {
+                let visible = match cur.word()? {
+                    "visible" => true,
+                    "invisible" => false,
+                    w => return Err(cur.err(format!("expected visible or invisible, found {}", w))),
+                };
+                let type_name = cur.bytes()?;
+                cur.end()?;
+                let entry = self.annotation_block(type_name)?;
+                // consecutive annotations share one attribute
+                match (attrs.last_mut(), visible) {
+                    (Some(AttributeType::RuntimeVisibleAnnotations { annotations, .. }), true)
+                    | (
+                        Some(AttributeType::RuntimeInvisibleAnnotations { annotations, .. }),
+                        false,
+                    ) => {
+                        annotations.push(entry);
+                        return Ok(());
+                    }
+                    _ => (),
+                }
+                let raw = Arc::new(vec![]);
+                if visible {
+                    AttributeType::RuntimeVisibleAnnotations {
+                        raw,
+                        annotations: vec![entry],
+                    }
+                } else {
+                    AttributeType::RuntimeInvisibleAnnotations {
+                        raw,
+                        annotations: vec![entry],
+                    }
+                }
+            }
+            ".annotationdefault" => AttributeType::AnnotationDefault {
+                raw: Arc::new(vec![]),
+                default_value: self.element_value(cur)?,
+            },
+            ".bootstrapmethods" => {
+                let mut methods = vec![];
+                for line in self.block("bootstrapmethods")? {
+                    let mut row = Cursor::new(&line);
+                    let method_ref = self.constant(&row.constant()?)?;
+                    let mut args = vec![];
+                    while !row.is_done() {
+                        args.push(self.constant(&row.constant()?)?);
+                    }
+                    methods.push(BootstrapMethod { method_ref, args });
+                }
+                AttributeType::BootstrapMethods {
+                    n: methods.len() as u16,
+                    methods,
+                }
+            }
+            ".methodparameters" => {
+                let mut parameters = vec![];
+                for line in self.block("methodparameters")? {
+                    let mut row = Cursor::new(&line);
+                    let name_index = if row.is_none_marker() {
+                        0
+                    } else {
+                        self.utf8(&row.reference()?)?
+                    };
+                    let acc_flags = row.flags(PARAMETER_FLAGS, 0)?;
+                    parameters.push(MethodParameter {
+                        name_index,
+                        acc_flags,
+                    });
+                }
+                AttributeType::MethodParameters { parameters }
+            }
+            ".nesthost" => AttributeType::NestHost {
+                host_class_index: self.class(&cur.reference()?)?,
+            },
+            ".nestmembers" | ".permittedsubclasses" => {
+                let mut classes = vec![];
+                while !cur.is_done() {
+                    classes.push(self.class(&cur.reference()?)?);
+                }
+                if directive == ".nestmembers" {
+                    AttributeType::NestMembers { classes }
+                } else {
+                    AttributeType::PermittedSubclasses { classes }
+                }
+            }
+            ".attribute" => {
+                let name = cur.bytes()?;
+                let data = cur.bytes()?;
+                let name_str = String::from_utf8_lossy(&name).into_owned();
+                let cp = Arc::new(self.b.entries().to_vec());
+                class_parser::parse_attribute(&name, &data, cp)
+                    .ok_or_else(|| cur.err(format!("malformed {} attribute", name_str)))?
+            }
+            _ => return Err(cur.err(format!("unknown directive {}", directive))),
+        };
+        cur.end()?;
+        attrs.push(attr);
+        Ok(())
+    }
+
+    fn optional_class(&mut self, cur: &mut Cursor) -> Result<u16, Error> {
+        if cur.is_none_marker() {
+            Ok(0)
+        } else {
+            self.class(&cur.reference()?)
+        }
+    }
+
+    fn annotation_block(&mut self, type_name: Vec<u8>) -> Result<AnnotationEntry, Error> {
+        let mut pairs = vec![];
+        for line in self.block("annotation")? {
+            let mut row = Cursor::new(&line);
+            pairs.push(self.element_value_pair(&mut row)?);
+            row.end()?;
+        }
+        self.annotation_entry(type_name, pairs)
+    }
+
+    fn annotation_entry(
+        &mut self,
+        type_name: Vec<u8>,
+        pairs: Vec<ElementValuePair>,
+    ) -> Result<AnnotationEntry, Error> {
+        let type_index = self.utf8(&Ref::Bytes(type_name.clone()))?;
+        Ok(AnnotationEntry {
+            type_index,
+            type_name: Arc::new(type_name),
+            pairs,
+        })
+    }
+
+    fn element_value_pair(&mut self, cur: &mut Cursor) -> Result<ElementValuePair, Error> {
+        let name_index = self.utf8(&cur.reference()?)?;
+        cur.expect("=")?;
+        Ok(ElementValuePair {
+            name_index,
+            value: self.element_value(cur)?,
+        })
+    }
+
+    fn element_value(&mut self, cur: &mut Cursor) -> Result<ElementValueType, Error> {
+        let kind = cur.word()?;
+        let int = |this: &mut Self, cur: &mut Cursor| -> Result<u16, Error> {
+            match cur.peek_word().and_then(raw_index) {
+                Some(idx) => {
+                    cur.pos += 1;
+                    Ok(idx)
+                }
+                None => this.constant(&Const::Int(cur.number()?)),
+            }
+        };
+        let v = match kind {
+            "byte" => ElementValueType::Byte {
+                val_index: int(self, cur)?,
+            },
+            "char" => ElementValueType::Char {
+                val_index: int(self, cur)?,
+            },
+            "int" => ElementValueType::Int {
+                val_index: int(self, cur)?,
+            },
+            "short" => ElementValueType::Short {
+                val_index: int(self, cur)?,
+            },
+            "boolean" => ElementValueType::Boolean {
+                val_index: int(self, cur)?,
+            },
+            "long" | "float" | "double" => {
+                let c = match cur.peek_word().and_then(raw_index) {
+                    Some(idx) => {
+                        cur.pos += 1;
+                        Const::Index(idx)
+                    }
+                    None => match kind {
+                        "long" => Const::Long(cur.number()?),
+                        "float" => Const::Float(cur.float_bits()?),
+                        _ => Const::Double(cur.double_bits()?),
+                    },
+                };
+                let val_index = self.constant(&c)?;
+                match kind {
+                    "long" => ElementValueType::Long { val_index },
+                    "float" => ElementValueType::Float { val_index },
+                    _ => ElementValueType::Double { val_index },
+                }
+            }
+            "string" => ElementValueType::String {
+                val_index: self.utf8(&cur.reference()?)?,
+            },
+            "enum" => ElementValueType::Enum {
+                type_index: self.utf8(&cur.reference()?)?,
+                val_index: self.utf8(&cur.reference()?)?,
+            },
+            "class" => ElementValueType::Class {
+                index: self.utf8(&cur.reference()?)?,
+            },
+            "annotation" => {
+                let type_name = cur.bytes()?;
+                cur.expect("{")?;
+                let mut pairs = vec![];
+                while cur.peek_word() != Some("}") {
+                    pairs.push(self.element_value_pair(cur)?);
+                }
+                cur.pos += 1;
+                ElementValueType::Annotation(AnnotationElementValue {
+                    value: self.annotation_entry(type_name, pairs)?,
+                })
+            }
+            "array" => {
+                cur.expect("{")?;
+                let mut values = vec![];
+                while cur.peek_word() != Some("}") {
+                    values.push(self.element_value(cur)?);
+                }
+                cur.pos += 1;
+                ElementValueType::Array { values }
+            }
+            _ => return Err(cur.err(format!("unknown element value kind {}", kind))),
+        };
+        Ok(v)
+    }
+
+    fn code(&mut self, header: &mut Cursor) -> Result<Code, Error> {
+        header.expect("stack")?;
+        let max_stack = header.number()?;
+        header.expect("locals")?;
+        let max_locals = header.number()?;
+        header.end()?;
+
+        // first pass: lay out instructions and find the labels
+        let start = self.line;
+        let mut pc = 0;
+        let mut labels = HashMap::new();
+        let mut insns = vec![];
+        let mut directives: Vec<(Line, Vec<Line>)> = vec![];
+        loop {
+            let mut line = self
+                .take_line()
+                .ok_or_else(|| Error::new(start, "missing .end code"))?;
+            if let Some(Token::Word(w)) = line.tokens.first() {
+                if let Some(name) = w.strip_suffix(':') {
+                    if labels.insert(name.to_string(), pc).is_some() {
+                        return Err(self.err(format!("duplicate label {}", name)));
+                    }
+                    line.tokens.remove(0);
+                    if line.tokens.is_empty() {
+                        continue;
+                    }
+                }
+            }
+            let first = match line.tokens.first() {
+                Some(Token::Word(w)) => w.clone(),
+                _ => return Err(self.err("expected an instruction or directive")),
+            };
+            match first.as_str() {
+                ".end" => {
+                    let mut cur = Cursor::new(&line);
+                    cur.word()?;
+                    cur.expect("code")?;
+                    cur.end()?;
+                    break;
+                }
+                ".linenumbertable"
+                | ".localvariabletable"
+                | ".localvariabletypetable"
+                | ".stackmaptable" => {
+                    let rows = self.block(&first[1..])?;
+                    directives.push((line, rows));
+                }
+                _ if first.starts_with('.') => directives.push((line, vec![])),
+                _ => {
+                    let opcode = bytecode::opcode(&first)
+                        .ok_or_else(|| self.err(format!("unknown instruction {}", first)))?;
+                    let len = instruction_len(opcode, &line, pc)?;
+                    insns.push(Insn { line, pc, opcode });
+                    pc += len;
+                }
+            }
+        }
+
+        let mut code = Vec::with_capacity(pc);
+        for insn in insns.iter() {
+            self.line = insn.line.no;
+            self.instruction(&mut code, insn, &labels)?;
+        }
+
+        let mut exceptions = vec![];
+        let mut attrs = vec![];
+        for (line, rows) in directives.iter() {
+            self.line = line.no;
+            let mut cur = Cursor::new(line);
+            let pc16 = |cur: &mut Cursor| -> Result<u16, Error> {
+                let pc = cur.label(&labels)?;
+                if pc > u16::MAX as usize {
+                    return Err(cur.err("label beyond 65535"));
+                }
+                Ok(pc as u16)
+            };
+            match cur.word()? {
+                ".catch" => {
+                    let catch_type = if cur.peek_word() == Some("any") {
+                        cur.pos += 1;
+                        0
+                    } else {
+                        self.class(&cur.reference()?)?
+                    };
+                    cur.expect("from")?;
+                    let start_pc = pc16(&mut cur)?;
+                    cur.expect("to")?;
+                    let end_pc = pc16(&mut cur)?;
+                    cur.expect("using")?;
+                    let handler_pc = pc16(&mut cur)?;
+                    cur.end()?;
+                    exceptions.push(CodeException {
+                        start_pc,
+                        end_pc,
+                        handler_pc,
+                        catch_type,
+                    });
+                }
+                ".linenumbertable" => {
+                    let mut tables = vec![];
+                    for row in rows.iter() {
+                        let mut row = Cursor::new(row);
+                        let start_pc = pc16(&mut row)?;
+                        let number = row.number()?;
+                        row.end()?;
+                        tables.push(LineNumber { start_pc, number });
+                    }
+                    attrs.push(AttributeType::LineNumberTable { tables });
+                }
+                d @ ".localvariabletable" | d @ ".localvariabletypetable" => {
+                    let mut tables = vec![];
+                    for row in rows.iter() {
+                        let mut row = Cursor::new(row);
+                        let index = row.number()?;
+                        row.expect("is")?;
+                        let name_index = self.utf8(&row.reference()?)?;
+                        let signature_index = self.utf8(&row.reference()?)?;
+                        row.expect("from")?;
+                        let start_pc = pc16(&mut row)?;
+                        row.expect("to")?;
+                        let end_pc = pc16(&mut row)?;
+                        row.end()?;
+                        if end_pc < start_pc {
+                            return Err(row.err("variable range ends before it starts"));
+                        }
+                        tables.push(LocalVariable {
+                            start_pc,
+                            length: end_pc - start_pc,
+                            name_index,
+                            signature_index,
+                            index,
+                        });
+                    }
+                    attrs.push(if d == ".localvariabletable" {
+                        AttributeType::LocalVariableTable { tables }
+                    } else {
+                        AttributeType::LocalVariableTypeTable { tables }
+                    });
+                }
+                ".stackmaptable" => {
+                    let mut entries = vec![];
+                    let mut prev: Option<u16> = None;
+                    for row in rows.iter() {
+                        let mut row = Cursor::new(row);
+                        let kind = row.word()?;
+                        let pc = pc16(&mut row)?;
+                        let offset_delta = match prev {
+                            None => Some(pc),
+                            Some(prev) => pc.checked_sub(prev).and_then(|d| d.checked_sub(1)),
+                        }
+                        .ok_or_else(|| row.err("frames must be in increasing pc order"))?;
+                        prev = Some(pc);
+                        entries.push(self.frame(&mut row, kind, offset_delta, &labels)?);
+                        row.end()?;
+                    }
+                    attrs.push(AttributeType::StackMapTable { entries });
+                }
+                _ => {
+                    cur.pos = 0;
+                    self.attribute(&mut cur, &mut attrs)?;
+                }
+            }
+        }
+
+        Ok(Code {
+            max_stack,
+            max_locals,
+            code: Arc::new(code),
+            exceptions,
+            attrs,
+        })
+    }
+
+    fn frame(
+        &mut self,
+        row: &mut Cursor,
+        kind: &str,
+        offset_delta: u16,
+        labels: &HashMap<String, usize>,
+    ) -> Result<StackMapFrame, Error> {
+        let compact = |row: &Cursor, base: u16| -> Result<u8, Error> {
+            if offset_delta <= 63 {
+                Ok((base + offset_delta) as u8)
+            } else {
+                Err(row.err(format!("offset {} needs the _extended form", offset_delta)))
+            }
+        };
+        let frame = match kind {
+            "same" => StackMapFrame::Same {
+                tag: compact(row, 0)?,
+                offset_delta,
+            },
+            "same_extended" => StackMapFrame::SameExtended {
+                tag: 251,
+                offset_delta,
+            },
+            "same_locals_1_stack_item" => StackMapFrame::SameLocals1StackItem {
+                tag: compact(row, 64)?,
+                offset_delta,
+                stack: [self.vti(row, labels)?],
+            },
+            "same_locals_1_stack_item_extended" => StackMapFrame::SameLocals1StackItemExtended {
+                tag: 247,
+                offset_delta,
+                stack: [self.vti(row, labels)?],
+            },
+            "chop" => {
+                let k: u8 = row.number()?;
+                if !(1..=3).contains(&k) {
+                    return Err(row.err("chop removes 1 to 3 locals"));
+                }
+                StackMapFrame::Chop {
+                    tag: 251 - k,
+                    offset_delta,
+                }
+            }
+            "append" => {
+                let mut locals = vec![];
+                while !row.is_done() {
+                    locals.push(self.vti(row, labels)?);
+                }
+                if !(1..=3).contains(&locals.len()) {
+                    return Err(row.err("append adds 1 to 3 locals"));
+                }
+                StackMapFrame::Append {
+                    tag: 251 + locals.len() as u8,
+                    offset_delta,
+                    locals,
+                }
+            }
+            "full" => {
+                row.expect("locals")?;
+                let mut locals = vec![];
+                while row.peek_word() != Some("stack") {
+                    locals.push(self.vti(row, labels)?);
+                }
+                row.pos += 1;
+                let mut stack = vec![];
+                while !row.is_done() {
+                    stack.push(self.vti(row, labels)?);
+                }
+                StackMapFrame::Full {
+                    tag: 255,
+                    offset_delta,
+                    locals,
+                    stack,
+                }
+            }
+            _ => return Err(row.err(format!("unknown frame type {}", kind))),
+        };
+        Ok(frame)
+    }
+
+    fn vti(
+        &mut self,
+        row: &mut Cursor,
+        labels: &HashMap<String, usize>,
+    ) -> Result<VerificationTypeInfo, Error> {
+        let v = match row.word()? {
+            "Top" => VerificationTypeInfo::Top,
+            "Integer" => VerificationTypeInfo::Integer,
+            "Float" => VerificationTypeInfo::Float,
+            "Long" => VerificationTypeInfo::Long,
+            "Double" => VerificationTypeInfo::Double,
+            "Null" => VerificationTypeInfo::Null,
+            "UninitializedThis" => VerificationTypeInfo::UninitializedThis,
+            "Object" => VerificationTypeInfo::Object {
+                cpool_index: self.class(&row.reference()?)?,
+            },
+            "Uninitialized" => VerificationTypeInfo::Uninitialized {
+                offset: row.label(labels)? as u16,
+            },
+            w => return Err(row.err(format!("unknown verification type {}", w))),
+        };
+        Ok(v)
+    }
+
+    fn instruction(
+        &mut self,
+        code: &mut Vec<u8>,
+        insn: &Insn,
+        labels: &HashMap<String, usize>,
+    ) -> Result<(), Error> {
+        let mut cur = Cursor::new(&insn.line);
+        cur.word()?;
+        let pc = insn.pc;
+        let op = insn.opcode;
+        let offset =
+            |cur: &mut Cursor| -> Result<i64, Error> { Ok(cur.label(labels)? as i64 - pc as i64) };
+        let u8_operand = |cur: &mut Cursor, what: &str| -> Result<u8, Error> {
+            let v: u16 = cur.number()?;
+            if v > u8::MAX as u16 {
+                return Err(cur.err(format!("{} {} needs wide", what, v)));
+            }
+            Ok(v as u8)
+        };
+
+        code.push(op);
+        match bytecode::operand_kind(op) {
+            OperandKind::None => (),
+            OperandKind::Byte => code.push(cur.number::<i8>()? as u8),
+            OperandKind::Short => code.extend_from_slice(&cur.number::<i16>()?.to_be_bytes()),
+            OperandKind::ConstNarrow => {
+                let idx = self.constant(&cur.constant()?)?;
+                if idx > u8::MAX as u16 {
+                    return Err(cur.err(format!("constant [{}] needs ldc_w", idx)));
+                }
+                code.push(idx as u8);
+            }
+            OperandKind::Const => {
+                let idx = match op {
+                    bytecode::NEW
+                    | bytecode::ANEWARRAY
+                    | bytecode::CHECKCAST
+                    | bytecode::INSTANCEOF => self.class(&cur.reference()?)?,
+                    _ => self.constant(&cur.constant()?)?,
+                };
+                code.extend_from_slice(&idx.to_be_bytes());
+            }
+            OperandKind::Local => code.push(u8_operand(&mut cur, "local")?),
+            OperandKind::Iinc => {
+                code.push(u8_operand(&mut cur, "local")?);
+                code.push(cur.number::<i8>()? as u8);
+            }
+            OperandKind::Branch => {
+                let off = offset(&mut cur)?;
+                if off < i16::MIN as i64 || off > i16::MAX as i64 {
+                    return Err(cur.err("branch too far, use goto_w"));
+                }
+                code.extend_from_slice(&(off as i16).to_be_bytes());
+            }
+            OperandKind::BranchWide => {
+                code.extend_from_slice(&(offset(&mut cur)? as i32).to_be_bytes());
+            }
+            OperandKind::TableSwitch => {
+                code.resize(code.len() + bytecode::switch_padding(pc), 0);
+                let low: i32 = cur.number()?;
+                let mut targets = vec![];
+                while cur.peek_word() != Some("default") {
+                    targets.push(offset(&mut cur)? as i32);
+                }
+                cur.pos += 1;
+                let default = offset(&mut cur)? as i32;
+                if targets.is_empty() {
+                    return Err(cur.err("tableswitch needs at least one target"));
+                }
+                let high = i32::try_from(targets.len())
+                    .ok()
+                    .and_then(|n| low.checked_add(n - 1))
+                    .ok_or_else(|| cur.err("tableswitch targets run past the largest int"))?;
+                for v in [default, low, high].iter().chain(targets.iter()) {
+                    code.extend_from_slice(&v.to_be_bytes());
+                }
+            }
+            OperandKind::LookupSwitch => {
+                code.resize(code.len() + bytecode::switch_padding(pc), 0);
+                let mut pairs = vec![];
+                while cur.peek_word() != Some("default") {
+                    let w = cur.word()?;
+                    let (key, target) = w
+                        .split_once(':')
+                        .ok_or_else(|| cur.err(format!("expected KEY:LABEL, found {}", w)))?;
+                    let key: i32 = key
+                        .parse()
+                        .map_err(|_| cur.err(format!("bad key {}", key)))?;
+                    let target = *labels
+                        .get(target)
+                        .ok_or_else(|| cur.err(format!("undefined label {}", target)))?;
+                    pairs.push((key, (target as i64 - pc as i64) as i32));
+                }
+                cur.pos += 1;
+                let default = offset(&mut cur)? as i32;
+                pairs.sort_by_key(|(key, _)| *key);
+                code.extend_from_slice(&default.to_be_bytes());
+                code.extend_from_slice(&(pairs.len() as i32).to_be_bytes());
+                for (key, off) in pairs {
+                    code.extend_from_slice(&key.to_be_bytes());
+                    code.extend_from_slice(&off.to_be_bytes());
+                }
+            }
+            OperandKind::InvokeInterface => {
+                let idx = self.constant(&cur.constant()?)?;
+                code.extend_from_slice(&idx.to_be_bytes());
+                code.push(cur.number()?);
+                code.push(0);
+            }
+            OperandKind::InvokeDynamic => {
+                let idx = self.constant(&cur.constant()?)?;
+                code.extend_from_slice(&idx.to_be_bytes());
+                code.extend_from_slice(&[0, 0]);
+            }
+            OperandKind::NewArray => {
+                let w = cur.word()?;
+                let atype = match ARRAY_TYPES.iter().position(|t| !t.is_empty() && *t == w) {
+                    Some(t) => t as u8,
+                    None => w
+                        .parse()
+                        .map_err(|_| cur.err(format!("unknown array type {}", w)))?,
+                };
+                code.push(atype);
+            }
+            OperandKind::MultiANewArray => {
+                let idx = self.class(&cur.reference()?)?;
+                code.extend_from_slice(&idx.to_be_bytes());
+                code.push(cur.number()?);
+            }
+            OperandKind::Wide => {
+                let w = cur.word()?;
+                let inner = bytecode::opcode(w)
+                    .filter(|op| {
+                        matches!(
+                            bytecode::operand_kind(*op),
+                            OperandKind::Local | OperandKind::Iinc
+                        )
+                    })
+                    .ok_or_else(|| cur.err(format!("{} can't be wide", w)))?;
+                code.push(inner);
+                code.extend_from_slice(&cur.number::<u16>()?.to_be_bytes());
+                if inner == bytecode::IINC {
+                    code.extend_from_slice(&cur.number::<i16>()?.to_be_bytes());
+                }
+            }
+        }
+        cur.end()
+    }
+}
+
+fn instruction_len(opcode: u8, line: &Line, pc: usize) -> Result<usize, Error> {
+    // operands, not counting the mnemonic
+    let operands = line.tokens.len() - 1;
+    let len = match bytecode::operand_kind(opcode) {
+        OperandKind::None => 1,
+        OperandKind::Byte
+        | OperandKind::ConstNarrow
+        | OperandKind::Local
+        | OperandKind::NewArray => 2,
+        OperandKind::Short | OperandKind::Const | OperandKind::Iinc | OperandKind::Branch => 3,
+        OperandKind::MultiANewArray => 4,
+        OperandKind::BranchWide | OperandKind::InvokeInterface | OperandKind::InvokeDynamic => 5,
+        OperandKind::TableSwitch => {
+            // low, targets..., default, label
+            let targets = operands.saturating_sub(3);
+            1 + bytecode::switch_padding(pc) + 12 + 4 * targets
+        }
+        OperandKind::LookupSwitch => {
+            // pairs..., default, label
+            let pairs = operands.saturating_sub(2);
+            1 + bytecode::switch_padding(pc) + 8 + 8 * pairs
+        }
+        OperandKind::Wide => match line.tokens.get(1) {
+            Some(Token::Word(w)) if w == "iinc" => 6,
+            _ => 4,
+        },
+    };
+    Ok(len)
+}
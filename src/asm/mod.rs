@@ -0,0 +1,265 @@
+//! Text form of class files, in the spirit of Jasmin and Krakatau.
+//!
+//! [`print`] disassembles a [`ClassFile`](crate::class_parser::format::class_file::ClassFile)
+//! and [`assemble`] turns the text back into one. The printer lists the whole
+//! constant pool with `.const` lines so that assembling its output reproduces
+//! the original indices; hand written sources can leave them out and refer to
+//! constants symbolically only.
+//!
+//! ```text
+//! .version 52 0
+//! .class public super HelloWorld
+//! .super java/lang/Object
+//!
+//! .method public static main ([Ljava/lang/String;)V
+//!     .code stack 2 locals 1
+//!         getstatic Field java/lang/System out Ljava/io/PrintStream;
+//!         ldc String "Hello, World"
+//!         invokevirtual Method java/io/PrintStream println (Ljava/lang/String;)V
+//!         return
+//!     .end code
+//! .end method
+//! .end class
+//! ```
+//!
+//! Constants are written as `Utf8 "..."`, `Int 1`, `Float 1.5`, `Long 1`,
+//! `Double 1.5`, `Class name`, `String "..."`, `Field class name desc`,
+//! `Method ...`, `InterfaceMethod ...`, `NameAndType name desc`,
+//! `MethodHandle kind <ref>`, `MethodType desc`,
+//! `InvokeDynamic bootstrap name desc`, `Dynamic bootstrap name desc`,
+//! `Module name` and `Package name`, or as a raw pool index `[n]`. Every
+//! component may also be a raw index, e.g. `Method [2] [3]`. Floats written
+//! in hex are raw bits. Comments start with a `;` at the beginning of a token.
+//!
+//! Attributes without a directive of their own, including ones the parser
+//! doesn't know, are written as `.attribute name "raw bytes"`.
+
+mod assembler;
+mod printer;
+
+pub use assembler::assemble;
+pub use printer::print;
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    /// 1-based source line, 0 when the error is not tied to one
+    pub line: usize,
+    pub message: String,
+}
+
+impl Error {
+    fn new(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "line {}: {}", self.line, self.message)
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+const CLASS_FLAGS: &[(&str, u16)] = &[
+    ("public", 0x0001),
+    ("final", 0x0010),
+    ("super", 0x0020),
+    ("interface", 0x0200),
+    ("abstract", 0x0400),
+    ("synthetic", 0x1000),
+    ("annotation", 0x2000),
+    ("enum", 0x4000),
+    ("module", 0x8000),
+];
+
+const FIELD_FLAGS: &[(&str, u16)] = &[
+    ("public", 0x0001),
+    ("private", 0x0002),
+    ("protected", 0x0004),
+    ("static", 0x0008),
+    ("final", 0x0010),
+    ("volatile", 0x0040),
+    ("transient", 0x0080),
+    ("synthetic", 0x1000),
+    ("enum", 0x4000),
+];
+
+const METHOD_FLAGS: &[(&str, u16)] = &[
+    ("public", 0x0001),
+    ("private", 0x0002),
+    ("protected", 0x0004),
+    ("static", 0x0008),
+    ("final", 0x0010),
+    ("synchronized", 0x0020),
+    ("bridge", 0x0040),
+    ("varargs", 0x0080),
+    ("native", 0x0100),
+    ("abstract", 0x0400),
+    ("strict", 0x0800),
+    ("synthetic", 0x1000),
+];
+
+const INNER_CLASS_FLAGS: &[(&str, u16)] = &[
+    ("public", 0x0001),
+    ("private", 0x0002),
+    ("protected", 0x0004),
+    ("static", 0x0008),
+    ("final", 0x0010),
+    ("interface", 0x0200),
+    ("abstract", 0x0400),
+    ("synthetic", 0x1000),
+    ("annotation", 0x2000),
+    ("enum", 0x4000),
+];
+
+const PARAMETER_FLAGS: &[(&str, u16)] = &[
+    ("final", 0x0010),
+    ("synthetic", 0x1000),
+    ("mandated", 0x8000),
+];
+
+// spec 4.4.8, indexed by reference_kind
+const HANDLE_KINDS: [&str; 10] = [
+    "",
+    "getField",
+    "getStatic",
+    "putField",
+    "putStatic",
+    "invokeVirtual",
+    "invokeStatic",
+    "invokeSpecial",
+    "newInvokeSpecial",
+    "invokeInterface",
+];
+
+// spec 6.5 newarray, indexed by atype
+const ARRAY_TYPES: [&str; 12] = [
+    "", "", "", "", "boolean", "char", "float", "double", "byte", "short", "int", "long",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    /// quoted, with escapes already resolved
+    Str(Vec<u8>),
+}
+
+fn tokenize(line: &str, line_no: usize) -> Result<Vec<Token>, Error> {
+    let mut tokens = vec![];
+    let bytes = line.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b' ' | b'\t' | b'\r' => i += 1,
+            b';' => break,
+            b'"' => {
+                let mut s = vec![];
+                i += 1;
+                loop {
+                    match bytes.get(i) {
+                        None => return Err(Error::new(line_no, "unterminated string")),
+                        Some(b'"') => break,
+                        Some(b'\\') => {
+                            match bytes.get(i + 1) {
+                                Some(b'"') => s.push(b'"'),
+                                Some(b'\\') => s.push(b'\\'),
+                                Some(b'n') => s.push(b'\n'),
+                                Some(b't') => s.push(b'\t'),
+                                Some(b'r') => s.push(b'\r'),
+                                Some(b'x') => {
+                                    let hex = line
+                                        .get(i + 2..i + 4)
+                                        .and_then(|h| u8::from_str_radix(h, 16).ok())
+                                        .ok_or_else(|| Error::new(line_no, "bad \\x escape"))?;
+                                    s.push(hex);
+                                    i += 2;
+                                }
+                                _ => return Err(Error::new(line_no, "bad escape")),
+                            }
+                            i += 2;
+                        }
+                        Some(&b) => {
+                            s.push(b);
+                            i += 1;
+                        }
+                    }
+                }
+                i += 1;
+                tokens.push(Token::Str(s));
+            }
+            _ => {
+                let start = i;
+                while i < bytes.len() && !matches!(bytes[i], b' ' | b'\t' | b'\r') {
+                    i += 1;
+                }
+                tokens.push(Token::Word(line[start..i].to_string()));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn escape(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() + 2);
+    out.push('"');
+    for &b in bytes {
+        match b {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            b'\n' => out.push_str("\\n"),
+            b'\t' => out.push_str("\\t"),
+            b'\r' => out.push_str("\\r"),
+            0x20..=0x7e => out.push(b as char),
+            _ => out.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// `[12]`, which no valid name or descriptor looks like
+fn raw_index(s: &str) -> Option<u16> {
+    s.strip_prefix('[')?.strip_suffix(']')?.parse().ok()
+}
+
+/// Names are printed bare when the tokenizer would read them back unchanged.
+fn word(bytes: &[u8]) -> String {
+    let bare = !bytes.is_empty()
+        && bytes
+            .iter()
+            .all(|b| (0x21..=0x7e).contains(b) && *b != b'"')
+        && bytes[0] != b';'
+        && bytes != b"-"
+        && !bytes.ends_with(b":")
+        && std::str::from_utf8(bytes).is_ok_and(|s| raw_index(s).is_none());
+    if bare {
+        String::from_utf8_lossy(bytes).into_owned()
+    } else {
+        escape(bytes)
+    }
+}
+
+fn flags_to_string(flags: u16, table: &[(&str, u16)]) -> String {
+    let mut words = vec![];
+    let mut rest = flags;
+    for (name, bit) in table {
+        if flags & bit != 0 {
+            words.push(name.to_string());
+            rest &= !bit;
+        }
+    }
+    if rest != 0 {
+        words.push(format!("0x{:04x}", rest));
+    }
+    words.join(" ")
+}
@@ -0,0 +1,994 @@
+use super::{
+    escape, flags_to_string, word, Error, ARRAY_TYPES, CLASS_FLAGS, FIELD_FLAGS, HANDLE_KINDS,
+    INNER_CLASS_FLAGS, METHOD_FLAGS, PARAMETER_FLAGS,
+};
+use crate::class_parser::format::{
+    attributes::{
+        AnnotationEntry, Code, ElementValueType, StackMapFrame, Type as AttributeType,
+        VerificationTypeInfo,
+    },
+    class_file::ClassFile,
+    constant_pool::Type as ConstantType,
+};
+use crate::class_parser::{
+    self,
+    bytecode::{self, OperandKind},
+};
+
+use std::collections::{BTreeSet, HashMap};
+
+/// Disassembles `cf` into the text form read by [`assemble`](super::assemble).
+pub fn print(cf: &ClassFile) -> Result<String, Error> {
+    let mut first = HashMap::new();
+    for (i, c) in cf.cp.iter().enumerate() {
+        if !matches!(c, ConstantType::Nop | ConstantType::Unknown) {
+            first.entry(c).or_insert(i as u16);
+        }
+    }
+    let mut p = Printer {
+        cp: &cf.cp,
+        first,
+        out: String::new(),
+    };
+    p.class_file(cf)?;
+    Ok(p.out)
+}
+
+fn u16_at(code: &[u8], at: usize) -> u16 {
+    u16::from_be_bytes([code[at], code[at + 1]])
+}
+
+fn i32_at(code: &[u8], at: usize) -> i32 {
+    i32::from_be_bytes([code[at], code[at + 1], code[at + 2], code[at + 3]])
+}
+
+fn float(v: [u8; 4]) -> String {
+    let f = f32::from_bits(u32::from_be_bytes(v));
+    if f.is_finite() {
+        format!("{:?}", f)
+    } else {
+        format!("0x{:08x}", u32::from_be_bytes(v))
+    }
+}
+
+fn double(v: [u8; 8]) -> String {
+    let d = f64::from_bits(u64::from_be_bytes(v));
+    if d.is_finite() {
+        format!("{:?}", d)
+    } else {
+        format!("0x{:016x}", u64::from_be_bytes(v))
+    }
+}
+
+fn label(pc: usize) -> String {
+    format!("L{}", pc)
+}
+
+// nested constants more than this deep are printed as raw indices
+const MAX_DEPTH: usize = 4;
+
+struct Printer<'a> {
+    cp: &'a [ConstantType],
+    /// first slot holding each constant, which is what the assembler's
+    /// lookups resolve to
+    first: HashMap<&'a ConstantType, u16>,
+    out: String,
+}
+
+impl<'a> Printer<'a> {
+    fn line(&mut self, indent: usize, text: &str) {
+        for _ in 0..indent {
+            self.out.push_str("    ");
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn utf8(&self, idx: u16) -> Option<&'a [u8]> {
+        match self.cp.get(idx as usize) {
+            Some(ConstantType::Utf8 { bytes }) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// The slot a symbolic spelling of the constant at `idx` would be
+    /// assembled into. Differs from `idx` for duplicated constants.
+    fn canonical(&self, idx: u16, depth: usize) -> Option<u16> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        let at = |i: &u16| self.canonical(*i, depth + 1);
+        let key = match self.cp.get(idx as usize)? {
+            ConstantType::Nop | ConstantType::Unknown => return None,
+            ConstantType::Class { name_index } => ConstantType::Class {
+                name_index: at(name_index)?,
+            },
+            ConstantType::String { string_index } => ConstantType::String {
+                string_index: at(string_index)?,
+            },
+            ConstantType::FieldRef {
+                class_index,
+                name_and_type_index,
+            } => ConstantType::FieldRef {
+                class_index: at(class_index)?,
+                name_and_type_index: at(name_and_type_index)?,
+            },
+            ConstantType::MethodRef {
+                class_index,
+                name_and_type_index,
+            } => ConstantType::MethodRef {
+                class_index: at(class_index)?,
+                name_and_type_index: at(name_and_type_index)?,
+            },
+            ConstantType::InterfaceMethodRef {
+                class_index,
+                name_and_type_index,
+            } => ConstantType::InterfaceMethodRef {
+                class_index: at(class_index)?,
+                name_and_type_index: at(name_and_type_index)?,
+            },
+            ConstantType::NameAndType {
+                name_index,
+                desc_index,
+            } => ConstantType::NameAndType {
+                name_index: at(name_index)?,
+                desc_index: at(desc_index)?,
+            },
+            ConstantType::MethodHandle {
+                ref_kind,
+                ref_index,
+            } => ConstantType::MethodHandle {
+                ref_kind: *ref_kind,
+                ref_index: at(ref_index)?,
+            },
+            ConstantType::MethodType { desc_index } => ConstantType::MethodType {
+                desc_index: at(desc_index)?,
+            },
+            ConstantType::InvokeDynamic {
+                bootstrap_method_attr_index,
+                name_and_type_index,
+            } => ConstantType::InvokeDynamic {
+                bootstrap_method_attr_index: *bootstrap_method_attr_index,
+                name_and_type_index: at(name_and_type_index)?,
+            },
+            ConstantType::Dynamic {
+                bootstrap_method_attr_index,
+                name_and_type_index,
+            } => ConstantType::Dynamic {
+                bootstrap_method_attr_index: *bootstrap_method_attr_index,
+                name_and_type_index: at(name_and_type_index)?,
+            },
+            ConstantType::Module { name_index } => ConstantType::Module {
+                name_index: at(name_index)?,
+            },
+            ConstantType::Package { name_index } => ConstantType::Package {
+                name_index: at(name_index)?,
+            },
+            c => c.clone(),
+        };
+        self.first.get(&key).copied()
+    }
+
+    fn is_canonical(&self, idx: u16) -> bool {
+        self.canonical(idx, 0) == Some(idx)
+    }
+
+    fn utf8_ref(&self, idx: u16) -> String {
+        match self.utf8(idx) {
+            Some(bytes) if self.is_canonical(idx) => word(bytes),
+            _ => format!("[{}]", idx),
+        }
+    }
+
+    fn string_lit(&self, idx: u16) -> String {
+        match self.utf8(idx) {
+            Some(bytes) if self.is_canonical(idx) => escape(bytes),
+            _ => format!("[{}]", idx),
+        }
+    }
+
+    fn class_ref(&self, idx: u16) -> String {
+        match self.cp.get(idx as usize) {
+            Some(ConstantType::Class { name_index })
+                if self.utf8(*name_index).is_some() && self.is_canonical(idx) =>
+            {
+                self.utf8_ref(*name_index)
+            }
+            _ => format!("[{}]", idx),
+        }
+    }
+
+    fn nat_ref(&self, idx: u16) -> String {
+        match self.cp.get(idx as usize) {
+            Some(ConstantType::NameAndType {
+                name_index,
+                desc_index,
+            }) if self.utf8(*name_index).is_some()
+                && self.utf8(*desc_index).is_some()
+                && self.is_canonical(idx) =>
+            {
+                format!(
+                    "{} {}",
+                    self.utf8_ref(*name_index),
+                    self.utf8_ref(*desc_index)
+                )
+            }
+            _ => format!("[{}]", idx),
+        }
+    }
+
+    // "-" stands for an absent (zero) index
+    fn or_none(idx: u16, f: impl FnOnce(u16) -> String) -> String {
+        if idx == 0 {
+            "-".to_string()
+        } else {
+            f(idx)
+        }
+    }
+
+    fn handle_kind(kind: u8) -> String {
+        HANDLE_KINDS
+            .get(kind as usize)
+            .filter(|k| !k.is_empty())
+            .map(|k| k.to_string())
+            .unwrap_or_else(|| kind.to_string())
+    }
+
+    /// The constant at `idx` spelled out symbolically where that assembles
+    /// back to the same slot.
+    fn const_ref(&self, idx: u16) -> String {
+        if self.is_canonical(idx) {
+            self.symbolic(idx)
+        } else {
+            format!("[{}]", idx)
+        }
+    }
+
+    fn symbolic(&self, idx: u16) -> String {
+        let c = match self.cp.get(idx as usize) {
+            Some(c) => c,
+            None => return format!("[{}]", idx),
+        };
+        match c {
+            ConstantType::Utf8 { bytes } => format!("Utf8 {}", escape(bytes)),
+            ConstantType::Integer { v } => format!("Int {}", i32::from_be_bytes(*v)),
+            ConstantType::Float { v } => format!("Float {}", float(*v)),
+            ConstantType::Long { v } => format!("Long {}", i64::from_be_bytes(*v)),
+            ConstantType::Double { v } => format!("Double {}", double(*v)),
+            ConstantType::Class { name_index } => format!("Class {}", self.utf8_ref(*name_index)),
+            ConstantType::String { string_index } => {
+                format!("String {}", self.string_lit(*string_index))
+            }
+            ConstantType::FieldRef {
+                class_index,
+                name_and_type_index,
+            } => format!(
+                "Field {} {}",
+                self.class_ref(*class_index),
+                self.nat_ref(*name_and_type_index)
+            ),
+            ConstantType::MethodRef {
+                class_index,
+                name_and_type_index,
+            } => format!(
+                "Method {} {}",
+                self.class_ref(*class_index),
+                self.nat_ref(*name_and_type_index)
+            ),
+            ConstantType::InterfaceMethodRef {
+                class_index,
+                name_and_type_index,
+            } => format!(
+                "InterfaceMethod {} {}",
+                self.class_ref(*class_index),
+                self.nat_ref(*name_and_type_index)
+            ),
+            ConstantType::NameAndType {
+                name_index,
+                desc_index,
+            } => format!(
+                "NameAndType {} {}",
+                self.utf8_ref(*name_index),
+                self.utf8_ref(*desc_index)
+            ),
+            ConstantType::MethodHandle {
+                ref_kind,
+                ref_index,
+            } => format!(
+                "MethodHandle {} {}",
+                Self::handle_kind(*ref_kind),
+                self.const_ref(*ref_index)
+            ),
+            ConstantType::MethodType { desc_index } => {
+                format!("MethodType {}", self.utf8_ref(*desc_index))
+            }
+            ConstantType::InvokeDynamic {
+                bootstrap_method_attr_index,
+                name_and_type_index,
+            } => format!(
+                "InvokeDynamic {} {}",
+                bootstrap_method_attr_index,
+                self.nat_ref(*name_and_type_index)
+            ),
+            ConstantType::Dynamic {
+                bootstrap_method_attr_index,
+                name_and_type_index,
+            } => format!(
+                "Dynamic {} {}",
+                bootstrap_method_attr_index,
+                self.nat_ref(*name_and_type_index)
+            ),
+            ConstantType::Module { name_index } => {
+                format!("Module {}", self.utf8_ref(*name_index))
+            }
+            ConstantType::Package { name_index } => {
+                format!("Package {}", self.utf8_ref(*name_index))
+            }
+            ConstantType::Nop | ConstantType::Unknown => format!("[{}]", idx),
+        }
+    }
+
+    /// The constant at `idx` with every component as a raw index, so the
+    /// pool can be rebuilt slot by slot.
+    fn const_raw(&self, idx: u16) -> Option<String> {
+        let raw = match &self.cp[idx as usize] {
+            ConstantType::Nop | ConstantType::Unknown => return None,
+            ConstantType::Utf8 { .. }
+            | ConstantType::Integer { .. }
+            | ConstantType::Float { .. }
+            | ConstantType::Long { .. }
+            | ConstantType::Double { .. } => return Some(self.symbolic(idx)),
+            ConstantType::Class { name_index } => format!("Class [{}]", name_index),
+            ConstantType::String { string_index } => format!("String [{}]", string_index),
+            ConstantType::FieldRef {
+                class_index,
+                name_and_type_index,
+            } => format!("Field [{}] [{}]", class_index, name_and_type_index),
+            ConstantType::MethodRef {
+                class_index,
+                name_and_type_index,
+            } => format!("Method [{}] [{}]", class_index, name_and_type_index),
+            ConstantType::InterfaceMethodRef {
+                class_index,
+                name_and_type_index,
+            } => format!(
+                "InterfaceMethod [{}] [{}]",
+                class_index, name_and_type_index
+            ),
+            ConstantType::NameAndType {
+                name_index,
+                desc_index,
+            } => format!("NameAndType [{}] [{}]", name_index, desc_index),
+            ConstantType::MethodHandle {
+                ref_kind,
+                ref_index,
+            } => format!(
+                "MethodHandle {} [{}]",
+                Self::handle_kind(*ref_kind),
+                ref_index
+            ),
+            ConstantType::MethodType { desc_index } => format!("MethodType [{}]", desc_index),
+            ConstantType::InvokeDynamic {
+                bootstrap_method_attr_index,
+                name_and_type_index,
+            } => format!(
+                "InvokeDynamic {} [{}]",
+                bootstrap_method_attr_index, name_and_type_index
+            ),
+            ConstantType::Dynamic {
+                bootstrap_method_attr_index,
+                name_and_type_index,
+            } => format!(
+                "Dynamic {} [{}]",
+                bootstrap_method_attr_index, name_and_type_index
+            ),
+            ConstantType::Module { name_index } => format!("Module [{}]", name_index),
+            ConstantType::Package { name_index } => format!("Package [{}]", name_index),
+        };
+        Some(format!("{} ; {}", raw, self.symbolic(idx)))
+    }
+
+    fn class_file(&mut self, cf: &ClassFile) -> Result<(), Error> {
+        self.line(
+            0,
+            &format!(".version {} {}", cf.version.major, cf.version.minor),
+        );
+        self.line(
+            0,
+            &join(&[
+                ".class",
+                &flags_to_string(cf.acc_flags, CLASS_FLAGS),
+                &self.class_ref(cf.this_class),
+            ]),
+        );
+        if cf.super_class != 0 {
+            self.line(0, &format!(".super {}", self.class_ref(cf.super_class)));
+        }
+        for interface in cf.interfaces.iter() {
+            self.line(0, &format!(".implements {}", self.class_ref(*interface)));
+        }
+
+        self.out.push('\n');
+        for idx in 1..self.cp.len() {
+            if let Some(c) = self.const_raw(idx as u16) {
+                self.line(0, &format!(".const [{}] = {}", idx, c));
+            }
+        }
+
+        for field in cf.fields.iter() {
+            self.out.push('\n');
+            self.line(
+                0,
+                &join(&[
+                    ".field",
+                    &flags_to_string(field.acc_flags, FIELD_FLAGS),
+                    &self.utf8_ref(field.name_index),
+                    &self.utf8_ref(field.desc_index),
+                ]),
+            );
+            self.attrs(&field.attrs, 1)?;
+            self.line(0, ".end field");
+        }
+
+        for method in cf.methods.iter() {
+            self.out.push('\n');
+            self.line(
+                0,
+                &join(&[
+                    ".method",
+                    &flags_to_string(method.acc_flags, METHOD_FLAGS),
+                    &self.utf8_ref(method.name_index),
+                    &self.utf8_ref(method.desc_index),
+                ]),
+            );
+            self.attrs(&method.attrs, 1)?;
+            self.line(0, ".end method");
+        }
+
+        self.out.push('\n');
+        self.attrs(&cf.attrs, 0)?;
+        self.line(0, ".end class");
+        Ok(())
+    }
+
+    fn attrs(&mut self, attrs: &[AttributeType], indent: usize) -> Result<(), Error> {
+        for attr in attrs.iter() {
+            match attr {
+                AttributeType::ConstantValue {
+                    constant_value_index,
+                } => {
+                    let c = self.const_ref(*constant_value_index);
+                    self.line(indent, &format!(".constantvalue {}", c));
+                }
+                AttributeType::Code(code) => self.code(code, indent)?,
+                AttributeType::Exceptions { exceptions } => {
+                    let names: Vec<String> =
+                        exceptions.iter().map(|e| self.class_ref(*e)).collect();
+                    self.line(indent, &format!(".exceptions {}", names.join(" ")));
+                }
+                AttributeType::InnerClasses { classes } => {
+                    self.line(indent, ".innerclasses");
+                    for c in classes.iter() {
+                        let row = join(&[
+                            &Self::or_none(c.inner_class_info_index, |i| self.class_ref(i)),
+                            &Self::or_none(c.outer_class_info_index, |i| self.class_ref(i)),
+                            &Self::or_none(c.inner_name_index, |i| self.utf8_ref(i)),
+                            &flags_to_string(c.inner_class_access_flags, INNER_CLASS_FLAGS),
+                        ]);
+                        self.line(indent + 1, &row);
+                    }
+                    self.line(indent, ".end innerclasses");
+                }
+                AttributeType::EnclosingMethod { em } => {
+                    let line = format!(
+                        ".enclosingmethod {} {}",
+                        self.class_ref(em.class_index),
+                        Self::or_none(em.method_index, |i| self.nat_ref(i))
+                    );
+                    self.line(indent, &line);
+                }
+                AttributeType::Synthetic => self.line(indent, ".synthetic"),
+                AttributeType::Deprecated => self.line(indent, ".deprecated"),
+                AttributeType::Signature { signature_index } => {
+                    let s = self.string_lit(*signature_index);
+                    self.line(indent, &format!(".signature {}", s));
+                }
+                AttributeType::SourceFile { source_file_index } => {
+                    let s = self.string_lit(*source_file_index);
+                    self.line(indent, &format!(".sourcefile {}", s));
+                }
+                AttributeType::SourceDebugExtension { debug_extension } => {
+                    let s = escape(debug_extension);
+                    self.line(indent, &format!(".sourcedebugextension {}", s));
+                }
+                AttributeType::RuntimeVisibleAnnotations { annotations, .. } => {
+                    for a in annotations.iter() {
+                        self.annotation(a, "visible", indent)?;
+                    }
+                }
+                AttributeType::RuntimeInvisibleAnnotations { annotations, .. } => {
+                    for a in annotations.iter() {
+                        self.annotation(a, "invisible", indent)?;
+                    }
+                }
+                AttributeType::RuntimeVisibleParameterAnnotations { raw, .. }
+                | AttributeType::RuntimeInvisibleParameterAnnotations { raw, .. }
+                | AttributeType::RuntimeVisibleTypeAnnotations { raw, .. }
+                | AttributeType::RuntimeInvisibleTypeAnnotations { raw, .. } => {
+                    self.raw_attr(attr.name(), raw, indent)
+                }
+                AttributeType::AnnotationDefault { default_value, .. } => {
+                    let v = self.element_value(default_value)?;
+                    self.line(indent, &format!(".annotationdefault {}", v));
+                }
+                AttributeType::BootstrapMethods { methods, .. } => {
+                    self.line(indent, ".bootstrapmethods");
+                    for m in methods.iter() {
+                        let mut row = vec![self.const_ref(m.method_ref)];
+                        row.extend(m.args.iter().map(|a| self.const_ref(*a)));
+                        self.line(indent + 1, &row.join(" "));
+                    }
+                    self.line(indent, ".end bootstrapmethods");
+                }
+                AttributeType::MethodParameters { parameters } => {
+                    self.line(indent, ".methodparameters");
+                    for p in parameters.iter() {
+                        let row = join(&[
+                            &Self::or_none(p.name_index, |i| self.utf8_ref(i)),
+                            &flags_to_string(p.acc_flags, PARAMETER_FLAGS),
+                        ]);
+                        self.line(indent + 1, &row);
+                    }
+                    self.line(indent, ".end methodparameters");
+                }
+                AttributeType::NestHost { host_class_index } => {
+                    let c = self.class_ref(*host_class_index);
+                    self.line(indent, &format!(".nesthost {}", c));
+                }
+                AttributeType::NestMembers { classes } => {
+                    let names: Vec<String> = classes.iter().map(|c| self.class_ref(*c)).collect();
+                    self.line(indent, &format!(".nestmembers {}", names.join(" ")));
+                }
+                AttributeType::PermittedSubclasses { classes } => {
+                    let names: Vec<String> = classes.iter().map(|c| self.class_ref(*c)).collect();
+                    self.line(indent, &format!(".permittedsubclasses {}", names.join(" ")));
+                }
+                // components hold attributes of their own, kept in raw form
+                AttributeType::Record { .. } => {
+                    let raw = class_parser::attribute_body(self.cp, attr)
+                        .map_err(|e| Error::new(0, e.to_string()))?;
+                    self.raw_attr(attr.name(), &raw, indent)
+                }
+                AttributeType::StackMapTable { .. }
+                | AttributeType::LineNumberTable { .. }
+                | AttributeType::LocalVariableTable { .. }
+                | AttributeType::LocalVariableTypeTable { .. } => {
+                    return Err(Error::new(
+                        0,
+                        format!(
+                            "{} outside of Code",
+                            String::from_utf8_lossy(attr.tag().name())
+                        ),
+                    ))
+                }
+                AttributeType::Unknown { name, data }
+                | AttributeType::Custom { name, data, .. } => self.raw_attr(name, data, indent),
+            }
+        }
+        Ok(())
+    }
+
+    fn raw_attr(&mut self, name: &[u8], raw: &[u8], indent: usize) {
+        let line = format!(".attribute {} {}", word(name), escape(raw));
+        self.line(indent, &line);
+    }
+
+    fn annotation(
+        &mut self,
+        a: &AnnotationEntry,
+        visibility: &str,
+        indent: usize,
+    ) -> Result<(), Error> {
+        let head = format!(".annotation {} {}", visibility, self.utf8_ref(a.type_index));
+        self.line(indent, &head);
+        for pair in a.pairs.iter() {
+            let row = format!(
+                "{} = {}",
+                self.utf8_ref(pair.name_index),
+                self.element_value(&pair.value)?
+            );
+            self.line(indent + 1, &row);
+        }
+        self.line(indent, ".end annotation");
+        Ok(())
+    }
+
+    fn literal(&self, idx: u16) -> String {
+        if !self.is_canonical(idx) {
+            return format!("[{}]", idx);
+        }
+        match self.cp.get(idx as usize) {
+            Some(ConstantType::Integer { v }) => i32::from_be_bytes(*v).to_string(),
+            Some(ConstantType::Float { v }) => float(*v),
+            Some(ConstantType::Long { v }) => i64::from_be_bytes(*v).to_string(),
+            Some(ConstantType::Double { v }) => double(*v),
+            _ => format!("[{}]", idx),
+        }
+    }
+
+    fn element_value(&self, v: &ElementValueType) -> Result<String, Error> {
+        let s = match v {
+            ElementValueType::Byte { val_index } => format!("byte {}", self.literal(*val_index)),
+            ElementValueType::Char { val_index } => format!("char {}", self.literal(*val_index)),
+            ElementValueType::Double { val_index } => {
+                format!("double {}", self.literal(*val_index))
+            }
+            ElementValueType::Float { val_index } => format!("float {}", self.literal(*val_index)),
+            ElementValueType::Int { val_index } => format!("int {}", self.literal(*val_index)),
+            ElementValueType::Long { val_index } => format!("long {}", self.literal(*val_index)),
+            ElementValueType::Short { val_index } => format!("short {}", self.literal(*val_index)),
+            ElementValueType::Boolean { val_index } => {
+                format!("boolean {}", self.literal(*val_index))
+            }
+            ElementValueType::String { val_index } => {
+                format!("string {}", self.string_lit(*val_index))
+            }
+            ElementValueType::Enum {
+                type_index,
+                val_index,
+            } => format!(
+                "enum {} {}",
+                self.utf8_ref(*type_index),
+                self.utf8_ref(*val_index)
+            ),
+            ElementValueType::Class { index } => format!("class {}", self.utf8_ref(*index)),
+            ElementValueType::Annotation(a) => {
+                let mut s = format!("annotation {} {{", self.utf8_ref(a.value.type_index));
+                for pair in a.value.pairs.iter() {
+                    s.push_str(&format!(
+                        " {} = {}",
+                        self.utf8_ref(pair.name_index),
+                        self.element_value(&pair.value)?
+                    ));
+                }
+                s.push_str(" }");
+                s
+            }
+            ElementValueType::Array { values } => {
+                let mut s = "array {".to_string();
+                for v in values.iter() {
+                    s.push(' ');
+                    s.push_str(&self.element_value(v)?);
+                }
+                s.push_str(" }");
+                s
+            }
+            ElementValueType::Unknown => return Err(Error::new(0, "unknown element value")),
+        };
+        Ok(s)
+    }
+
+    fn code(&mut self, code: &Code, indent: usize) -> Result<(), Error> {
+        let bytes = &code.code[..];
+        let mut insns = vec![];
+        for insn in bytecode::Instructions::new(bytes) {
+            insns.push(insn.map_err(|pc| Error::new(0, format!("bad instruction at pc {}", pc)))?);
+        }
+
+        let mut targets = BTreeSet::new();
+        for insn in insns.iter() {
+            targets.extend(bytecode::branch_targets(bytes, insn));
+        }
+        for e in code.exceptions.iter() {
+            targets.insert(e.start_pc as usize);
+            targets.insert(e.end_pc as usize);
+            targets.insert(e.handler_pc as usize);
+        }
+        for attr in code.attrs.iter() {
+            match attr {
+                AttributeType::LineNumberTable { tables } => {
+                    targets.extend(tables.iter().map(|l| l.start_pc as usize));
+                }
+                AttributeType::LocalVariableTable { tables }
+                | AttributeType::LocalVariableTypeTable { tables } => {
+                    for v in tables.iter() {
+                        targets.insert(v.start_pc as usize);
+                        targets.insert(v.start_pc as usize + v.length as usize);
+                    }
+                }
+                AttributeType::StackMapTable { entries } => {
+                    for (pc, frame) in frame_pcs(entries) {
+                        targets.insert(pc);
+                        for vti in frame_types(frame) {
+                            if let VerificationTypeInfo::Uninitialized { offset } = vti {
+                                targets.insert(*offset as usize);
+                            }
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+        let boundaries: BTreeSet<usize> = insns
+            .iter()
+            .map(|i| i.pc)
+            .chain(std::iter::once(bytes.len()))
+            .collect();
+        if let Some(pc) = targets.difference(&boundaries).next() {
+            return Err(Error::new(
+                0,
+                format!("pc {} is not an instruction boundary", pc),
+            ));
+        }
+
+        self.line(
+            indent,
+            &format!(".code stack {} locals {}", code.max_stack, code.max_locals),
+        );
+        for insn in insns.iter() {
+            if targets.contains(&insn.pc) {
+                self.line(indent, &format!("{}:", label(insn.pc)));
+            }
+            let text = self.instruction(bytes, insn);
+            self.line(indent + 1, &text);
+        }
+        if targets.contains(&bytes.len()) {
+            self.line(indent, &format!("{}:", label(bytes.len())));
+        }
+
+        for e in code.exceptions.iter() {
+            let catch = match self.class_ref(e.catch_type) {
+                _ if e.catch_type == 0 => "any".to_string(),
+                // a class that happens to be called any
+                c if c == "any" => escape(b"any"),
+                c => c,
+            };
+            let line = format!(
+                ".catch {} from {} to {} using {}",
+                catch,
+                label(e.start_pc as usize),
+                label(e.end_pc as usize),
+                label(e.handler_pc as usize)
+            );
+            self.line(indent + 1, &line);
+        }
+
+        for attr in code.attrs.iter() {
+            match attr {
+                AttributeType::LineNumberTable { tables } => {
+                    self.line(indent + 1, ".linenumbertable");
+                    for l in tables.iter() {
+                        self.line(
+                            indent + 2,
+                            &format!("{} {}", label(l.start_pc as usize), l.number),
+                        );
+                    }
+                    self.line(indent + 1, ".end linenumbertable");
+                }
+                AttributeType::LocalVariableTable { tables }
+                | AttributeType::LocalVariableTypeTable { tables } => {
+                    let name = if let AttributeType::LocalVariableTable { .. } = attr {
+                        "localvariabletable"
+                    } else {
+                        "localvariabletypetable"
+                    };
+                    self.line(indent + 1, &format!(".{}", name));
+                    for v in tables.iter() {
+                        let row = format!(
+                            "{} is {} {} from {} to {}",
+                            v.index,
+                            self.utf8_ref(v.name_index),
+                            self.utf8_ref(v.signature_index),
+                            label(v.start_pc as usize),
+                            label(v.start_pc as usize + v.length as usize)
+                        );
+                        self.line(indent + 2, &row);
+                    }
+                    self.line(indent + 1, &format!(".end {}", name));
+                }
+                AttributeType::StackMapTable { entries } => {
+                    self.line(indent + 1, ".stackmaptable");
+                    for (pc, frame) in frame_pcs(entries) {
+                        let row = self.frame(pc, frame)?;
+                        self.line(indent + 2, &row);
+                    }
+                    self.line(indent + 1, ".end stackmaptable");
+                }
+                _ => self.attrs(std::slice::from_ref(attr), indent + 1)?,
+            }
+        }
+        self.line(indent, ".end code");
+        Ok(())
+    }
+
+    fn instruction(&self, code: &[u8], insn: &bytecode::Instruction) -> String {
+        let pc = insn.pc;
+        let op = insn.opcode;
+        let m = bytecode::mnemonic(op).unwrap_or("");
+        match bytecode::operand_kind(op) {
+            OperandKind::None => m.to_string(),
+            OperandKind::Byte => format!("{} {}", m, code[pc + 1] as i8),
+            OperandKind::Short => format!("{} {}", m, u16_at(code, pc + 1) as i16),
+            OperandKind::ConstNarrow => format!("{} {}", m, self.const_ref(code[pc + 1] as u16)),
+            OperandKind::Const => {
+                let idx = u16_at(code, pc + 1);
+                match op {
+                    bytecode::NEW
+                    | bytecode::ANEWARRAY
+                    | bytecode::CHECKCAST
+                    | bytecode::INSTANCEOF => format!("{} {}", m, self.class_ref(idx)),
+                    _ => format!("{} {}", m, self.const_ref(idx)),
+                }
+            }
+            OperandKind::Local => format!("{} {}", m, code[pc + 1]),
+            OperandKind::Iinc => format!("{} {} {}", m, code[pc + 1], code[pc + 2] as i8),
+            OperandKind::Branch => {
+                let target = pc as i64 + u16_at(code, pc + 1) as i16 as i64;
+                format!("{} {}", m, label(target as usize))
+            }
+            OperandKind::BranchWide => {
+                let target = pc as i64 + i32_at(code, pc + 1) as i64;
+                format!("{} {}", m, label(target as usize))
+            }
+            OperandKind::TableSwitch => {
+                let base = pc + 1 + bytecode::switch_padding(pc);
+                let target = |at: usize| label((pc as i64 + i32_at(code, at) as i64) as usize);
+                let low = i32_at(code, base + 4);
+                let high = i32_at(code, base + 8);
+                let mut s = format!("{} {}", m, low);
+                for i in 0..(high as i64 - low as i64 + 1) as usize {
+                    s.push(' ');
+                    s.push_str(&target(base + 12 + i * 4));
+                }
+                s.push_str(&format!(" default {}", target(base)));
+                s
+            }
+            OperandKind::LookupSwitch => {
+                let base = pc + 1 + bytecode::switch_padding(pc);
+                let target = |at: usize| label((pc as i64 + i32_at(code, at) as i64) as usize);
+                let npairs = i32_at(code, base + 4) as usize;
+                let mut s = m.to_string();
+                for i in 0..npairs {
+                    let at = base + 8 + i * 8;
+                    s.push_str(&format!(" {}:{}", i32_at(code, at), target(at + 4)));
+                }
+                s.push_str(&format!(" default {}", target(base)));
+                s
+            }
+            OperandKind::InvokeInterface => format!(
+                "{} {} {}",
+                m,
+                self.const_ref(u16_at(code, pc + 1)),
+                code[pc + 3]
+            ),
+            OperandKind::InvokeDynamic => format!("{} {}", m, self.const_ref(u16_at(code, pc + 1))),
+            OperandKind::NewArray => {
+                let atype = code[pc + 1];
+                match ARRAY_TYPES.get(atype as usize).filter(|t| !t.is_empty()) {
+                    Some(t) => format!("{} {}", m, t),
+                    None => format!("{} {}", m, atype),
+                }
+            }
+            OperandKind::MultiANewArray => format!(
+                "{} {} {}",
+                m,
+                self.class_ref(u16_at(code, pc + 1)),
+                code[pc + 3]
+            ),
+            OperandKind::Wide => {
+                let inner = code[pc + 1];
+                let inner_m = bytecode::mnemonic(inner).unwrap_or("");
+                if inner == bytecode::IINC {
+                    format!(
+                        "{} {} {} {}",
+                        m,
+                        inner_m,
+                        u16_at(code, pc + 2),
+                        u16_at(code, pc + 4) as i16
+                    )
+                } else {
+                    format!("{} {} {}", m, inner_m, u16_at(code, pc + 2))
+                }
+            }
+        }
+    }
+
+    fn vti(&self, v: &VerificationTypeInfo) -> String {
+        match v {
+            VerificationTypeInfo::Top => "Top".to_string(),
+            VerificationTypeInfo::Integer => "Integer".to_string(),
+            VerificationTypeInfo::Float => "Float".to_string(),
+            VerificationTypeInfo::Long => "Long".to_string(),
+            VerificationTypeInfo::Double => "Double".to_string(),
+            VerificationTypeInfo::Null => "Null".to_string(),
+            VerificationTypeInfo::UninitializedThis => "UninitializedThis".to_string(),
+            VerificationTypeInfo::Object { cpool_index } => {
+                format!("Object {}", self.class_ref(*cpool_index))
+            }
+            VerificationTypeInfo::Uninitialized { offset } => {
+                format!("Uninitialized {}", label(*offset as usize))
+            }
+        }
+    }
+
+    fn vtis(&self, vs: &[VerificationTypeInfo]) -> String {
+        vs.iter().map(|v| self.vti(v)).collect::<Vec<_>>().join(" ")
+    }
+
+    fn frame(&self, pc: usize, frame: &StackMapFrame) -> Result<String, Error> {
+        let at = label(pc);
+        let s = match frame {
+            StackMapFrame::Same { .. } => format!("same {}", at),
+            StackMapFrame::SameExtended { .. } => format!("same_extended {}", at),
+            StackMapFrame::SameLocals1StackItem { stack, .. } => {
+                format!("same_locals_1_stack_item {} {}", at, self.vti(&stack[0]))
+            }
+            StackMapFrame::SameLocals1StackItemExtended { stack, .. } => format!(
+                "same_locals_1_stack_item_extended {} {}",
+                at,
+                self.vti(&stack[0])
+            ),
+            StackMapFrame::Chop { tag, .. } => format!("chop {} {}", at, 251 - tag),
+            StackMapFrame::Append { locals, .. } => {
+                format!("append {} {}", at, self.vtis(locals))
+            }
+            StackMapFrame::Full { locals, stack, .. } => join(&[
+                "full",
+                &at,
+                "locals",
+                &self.vtis(locals),
+                "stack",
+                &self.vtis(stack),
+            ]),
+            StackMapFrame::Reserved(tag) => {
+                return Err(Error::new(0, format!("reserved frame type {}", tag)))
+            }
+        };
+        Ok(s)
+    }
+}
+
+fn join(parts: &[&str]) -> String {
+    parts
+        .iter()
+        .filter(|p| !p.is_empty())
+        .copied()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// spec 4.7.4: the first frame is at offset_delta, every later one at
+// previous + offset_delta + 1
+fn frame_pcs(entries: &[StackMapFrame]) -> Vec<(usize, &StackMapFrame)> {
+    let mut pcs = vec![];
+    let mut pc: Option<usize> = None;
+    for frame in entries.iter() {
+        let delta = match frame {
+            StackMapFrame::Same { offset_delta, .. }
+            | StackMapFrame::SameExtended { offset_delta, .. }
+            | StackMapFrame::SameLocals1StackItem { offset_delta, .. }
+            | StackMapFrame::SameLocals1StackItemExtended { offset_delta, .. }
+            | StackMapFrame::Chop { offset_delta, .. }
+            | StackMapFrame::Append { offset_delta, .. }
+            | StackMapFrame::Full { offset_delta, .. } => *offset_delta as usize,
+            StackMapFrame::Reserved(_) => 0,
+        };
+        let next = match pc {
+            None => delta,
+            Some(prev) => prev + delta + 1,
+        };
+        pcs.push((next, frame));
+        pc = Some(next);
+    }
+    pcs
+}
+
+fn frame_types(frame: &StackMapFrame) -> Vec<&VerificationTypeInfo> {
+    match frame {
+        StackMapFrame::SameLocals1StackItem { stack, .. }
+        | StackMapFrame::SameLocals1StackItemExtended { stack, .. } => stack.iter().collect(),
+        StackMapFrame::Append { locals, .. } => locals.iter().collect(),
+        StackMapFrame::Full { locals, stack, .. } => locals.iter().chain(stack.iter()).collect(),
+        _ => vec![],
+    }
+}
@@ -0,0 +1,355 @@
+//! Finds bytecode that uses dangerous APIs: every reference to a method or
+//! field matching one of a set of rules, with the method and source line it
+//! is made from.
+//!
+//! Rules are read from TOML or JSON:
+//!
+//! ```toml
+//! [[rule]]
+//! id = "command-exec"
+//! description = "runs an operating system command"
+//! class = "java.lang.Runtime"
+//! member = "exec"
+//!
+//! [[rule]]
+//! id = "reflection-access"
+//! class = "java/lang/reflect/*"
+//! member = "setAccessible"
+//! kind = "method"
+//! ```
+//!
+//! `class`, `member` and the optional `descriptor` match exactly except for
+//! `*`, which matches any run of characters. Classes may be written with
+//! dots or slashes. `kind` is `method`, `field` or, by default, `any`.
+
+use super::class_parser::{
+    bytecode,
+    format::{
+        attributes::{BootstrapMethod, Code, Type as AttributeType},
+        class_file::ClassFile,
+        constant_pool::{self, Type as ConstantType},
+    },
+};
+use super::util::glob_match;
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+const BUILTIN_RULES: &str = r#"
+[[rule]]
+id = "command-exec"
+description = "runs an operating system command"
+class = "java/lang/Runtime"
+member = "exec"
+
+[[rule]]
+id = "process-builder"
+description = "runs an operating system command"
+class = "java/lang/ProcessBuilder"
+member = "start"
+
+[[rule]]
+id = "deserialization"
+description = "deserializes untrusted data"
+class = "java/io/ObjectInputStream"
+member = "readObject"
+
+[[rule]]
+id = "reflection-access"
+description = "suppresses access checks"
+class = "java/lang/reflect/*"
+member = "setAccessible"
+
+[[rule]]
+id = "native-library"
+description = "loads native code"
+class = "java/lang/System"
+member = "load*"
+
+[[rule]]
+id = "unsafe"
+description = "uses sun.misc.Unsafe"
+class = "sun/misc/Unsafe"
+member = "*"
+"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MemberKind {
+    #[default]
+    Any,
+    Method,
+    Field,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rule {
+    pub id: String,
+    #[serde(default)]
+    pub description: String,
+    pub class: String,
+    pub member: String,
+    #[serde(default)]
+    pub descriptor: Option<String>,
+    #[serde(default)]
+    pub kind: MemberKind,
+}
+
+impl Rule {
+    fn matches(&self, member: &Member) -> bool {
+        let kind = match self.kind {
+            MemberKind::Any => true,
+            kind => kind == member.kind,
+        };
+        kind && glob_match(&self.class, &member.class)
+            && glob_match(&self.member, &member.name)
+            && self
+                .descriptor
+                .as_ref()
+                .is_none_or(|d| glob_match(d, &member.descriptor))
+    }
+}
+
+#[derive(Debug)]
+pub enum RuleError {
+    Io(io::Error),
+    Toml(toml::de::Error),
+    Json(serde_json::Error),
+    /// a rule with an empty id, class or member
+    Incomplete(usize),
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleError::Io(e) => write!(f, "{}", e),
+            RuleError::Toml(e) => write!(f, "{}", e),
+            RuleError::Json(e) => write!(f, "{}", e),
+            RuleError::Incomplete(i) => {
+                write!(f, "rule {} needs an id, a class and a member", i + 1)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RuleSet {
+    #[serde(rename = "rule", default)]
+    pub rules: Vec<Rule>,
+}
+
+impl RuleSet {
+    /// Process execution, deserialization, `setAccessible`, native
+    /// libraries and `Unsafe`.
+    pub fn builtin() -> Self {
+        Self::from_toml(BUILTIN_RULES).unwrap()
+    }
+
+    pub fn from_toml(s: &str) -> Result<Self, RuleError> {
+        toml::from_str::<Self>(s)
+            .map_err(RuleError::Toml)?
+            .validated()
+    }
+
+    pub fn from_json(s: &str) -> Result<Self, RuleError> {
+        serde_json::from_str::<Self>(s)
+            .map_err(RuleError::Json)?
+            .validated()
+    }
+
+    /// Reads `.json` files as JSON and everything else as TOML.
+    pub fn load(path: &Path) -> Result<Self, RuleError> {
+        let text = fs::read_to_string(path).map_err(RuleError::Io)?;
+        match path.extension() {
+            Some(ext) if ext == "json" => Self::from_json(&text),
+            _ => Self::from_toml(&text),
+        }
+    }
+
+    fn validated(mut self) -> Result<Self, RuleError> {
+        for (i, rule) in self.rules.iter_mut().enumerate() {
+            if rule.id.is_empty() || rule.class.is_empty() || rule.member.is_empty() {
+                return Err(RuleError::Incomplete(i));
+            }
+            rule.class = rule.class.replace('.', "/");
+        }
+        Ok(self)
+    }
+
+    /// Every reference from the code of `cf` that a rule matches, in method
+    /// and pc order, once per rule.
+    pub fn check(&self, cf: &ClassFile) -> Vec<Finding> {
+        let class = constant_pool::get_class_name(&cf.cp, cf.this_class as usize)
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+            .unwrap_or_default();
+        let source_file = cf.attrs.iter().find_map(|attr| match attr {
+            AttributeType::SourceFile { source_file_index } => {
+                Some(utf8(&cf.cp, *source_file_index))
+            }
+            _ => None,
+        });
+        let bootstrap = cf
+            .attrs
+            .iter()
+            .find_map(|attr| match attr {
+                AttributeType::BootstrapMethods { methods, .. } => Some(&methods[..]),
+                _ => None,
+            })
+            .unwrap_or(&[]);
+
+        let mut findings = vec![];
+        for method in cf.methods.iter() {
+            let code = match method.attrs.iter().find_map(|attr| match attr {
+                AttributeType::Code(code) => Some(code),
+                _ => None,
+            }) {
+                Some(code) => code,
+                None => continue,
+            };
+            for (pc, member) in references(&cf.cp, bootstrap, code) {
+                for rule in self.rules.iter().filter(|rule| rule.matches(&member)) {
+                    findings.push(Finding {
+                        rule: rule.id.clone(),
+                        class: class.clone(),
+                        method: utf8(&cf.cp, method.name_index),
+                        descriptor: utf8(&cf.cp, method.desc_index),
+                        source_file: source_file.clone(),
+                        line: code.line_number(pc as u16),
+                        pc,
+                        target: member.to_string(),
+                    });
+                }
+            }
+        }
+        findings
+    }
+}
+
+/// A reference from bytecode that matched a rule.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Finding {
+    pub rule: String,
+    /// the class the reference is made from
+    pub class: String,
+    pub method: String,
+    pub descriptor: String,
+    pub source_file: Option<String>,
+    pub line: Option<u16>,
+    pub pc: usize,
+    /// the referenced member as `class.name:descriptor`
+    pub target: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}.{}", self.rule, self.class, self.method)?;
+        match (&self.source_file, self.line) {
+            (Some(file), Some(line)) => write!(f, "({}:{})", file, line)?,
+            (Some(file), None) => write!(f, "({})", file)?,
+            (None, Some(line)) => write!(f, "(line {})", line)?,
+            (None, None) => write!(f, "{}", self.descriptor)?,
+        }
+        write!(f, " -> {}", self.target)
+    }
+}
+
+struct Member {
+    kind: MemberKind,
+    class: String,
+    name: String,
+    descriptor: String,
+}
+
+impl fmt::Display for Member {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}:{}", self.class, self.name, self.descriptor)
+    }
+}
+
+fn utf8(cp: &Arc<Vec<ConstantType>>, idx: u16) -> String {
+    constant_pool::get_utf8(cp, idx as usize)
+        .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+        .unwrap_or_default()
+}
+
+fn member(cp: &Arc<Vec<ConstantType>>, idx: u16) -> Option<Member> {
+    let (kind, class_index, name_and_type_index) = match cp.get(idx as usize)? {
+        ConstantType::FieldRef {
+            class_index,
+            name_and_type_index,
+        } => (MemberKind::Field, class_index, name_and_type_index),
+        ConstantType::MethodRef {
+            class_index,
+            name_and_type_index,
+        }
+        | ConstantType::InterfaceMethodRef {
+            class_index,
+            name_and_type_index,
+        } => (MemberKind::Method, class_index, name_and_type_index),
+        // a method reference like `Runtime.getRuntime()::exec`
+        ConstantType::MethodHandle { ref_index, .. } => return member(cp, *ref_index),
+        _ => return None,
+    };
+    let (name_index, desc_index) = match cp.get(*name_and_type_index as usize)? {
+        ConstantType::NameAndType {
+            name_index,
+            desc_index,
+        } => (*name_index, *desc_index),
+        _ => return None,
+    };
+    let class = constant_pool::get_class_name(cp, *class_index as usize)?;
+    Some(Member {
+        kind,
+        class: String::from_utf8_lossy(&class).into_owned(),
+        name: utf8(cp, name_index),
+        descriptor: utf8(cp, desc_index),
+    })
+}
+
+// the members the code refers to directly, through constant method handles
+// and through the bootstrap method and arguments of invokedynamic
+fn references(
+    cp: &Arc<Vec<ConstantType>>,
+    bootstrap: &[BootstrapMethod],
+    code: &Code,
+) -> Vec<(usize, Member)> {
+    let mut refs = vec![];
+    for insn in bytecode::Instructions::new(&code.code) {
+        let insn = match insn {
+            Ok(insn) => insn,
+            Err(_) => break,
+        };
+        let idx = match bytecode::cp_operand(insn.opcode) {
+            Some(bytecode::CpOperand::Narrow) => code.code[insn.pc + 1] as u16,
+            Some(bytecode::CpOperand::Wide) => {
+                u16::from_be_bytes([code.code[insn.pc + 1], code.code[insn.pc + 2]])
+            }
+            None => continue,
+        };
+        if insn.opcode == bytecode::INVOKEDYNAMIC {
+            let bsm = match cp.get(idx as usize) {
+                Some(ConstantType::InvokeDynamic {
+                    bootstrap_method_attr_index,
+                    ..
+                }) => bootstrap.get(*bootstrap_method_attr_index as usize),
+                _ => None,
+            };
+            if let Some(bsm) = bsm {
+                let args = std::iter::once(&bsm.method_ref).chain(bsm.args.iter());
+                refs.extend(
+                    args.filter_map(|&arg| member(cp, arg))
+                        .map(|m| (insn.pc, m)),
+                );
+            }
+        } else if let Some(m) = member(cp, idx) {
+            refs.push((insn.pc, m));
+        }
+    }
+    refs
+}
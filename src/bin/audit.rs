@@ -0,0 +1,77 @@
+use clap::{App, Arg};
+use jvm::{
+    audit::RuleSet,
+    class_parser::{parse_with, ParseOptions},
+    class_path_manager::{ClassPathError, ClassPathManager},
+};
+use std::path::Path;
+use std::process;
+
+fn main() {
+    let matches = App::new("dangerous API scanner")
+        .version("1.0")
+        .author("zhiqiangxu")
+        .arg(
+            Arg::new("classpath")
+                .about("jars and class directories to audit, separated like CLASSPATH")
+                .required(true),
+        )
+        .arg(
+            Arg::new("rules")
+                .short('r')
+                .long("rules")
+                .about("TOML or JSON rule file, the built-in rules by default")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .about("print the findings as JSON"),
+        )
+        .get_matches();
+
+    let rules = match matches.value_of("rules") {
+        Some(path) => RuleSet::load(Path::new(path)).unwrap_or_else(|e| {
+            eprintln!("can't load rules from {}: {}", path, e);
+            process::exit(2);
+        }),
+        None => RuleSet::builtin(),
+    };
+
+    let classpath = matches.value_of("classpath").unwrap();
+    let mut cpm = ClassPathManager::new();
+    if let Err(e) = cpm.add_class_paths(classpath) {
+        eprintln!("can't open {}: {}", classpath, e);
+        process::exit(2);
+    }
+
+    let options = ParseOptions::default();
+    let mut findings = vec![];
+    let read = cpm.visit_entries(|source, name, data| -> Result<(), ClassPathError> {
+        if !name.ends_with(".class") {
+            return Ok(());
+        }
+        match parse_with(&data, &options) {
+            Ok((_, cf)) => findings.extend(rules.check(&cf)),
+            Err(e) => eprintln!("{}!{}: {}", source, name, e),
+        }
+        Ok(())
+    });
+    if let Err(e) = read {
+        eprintln!("reading {} failed: {}", classpath, e);
+        process::exit(2);
+    }
+
+    if matches.is_present("json") {
+        println!("{}", serde_json::to_string_pretty(&findings).unwrap());
+    } else {
+        for finding in findings.iter() {
+            println!("{}", finding);
+        }
+        println!("{} findings", findings.len());
+    }
+
+    if !findings.is_empty() {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,81 @@
+use clap::{App, Arg};
+use jvm::{asm, class_parser};
+use std::fs;
+use std::process;
+
+fn main() {
+    let matches = App::new("class file assembler")
+        .version("1.0")
+        .author("zhiqiangxu")
+        .arg(
+            Arg::new("input")
+                .about("assembly source, or a class file with --disassemble")
+                .required(true),
+        )
+        .arg(
+            Arg::new("disassemble")
+                .short('d')
+                .long("disassemble")
+                .about("print a class file as assembly source"),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .about("file to write, stdout when disassembling without it")
+                .takes_value(true),
+        )
+        .get_matches();
+
+    let input = matches.value_of("input").unwrap();
+    let data = match fs::read(input) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("can't read {}: {}", input, e);
+            process::exit(1);
+        }
+    };
+
+    let out = if matches.is_present("disassemble") {
+        let cf = match class_parser::parse(&data) {
+            Ok((_, cf)) => cf,
+            Err(e) => {
+                eprintln!("{}: not a class file: {:?}", input, e);
+                process::exit(1);
+            }
+        };
+        match asm::print(&cf) {
+            Ok(text) => text.into_bytes(),
+            Err(e) => {
+                eprintln!("{}: {}", input, e);
+                process::exit(1);
+            }
+        }
+    } else {
+        let text = String::from_utf8_lossy(&data);
+        let bytes = asm::assemble(&text)
+            .map_err(|e| e.to_string())
+            .and_then(|cf| class_parser::write(&cf).map_err(|e| e.to_string()));
+        match bytes {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                eprintln!("{}: {}", input, e);
+                process::exit(1);
+            }
+        }
+    };
+
+    match matches.value_of("output") {
+        Some(output) => {
+            if let Err(e) = fs::write(output, out) {
+                eprintln!("can't write {}: {}", output, e);
+                process::exit(1);
+            }
+        }
+        None if matches.is_present("disassemble") => print!("{}", String::from_utf8_lossy(&out)),
+        None => {
+            eprintln!("--output is required when assembling");
+            process::exit(2);
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use clap::{App, Arg};
+use jvm::{
+    class_parser::{parse_with, ParseOptions},
+    class_path_manager::{ClassPathError, ClassPathManager},
+    metrics::{class_metrics, ClassMetrics, MetricsOptions},
+};
+use std::process;
+
+fn print_table(classes: &[ClassMetrics]) {
+    for class in classes {
+        println!(
+            "{} ({} methods, {} bytes, complexity {} max {}, {} handlers, {} calls)",
+            class.name,
+            class.methods.len(),
+            class.code_length,
+            class.complexity,
+            class.max_complexity,
+            class.handlers,
+            class.calls
+        );
+        println!(
+            "{:>7} {:>7} {:>5} {:>5} {:>6} {:>4} {:>5}  method",
+            "length", "insns", "cc", "stack", "locals", "exc", "calls"
+        );
+        for m in class.methods.iter() {
+            let near: Vec<_> = m.near.iter().map(|limit| limit.to_string()).collect();
+            println!(
+                "{:>7} {:>7} {:>5} {:>5} {:>6} {:>4} {:>5}  {}{}{}",
+                m.code_length,
+                m.instructions,
+                m.complexity,
+                m.max_stack,
+                m.max_locals,
+                m.handlers,
+                m.calls(),
+                m.name,
+                m.descriptor,
+                if near.is_empty() {
+                    String::new()
+                } else {
+                    format!("  near {}", near.join(", "))
+                }
+            );
+        }
+    }
+}
+
+fn main() {
+    let matches = App::new("method metrics")
+        .version("1.0")
+        .author("zhiqiangxu")
+        .arg(
+            Arg::new("classpath")
+                .about("jars and class directories to measure, separated like CLASSPATH")
+                .required(true),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .about("print JSON, not a table"),
+        )
+        .arg(
+            Arg::new("flagged")
+                .long("flagged")
+                .about("only list methods close to a size limit"),
+        )
+        .arg(
+            Arg::new("margin")
+                .long("margin")
+                .about("how close to a limit is close, in percent, 10 by default")
+                .takes_value(true),
+        )
+        .get_matches();
+
+    let mut options = MetricsOptions::default();
+    if let Some(margin) = matches.value_of("margin") {
+        options.margin = margin.parse().unwrap_or_else(|_| {
+            eprintln!("bad margin {}", margin);
+            process::exit(2);
+        });
+    }
+
+    let classpath = matches.value_of("classpath").unwrap();
+    let mut cpm = ClassPathManager::new();
+    if let Err(e) = cpm.add_class_paths(classpath) {
+        eprintln!("can't open {}: {}", classpath, e);
+        process::exit(2);
+    }
+
+    let parse_options = ParseOptions::default();
+    let mut classes = vec![];
+    let mut failed = false;
+    let read = cpm.visit_entries(|source, name, data| -> Result<(), ClassPathError> {
+        if !name.ends_with(".class") {
+            return Ok(());
+        }
+        let metrics = parse_with(&data, &parse_options)
+            .map_err(|e| e.to_string())
+            .and_then(|(_, cf)| class_metrics(&cf, &options).map_err(|e| e.to_string()));
+        match metrics {
+            Ok(mut class) => {
+                if matches.is_present("flagged") {
+                    class.methods.retain(|m| !m.near.is_empty());
+                }
+                if !class.methods.is_empty() {
+                    classes.push(class);
+                }
+            }
+            Err(e) => {
+                eprintln!("{}!{}: {}", source, name, e);
+                failed = true;
+            }
+        }
+        Ok(())
+    });
+    if let Err(e) = read {
+        eprintln!("reading {} failed: {}", classpath, e);
+        process::exit(2);
+    }
+
+    if matches.is_present("json") {
+        println!("{}", serde_json::to_string_pretty(&classes).unwrap());
+    } else {
+        print_table(&classes);
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
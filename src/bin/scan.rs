@@ -0,0 +1,118 @@
+use clap::{App, Arg};
+use jvm::{
+    class_parser::format::version::Version,
+    class_path_manager::ClassPathManager,
+    scan::{scan, ScanOptions},
+};
+use std::process;
+
+fn main() {
+    let matches = App::new("class file scanner")
+        .version("1.0")
+        .author("zhiqiangxu")
+        .arg(
+            Arg::new("classpath")
+                .about("jars and class directories to parse, separated like CLASSPATH")
+                .required(true),
+        )
+        .arg(
+            Arg::new("threads")
+                .short('j')
+                .long("threads")
+                .about("worker threads, one per CPU by default")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("max-release")
+                .long("max-release")
+                .about("reject classes newer than this Java release, e.g. 11")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("intern")
+                .long("intern")
+                .about("intern names and descriptors across classes and report the memory saved"),
+        )
+        .arg(
+            Arg::new("quiet")
+                .short('q')
+                .long("quiet")
+                .about("only print failures and the totals"),
+        )
+        .get_matches();
+
+    let mut options = ScanOptions {
+        intern: matches.is_present("intern"),
+        ..ScanOptions::default()
+    };
+    if let Some(threads) = matches.value_of("threads") {
+        options.threads = threads.parse().unwrap_or_else(|_| {
+            eprintln!("bad thread count {}", threads);
+            process::exit(2);
+        });
+    }
+    if let Some(release) = matches.value_of("max-release") {
+        options.parse.max_version = match release.parse().ok().and_then(Version::of_release) {
+            Some(version) => version,
+            None => {
+                eprintln!("bad release {}", release);
+                process::exit(2);
+            }
+        };
+    }
+
+    let classpath = matches.value_of("classpath").unwrap();
+    let mut cpm = ClassPathManager::new();
+    if let Err(e) = cpm.add_class_paths(classpath) {
+        eprintln!("can't open {}: {}", classpath, e);
+        process::exit(2);
+    }
+
+    let report = match scan(&cpm, &options) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("scanning failed: {}", e);
+            process::exit(2);
+        }
+    };
+
+    if !matches.is_present("quiet") {
+        println!("versions:");
+        for (version, n) in report.versions.iter() {
+            println!("{:>10} {}", n, version);
+        }
+        println!("attributes:");
+        for (name, n) in report.attributes.iter() {
+            println!("{:>10} {}", n, name);
+        }
+    }
+    if let Some(symbols) = report.symbols {
+        println!(
+            "symbols: {} distinct, {} bytes; {} of {} Utf8 constants shared, {} bytes saved",
+            symbols.symbols,
+            symbols.bytes,
+            symbols.hits,
+            symbols.lookups,
+            symbols.saved_bytes()
+        );
+    }
+    for f in report.failures.iter() {
+        println!(
+            "FAILED {}!{}: {} (at byte {})",
+            f.source, f.name, f.error, f.offset
+        );
+    }
+    println!(
+        "{} classes, {} bytes in {:.2}s ({:.0} classes/s, {:.1} MB/s), {} failed",
+        report.classes,
+        report.bytes,
+        report.elapsed.as_secs_f64(),
+        report.classes_per_sec(),
+        report.bytes_per_sec() / (1 << 20) as f64,
+        report.failures.len()
+    );
+
+    if !report.failures.is_empty() {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,73 @@
+use clap::{App, Arg};
+use jvm::{
+    class_path_manager::ClassPathManager,
+    serialization::{decode, resolve},
+};
+use std::fs;
+use std::process;
+
+fn main() {
+    let matches = App::new("Java serialization stream decoder")
+        .version("1.0")
+        .author("zhiqiangxu")
+        .arg(
+            Arg::new("input")
+                .about("file holding an ObjectOutputStream stream")
+                .required(true),
+        )
+        .arg(
+            Arg::new("classpath")
+                .long("classpath")
+                .about("check the serialVersionUID of every class against these jars and class directories")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("quiet")
+                .short('q')
+                .long("quiet")
+                .about("don't print the objects"),
+        )
+        .get_matches();
+
+    let input = matches.value_of("input").unwrap();
+    let data = fs::read(input).unwrap_or_else(|e| {
+        eprintln!("can't read {}: {}", input, e);
+        process::exit(2);
+    });
+    let stream = decode(&data).unwrap_or_else(|e| {
+        eprintln!("{}: {}", input, e);
+        process::exit(1);
+    });
+    if !matches.is_present("quiet") {
+        print!("{}", stream.dump());
+    }
+
+    let classpath = match matches.value_of("classpath") {
+        Some(classpath) => classpath,
+        None => return,
+    };
+    let mut cpm = ClassPathManager::new();
+    if let Err(e) = cpm.add_class_paths(classpath) {
+        eprintln!("can't open {}: {}", classpath, e);
+        process::exit(2);
+    }
+
+    let mut mismatches = 0;
+    for r in resolve(&stream, &cpm) {
+        match (r.local_uid, &r.source) {
+            (Some(uid), Some(source)) if r.is_mismatch() => {
+                mismatches += 1;
+                println!(
+                    "MISMATCH {}: stream {}, {} in {}",
+                    r.name, r.stream_uid, uid, source
+                );
+            }
+            (Some(_), _) => {}
+            (None, Some(source)) => println!("UNREADABLE {} in {}", r.name, source),
+            (None, None) => println!("MISSING {}", r.name),
+        }
+    }
+    if mismatches > 0 {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,112 @@
+use clap::{App, Arg};
+use jvm::{
+    class_path_manager::ClassPathManager,
+    shade::{MergeStrategy, Relocation, Shader},
+};
+use std::fs::File;
+use std::process;
+
+fn split_pair<'a>(arg: &'a str, what: &str) -> (&'a str, &'a str) {
+    match arg.find('=') {
+        Some(i) => (&arg[..i], &arg[i + 1..]),
+        None => {
+            eprintln!("{} must look like FROM=TO: {}", what, arg);
+            process::exit(2);
+        }
+    }
+}
+
+fn main() {
+    let matches = App::new("jar shading")
+        .version("1.0")
+        .author("zhiqiangxu")
+        .arg(
+            Arg::new("cp")
+                .long("cp")
+                .about("jars and directories to merge")
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::new("relocate")
+                .long("relocate")
+                .about("package relocation, e.g. com.google.=shaded.com.google.")
+                .takes_value(true)
+                .multiple(true),
+        )
+        .arg(
+            Arg::new("merge")
+                .long("merge")
+                .about("conflict rule for resources, e.g. META-INF/*.txt=concat (first, last, concat, fail)")
+                .takes_value(true)
+                .multiple(true),
+        )
+        .arg(
+            Arg::new("main-class")
+                .long("main-class")
+                .about("Main-Class of the shaded jar")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .about("jar to write")
+                .takes_value(true)
+                .required(true),
+        )
+        .get_matches();
+
+    let mut cpm = ClassPathManager::new();
+    if let Err(e) = cpm.add_class_paths(matches.value_of("cp").unwrap()) {
+        eprintln!("bad class path: {}", e);
+        process::exit(1);
+    }
+
+    let relocations = matches
+        .values_of("relocate")
+        .map(|vs| {
+            vs.map(|v| {
+                let (from, to) = split_pair(v, "relocation");
+                Relocation::new(from, to)
+            })
+            .collect()
+        })
+        .unwrap_or_default();
+
+    let mut shader = Shader::new(relocations);
+    if let Some(rules) = matches.values_of("merge") {
+        for rule in rules {
+            let (pattern, strategy) = split_pair(rule, "merge rule");
+            match strategy.parse::<MergeStrategy>() {
+                Ok(strategy) => shader.merge_rule(pattern, strategy),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(2);
+                }
+            };
+        }
+    }
+    if let Some(main_class) = matches.value_of("main-class") {
+        shader.main_class(main_class);
+    }
+
+    let output = matches.value_of("output").unwrap();
+    let result = File::create(output).and_then(|f| shader.shade(&cpm, f));
+    match result {
+        Ok(report) => {
+            println!(
+                "{}: {} classes ({} relocated), {} resources, {} duplicates",
+                output,
+                report.classes,
+                report.relocated_classes,
+                report.resources,
+                report.duplicates.len()
+            );
+        }
+        Err(e) => {
+            eprintln!("shading failed: {}", e);
+            process::exit(1);
+        }
+    }
+}
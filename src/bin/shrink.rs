@@ -0,0 +1,150 @@
+use clap::{App, Arg};
+use jvm::{
+    class_path_manager::ClassPathManager,
+    shrink::{shrink_jar, StripOptions},
+};
+use std::fs::{metadata, File};
+use std::process;
+
+fn percent(saved: usize, before: usize) -> f64 {
+    if before == 0 {
+        0.0
+    } else {
+        saved as f64 * 100.0 / before as f64
+    }
+}
+
+fn main() {
+    let matches = App::new("class file minimizer")
+        .version("1.0")
+        .author("zhiqiangxu")
+        .arg(
+            Arg::new("input")
+                .about("jar or class directory to shrink")
+                .required(true),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .about("jar to write")
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(Arg::new("all").long("all").about("strip everything below"))
+        .arg(
+            Arg::new("lines")
+                .long("lines")
+                .about("strip LineNumberTable"),
+        )
+        .arg(
+            Arg::new("vars")
+                .long("vars")
+                .about("strip LocalVariableTable"),
+        )
+        .arg(
+            Arg::new("var-types")
+                .long("var-types")
+                .about("strip LocalVariableTypeTable"),
+        )
+        .arg(Arg::new("source").long("source").about("strip SourceFile"))
+        .arg(
+            Arg::new("debug-extension")
+                .long("debug-extension")
+                .about("strip SourceDebugExtension"),
+        )
+        .arg(
+            Arg::new("invisible-annotations")
+                .long("invisible-annotations")
+                .about("strip RuntimeInvisible*Annotations"),
+        )
+        .arg(
+            Arg::new("quiet")
+                .short('q')
+                .long("quiet")
+                .about("only print the totals"),
+        )
+        .get_matches();
+
+    let options = if matches.is_present("all") {
+        StripOptions::all()
+    } else {
+        StripOptions {
+            line_numbers: matches.is_present("lines"),
+            local_variables: matches.is_present("vars"),
+            local_variable_types: matches.is_present("var-types"),
+            source_file: matches.is_present("source"),
+            source_debug_extension: matches.is_present("debug-extension"),
+            invisible_annotations: matches.is_present("invisible-annotations"),
+        }
+    };
+
+    let input = matches.value_of("input").unwrap();
+    let output = matches.value_of("output").unwrap();
+
+    let mut cpm = ClassPathManager::new();
+    if let Err(e) = cpm.add_class_path(input) {
+        eprintln!("can't open {}: {}", input, e);
+        process::exit(1);
+    }
+
+    let report = match File::create(output).and_then(|f| shrink_jar(&cpm, &options, f)) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("shrinking failed: {}", e);
+            process::exit(1);
+        }
+    };
+
+    if !matches.is_present("quiet") {
+        for class in report.classes.iter() {
+            println!(
+                "{:>8} -> {:>8} ({:>5.1}%) {}",
+                class.before,
+                class.after,
+                percent(class.saved(), class.before),
+                class.name
+            );
+            if !class.undecoded.is_empty() {
+                println!(
+                    "{:>8}    constant pool kept, undecoded: {}",
+                    "",
+                    class.undecoded.join(", ")
+                );
+            }
+        }
+    }
+
+    let (before, after) = (report.before(), report.after());
+    println!(
+        "classes: {} -> {} bytes, saved {} ({:.1}%)",
+        before,
+        after,
+        before.saturating_sub(after),
+        percent(before.saturating_sub(after), before)
+    );
+    let kept = report
+        .classes
+        .iter()
+        .filter(|c| !c.undecoded.is_empty())
+        .count();
+    if kept > 0 {
+        println!(
+            "constant pool kept in {} classes with undecoded attributes",
+            kept
+        );
+    }
+    if let Ok(m) = metadata(input) {
+        if m.is_file() {
+            let jar_before = m.len() as usize;
+            let jar_after = report.jar_after as usize;
+            println!(
+                "jar: {} -> {} bytes, saved {} ({:.1}%)",
+                jar_before,
+                jar_after,
+                jar_before.saturating_sub(jar_after),
+                percent(jar_before.saturating_sub(jar_after), jar_before)
+            );
+        }
+    }
+}
@@ -1,13 +1,14 @@
 use super::class_parser::format::class_file::ClassFile;
 use super::class_parser::parse;
 use super::class_path_manager;
+use super::symbol::{self, Symbol, SymbolTable};
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
 pub struct ClassLoader {
     parent_loader: Option<Arc<ClassLoader>>,
     cpm: Arc<class_path_manager::ClassPathManager>,
-    loaded_class: Mutex<HashMap<String, Arc<Box<ClassFile>>>>,
+    loaded_class: Mutex<HashMap<String, Arc<ClassFile>>>,
 }
 
 impl ClassLoader {
@@ -22,7 +23,17 @@ impl ClassLoader {
         }
     }
 
-    pub fn load_class(&self, name: &str) -> Option<Arc<Box<ClassFile>>> {
+    /// The table the Utf8 constants of loaded classes are interned in, shared
+    /// by every loader.
+    pub fn symbols(&self) -> &'static SymbolTable {
+        symbol::global()
+    }
+
+    pub fn symbol(&self, name: &str) -> Symbol {
+        self.symbols().intern(name.as_bytes())
+    }
+
+    pub fn load_class(&self, name: &str) -> Option<Arc<ClassFile>> {
         if let Some(parent_loader) = &self.parent_loader {
             let result = parent_loader.load_class(name);
             if result.is_some() {
@@ -30,7 +41,7 @@ impl ClassLoader {
             }
         }
 
-        let loaded_class = self.loaded_class.lock().unwrap();
+        let mut loaded_class = self.loaded_class.lock().unwrap();
 
         let result = loaded_class.get(name);
         if let Some(cls) = result {
@@ -38,8 +49,13 @@ impl ClassLoader {
         }
 
         match self.cpm.search_class(name) {
-            Ok(class_path_manager::ClassPathResult(_, bytes)) => match parse(&bytes) {
-                Ok(cf) => Some(Arc::new(Box::new(cf.1))),
+            Ok(class_path_manager::ClassPathResult(_, bytes, _)) => match parse(&bytes) {
+                Ok((_, mut cf)) => {
+                    self.symbols().intern_class(&mut cf);
+                    let cls = Arc::new(cf);
+                    loaded_class.insert(name.to_string(), cls.clone());
+                    Some(cls)
+                }
 
                 Err(e) => unreachable!("name={}, {}", name, e),
             },
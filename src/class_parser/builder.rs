@@ -0,0 +1,638 @@
+use super::bytecode::{self, CpOperand};
+use super::format::{
+    attributes::{AnnotationEntry, ElementValuePair, ElementValueType, Type as AttributeType},
+    class_file::ClassFile,
+    constant_pool::{self, Type},
+};
+use super::write::refresh_raw;
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+// constant_pool_count is a u16 and counts the unused slot 0 (spec 4.1)
+pub const MAX_CONSTANT_POOL_COUNT: usize = u16::MAX as usize;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// adding the constant would need more slots than a class file can hold
+    TooManyConstants { needed: usize },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::TooManyConstants { needed } => write!(
+                f,
+                "constant pool overflow: {} slots needed, at most {} allowed",
+                needed, MAX_CONSTANT_POOL_COUNT
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Mutable constant pool that hands out indices for new constants, reusing
+/// an existing slot whenever an identical constant is already present.
+///
+/// Entries are only ever appended, so indices taken from the pool the
+/// builder started with remain valid.
+pub struct ConstantPoolBuilder {
+    entries: Vec<Type>,
+    index: HashMap<Type, u16>,
+}
+
+impl ConstantPoolBuilder {
+    pub fn new() -> Self {
+        Self {
+            entries: vec![Type::Nop],
+            index: HashMap::new(),
+        }
+    }
+
+    pub fn from_pool(cp: &[Type]) -> Self {
+        let mut entries = cp.to_vec();
+        if entries.is_empty() {
+            entries.push(Type::Nop);
+        }
+
+        let mut index = HashMap::new();
+        for (i, c) in entries.iter().enumerate() {
+            match c {
+                Type::Nop | Type::Unknown => (),
+                _ => {
+                    index.entry(c.clone()).or_insert(i as u16);
+                }
+            }
+        }
+
+        Self { entries, index }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.len() <= 1
+    }
+
+    pub fn get(&self, idx: u16) -> Option<&Type> {
+        self.entries.get(idx as usize)
+    }
+
+    pub fn entries(&self) -> &[Type] {
+        &self.entries
+    }
+
+    /// Returns the index of `c`, appending it if it is not in the pool yet.
+    pub fn add(&mut self, c: Type) -> Result<u16, Error> {
+        if let Some(idx) = self.index.get(&c) {
+            return Ok(*idx);
+        }
+
+        //spec 4.4.5
+        let slots = match c {
+            Type::Long { .. } | Type::Double { .. } => 2,
+            _ => 1,
+        };
+        let needed = self.entries.len() + slots;
+        if needed > MAX_CONSTANT_POOL_COUNT {
+            return Err(Error::TooManyConstants { needed });
+        }
+
+        let idx = self.entries.len() as u16;
+        self.entries.push(c.clone());
+        if slots == 2 {
+            self.entries.push(Type::Nop);
+        }
+        self.index.insert(c, idx);
+
+        Ok(idx)
+    }
+
+    /// Overwrites the constant at `idx`, so everything referring to it sees
+    /// `c` from now on. Long/Double can only replace each other.
+    pub fn replace(&mut self, idx: u16, c: Type) {
+        let wide = |c: &Type| matches!(c, Type::Long { .. } | Type::Double { .. });
+        let old = &self.entries[idx as usize];
+        assert_eq!(wide(old), wide(&c), "constant pool slot width mismatch");
+
+        let old = std::mem::replace(&mut self.entries[idx as usize], c.clone());
+        if self.index.get(&old) == Some(&idx) {
+            self.index.remove(&old);
+        }
+        self.index.entry(c).or_insert(idx);
+    }
+
+    pub fn utf8(&mut self, s: &str) -> Result<u16, Error> {
+        self.utf8_bytes(constant_pool::encode_modified_utf8(s))
+    }
+
+    pub fn utf8_bytes(&mut self, bytes: Vec<u8>) -> Result<u16, Error> {
+        self.add(Type::Utf8 {
+            bytes: Arc::new(bytes),
+        })
+    }
+
+    pub fn class(&mut self, name: &str) -> Result<u16, Error> {
+        let name_index = self.utf8(name)?;
+        self.add(Type::Class { name_index })
+    }
+
+    pub fn string(&mut self, s: &str) -> Result<u16, Error> {
+        let string_index = self.utf8(s)?;
+        self.add(Type::String { string_index })
+    }
+
+    pub fn integer(&mut self, v: i32) -> Result<u16, Error> {
+        self.add(Type::Integer { v: v.to_be_bytes() })
+    }
+
+    pub fn float(&mut self, v: f32) -> Result<u16, Error> {
+        self.add(Type::Float {
+            v: v.to_bits().to_be_bytes(),
+        })
+    }
+
+    pub fn long(&mut self, v: i64) -> Result<u16, Error> {
+        self.add(Type::Long { v: v.to_be_bytes() })
+    }
+
+    pub fn double(&mut self, v: f64) -> Result<u16, Error> {
+        self.add(Type::Double {
+            v: v.to_bits().to_be_bytes(),
+        })
+    }
+
+    pub fn name_and_type(&mut self, name: &str, desc: &str) -> Result<u16, Error> {
+        let name_index = self.utf8(name)?;
+        let desc_index = self.utf8(desc)?;
+        self.add(Type::NameAndType {
+            name_index,
+            desc_index,
+        })
+    }
+
+    pub fn field_ref(&mut self, class: &str, name: &str, desc: &str) -> Result<u16, Error> {
+        let class_index = self.class(class)?;
+        let name_and_type_index = self.name_and_type(name, desc)?;
+        self.add(Type::FieldRef {
+            class_index,
+            name_and_type_index,
+        })
+    }
+
+    pub fn method_ref(&mut self, class: &str, name: &str, desc: &str) -> Result<u16, Error> {
+        let class_index = self.class(class)?;
+        let name_and_type_index = self.name_and_type(name, desc)?;
+        self.add(Type::MethodRef {
+            class_index,
+            name_and_type_index,
+        })
+    }
+
+    pub fn interface_method_ref(
+        &mut self,
+        class: &str,
+        name: &str,
+        desc: &str,
+    ) -> Result<u16, Error> {
+        let class_index = self.class(class)?;
+        let name_and_type_index = self.name_and_type(name, desc)?;
+        self.add(Type::InterfaceMethodRef {
+            class_index,
+            name_and_type_index,
+        })
+    }
+
+    pub fn method_handle(&mut self, ref_kind: u8, ref_index: u16) -> Result<u16, Error> {
+        self.add(Type::MethodHandle {
+            ref_kind,
+            ref_index,
+        })
+    }
+
+    pub fn method_type(&mut self, desc: &str) -> Result<u16, Error> {
+        let desc_index = self.utf8(desc)?;
+        self.add(Type::MethodType { desc_index })
+    }
+
+    pub fn invoke_dynamic(
+        &mut self,
+        bootstrap_method_attr_index: u16,
+        name: &str,
+        desc: &str,
+    ) -> Result<u16, Error> {
+        let name_and_type_index = self.name_and_type(name, desc)?;
+        self.add(Type::InvokeDynamic {
+            bootstrap_method_attr_index,
+            name_and_type_index,
+        })
+    }
+
+    pub fn build(self) -> Arc<Vec<Type>> {
+        Arc::new(self.entries)
+    }
+}
+
+impl Default for ConstantPoolBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn constant_indices_mut(c: &mut Type, f: &mut dyn FnMut(&mut u16)) {
+    match c {
+        Type::Class { name_index } | Type::Module { name_index } | Type::Package { name_index } => {
+            f(name_index)
+        }
+        Type::FieldRef {
+            class_index,
+            name_and_type_index,
+        }
+        | Type::MethodRef {
+            class_index,
+            name_and_type_index,
+        }
+        | Type::InterfaceMethodRef {
+            class_index,
+            name_and_type_index,
+        } => {
+            f(class_index);
+            f(name_and_type_index);
+        }
+        Type::String { string_index } => f(string_index),
+        Type::NameAndType {
+            name_index,
+            desc_index,
+        } => {
+            f(name_index);
+            f(desc_index);
+        }
+        Type::MethodHandle { ref_index, .. } => f(ref_index),
+        Type::MethodType { desc_index } => f(desc_index),
+        // bootstrap_method_attr_index points into BootstrapMethods, not the pool
+        Type::InvokeDynamic {
+            name_and_type_index,
+            ..
+        }
+        | Type::Dynamic {
+            name_and_type_index,
+            ..
+        } => f(name_and_type_index),
+        _ => (),
+    }
+}
+
+fn element_value_indices_mut(v: &mut ElementValueType, f: &mut dyn FnMut(&mut u16)) {
+    match v {
+        ElementValueType::Byte { val_index }
+        | ElementValueType::Char { val_index }
+        | ElementValueType::Double { val_index }
+        | ElementValueType::Float { val_index }
+        | ElementValueType::Int { val_index }
+        | ElementValueType::Long { val_index }
+        | ElementValueType::Short { val_index }
+        | ElementValueType::Boolean { val_index }
+        | ElementValueType::String { val_index } => f(val_index),
+        ElementValueType::Enum {
+            type_index,
+            val_index,
+        } => {
+            f(type_index);
+            f(val_index);
+        }
+        ElementValueType::Class { index } => f(index),
+        ElementValueType::Annotation(a) => annotation_indices_mut(&mut a.value, f),
+        ElementValueType::Array { values } => values
+            .iter_mut()
+            .for_each(|v| element_value_indices_mut(v, f)),
+        ElementValueType::Unknown => (),
+    }
+}
+
+fn pairs_indices_mut(pairs: &mut [ElementValuePair], f: &mut dyn FnMut(&mut u16)) {
+    for pair in pairs {
+        f(&mut pair.name_index);
+        element_value_indices_mut(&mut pair.value, f);
+    }
+}
+
+fn annotation_indices_mut(a: &mut AnnotationEntry, f: &mut dyn FnMut(&mut u16)) {
+    f(&mut a.type_index);
+    pairs_indices_mut(&mut a.pairs, f);
+}
+
+fn code_indices_mut(code: &mut Arc<Vec<u8>>, f: &mut dyn FnMut(&mut u16)) -> io::Result<()> {
+    let mut patches = vec![];
+    for insn in bytecode::Instructions::new(code) {
+        // the operands past it can't be found, renumbering the rest would
+        // leave them pointing at other constants
+        let insn = insn.map_err(|pc| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("truncated instruction at pc {}", pc),
+            )
+        })?;
+        let at = insn.pc + 1;
+        match bytecode::cp_operand(insn.opcode) {
+            Some(CpOperand::Narrow) => {
+                let mut idx = code[at] as u16;
+                f(&mut idx);
+                if idx != code[at] as u16 {
+                    patches.push((insn.pc, idx));
+                }
+            }
+            Some(CpOperand::Wide) => {
+                let old = u16::from_be_bytes([code[at], code[at + 1]]);
+                let mut idx = old;
+                f(&mut idx);
+                if idx != old {
+                    patches.push((insn.pc, idx));
+                }
+            }
+            None => (),
+        }
+    }
+
+    if patches.is_empty() {
+        return Ok(());
+    }
+    let code = Arc::make_mut(code);
+    for (pc, idx) in patches {
+        if code[pc] == bytecode::LDC {
+            assert!(idx <= u8::MAX as u16, "ldc index out of range: {}", idx);
+            code[pc + 1] = idx as u8;
+        } else {
+            code[pc + 1..pc + 3].copy_from_slice(&idx.to_be_bytes());
+        }
+    }
+    Ok(())
+}
+
+fn attr_indices_mut(attr: &mut AttributeType, f: &mut dyn FnMut(&mut u16)) -> io::Result<()> {
+    match attr {
+        AttributeType::ConstantValue {
+            constant_value_index,
+        } => f(constant_value_index),
+        AttributeType::Code(code) => {
+            code_indices_mut(&mut code.code, f)?;
+            code.exceptions
+                .iter_mut()
+                .for_each(|e| f(&mut e.catch_type));
+            attrs_indices_mut(&mut code.attrs, f)?;
+        }
+        AttributeType::StackMapTable { entries } => {
+            use super::format::attributes::{StackMapFrame, VerificationTypeInfo};
+            let mut info = |v: &mut VerificationTypeInfo| {
+                if let VerificationTypeInfo::Object { cpool_index } = v {
+                    f(cpool_index)
+                }
+            };
+            for frame in entries {
+                match frame {
+                    StackMapFrame::SameLocals1StackItem { stack, .. }
+                    | StackMapFrame::SameLocals1StackItemExtended { stack, .. } => {
+                        stack.iter_mut().for_each(&mut info)
+                    }
+                    StackMapFrame::Append { locals, .. } => locals.iter_mut().for_each(&mut info),
+                    StackMapFrame::Full { locals, stack, .. } => {
+                        locals.iter_mut().for_each(&mut info);
+                        stack.iter_mut().for_each(&mut info);
+                    }
+                    _ => (),
+                }
+            }
+        }
+        AttributeType::Exceptions { exceptions } => exceptions.iter_mut().for_each(&mut *f),
+        AttributeType::InnerClasses { classes } => {
+            for c in classes {
+                f(&mut c.inner_class_info_index);
+                f(&mut c.outer_class_info_index);
+                f(&mut c.inner_name_index);
+            }
+        }
+        AttributeType::EnclosingMethod { em } => {
+            f(&mut em.class_index);
+            f(&mut em.method_index);
+        }
+        AttributeType::Signature { signature_index } => f(signature_index),
+        AttributeType::SourceFile { source_file_index } => f(source_file_index),
+        AttributeType::LocalVariableTable { tables }
+        | AttributeType::LocalVariableTypeTable { tables } => {
+            for t in tables {
+                f(&mut t.name_index);
+                f(&mut t.signature_index);
+            }
+        }
+        AttributeType::RuntimeVisibleAnnotations { annotations, .. }
+        | AttributeType::RuntimeInvisibleAnnotations { annotations, .. } => annotations
+            .iter_mut()
+            .for_each(|a| annotation_indices_mut(a, f)),
+        AttributeType::RuntimeVisibleParameterAnnotations { parameters, .. }
+        | AttributeType::RuntimeInvisibleParameterAnnotations { parameters, .. } => parameters
+            .iter_mut()
+            .flatten()
+            .for_each(|a| annotation_indices_mut(a, f)),
+        AttributeType::RuntimeVisibleTypeAnnotations { annotations, .. }
+        | AttributeType::RuntimeInvisibleTypeAnnotations { annotations, .. } => {
+            for a in annotations {
+                f(&mut a.type_index);
+                pairs_indices_mut(&mut a.pairs, f);
+            }
+        }
+        AttributeType::AnnotationDefault { default_value, .. } => {
+            element_value_indices_mut(default_value, f)
+        }
+        AttributeType::BootstrapMethods { methods, .. } => {
+            for m in methods {
+                f(&mut m.method_ref);
+                m.args.iter_mut().for_each(&mut *f);
+            }
+        }
+        AttributeType::MethodParameters { parameters } => {
+            parameters.iter_mut().for_each(|p| f(&mut p.name_index))
+        }
+        AttributeType::NestHost { host_class_index } => f(host_class_index),
+        AttributeType::NestMembers { classes } | AttributeType::PermittedSubclasses { classes } => {
+            classes.iter_mut().for_each(&mut *f)
+        }
+        AttributeType::Record { components } => {
+            for c in components {
+                f(&mut c.name_index);
+                f(&mut c.descriptor_index);
+                attrs_indices_mut(&mut c.attrs, f)?;
+            }
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+fn attrs_indices_mut(attrs: &mut [AttributeType], f: &mut dyn FnMut(&mut u16)) -> io::Result<()> {
+    attrs.iter_mut().try_for_each(|a| attr_indices_mut(a, f))
+}
+
+/// Calls `f` with every constant pool index stored in the class outside of
+/// the pool itself. Index 0 ("none") is never passed. Code that doesn't
+/// decode into whole instructions is an `InvalidData` error, `f` may have
+/// seen some indices by then.
+pub fn for_each_index_mut(cf: &mut ClassFile, f: &mut dyn FnMut(&mut u16)) -> io::Result<()> {
+    let mut f = |idx: &mut u16| {
+        if *idx != 0 {
+            f(idx)
+        }
+    };
+
+    f(&mut cf.this_class);
+    f(&mut cf.super_class);
+    cf.interfaces.iter_mut().for_each(&mut f);
+    for field in cf.fields.iter_mut() {
+        f(&mut field.name_index);
+        f(&mut field.desc_index);
+        attrs_indices_mut(&mut field.attrs, &mut f)?;
+    }
+    for method in cf.methods.iter_mut() {
+        f(&mut method.name_index);
+        f(&mut method.desc_index);
+        attrs_indices_mut(&mut method.attrs, &mut f)?;
+    }
+    attrs_indices_mut(&mut cf.attrs, &mut f)
+}
+
+fn attr_names<'a>(attrs: &'a [AttributeType], names: &mut HashSet<&'a [u8]>) {
+    for attr in attrs {
+        names.insert(attr.name());
+        match attr {
+            AttributeType::Code(code) => attr_names(&code.attrs, names),
+            AttributeType::Record { components } => {
+                components.iter().for_each(|c| attr_names(&c.attrs, names))
+            }
+            _ => (),
+        }
+    }
+}
+
+fn undecoded(attrs: &[AttributeType], names: &mut BTreeSet<String>) {
+    for attr in attrs {
+        match attr {
+            AttributeType::Unknown { name, .. } | AttributeType::Custom { name, .. } => {
+                names.insert(String::from_utf8_lossy(name).into_owned());
+            }
+            AttributeType::Code(code) => undecoded(&code.attrs, names),
+            AttributeType::Record { components } => {
+                components.iter().for_each(|c| undecoded(&c.attrs, names))
+            }
+            _ => (),
+        }
+    }
+}
+
+/// What `compact` did to a class.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Compaction {
+    /// this many constant pool slots were removed, possibly none
+    Removed(usize),
+    /// the class was left alone because of these attributes, which the
+    /// parser didn't decode
+    Undecoded(Vec<String>),
+}
+
+/// Drops every constant the class no longer references and renumbers the
+/// remaining ones, keeping their relative order (so `ldc` operands never
+/// grow past one byte).
+///
+/// Classes with attributes the parser didn't decode are left alone, as
+/// those may hold indices that can't be found to renumber; the result names
+/// the attributes. Code that doesn't decode into whole instructions is an
+/// `InvalidData` error, with the class left as it was.
+pub fn compact(cf: &mut ClassFile) -> io::Result<Compaction> {
+    let mut skipped = BTreeSet::new();
+    undecoded(&cf.attrs, &mut skipped);
+    cf.fields
+        .iter()
+        .for_each(|f| undecoded(&f.attrs, &mut skipped));
+    cf.methods
+        .iter()
+        .for_each(|m| undecoded(&m.attrs, &mut skipped));
+    if !skipped.is_empty() {
+        return Ok(Compaction::Undecoded(skipped.into_iter().collect()));
+    }
+    let old = cf.cp.clone();
+    let mut used = vec![false; old.len()];
+
+    // roots: everything the class refers to, plus attribute names
+    let mut pending = vec![];
+    for_each_index_mut(cf, &mut |idx| pending.push(*idx))?;
+
+    let mut names = HashSet::new();
+    attr_names(&cf.attrs, &mut names);
+    cf.fields
+        .iter()
+        .for_each(|f| attr_names(&f.attrs, &mut names));
+    cf.methods
+        .iter()
+        .for_each(|m| attr_names(&m.attrs, &mut names));
+    for (i, c) in old.iter().enumerate() {
+        if let Type::Utf8 { bytes } = c {
+            if names.remove(bytes.as_slice()) {
+                pending.push(i as u16);
+            }
+        }
+    }
+
+    while let Some(idx) = pending.pop() {
+        let idx = idx as usize;
+        if idx == 0 || idx >= used.len() || used[idx] {
+            continue;
+        }
+        used[idx] = true;
+        let mut c = old[idx].clone();
+        constant_indices_mut(&mut c, &mut |i| pending.push(*i));
+    }
+
+    let mut mapping = vec![0u16; old.len()];
+    let mut entries = vec![Type::Nop];
+    for (i, c) in old.iter().enumerate().skip(1) {
+        if !used[i] {
+            continue;
+        }
+        mapping[i] = entries.len() as u16;
+        entries.push(c.clone());
+        if let Type::Long { .. } | Type::Double { .. } = c {
+            entries.push(Type::Nop);
+        }
+    }
+
+    let removed = old.len() - entries.len();
+    if removed == 0 {
+        return Ok(Compaction::Removed(0));
+    }
+
+    // dangling indices are left alone, there's nothing sensible to map them to
+    let mut remap = |i: &mut u16| {
+        if let Some(new) = mapping.get(*i as usize) {
+            *i = *new;
+        }
+    };
+    for c in entries.iter_mut() {
+        constant_indices_mut(c, &mut remap);
+    }
+    cf.cp = Arc::new(entries);
+    // the roots were walked above, so this can't fail half way
+    for_each_index_mut(cf, &mut remap)?;
+
+    // the decoded annotations were remapped, bring the raw copies along
+    refresh_raw(&mut cf.attrs)?;
+    for field in cf.fields.iter_mut() {
+        refresh_raw(&mut field.attrs)?;
+    }
+    for method in cf.methods.iter_mut() {
+        refresh_raw(&mut method.attrs)?;
+    }
+
+    Ok(Compaction::Removed(removed))
+}
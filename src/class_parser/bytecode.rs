@@ -0,0 +1,475 @@
+pub const LDC: u8 = 0x12;
+pub const LDC_W: u8 = 0x13;
+pub const LDC2_W: u8 = 0x14;
+pub const TABLESWITCH: u8 = 0xaa;
+pub const LOOKUPSWITCH: u8 = 0xab;
+pub const GETSTATIC: u8 = 0xb2;
+pub const PUTSTATIC: u8 = 0xb3;
+pub const GETFIELD: u8 = 0xb4;
+pub const PUTFIELD: u8 = 0xb5;
+pub const INVOKEVIRTUAL: u8 = 0xb6;
+pub const INVOKESPECIAL: u8 = 0xb7;
+pub const INVOKESTATIC: u8 = 0xb8;
+pub const INVOKEINTERFACE: u8 = 0xb9;
+pub const INVOKEDYNAMIC: u8 = 0xba;
+pub const NEW: u8 = 0xbb;
+pub const ANEWARRAY: u8 = 0xbd;
+pub const CHECKCAST: u8 = 0xc0;
+pub const INSTANCEOF: u8 = 0xc1;
+pub const WIDE: u8 = 0xc4;
+pub const MULTIANEWARRAY: u8 = 0xc5;
+pub const IINC: u8 = 0x84;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Instruction {
+    pub pc: usize,
+    pub opcode: u8,
+    pub len: usize,
+}
+
+/// Where an instruction keeps its constant pool operand.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CpOperand {
+    /// one byte index right after the opcode (`ldc`)
+    Narrow,
+    /// two byte index right after the opcode
+    Wide,
+}
+
+fn read_i32(code: &[u8], at: usize) -> Option<i32> {
+    let b = code.get(at..at + 4)?;
+    Some(i32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+// spec 6.5: tableswitch/lookupswitch operands are 4-byte aligned
+// relative to the start of the code array
+pub fn switch_padding(pc: usize) -> usize {
+    (4 - (pc + 1) % 4) % 4
+}
+
+/// Length in bytes of the instruction starting at `pc`, or None if it is
+/// truncated or uses an undefined opcode.
+pub fn instruction_len(code: &[u8], pc: usize) -> Option<usize> {
+    let opcode = *code.get(pc)?;
+    let len = match opcode {
+        0x00..=0x0f => 1,
+        0x10 => 2,
+        0x11 => 3,
+        LDC => 2,
+        LDC_W | LDC2_W => 3,
+        0x15..=0x19 => 2,
+        0x1a..=0x35 => 1,
+        0x36..=0x3a => 2,
+        0x3b..=0x83 => 1,
+        IINC => 3,
+        0x85..=0x98 => 1,
+        0x99..=0xa8 => 3,
+        0xa9 => 2,
+        TABLESWITCH => {
+            let base = pc + 1 + switch_padding(pc);
+            let low = read_i32(code, base + 4)? as i64;
+            let high = read_i32(code, base + 8)? as i64;
+            if high < low {
+                return None;
+            }
+            base - pc + 12 + (high - low + 1) as usize * 4
+        }
+        LOOKUPSWITCH => {
+            let base = pc + 1 + switch_padding(pc);
+            let npairs = read_i32(code, base + 4)?;
+            if npairs < 0 {
+                return None;
+            }
+            base - pc + 8 + npairs as usize * 8
+        }
+        0xac..=0xb1 => 1,
+        GETSTATIC..=INVOKESTATIC => 3,
+        INVOKEINTERFACE | INVOKEDYNAMIC => 5,
+        NEW => 3,
+        0xbc => 2,
+        ANEWARRAY => 3,
+        0xbe | 0xbf => 1,
+        CHECKCAST | INSTANCEOF => 3,
+        0xc2 | 0xc3 => 1,
+        WIDE => {
+            if *code.get(pc + 1)? == IINC {
+                6
+            } else {
+                4
+            }
+        }
+        MULTIANEWARRAY => 4,
+        0xc6 | 0xc7 => 3,
+        0xc8 | 0xc9 => 5,
+        _ => return None,
+    };
+
+    if pc + len > code.len() {
+        return None;
+    }
+    Some(len)
+}
+
+pub fn cp_operand(opcode: u8) -> Option<CpOperand> {
+    match opcode {
+        LDC => Some(CpOperand::Narrow),
+        LDC_W
+        | LDC2_W
+        | GETSTATIC..=INVOKEDYNAMIC
+        | NEW
+        | ANEWARRAY
+        | CHECKCAST
+        | INSTANCEOF
+        | MULTIANEWARRAY => Some(CpOperand::Wide),
+        _ => None,
+    }
+}
+
+/// Every pc `insn` may jump to, for switches the default target first. The
+/// instruction must have been checked by [`instruction_len`].
+pub fn branch_targets(code: &[u8], insn: &Instruction) -> Vec<usize> {
+    let at = |offset: i64| (insn.pc as i64 + offset) as usize;
+    let i32_at = |pos: usize| read_i32(code, pos).unwrap_or(0) as i64;
+    match operand_kind(insn.opcode) {
+        OperandKind::Branch => {
+            let offset = i16::from_be_bytes([code[insn.pc + 1], code[insn.pc + 2]]);
+            vec![at(offset as i64)]
+        }
+        OperandKind::BranchWide => vec![at(i32_at(insn.pc + 1))],
+        OperandKind::TableSwitch => {
+            let base = insn.pc + 1 + switch_padding(insn.pc);
+            let n = i32_at(base + 8) - i32_at(base + 4) + 1;
+            let mut targets = vec![at(i32_at(base))];
+            for i in 0..n as usize {
+                targets.push(at(i32_at(base + 12 + i * 4)));
+            }
+            targets
+        }
+        OperandKind::LookupSwitch => {
+            let base = insn.pc + 1 + switch_padding(insn.pc);
+            let npairs = i32_at(base + 4) as usize;
+            let mut targets = vec![at(i32_at(base))];
+            for i in 0..npairs {
+                targets.push(at(i32_at(base + 12 + i * 8)));
+            }
+            targets
+        }
+        _ => vec![],
+    }
+}
+
+/// Yields every instruction in order, stopping with an `Err(pc)` at the
+/// first malformed one.
+pub struct Instructions<'a> {
+    code: &'a [u8],
+    pc: usize,
+    failed: bool,
+}
+
+impl<'a> Instructions<'a> {
+    pub fn new(code: &'a [u8]) -> Self {
+        Self {
+            code,
+            pc: 0,
+            failed: false,
+        }
+    }
+}
+
+impl<'a> Iterator for Instructions<'a> {
+    type Item = Result<Instruction, usize>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.pc >= self.code.len() {
+            return None;
+        }
+
+        let pc = self.pc;
+        match instruction_len(self.code, pc) {
+            Some(len) => {
+                self.pc += len;
+                Some(Ok(Instruction {
+                    pc,
+                    opcode: self.code[pc],
+                    len,
+                }))
+            }
+            None => {
+                self.failed = true;
+                Some(Err(pc))
+            }
+        }
+    }
+}
+
+const MNEMONICS: [&str; 202] = [
+    "nop",
+    "aconst_null",
+    "iconst_m1",
+    "iconst_0",
+    "iconst_1",
+    "iconst_2",
+    "iconst_3",
+    "iconst_4",
+    "iconst_5",
+    "lconst_0",
+    "lconst_1",
+    "fconst_0",
+    "fconst_1",
+    "fconst_2",
+    "dconst_0",
+    "dconst_1",
+    "bipush",
+    "sipush",
+    "ldc",
+    "ldc_w",
+    "ldc2_w",
+    "iload",
+    "lload",
+    "fload",
+    "dload",
+    "aload",
+    "iload_0",
+    "iload_1",
+    "iload_2",
+    "iload_3",
+    "lload_0",
+    "lload_1",
+    "lload_2",
+    "lload_3",
+    "fload_0",
+    "fload_1",
+    "fload_2",
+    "fload_3",
+    "dload_0",
+    "dload_1",
+    "dload_2",
+    "dload_3",
+    "aload_0",
+    "aload_1",
+    "aload_2",
+    "aload_3",
+    "iaload",
+    "laload",
+    "faload",
+    "daload",
+    "aaload",
+    "baload",
+    "caload",
+    "saload",
+    "istore",
+    "lstore",
+    "fstore",
+    "dstore",
+    "astore",
+    "istore_0",
+    "istore_1",
+    "istore_2",
+    "istore_3",
+    "lstore_0",
+    "lstore_1",
+    "lstore_2",
+    "lstore_3",
+    "fstore_0",
+    "fstore_1",
+    "fstore_2",
+    "fstore_3",
+    "dstore_0",
+    "dstore_1",
+    "dstore_2",
+    "dstore_3",
+    "astore_0",
+    "astore_1",
+    "astore_2",
+    "astore_3",
+    "iastore",
+    "lastore",
+    "fastore",
+    "dastore",
+    "aastore",
+    "bastore",
+    "castore",
+    "sastore",
+    "pop",
+    "pop2",
+    "dup",
+    "dup_x1",
+    "dup_x2",
+    "dup2",
+    "dup2_x1",
+    "dup2_x2",
+    "swap",
+    "iadd",
+    "ladd",
+    "fadd",
+    "dadd",
+    "isub",
+    "lsub",
+    "fsub",
+    "dsub",
+    "imul",
+    "lmul",
+    "fmul",
+    "dmul",
+    "idiv",
+    "ldiv",
+    "fdiv",
+    "ddiv",
+    "irem",
+    "lrem",
+    "frem",
+    "drem",
+    "ineg",
+    "lneg",
+    "fneg",
+    "dneg",
+    "ishl",
+    "lshl",
+    "ishr",
+    "lshr",
+    "iushr",
+    "lushr",
+    "iand",
+    "land",
+    "ior",
+    "lor",
+    "ixor",
+    "lxor",
+    "iinc",
+    "i2l",
+    "i2f",
+    "i2d",
+    "l2i",
+    "l2f",
+    "l2d",
+    "f2i",
+    "f2l",
+    "f2d",
+    "d2i",
+    "d2l",
+    "d2f",
+    "i2b",
+    "i2c",
+    "i2s",
+    "lcmp",
+    "fcmpl",
+    "fcmpg",
+    "dcmpl",
+    "dcmpg",
+    "ifeq",
+    "ifne",
+    "iflt",
+    "ifge",
+    "ifgt",
+    "ifle",
+    "if_icmpeq",
+    "if_icmpne",
+    "if_icmplt",
+    "if_icmpge",
+    "if_icmpgt",
+    "if_icmple",
+    "if_acmpeq",
+    "if_acmpne",
+    "goto",
+    "jsr",
+    "ret",
+    "tableswitch",
+    "lookupswitch",
+    "ireturn",
+    "lreturn",
+    "freturn",
+    "dreturn",
+    "areturn",
+    "return",
+    "getstatic",
+    "putstatic",
+    "getfield",
+    "putfield",
+    "invokevirtual",
+    "invokespecial",
+    "invokestatic",
+    "invokeinterface",
+    "invokedynamic",
+    "new",
+    "newarray",
+    "anewarray",
+    "arraylength",
+    "athrow",
+    "checkcast",
+    "instanceof",
+    "monitorenter",
+    "monitorexit",
+    "wide",
+    "multianewarray",
+    "ifnull",
+    "ifnonnull",
+    "goto_w",
+    "jsr_w",
+];
+
+pub fn mnemonic(opcode: u8) -> Option<&'static str> {
+    MNEMONICS.get(opcode as usize).copied()
+}
+
+pub fn opcode(mnemonic: &str) -> Option<u8> {
+    MNEMONICS
+        .iter()
+        .position(|m| *m == mnemonic)
+        .map(|op| op as u8)
+}
+
+/// How the bytes following an opcode are laid out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OperandKind {
+    None,
+    /// bipush
+    Byte,
+    /// sipush
+    Short,
+    /// ldc
+    ConstNarrow,
+    /// ldc_w, ldc2_w, field/method access, new, anewarray, checkcast, instanceof
+    Const,
+    /// load/store/ret, one byte index (two after wide)
+    Local,
+    /// local index and signed increment
+    Iinc,
+    /// signed 16-bit offset relative to the opcode
+    Branch,
+    /// signed 32-bit offset relative to the opcode
+    BranchWide,
+    TableSwitch,
+    LookupSwitch,
+    /// constant index, argument count and a zero byte
+    InvokeInterface,
+    /// constant index and two zero bytes
+    InvokeDynamic,
+    /// primitive array type code
+    NewArray,
+    /// class index and dimension count
+    MultiANewArray,
+    Wide,
+}
+
+pub fn operand_kind(opcode: u8) -> OperandKind {
+    match opcode {
+        0x10 => OperandKind::Byte,
+        0x11 => OperandKind::Short,
+        LDC => OperandKind::ConstNarrow,
+        LDC_W | LDC2_W | GETSTATIC..=INVOKESTATIC | NEW | ANEWARRAY | CHECKCAST | INSTANCEOF => {
+            OperandKind::Const
+        }
+        0x15..=0x19 | 0x36..=0x3a | 0xa9 => OperandKind::Local,
+        IINC => OperandKind::Iinc,
+        0x99..=0xa8 | 0xc6 | 0xc7 => OperandKind::Branch,
+        0xc8 | 0xc9 => OperandKind::BranchWide,
+        TABLESWITCH => OperandKind::TableSwitch,
+        LOOKUPSWITCH => OperandKind::LookupSwitch,
+        INVOKEINTERFACE => OperandKind::InvokeInterface,
+        INVOKEDYNAMIC => OperandKind::InvokeDynamic,
+        0xbc => OperandKind::NewArray,
+        MULTIANEWARRAY => OperandKind::MultiANewArray,
+        WIDE => OperandKind::Wide,
+        _ => OperandKind::None,
+    }
+}
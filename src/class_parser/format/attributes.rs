@@ -1,3 +1,5 @@
+use std::any::Any;
+use std::fmt;
 use std::sync::Arc;
 use tracing::info;
 #[derive(Debug)]
@@ -48,11 +50,11 @@ pub enum Type {
     },
     RuntimeVisibleParameterAnnotations {
         raw: Arc<Vec<u8>>,
-        annotations: Vec<AnnotationEntry>,
+        parameters: Vec<Vec<AnnotationEntry>>,
     },
     RuntimeInvisibleParameterAnnotations {
         raw: Arc<Vec<u8>>,
-        annotations: Vec<AnnotationEntry>,
+        parameters: Vec<Vec<AnnotationEntry>>,
     },
     RuntimeVisibleTypeAnnotations {
         raw: Arc<Vec<u8>>,
@@ -73,7 +75,41 @@ pub enum Type {
     MethodParameters {
         parameters: Vec<MethodParameter>,
     },
-    Unknown,
+    NestHost {
+        host_class_index: u16,
+    },
+    NestMembers {
+        classes: Vec<u16>,
+    },
+    Record {
+        components: Vec<RecordComponent>,
+    },
+    PermittedSubclasses {
+        classes: Vec<u16>,
+    },
+    /// An attribute the parser doesn't decode, kept as is.
+    Unknown {
+        name: Arc<Vec<u8>>,
+        data: Arc<Vec<u8>>,
+    },
+    /// An attribute decoded by a decoder registered in `ParseOptions`. It is
+    /// written back from `data`.
+    Custom {
+        name: Arc<Vec<u8>>,
+        data: Arc<Vec<u8>>,
+        value: Arc<dyn CustomAttribute>,
+    },
+}
+
+/// What an `AttributeDecoder` turns a vendor attribute into.
+pub trait CustomAttribute: fmt::Debug + Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T: fmt::Debug + Send + Sync + 'static> CustomAttribute for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -101,6 +137,10 @@ pub enum Tag {
     AnnotationDefault,
     BootstrapMethods,
     MethodParameters,
+    NestHost,
+    NestMembers,
+    Record,
+    PermittedSubclasses,
     Unknown,
 }
 
@@ -130,16 +170,165 @@ impl From<&[u8]> for Tag {
             b"AnnotationDefault" => Tag::AnnotationDefault,
             b"BootstrapMethods" => Tag::BootstrapMethods,
             b"MethodParameters" => Tag::MethodParameters,
+            b"NestHost" => Tag::NestHost,
+            b"NestMembers" => Tag::NestMembers,
+            b"Record" => Tag::Record,
+            b"PermittedSubclasses" => Tag::PermittedSubclasses,
             _ => {
-                info!("Unknown attr {}", unsafe {
-                    std::str::from_utf8_unchecked(raw)
-                });
+                info!("Unknown attr {}", String::from_utf8_lossy(raw));
                 // error!("Unknown attr {}", String::from_utf8_lossy(raw));
                 Tag::Unknown
             }
         }
     }
 }
+impl Tag {
+    /// Every attribute with a parser.
+    pub const KNOWN: [Tag; 27] = [
+        Tag::ConstantValue,
+        Tag::Code,
+        Tag::StackMapTable,
+        Tag::Exceptions,
+        Tag::InnerClasses,
+        Tag::EnclosingMethod,
+        Tag::Synthetic,
+        Tag::Signature,
+        Tag::SourceFile,
+        Tag::SourceDebugExtension,
+        Tag::LineNumberTable,
+        Tag::LocalVariableTable,
+        Tag::LocalVariableTypeTable,
+        Tag::Deprecated,
+        Tag::RuntimeVisibleAnnotations,
+        Tag::RuntimeInvisibleAnnotations,
+        Tag::RuntimeVisibleParameterAnnotations,
+        Tag::RuntimeInvisibleParameterAnnotations,
+        Tag::RuntimeVisibleTypeAnnotations,
+        Tag::RuntimeInvisibleTypeAnnotations,
+        Tag::AnnotationDefault,
+        Tag::BootstrapMethods,
+        Tag::MethodParameters,
+        Tag::NestHost,
+        Tag::NestMembers,
+        Tag::Record,
+        Tag::PermittedSubclasses,
+    ];
+
+    pub fn name(self) -> &'static [u8] {
+        match self {
+            Tag::ConstantValue => b"ConstantValue",
+            Tag::Code => b"Code",
+            Tag::StackMapTable => b"StackMapTable",
+            Tag::Exceptions => b"Exceptions",
+            Tag::InnerClasses => b"InnerClasses",
+            Tag::EnclosingMethod => b"EnclosingMethod",
+            Tag::Synthetic => b"Synthetic",
+            Tag::Signature => b"Signature",
+            Tag::SourceFile => b"SourceFile",
+            Tag::SourceDebugExtension => b"SourceDebugExtension",
+            Tag::LineNumberTable => b"LineNumberTable",
+            Tag::LocalVariableTable => b"LocalVariableTable",
+            Tag::LocalVariableTypeTable => b"LocalVariableTypeTable",
+            Tag::Deprecated => b"Deprecated",
+            Tag::RuntimeVisibleAnnotations => b"RuntimeVisibleAnnotations",
+            Tag::RuntimeInvisibleAnnotations => b"RuntimeInvisibleAnnotations",
+            Tag::RuntimeVisibleParameterAnnotations => b"RuntimeVisibleParameterAnnotations",
+            Tag::RuntimeInvisibleParameterAnnotations => b"RuntimeInvisibleParameterAnnotations",
+            Tag::RuntimeVisibleTypeAnnotations => b"RuntimeVisibleTypeAnnotations",
+            Tag::RuntimeInvisibleTypeAnnotations => b"RuntimeInvisibleTypeAnnotations",
+            Tag::AnnotationDefault => b"AnnotationDefault",
+            Tag::BootstrapMethods => b"BootstrapMethods",
+            Tag::MethodParameters => b"MethodParameters",
+            Tag::NestHost => b"NestHost",
+            Tag::NestMembers => b"NestMembers",
+            Tag::Record => b"Record",
+            Tag::PermittedSubclasses => b"PermittedSubclasses",
+            Tag::Unknown => b"",
+        }
+    }
+
+    /// The first class file major version that defines the attribute, spec
+    /// table 4.7-A. Older class files treat the name like any unknown one.
+    pub fn since(self) -> u16 {
+        match self {
+            Tag::EnclosingMethod
+            | Tag::Signature
+            | Tag::SourceDebugExtension
+            | Tag::LocalVariableTypeTable
+            | Tag::RuntimeVisibleAnnotations
+            | Tag::RuntimeInvisibleAnnotations
+            | Tag::RuntimeVisibleParameterAnnotations
+            | Tag::RuntimeInvisibleParameterAnnotations
+            | Tag::AnnotationDefault => 49,
+            Tag::StackMapTable => 50,
+            Tag::BootstrapMethods => 51,
+            Tag::RuntimeVisibleTypeAnnotations
+            | Tag::RuntimeInvisibleTypeAnnotations
+            | Tag::MethodParameters => 52,
+            Tag::NestHost | Tag::NestMembers => 55,
+            Tag::Record => 60,
+            Tag::PermittedSubclasses => 61,
+            _ => 45,
+        }
+    }
+}
+
+impl Type {
+    pub fn tag(&self) -> Tag {
+        match self {
+            Type::ConstantValue { .. } => Tag::ConstantValue,
+            Type::Code(_) => Tag::Code,
+            Type::StackMapTable { .. } => Tag::StackMapTable,
+            Type::Exceptions { .. } => Tag::Exceptions,
+            Type::InnerClasses { .. } => Tag::InnerClasses,
+            Type::EnclosingMethod { .. } => Tag::EnclosingMethod,
+            Type::Synthetic => Tag::Synthetic,
+            Type::Signature { .. } => Tag::Signature,
+            Type::SourceFile { .. } => Tag::SourceFile,
+            Type::SourceDebugExtension { .. } => Tag::SourceDebugExtension,
+            Type::LineNumberTable { .. } => Tag::LineNumberTable,
+            Type::LocalVariableTable { .. } => Tag::LocalVariableTable,
+            Type::LocalVariableTypeTable { .. } => Tag::LocalVariableTypeTable,
+            Type::Deprecated => Tag::Deprecated,
+            Type::RuntimeVisibleAnnotations { .. } => Tag::RuntimeVisibleAnnotations,
+            Type::RuntimeInvisibleAnnotations { .. } => Tag::RuntimeInvisibleAnnotations,
+            Type::RuntimeVisibleParameterAnnotations { .. } => {
+                Tag::RuntimeVisibleParameterAnnotations
+            }
+            Type::RuntimeInvisibleParameterAnnotations { .. } => {
+                Tag::RuntimeInvisibleParameterAnnotations
+            }
+            Type::RuntimeVisibleTypeAnnotations { .. } => Tag::RuntimeVisibleTypeAnnotations,
+            Type::RuntimeInvisibleTypeAnnotations { .. } => Tag::RuntimeInvisibleTypeAnnotations,
+            Type::AnnotationDefault { .. } => Tag::AnnotationDefault,
+            Type::BootstrapMethods { .. } => Tag::BootstrapMethods,
+            Type::MethodParameters { .. } => Tag::MethodParameters,
+            Type::NestHost { .. } => Tag::NestHost,
+            Type::NestMembers { .. } => Tag::NestMembers,
+            Type::Record { .. } => Tag::Record,
+            Type::PermittedSubclasses { .. } => Tag::PermittedSubclasses,
+            Type::Unknown { .. } | Type::Custom { .. } => Tag::Unknown,
+        }
+    }
+
+    /// The attribute's name as stored in the constant pool.
+    pub fn name(&self) -> &[u8] {
+        match self {
+            Type::Unknown { name, .. } | Type::Custom { name, .. } => name,
+            _ => self.tag().name(),
+        }
+    }
+
+    /// The value of a custom attribute, if it is one decoded into a `T`.
+    pub fn custom<T: 'static>(&self) -> Option<&T> {
+        match self {
+            // not value.as_any(), which would be the Arc's own impl
+            Type::Custom { value, .. } => CustomAttribute::as_any(&**value).downcast_ref(),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Code {
     pub max_stack: u16,
@@ -149,6 +338,23 @@ pub struct Code {
     pub attrs: Vec<Type>,
 }
 
+impl Code {
+    /// Source line of the instruction at `pc` according to the
+    /// LineNumberTable, i.e. the entry with the greatest start_pc <= pc.
+    pub fn line_number(&self, pc: u16) -> Option<u16> {
+        self.attrs
+            .iter()
+            .filter_map(|a| match a {
+                Type::LineNumberTable { tables } => Some(tables),
+                _ => None,
+            })
+            .flat_map(|tables| tables.iter())
+            .filter(|l| l.start_pc <= pc)
+            .max_by_key(|l| l.start_pc)
+            .map(|l| l.number)
+    }
+}
+
 #[derive(Debug)]
 pub struct CodeException {
     pub start_pc: u16,
@@ -225,6 +431,7 @@ pub struct LocalVariable {
 
 #[derive(Debug)]
 pub struct AnnotationEntry {
+    pub type_index: u16,
     pub type_name: Arc<Vec<u8>>,
     pub pairs: Vec<ElementValuePair>,
 }
@@ -263,6 +470,14 @@ pub struct MethodParameter {
     pub acc_flags: u16,
 }
 
+/// One component of a `Record` attribute, spec 4.7.30.
+#[derive(Debug)]
+pub struct RecordComponent {
+    pub name_index: u16,
+    pub descriptor_index: u16,
+    pub attrs: Vec<Type>,
+}
+
 #[derive(Debug)]
 pub struct BootstrapMethod {
     pub method_ref: u16,
@@ -271,6 +486,7 @@ pub struct BootstrapMethod {
 
 #[derive(Debug)]
 pub struct TypeAnnotation {
+    pub target_type: u8,
     pub target_info: TargetInfo,
     pub target_path: Vec<TypePath>,
     pub type_index: u16,
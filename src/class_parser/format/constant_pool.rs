@@ -1,6 +1,7 @@
+use std::convert::TryFrom;
 use std::sync::Arc;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Type {
     Nop,
     Class {
@@ -51,6 +52,16 @@ pub enum Type {
         bootstrap_method_attr_index: u16,
         name_and_type_index: u16,
     },
+    Dynamic {
+        bootstrap_method_attr_index: u16,
+        name_and_type_index: u16,
+    },
+    Module {
+        name_index: u16,
+    },
+    Package {
+        name_index: u16,
+    },
     Unknown,
 }
 
@@ -70,11 +81,16 @@ pub enum Tag {
     MethodHandle,
     MethodType,
     InvokeDynamic,
+    Dynamic,
+    Module,
+    Package,
 }
 
-impl From<u8> for Tag {
-    fn from(tag: u8) -> Self {
-        match tag {
+impl TryFrom<u8> for Tag {
+    type Error = u8;
+
+    fn try_from(tag: u8) -> Result<Self, u8> {
+        let tag = match tag {
             7 => Tag::Class,
             9 => Tag::FieldRef,
             10 => Tag::MethodRef,
@@ -89,7 +105,23 @@ impl From<u8> for Tag {
             15 => Tag::MethodHandle,
             16 => Tag::MethodType,
             18 => Tag::InvokeDynamic,
-            _ => unreachable!(),
+            17 => Tag::Dynamic,
+            19 => Tag::Module,
+            20 => Tag::Package,
+            _ => return Err(tag),
+        };
+        Ok(tag)
+    }
+}
+
+impl Tag {
+    /// The first class file major version the tag is valid in, spec 4.4
+    pub fn since(self) -> u16 {
+        match self {
+            Tag::MethodHandle | Tag::MethodType | Tag::InvokeDynamic => 51,
+            Tag::Module | Tag::Package => 53,
+            Tag::Dynamic => 55,
+            _ => 45,
         }
     }
 }
@@ -107,3 +139,71 @@ pub fn get_class_name(cp: &Arc<Vec<Type>>, idx: usize) -> Option<Arc<Vec<u8>>> {
         _ => None,
     }
 }
+
+// spec 4.4.7: modified UTF-8 encodes NUL as two bytes and supplementary
+// characters as surrogate pairs
+pub fn encode_modified_utf8(s: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(s.len());
+    for unit in s.encode_utf16() {
+        match unit {
+            0x01..=0x7F => out.push(unit as u8),
+            0x00 | 0x80..=0x7FF => {
+                out.push(0xC0 | (unit >> 6) as u8);
+                out.push(0x80 | (unit & 0x3F) as u8);
+            }
+            _ => {
+                out.push(0xE0 | (unit >> 12) as u8);
+                out.push(0x80 | ((unit >> 6) & 0x3F) as u8);
+                out.push(0x80 | (unit & 0x3F) as u8);
+            }
+        }
+    }
+    out
+}
+
+/// Decodes modified UTF-8 into UTF-16 code units, the form Java strings
+/// have at runtime. Malformed sequences become U+FFFD.
+pub fn decode_modified_utf8(bytes: &[u8]) -> Vec<u16> {
+    let mut out = Vec::with_capacity(bytes.len());
+    let cont = |i: usize| {
+        bytes
+            .get(i)
+            .filter(|b| *b & 0xC0 == 0x80)
+            .map(|b| (b & 0x3F) as u16)
+    };
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        match b {
+            0x00..=0x7F => {
+                out.push(b as u16);
+                i += 1;
+            }
+            0xC0..=0xDF => match cont(i + 1) {
+                Some(c) => {
+                    out.push(((b & 0x1F) as u16) << 6 | c);
+                    i += 2;
+                }
+                None => {
+                    out.push(0xFFFD);
+                    i += 1;
+                }
+            },
+            0xE0..=0xEF => match (cont(i + 1), cont(i + 2)) {
+                (Some(c1), Some(c2)) => {
+                    out.push(((b & 0x0F) as u16) << 12 | c1 << 6 | c2);
+                    i += 3;
+                }
+                _ => {
+                    out.push(0xFFFD);
+                    i += 1;
+                }
+            },
+            _ => {
+                out.push(0xFFFD);
+                i += 1;
+            }
+        }
+    }
+    out
+}
@@ -1,5 +1,100 @@
-#[derive(Debug)]
+use std::cmp::Ordering;
+use std::fmt;
+
+/// minor version of classes compiled with `--enable-preview`, spec 4.1
+pub const PREVIEW_MINOR: u16 = 0xFFFF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Version {
     pub minor: u16,
     pub major: u16,
 }
+
+impl Version {
+    pub const JAVA_1_1: Version = Version::new(45, 3);
+    pub const JAVA_1_2: Version = Version::new(46, 0);
+    pub const JAVA_1_3: Version = Version::new(47, 0);
+    pub const JAVA_1_4: Version = Version::new(48, 0);
+    pub const JAVA_5: Version = Version::new(49, 0);
+    pub const JAVA_6: Version = Version::new(50, 0);
+    pub const JAVA_7: Version = Version::new(51, 0);
+    pub const JAVA_8: Version = Version::new(52, 0);
+    pub const JAVA_9: Version = Version::new(53, 0);
+    pub const JAVA_10: Version = Version::new(54, 0);
+    pub const JAVA_11: Version = Version::new(55, 0);
+    pub const JAVA_12: Version = Version::new(56, 0);
+    pub const JAVA_13: Version = Version::new(57, 0);
+    pub const JAVA_14: Version = Version::new(58, 0);
+    pub const JAVA_15: Version = Version::new(59, 0);
+    pub const JAVA_16: Version = Version::new(60, 0);
+    pub const JAVA_17: Version = Version::new(61, 0);
+    pub const JAVA_18: Version = Version::new(62, 0);
+    pub const JAVA_19: Version = Version::new(63, 0);
+    pub const JAVA_20: Version = Version::new(64, 0);
+    pub const JAVA_21: Version = Version::new(65, 0);
+    pub const JAVA_22: Version = Version::new(66, 0);
+    pub const JAVA_23: Version = Version::new(67, 0);
+    pub const JAVA_24: Version = Version::new(68, 0);
+    pub const JAVA_25: Version = Version::new(69, 0);
+
+    /// The newest release whose class files are accepted by default.
+    pub const LATEST: Version = Version::JAVA_25;
+
+    pub const fn new(major: u16, minor: u16) -> Self {
+        Self { minor, major }
+    }
+
+    /// The class file version javac emits for `--release n`, where 1 to 4
+    /// stand for 1.1 to 1.4.
+    pub fn of_release(release: u16) -> Option<Version> {
+        match release {
+            0 => None,
+            1 => Some(Version::JAVA_1_1),
+            n => n.checked_add(44).map(|major| Version::new(major, 0)),
+        }
+    }
+
+    /// The feature release that introduced this version, 1 for 1.1 (and
+    /// 1.0, which shares major 45), 8 for Java 8 and so on.
+    pub fn release(&self) -> Option<u16> {
+        self.major.checked_sub(44).filter(|r| *r > 0)
+    }
+
+    /// `1.4`, `8`, `21`
+    pub fn release_name(&self) -> Option<String> {
+        self.release().map(|r| match r {
+            1..=4 => format!("1.{}", r),
+            r => r.to_string(),
+        })
+    }
+
+    /// Preview features are only valid from Java 12 on, and only in the
+    /// release they were compiled for.
+    pub fn is_preview(&self) -> bool {
+        self.minor == PREVIEW_MINOR && self.major >= Version::JAVA_12.major
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor).cmp(&(other.major, other.minor))
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// `52.0 (Java 8)`, `65.65535 (Java 21 preview)`
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)?;
+        if let Some(name) = self.release_name() {
+            let preview = if self.is_preview() { " preview" } else { "" };
+            write!(f, " (Java {}{})", name, preview)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,464 @@
+//! `metadata.proto` messages into the `Km*` model. Field numbers follow the
+//! descriptor in the Kotlin repository.
+
+use super::proto::{Reader, Strings, LEN, VARINT};
+use super::{
+    Error, KmClass, KmClassifier, KmConstructor, KmFunction, KmPackage, KmProperty, KmType,
+    KmTypeAlias, KmTypeParameter, KmTypeProjection, KmValueParameter, Variance,
+};
+
+use std::convert::TryFrom;
+
+// types nest through arguments, bounds and the type table
+const MAX_TYPE_DEPTH: usize = 64;
+
+const TYPE_TABLE: u32 = 30;
+
+fn malformed(message: impl Into<String>) -> Error {
+    Error::Malformed(message.into())
+}
+
+// flags of old compilers, which had two more bits after modality
+fn old_flags(flags: i32) -> u32 {
+    let flags = flags as u32;
+    (flags & 0x3F) + ((flags >> 8) << 6)
+}
+
+fn variance(v: i32) -> Variance {
+    match v {
+        0 => Variance::In,
+        1 => Variance::Out,
+        _ => Variance::Invariant,
+    }
+}
+
+/// Every occurrence of the length delimited field `number` in `data`.
+fn messages(data: &[u8], number: u32) -> Result<Vec<&[u8]>, Error> {
+    let mut out = vec![];
+    let mut r = Reader::new(data);
+    while let Some((field, wire)) = r.field()? {
+        if field == number && wire == LEN {
+            out.push(r.bytes()?);
+        } else {
+            r.skip(wire)?;
+        }
+    }
+    Ok(out)
+}
+
+/// What a type can refer to: the type table of the enclosing declaration
+/// and the type parameters in scope, by id.
+#[derive(Clone, Default)]
+struct Scope<'a> {
+    types: Vec<&'a [u8]>,
+    first_nullable: Option<usize>,
+    type_parameters: Vec<(i32, String)>,
+}
+
+pub(super) struct Decoder<'a> {
+    strings: &'a Strings,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(strings: &'a Strings) -> Self {
+        Self { strings }
+    }
+
+    /// `parent` extended with the type table of `data` (field 30), which
+    /// replaces the parent's, and with the type parameters in field
+    /// `type_parameters`.
+    fn scope<'d>(
+        &self,
+        parent: &Scope<'d>,
+        data: &'d [u8],
+        type_parameters: Option<u32>,
+    ) -> Result<Scope<'d>, Error> {
+        let mut scope = parent.clone();
+        if let Some(table) = messages(data, TYPE_TABLE)?.pop() {
+            scope.types.clear();
+            scope.first_nullable = None;
+            let mut r = Reader::new(table);
+            while let Some((field, wire)) = r.field()? {
+                match (field, wire) {
+                    (1, LEN) => scope.types.push(r.bytes()?),
+                    // -1 when no type is nullable
+                    (2, VARINT) => scope.first_nullable = usize::try_from(r.int()?).ok(),
+                    _ => r.skip(wire)?,
+                }
+            }
+        }
+        let tps = match type_parameters {
+            Some(number) => messages(data, number)?,
+            None => vec![],
+        };
+        for tp in tps {
+            let (mut id, mut name) = (None, None);
+            let mut r = Reader::new(tp);
+            while let Some((field, wire)) = r.field()? {
+                match (field, wire) {
+                    (1, VARINT) => id = Some(r.int()?),
+                    (2, VARINT) => name = Some(self.strings.get(r.int()?)?),
+                    _ => r.skip(wire)?,
+                }
+            }
+            if let (Some(id), Some(name)) = (id, name) {
+                scope.type_parameters.push((id, name));
+            }
+        }
+        Ok(scope)
+    }
+
+    pub fn class(&self, data: &[u8]) -> Result<KmClass, Error> {
+        let scope = self.scope(&Scope::default(), data, Some(5))?;
+        let mut class = KmClass {
+            flags: 6,
+            name: String::new(),
+            type_parameters: vec![],
+            supertypes: vec![],
+            constructors: vec![],
+            functions: vec![],
+            properties: vec![],
+            type_aliases: vec![],
+            nested_classes: vec![],
+            enum_entries: vec![],
+            sealed_subclasses: vec![],
+            companion_object: None,
+        };
+        let mut name = None;
+        let mut supertype_ids = vec![];
+        let mut nested = vec![];
+        let mut sealed = vec![];
+        let mut r = Reader::new(data);
+        while let Some((field, wire)) = r.field()? {
+            match (field, wire) {
+                (1, VARINT) => class.flags = r.int()? as u32,
+                (2, _) => r.ints(wire, &mut supertype_ids)?,
+                (3, VARINT) => name = Some(self.strings.class_name(r.int()?)?),
+                (4, VARINT) => class.companion_object = Some(self.strings.get(r.int()?)?),
+                (5, LEN) => class
+                    .type_parameters
+                    .push(self.type_parameter(r.bytes()?, &scope)?),
+                (6, LEN) => class.supertypes.push(self.type_(r.bytes()?, &scope, 0)?),
+                (7, _) => r.ints(wire, &mut nested)?,
+                (8, LEN) => class
+                    .constructors
+                    .push(self.constructor(r.bytes()?, &scope)?),
+                (9, LEN) => class.functions.push(self.function(r.bytes()?, &scope)?),
+                (10, LEN) => class.properties.push(self.property(r.bytes()?, &scope)?),
+                (11, LEN) => class
+                    .type_aliases
+                    .push(self.type_alias(r.bytes()?, &scope)?),
+                (13, LEN) => {
+                    let mut entry = Reader::new(r.bytes()?);
+                    while let Some((field, wire)) = entry.field()? {
+                        match (field, wire) {
+                            (1, VARINT) => class.enum_entries.push(self.strings.get(entry.int()?)?),
+                            _ => entry.skip(wire)?,
+                        }
+                    }
+                }
+                (16, _) => r.ints(wire, &mut sealed)?,
+                _ => r.skip(wire)?,
+            }
+        }
+        class.name = name.ok_or_else(|| malformed("class without a name"))?;
+        for id in supertype_ids {
+            class.supertypes.push(self.type_id(id, &scope, 0)?);
+        }
+        for n in nested {
+            class.nested_classes.push(self.strings.get(n)?);
+        }
+        for n in sealed {
+            class.sealed_subclasses.push(self.strings.class_name(n)?);
+        }
+        Ok(class)
+    }
+
+    pub fn package(&self, data: &[u8]) -> Result<KmPackage, Error> {
+        let scope = self.scope(&Scope::default(), data, None)?;
+        let mut package = KmPackage::default();
+        let mut r = Reader::new(data);
+        while let Some((field, wire)) = r.field()? {
+            match (field, wire) {
+                (3, LEN) => package.functions.push(self.function(r.bytes()?, &scope)?),
+                (4, LEN) => package.properties.push(self.property(r.bytes()?, &scope)?),
+                (5, LEN) => package
+                    .type_aliases
+                    .push(self.type_alias(r.bytes()?, &scope)?),
+                _ => r.skip(wire)?,
+            }
+        }
+        Ok(package)
+    }
+
+    pub fn lambda(&self, data: &[u8]) -> Result<KmFunction, Error> {
+        self.function(data, &Scope::default())
+    }
+
+    fn function<'d>(&self, data: &'d [u8], parent: &Scope<'d>) -> Result<KmFunction, Error> {
+        let scope = self.scope(parent, data, Some(4))?;
+        let (mut flags, mut old) = (None, None);
+        let mut name = None;
+        let mut type_parameters = vec![];
+        let mut receiver = None;
+        let mut parameters = vec![];
+        let mut return_type = None;
+        let mut r = Reader::new(data);
+        while let Some((field, wire)) = r.field()? {
+            match (field, wire) {
+                (1, VARINT) => old = Some(r.int()?),
+                (2, VARINT) => name = Some(self.strings.get(r.int()?)?),
+                (3, LEN) => return_type = Some(self.type_(r.bytes()?, &scope, 0)?),
+                (4, LEN) => type_parameters.push(self.type_parameter(r.bytes()?, &scope)?),
+                (5, LEN) => receiver = Some(self.type_(r.bytes()?, &scope, 0)?),
+                (6, LEN) => parameters.push(self.value_parameter(r.bytes()?, &scope)?),
+                (7, VARINT) => return_type = Some(self.type_id(r.int()?, &scope, 0)?),
+                (8, VARINT) => receiver = Some(self.type_id(r.int()?, &scope, 0)?),
+                (9, VARINT) => flags = Some(r.int()? as u32),
+                _ => r.skip(wire)?,
+            }
+        }
+        Ok(KmFunction {
+            flags: flags.unwrap_or_else(|| old.map_or(6, old_flags)),
+            name: name.ok_or_else(|| malformed("function without a name"))?,
+            type_parameters,
+            receiver,
+            parameters,
+            return_type: return_type.ok_or_else(|| malformed("function without a return type"))?,
+        })
+    }
+
+    fn property<'d>(&self, data: &'d [u8], parent: &Scope<'d>) -> Result<KmProperty, Error> {
+        let scope = self.scope(parent, data, Some(4))?;
+        let (mut flags, mut old) = (None, None);
+        let mut name = None;
+        let mut type_parameters = vec![];
+        let mut receiver = None;
+        let mut return_type = None;
+        let mut setter_parameter = None;
+        let (mut getter_flags, mut setter_flags) = (None, None);
+        let mut r = Reader::new(data);
+        while let Some((field, wire)) = r.field()? {
+            match (field, wire) {
+                (1, VARINT) => old = Some(r.int()?),
+                (2, VARINT) => name = Some(self.strings.get(r.int()?)?),
+                (3, LEN) => return_type = Some(self.type_(r.bytes()?, &scope, 0)?),
+                (4, LEN) => type_parameters.push(self.type_parameter(r.bytes()?, &scope)?),
+                (5, LEN) => receiver = Some(self.type_(r.bytes()?, &scope, 0)?),
+                (6, LEN) => setter_parameter = Some(self.value_parameter(r.bytes()?, &scope)?),
+                (7, VARINT) => getter_flags = Some(r.int()? as u32),
+                (8, VARINT) => setter_flags = Some(r.int()? as u32),
+                (9, VARINT) => return_type = Some(self.type_id(r.int()?, &scope, 0)?),
+                (10, VARINT) => receiver = Some(self.type_id(r.int()?, &scope, 0)?),
+                (11, VARINT) => flags = Some(r.int()? as u32),
+                _ => r.skip(wire)?,
+            }
+        }
+        let flags = flags.unwrap_or_else(|| old.map_or(518, old_flags));
+        // accessors default to the property's annotations, visibility and
+        // modality
+        let accessor = flags & 0x3F;
+        Ok(KmProperty {
+            flags,
+            name: name.ok_or_else(|| malformed("property without a name"))?,
+            type_parameters,
+            receiver,
+            return_type: return_type.ok_or_else(|| malformed("property without a type"))?,
+            setter_parameter,
+            getter_flags: getter_flags.unwrap_or(accessor),
+            setter_flags: setter_flags.unwrap_or(accessor),
+        })
+    }
+
+    fn constructor(&self, data: &[u8], scope: &Scope) -> Result<KmConstructor, Error> {
+        let mut constructor = KmConstructor {
+            flags: 6,
+            parameters: vec![],
+        };
+        let mut r = Reader::new(data);
+        while let Some((field, wire)) = r.field()? {
+            match (field, wire) {
+                (1, VARINT) => constructor.flags = r.int()? as u32,
+                (2, LEN) => constructor
+                    .parameters
+                    .push(self.value_parameter(r.bytes()?, scope)?),
+                _ => r.skip(wire)?,
+            }
+        }
+        Ok(constructor)
+    }
+
+    fn value_parameter(&self, data: &[u8], scope: &Scope) -> Result<KmValueParameter, Error> {
+        let mut flags = 0;
+        let mut name = None;
+        let mut ty = None;
+        let mut vararg_element = None;
+        let mut r = Reader::new(data);
+        while let Some((field, wire)) = r.field()? {
+            match (field, wire) {
+                (1, VARINT) => flags = r.int()? as u32,
+                (2, VARINT) => name = Some(self.strings.get(r.int()?)?),
+                (3, LEN) => ty = Some(self.type_(r.bytes()?, scope, 0)?),
+                (4, LEN) => vararg_element = Some(self.type_(r.bytes()?, scope, 0)?),
+                (5, VARINT) => ty = Some(self.type_id(r.int()?, scope, 0)?),
+                (6, VARINT) => vararg_element = Some(self.type_id(r.int()?, scope, 0)?),
+                _ => r.skip(wire)?,
+            }
+        }
+        Ok(KmValueParameter {
+            flags,
+            name: name.ok_or_else(|| malformed("parameter without a name"))?,
+            ty: ty.ok_or_else(|| malformed("parameter without a type"))?,
+            vararg_element,
+        })
+    }
+
+    fn type_parameter(&self, data: &[u8], scope: &Scope) -> Result<KmTypeParameter, Error> {
+        let mut tp = KmTypeParameter {
+            id: 0,
+            name: String::new(),
+            variance: Variance::Invariant,
+            reified: false,
+            upper_bounds: vec![],
+        };
+        let mut bound_ids = vec![];
+        let mut r = Reader::new(data);
+        while let Some((field, wire)) = r.field()? {
+            match (field, wire) {
+                (1, VARINT) => tp.id = r.int()?,
+                (2, VARINT) => tp.name = self.strings.get(r.int()?)?,
+                (3, VARINT) => tp.reified = r.bool()?,
+                (4, VARINT) => tp.variance = variance(r.int()?),
+                (5, LEN) => tp.upper_bounds.push(self.type_(r.bytes()?, scope, 0)?),
+                (6, _) => r.ints(wire, &mut bound_ids)?,
+                _ => r.skip(wire)?,
+            }
+        }
+        for id in bound_ids {
+            tp.upper_bounds.push(self.type_id(id, scope, 0)?);
+        }
+        Ok(tp)
+    }
+
+    fn type_alias<'d>(&self, data: &'d [u8], parent: &Scope<'d>) -> Result<KmTypeAlias, Error> {
+        let scope = self.scope(parent, data, Some(3))?;
+        let mut flags = 6;
+        let mut name = None;
+        let mut type_parameters = vec![];
+        let (mut underlying, mut expanded) = (None, None);
+        let mut r = Reader::new(data);
+        while let Some((field, wire)) = r.field()? {
+            match (field, wire) {
+                (1, VARINT) => flags = r.int()? as u32,
+                (2, VARINT) => name = Some(self.strings.get(r.int()?)?),
+                (3, LEN) => type_parameters.push(self.type_parameter(r.bytes()?, &scope)?),
+                (4, LEN) => underlying = Some(self.type_(r.bytes()?, &scope, 0)?),
+                (5, VARINT) => underlying = Some(self.type_id(r.int()?, &scope, 0)?),
+                (6, LEN) => expanded = Some(self.type_(r.bytes()?, &scope, 0)?),
+                (7, VARINT) => expanded = Some(self.type_id(r.int()?, &scope, 0)?),
+                _ => r.skip(wire)?,
+            }
+        }
+        Ok(KmTypeAlias {
+            flags,
+            name: name.ok_or_else(|| malformed("type alias without a name"))?,
+            type_parameters,
+            underlying: underlying.ok_or_else(|| malformed("type alias without a type"))?,
+            expanded: expanded.ok_or_else(|| malformed("type alias without an expansion"))?,
+        })
+    }
+
+    fn type_id(&self, id: i32, scope: &Scope, depth: usize) -> Result<KmType, Error> {
+        let i = usize::try_from(id).map_err(|_| malformed("negative type id"))?;
+        let data = scope
+            .types
+            .get(i)
+            .ok_or_else(|| malformed(format!("type id {} not in the type table", id)))?;
+        let mut ty = self.type_(data, scope, depth + 1)?;
+        if scope.first_nullable.is_some_and(|first| i >= first) {
+            ty.nullable = true;
+        }
+        Ok(ty)
+    }
+
+    fn type_(&self, data: &[u8], scope: &Scope, depth: usize) -> Result<KmType, Error> {
+        if depth > MAX_TYPE_DEPTH {
+            return Err(malformed("types nested too deeply"));
+        }
+        let mut flags = 0;
+        let mut classifier = None;
+        let mut arguments = vec![];
+        let mut nullable = false;
+        let mut flexible_upper_bound = None;
+        let mut r = Reader::new(data);
+        while let Some((field, wire)) = r.field()? {
+            match (field, wire) {
+                (1, VARINT) => flags = r.int()? as u32,
+                (2, LEN) => arguments.push(self.argument(r.bytes()?, scope, depth)?),
+                (3, VARINT) => nullable = r.bool()?,
+                (5, LEN) => {
+                    flexible_upper_bound =
+                        Some(Box::new(self.type_(r.bytes()?, scope, depth + 1)?))
+                }
+                (6, VARINT) => {
+                    classifier = Some(KmClassifier::Class(self.strings.class_name(r.int()?)?))
+                }
+                (7, VARINT) => {
+                    let id = r.int()?;
+                    // inner and local classes use those of their outer class,
+                    // whose metadata is elsewhere
+                    let name = scope
+                        .type_parameters
+                        .iter()
+                        .rev()
+                        .find(|(i, _)| *i == id)
+                        .map_or_else(|| format!("#{}", id), |(_, name)| name.clone());
+                    classifier = Some(KmClassifier::TypeParameter(name))
+                }
+                (8, VARINT) => {
+                    flexible_upper_bound = Some(Box::new(self.type_id(r.int()?, scope, depth)?))
+                }
+                (9, VARINT) => {
+                    classifier = Some(KmClassifier::TypeParameter(self.strings.get(r.int()?)?))
+                }
+                (12, VARINT) => {
+                    classifier = Some(KmClassifier::TypeAlias(self.strings.class_name(r.int()?)?))
+                }
+                _ => r.skip(wire)?,
+            }
+        }
+        Ok(KmType {
+            flags,
+            classifier: classifier.ok_or_else(|| malformed("type without a classifier"))?,
+            arguments,
+            nullable,
+            flexible_upper_bound,
+        })
+    }
+
+    fn argument(
+        &self,
+        data: &[u8],
+        scope: &Scope,
+        depth: usize,
+    ) -> Result<KmTypeProjection, Error> {
+        // INV unless given
+        let mut projection = 2;
+        let mut ty = None;
+        let mut r = Reader::new(data);
+        while let Some((field, wire)) = r.field()? {
+            match (field, wire) {
+                (1, VARINT) => projection = r.int()?,
+                (2, LEN) => ty = Some(self.type_(r.bytes()?, scope, depth + 1)?),
+                (3, VARINT) => ty = Some(self.type_id(r.int()?, scope, depth)?),
+                _ => r.skip(wire)?,
+            }
+        }
+        // STAR
+        if projection == 3 {
+            return Ok(KmTypeProjection::Star);
+        }
+        let ty = ty.ok_or_else(|| malformed("type argument without a type"))?;
+        Ok(KmTypeProjection::Type(variance(projection), ty))
+    }
+}
@@ -0,0 +1,896 @@
+//! Kotlin declarations as recorded by kotlinc in the `kotlin.Metadata`
+//! annotation.
+//!
+//! The class file only shows what the JVM sees; nullability, properties,
+//! extension receivers, data classes and the like live in the annotation's
+//! `d1` element, a protobuf message spread over strings, whose names are
+//! indices into `d2`. [`KotlinMetadata::from_class`] decodes them into the
+//! `Km*` model, and [`KotlinMetadata::public_api`] renders the declarations
+//! other modules can see.
+
+mod decode;
+mod proto;
+
+use super::format::{
+    attributes::{AnnotationEntry, ElementValueType, Type as AttributeType},
+    class_file::ClassFile,
+    constant_pool::{self, Type as ConstantType},
+};
+
+use std::fmt;
+use std::sync::Arc;
+
+pub const METADATA_DESCRIPTOR: &[u8] = b"Lkotlin/Metadata;";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// an element of the annotation has an unexpected type
+    BadElement(&'static str),
+    /// `d1` doesn't decode as the message its kind calls for
+    Malformed(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::BadElement(name) => write!(f, "kotlin.Metadata: bad element {}", name),
+            Error::Malformed(message) => write!(f, "kotlin.Metadata: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KotlinMetadata {
+    /// `mv`, the version of the metadata format
+    pub version: Vec<i32>,
+    /// `xi`
+    pub extra_int: i32,
+    /// `xs`, the facade class of a multi-file class part
+    pub extra_string: Option<String>,
+    /// `pn`, the Kotlin package when it differs from the JVM one
+    pub package_name: Option<String>,
+    pub content: Content,
+}
+
+/// What the class is, from the `k` element.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Content {
+    Class(KmClass),
+    /// top level declarations of `Foo.kt`, compiled into `FooKt`
+    File(KmPackage),
+    /// lambdas carry their function; other compiler generated classes nothing
+    Synthetic(Option<KmFunction>),
+    /// a `@JvmMultifileClass` facade, with the internal names of its parts
+    MultiFileFacade(Vec<String>),
+    MultiFilePart(KmPackage),
+    Unknown(i32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visibility {
+    Internal,
+    Private,
+    Protected,
+    Public,
+    PrivateToThis,
+    Local,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Modality {
+    Final,
+    Open,
+    Abstract,
+    Sealed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClassKind {
+    Class,
+    Interface,
+    EnumClass,
+    EnumEntry,
+    AnnotationClass,
+    Object,
+    CompanionObject,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemberKind {
+    Declaration,
+    FakeOverride,
+    Delegation,
+    Synthesized,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variance {
+    In,
+    Out,
+    Invariant,
+}
+
+// Flags.kt: every declaration starts with has_annotations, visibility and,
+// except constructors, modality; the rest depends on the declaration
+fn bit(flags: u32, n: u32) -> bool {
+    flags & (1 << n) != 0
+}
+
+fn visibility(flags: u32) -> Visibility {
+    match (flags >> 1) & 7 {
+        0 => Visibility::Internal,
+        1 => Visibility::Private,
+        2 => Visibility::Protected,
+        3 => Visibility::Public,
+        4 => Visibility::PrivateToThis,
+        _ => Visibility::Local,
+    }
+}
+
+fn modality(flags: u32) -> Modality {
+    match (flags >> 4) & 3 {
+        0 => Modality::Final,
+        1 => Modality::Open,
+        2 => Modality::Abstract,
+        _ => Modality::Sealed,
+    }
+}
+
+fn member_kind(flags: u32) -> MemberKind {
+    match (flags >> 6) & 3 {
+        0 => MemberKind::Declaration,
+        1 => MemberKind::FakeOverride,
+        2 => MemberKind::Delegation,
+        _ => MemberKind::Synthesized,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KmClass {
+    pub flags: u32,
+    /// e.g. `kotlin/collections/Map.Entry`
+    pub name: String,
+    pub type_parameters: Vec<KmTypeParameter>,
+    pub supertypes: Vec<KmType>,
+    pub constructors: Vec<KmConstructor>,
+    pub functions: Vec<KmFunction>,
+    pub properties: Vec<KmProperty>,
+    pub type_aliases: Vec<KmTypeAlias>,
+    /// simple names
+    pub nested_classes: Vec<String>,
+    pub enum_entries: Vec<String>,
+    pub sealed_subclasses: Vec<String>,
+    /// simple name of the companion object
+    pub companion_object: Option<String>,
+}
+
+impl KmClass {
+    pub fn visibility(&self) -> Visibility {
+        visibility(self.flags)
+    }
+
+    pub fn modality(&self) -> Modality {
+        modality(self.flags)
+    }
+
+    pub fn kind(&self) -> ClassKind {
+        match (self.flags >> 6) & 7 {
+            0 => ClassKind::Class,
+            1 => ClassKind::Interface,
+            2 => ClassKind::EnumClass,
+            3 => ClassKind::EnumEntry,
+            4 => ClassKind::AnnotationClass,
+            5 => ClassKind::Object,
+            _ => ClassKind::CompanionObject,
+        }
+    }
+
+    pub fn is_inner(&self) -> bool {
+        bit(self.flags, 9)
+    }
+
+    pub fn is_data(&self) -> bool {
+        bit(self.flags, 10)
+    }
+
+    pub fn is_external(&self) -> bool {
+        bit(self.flags, 11)
+    }
+
+    pub fn is_expect(&self) -> bool {
+        bit(self.flags, 12)
+    }
+
+    /// `value class`, formerly `inline class`
+    pub fn is_value(&self) -> bool {
+        bit(self.flags, 13)
+    }
+
+    pub fn is_fun_interface(&self) -> bool {
+        bit(self.flags, 14)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KmPackage {
+    pub functions: Vec<KmFunction>,
+    pub properties: Vec<KmProperty>,
+    pub type_aliases: Vec<KmTypeAlias>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KmFunction {
+    pub flags: u32,
+    pub name: String,
+    pub type_parameters: Vec<KmTypeParameter>,
+    /// set for extension functions
+    pub receiver: Option<KmType>,
+    pub parameters: Vec<KmValueParameter>,
+    pub return_type: KmType,
+}
+
+impl KmFunction {
+    pub fn visibility(&self) -> Visibility {
+        visibility(self.flags)
+    }
+
+    pub fn modality(&self) -> Modality {
+        modality(self.flags)
+    }
+
+    pub fn member_kind(&self) -> MemberKind {
+        member_kind(self.flags)
+    }
+
+    pub fn is_extension(&self) -> bool {
+        self.receiver.is_some()
+    }
+
+    pub fn is_operator(&self) -> bool {
+        bit(self.flags, 8)
+    }
+
+    pub fn is_infix(&self) -> bool {
+        bit(self.flags, 9)
+    }
+
+    pub fn is_inline(&self) -> bool {
+        bit(self.flags, 10)
+    }
+
+    pub fn is_tailrec(&self) -> bool {
+        bit(self.flags, 11)
+    }
+
+    pub fn is_external(&self) -> bool {
+        bit(self.flags, 12)
+    }
+
+    pub fn is_suspend(&self) -> bool {
+        bit(self.flags, 13)
+    }
+
+    pub fn is_expect(&self) -> bool {
+        bit(self.flags, 14)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KmProperty {
+    pub flags: u32,
+    pub name: String,
+    pub type_parameters: Vec<KmTypeParameter>,
+    /// set for extension properties
+    pub receiver: Option<KmType>,
+    pub return_type: KmType,
+    pub setter_parameter: Option<KmValueParameter>,
+    pub getter_flags: u32,
+    pub setter_flags: u32,
+}
+
+impl KmProperty {
+    pub fn visibility(&self) -> Visibility {
+        visibility(self.flags)
+    }
+
+    pub fn modality(&self) -> Modality {
+        modality(self.flags)
+    }
+
+    pub fn member_kind(&self) -> MemberKind {
+        member_kind(self.flags)
+    }
+
+    pub fn is_extension(&self) -> bool {
+        self.receiver.is_some()
+    }
+
+    pub fn is_var(&self) -> bool {
+        bit(self.flags, 8)
+    }
+
+    pub fn has_getter(&self) -> bool {
+        bit(self.flags, 9)
+    }
+
+    pub fn has_setter(&self) -> bool {
+        bit(self.flags, 10)
+    }
+
+    pub fn is_const(&self) -> bool {
+        bit(self.flags, 11)
+    }
+
+    pub fn is_lateinit(&self) -> bool {
+        bit(self.flags, 12)
+    }
+
+    pub fn has_constant(&self) -> bool {
+        bit(self.flags, 13)
+    }
+
+    pub fn is_external(&self) -> bool {
+        bit(self.flags, 14)
+    }
+
+    pub fn is_delegated(&self) -> bool {
+        bit(self.flags, 15)
+    }
+
+    pub fn is_expect(&self) -> bool {
+        bit(self.flags, 16)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KmConstructor {
+    pub flags: u32,
+    pub parameters: Vec<KmValueParameter>,
+}
+
+impl KmConstructor {
+    pub fn visibility(&self) -> Visibility {
+        visibility(self.flags)
+    }
+
+    pub fn is_secondary(&self) -> bool {
+        bit(self.flags, 4)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KmValueParameter {
+    pub flags: u32,
+    pub name: String,
+    /// for `vararg x: T` this is `Array<out T>`
+    pub ty: KmType,
+    pub vararg_element: Option<KmType>,
+}
+
+impl KmValueParameter {
+    pub fn declares_default_value(&self) -> bool {
+        bit(self.flags, 1)
+    }
+
+    pub fn is_crossinline(&self) -> bool {
+        bit(self.flags, 2)
+    }
+
+    pub fn is_noinline(&self) -> bool {
+        bit(self.flags, 3)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KmTypeParameter {
+    pub id: i32,
+    pub name: String,
+    pub variance: Variance,
+    pub reified: bool,
+    pub upper_bounds: Vec<KmType>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KmTypeAlias {
+    pub flags: u32,
+    pub name: String,
+    pub type_parameters: Vec<KmTypeParameter>,
+    pub underlying: KmType,
+    pub expanded: KmType,
+}
+
+impl KmTypeAlias {
+    pub fn visibility(&self) -> Visibility {
+        visibility(self.flags)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum KmClassifier {
+    Class(String),
+    /// the name, or `#id` for a parameter of an enclosing class
+    TypeParameter(String),
+    TypeAlias(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum KmTypeProjection {
+    Star,
+    Type(Variance, KmType),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KmType {
+    pub flags: u32,
+    pub classifier: KmClassifier,
+    pub arguments: Vec<KmTypeProjection>,
+    pub nullable: bool,
+    /// upper bound of a platform type `T!`, which is `T..T?` in the metadata
+    pub flexible_upper_bound: Option<Box<KmType>>,
+}
+
+impl KmType {
+    /// `suspend () -> Unit` and friends
+    pub fn is_suspend(&self) -> bool {
+        bit(self.flags, 0)
+    }
+
+    pub fn class_name(&self) -> Option<&str> {
+        match &self.classifier {
+            KmClassifier::Class(name) => Some(name),
+            _ => None,
+        }
+    }
+}
+
+// kotlin/collections/Map.Entry -> kotlin.collections.Map.Entry
+fn fq_name(name: &str) -> String {
+    name.replace('/', ".")
+}
+
+impl fmt::Display for Visibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Visibility::Internal => "internal",
+            Visibility::Private | Visibility::PrivateToThis => "private",
+            Visibility::Protected => "protected",
+            Visibility::Public => "public",
+            Visibility::Local => "local",
+        })
+    }
+}
+
+impl fmt::Display for KmType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_suspend() {
+            f.write_str("suspend ")?;
+        }
+        match &self.classifier {
+            KmClassifier::Class(name) | KmClassifier::TypeAlias(name) => {
+                f.write_str(&fq_name(name))?
+            }
+            KmClassifier::TypeParameter(name) => f.write_str(name)?,
+        }
+        if !self.arguments.is_empty() {
+            let args: Vec<String> = self
+                .arguments
+                .iter()
+                .map(|a| match a {
+                    KmTypeProjection::Star => "*".to_string(),
+                    KmTypeProjection::Type(Variance::In, t) => format!("in {}", t),
+                    KmTypeProjection::Type(Variance::Out, t) => format!("out {}", t),
+                    KmTypeProjection::Type(Variance::Invariant, t) => t.to_string(),
+                })
+                .collect();
+            write!(f, "<{}>", args.join(", "))?;
+        }
+        if self.nullable {
+            f.write_str("?")?;
+        }
+        if self.flexible_upper_bound.is_some() {
+            f.write_str("!")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for KmValueParameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_crossinline() {
+            f.write_str("crossinline ")?;
+        }
+        if self.is_noinline() {
+            f.write_str("noinline ")?;
+        }
+        match &self.vararg_element {
+            Some(element) => write!(f, "vararg {}: {}", self.name, element)?,
+            None => write!(f, "{}: {}", self.name, self.ty)?,
+        }
+        if self.declares_default_value() {
+            f.write_str(" = ...")?;
+        }
+        Ok(())
+    }
+}
+
+// `<reified T : Comparable<T>> ` and the `where` clause needed when a
+// parameter has several bounds
+fn type_parameters(tps: &[KmTypeParameter]) -> (String, String) {
+    if tps.is_empty() {
+        return (String::new(), String::new());
+    }
+    let mut heads = vec![];
+    let mut wheres = vec![];
+    for tp in tps.iter() {
+        let mut head = String::new();
+        if tp.reified {
+            head.push_str("reified ");
+        }
+        match tp.variance {
+            Variance::In => head.push_str("in "),
+            Variance::Out => head.push_str("out "),
+            Variance::Invariant => {}
+        }
+        head.push_str(&tp.name);
+        match &tp.upper_bounds[..] {
+            [] => {}
+            [bound] => head.push_str(&format!(" : {}", bound)),
+            bounds => {
+                for bound in bounds.iter() {
+                    wheres.push(format!("{} : {}", tp.name, bound));
+                }
+            }
+        }
+        heads.push(head);
+    }
+    let where_clause = if wheres.is_empty() {
+        String::new()
+    } else {
+        format!(" where {}", wheres.join(", "))
+    };
+    (format!("<{}> ", heads.join(", ")), where_clause)
+}
+
+fn parameters(ps: &[KmValueParameter]) -> String {
+    ps.iter()
+        .map(|p| p.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn modality_prefix(modality: Modality) -> &'static str {
+    match modality {
+        Modality::Final => "",
+        Modality::Open => "open ",
+        Modality::Abstract => "abstract ",
+        Modality::Sealed => "sealed ",
+    }
+}
+
+impl fmt::Display for KmFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}",
+            self.visibility(),
+            modality_prefix(self.modality())
+        )?;
+        for (set, word) in [
+            (self.is_expect(), "expect "),
+            (self.is_external(), "external "),
+            (self.is_tailrec(), "tailrec "),
+            (self.is_suspend(), "suspend "),
+            (self.is_inline(), "inline "),
+            (self.is_infix(), "infix "),
+            (self.is_operator(), "operator "),
+        ] {
+            if set {
+                f.write_str(word)?;
+            }
+        }
+        let (tps, where_clause) = type_parameters(&self.type_parameters);
+        write!(f, "fun {}", tps)?;
+        if let Some(receiver) = &self.receiver {
+            write!(f, "{}.", receiver)?;
+        }
+        write!(
+            f,
+            "{}({}): {}{}",
+            self.name,
+            parameters(&self.parameters),
+            self.return_type,
+            where_clause
+        )
+    }
+}
+
+impl fmt::Display for KmProperty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}",
+            self.visibility(),
+            modality_prefix(self.modality())
+        )?;
+        for (set, word) in [
+            (self.is_expect(), "expect "),
+            (self.is_external(), "external "),
+            (self.is_const(), "const "),
+            (self.is_lateinit(), "lateinit "),
+        ] {
+            if set {
+                f.write_str(word)?;
+            }
+        }
+        let (tps, where_clause) = type_parameters(&self.type_parameters);
+        let keyword = if self.is_var() { "var" } else { "val" };
+        write!(f, "{} {}", keyword, tps)?;
+        if let Some(receiver) = &self.receiver {
+            write!(f, "{}.", receiver)?;
+        }
+        write!(f, "{}: {}{}", self.name, self.return_type, where_clause)?;
+        if self.is_delegated() {
+            f.write_str(" by ...")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for KmConstructor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} constructor({})",
+            self.visibility(),
+            parameters(&self.parameters)
+        )
+    }
+}
+
+impl fmt::Display for KmTypeAlias {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (tps, _) = type_parameters(&self.type_parameters);
+        write!(
+            f,
+            "{} typealias {}{} = {}",
+            self.visibility(),
+            self.name,
+            tps.trim_end(),
+            self.underlying
+        )
+    }
+}
+
+/// The class header only, members are listed by
+/// [`KotlinMetadata::public_api`].
+impl fmt::Display for KmClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = self.kind();
+        write!(f, "{} ", self.visibility())?;
+        // interfaces are abstract and objects final by definition
+        if !matches!(
+            (kind, self.modality()),
+            (ClassKind::Interface, Modality::Abstract)
+                | (ClassKind::AnnotationClass, Modality::Final)
+                | (ClassKind::EnumClass, Modality::Final)
+                | (ClassKind::Object, Modality::Final)
+                | (ClassKind::CompanionObject, Modality::Final)
+        ) {
+            f.write_str(modality_prefix(self.modality()))?;
+        }
+        for (set, word) in [
+            (self.is_expect(), "expect "),
+            (self.is_external(), "external "),
+            (self.is_inner(), "inner "),
+            (self.is_data(), "data "),
+            (self.is_value(), "value "),
+            (self.is_fun_interface(), "fun "),
+        ] {
+            if set {
+                f.write_str(word)?;
+            }
+        }
+        f.write_str(match kind {
+            ClassKind::Class => "class ",
+            ClassKind::Interface => "interface ",
+            ClassKind::EnumClass => "enum class ",
+            ClassKind::EnumEntry => "enum entry ",
+            ClassKind::AnnotationClass => "annotation class ",
+            ClassKind::Object => "object ",
+            ClassKind::CompanionObject => "companion object ",
+        })?;
+        let (tps, where_clause) = type_parameters(&self.type_parameters);
+        write!(f, "{}{}", fq_name(&self.name), tps.trim_end())?;
+        let supertypes: Vec<String> = self
+            .supertypes
+            .iter()
+            .filter(|t| t.class_name() != Some("kotlin/Any"))
+            .map(|t| t.to_string())
+            .collect();
+        if !supertypes.is_empty() {
+            write!(f, " : {}", supertypes.join(", "))?;
+        }
+        f.write_str(&where_clause)
+    }
+}
+
+fn is_api(visibility: Visibility) -> bool {
+    matches!(visibility, Visibility::Public | Visibility::Protected)
+}
+
+fn members_api(
+    type_aliases: &[KmTypeAlias],
+    properties: &[KmProperty],
+    functions: &[KmFunction],
+    indent: &str,
+    out: &mut Vec<String>,
+) {
+    for alias in type_aliases.iter() {
+        if is_api(alias.visibility()) {
+            out.push(format!("{}{}", indent, alias));
+        }
+    }
+    for property in properties.iter() {
+        if is_api(property.visibility()) && property.member_kind() != MemberKind::FakeOverride {
+            out.push(format!("{}{}", indent, property));
+        }
+    }
+    for function in functions.iter() {
+        if is_api(function.visibility()) && function.member_kind() != MemberKind::FakeOverride {
+            out.push(format!("{}{}", indent, function));
+        }
+    }
+}
+
+impl KotlinMetadata {
+    /// The metadata of `cf`, if it was compiled by kotlinc.
+    pub fn from_class(cf: &ClassFile) -> Option<Result<Self, Error>> {
+        cf.attrs.iter().find_map(|a| match a {
+            AttributeType::RuntimeVisibleAnnotations { annotations, .. } => annotations
+                .iter()
+                .find(|a| a.type_name.as_slice() == METADATA_DESCRIPTOR)
+                .map(|a| Self::from_annotation(&cf.cp, a)),
+            _ => None,
+        })
+    }
+
+    pub fn from_annotation(
+        cp: &Arc<Vec<ConstantType>>,
+        annotation: &AnnotationEntry,
+    ) -> Result<Self, Error> {
+        let utf8 = |idx: u16| constant_pool::get_utf8(cp, idx as usize);
+        let int = |v: &ElementValueType| match v {
+            ElementValueType::Int { val_index } => match cp.get(*val_index as usize) {
+                Some(ConstantType::Integer { v }) => Some(i32::from_be_bytes(*v)),
+                _ => None,
+            },
+            _ => None,
+        };
+        let string = |v: &ElementValueType| match v {
+            ElementValueType::String { val_index } => utf8(*val_index),
+            _ => None,
+        };
+        fn array(v: &ElementValueType) -> Option<&[ElementValueType]> {
+            match v {
+                ElementValueType::Array { values } => Some(values),
+                _ => None,
+            }
+        }
+
+        // absent elements take the annotation's defaults
+        let mut kind = 1;
+        let mut version = vec![];
+        let mut d1: Vec<Vec<u16>> = vec![];
+        let mut d2 = vec![];
+        let mut extra_int = 0;
+        let mut extra_string = None;
+        let mut package_name = None;
+        for pair in annotation.pairs.iter() {
+            let name = match utf8(pair.name_index) {
+                Some(name) => name,
+                None => continue,
+            };
+            let v = &pair.value;
+            match name.as_slice() {
+                b"k" => kind = int(v).ok_or(Error::BadElement("k"))?,
+                b"xi" => extra_int = int(v).ok_or(Error::BadElement("xi"))?,
+                b"mv" => {
+                    version = array(v)
+                        .and_then(|vs| vs.iter().map(int).collect())
+                        .ok_or(Error::BadElement("mv"))?
+                }
+                b"d1" => {
+                    d1 = array(v)
+                        .and_then(|vs| vs.iter().map(string).collect::<Option<Vec<_>>>())
+                        .ok_or(Error::BadElement("d1"))?
+                        .iter()
+                        .map(|s| constant_pool::decode_modified_utf8(s))
+                        .collect()
+                }
+                b"d2" => {
+                    d2 = array(v)
+                        .and_then(|vs| vs.iter().map(string).collect::<Option<Vec<_>>>())
+                        .ok_or(Error::BadElement("d2"))?
+                        .iter()
+                        .map(|s| String::from_utf16_lossy(&constant_pool::decode_modified_utf8(s)))
+                        .collect()
+                }
+                b"xs" | b"pn" => {
+                    let s = string(v).ok_or(Error::BadElement("xs"))?;
+                    let s = String::from_utf16_lossy(&constant_pool::decode_modified_utf8(&s));
+                    let s = Some(s).filter(|s| !s.is_empty());
+                    if name.as_slice() == b"xs" {
+                        extra_string = s;
+                    } else {
+                        package_name = s;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let content = match kind {
+            4 => Content::MultiFileFacade(d1.iter().map(|s| String::from_utf16_lossy(s)).collect()),
+            1 | 2 | 3 | 5 => {
+                let bytes = proto::decode_bytes(&d1);
+                if bytes.is_empty() && kind == 3 {
+                    Content::Synthetic(None)
+                } else {
+                    let mut r = proto::Reader::new(&bytes);
+                    let strings = proto::Strings::new(d2, r.delimited()?)?;
+                    let decoder = decode::Decoder::new(&strings);
+                    match kind {
+                        1 => Content::Class(decoder.class(r.rest())?),
+                        2 => Content::File(decoder.package(r.rest())?),
+                        3 => Content::Synthetic(Some(decoder.lambda(r.rest())?)),
+                        _ => Content::MultiFilePart(decoder.package(r.rest())?),
+                    }
+                }
+            }
+            _ => Content::Unknown(kind),
+        };
+
+        Ok(Self {
+            version,
+            extra_int,
+            extra_string,
+            package_name,
+            content,
+        })
+    }
+
+    /// One line per public or protected declaration, Kotlin style, with
+    /// class members indented below the class. Fake overrides are left out,
+    /// as are the members of classes that aren't visible themselves; nested
+    /// classes and companions have metadata of their own.
+    pub fn public_api(&self) -> Vec<String> {
+        let mut out = vec![];
+        match &self.content {
+            Content::Class(class) if is_api(class.visibility()) => {
+                out.push(class.to_string());
+                for entry in class.enum_entries.iter() {
+                    out.push(format!("    {}", entry));
+                }
+                for constructor in class.constructors.iter() {
+                    if is_api(constructor.visibility()) {
+                        out.push(format!("    {}", constructor));
+                    }
+                }
+                members_api(
+                    &class.type_aliases,
+                    &class.properties,
+                    &class.functions,
+                    "    ",
+                    &mut out,
+                );
+            }
+            Content::File(p) | Content::MultiFilePart(p) => {
+                members_api(&p.type_aliases, &p.properties, &p.functions, "", &mut out)
+            }
+            _ => {}
+        }
+        out
+    }
+}
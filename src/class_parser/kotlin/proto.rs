@@ -0,0 +1,325 @@
+//! Just enough of the protobuf wire format, and of the JVM specific string
+//! table, to read what kotlinc writes into `d1`.
+
+use super::Error;
+
+use std::convert::TryFrom;
+
+fn malformed(message: &str) -> Error {
+    Error::Malformed(message.to_string())
+}
+
+pub(super) const VARINT: u8 = 0;
+pub(super) const LEN: u8 = 2;
+
+#[derive(Clone)]
+pub(super) struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    pub fn at_end(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    pub fn varint(&mut self) -> Result<u64, Error> {
+        let mut v = 0u64;
+        let mut shift = 0;
+        loop {
+            let b = *self
+                .data
+                .get(self.pos)
+                .ok_or_else(|| malformed("truncated varint"))?;
+            self.pos += 1;
+            if shift < 64 {
+                v |= ((b & 0x7F) as u64) << shift;
+            }
+            if b & 0x80 == 0 {
+                return Ok(v);
+            }
+            shift += 7;
+            if shift >= 70 {
+                return Err(malformed("varint too long"));
+            }
+        }
+    }
+
+    /// int32 fields are sign extended to 64 bits on the wire
+    pub fn int(&mut self) -> Result<i32, Error> {
+        Ok(self.varint()? as i32)
+    }
+
+    pub fn bool(&mut self) -> Result<bool, Error> {
+        Ok(self.varint()? != 0)
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.varint()? as usize;
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| malformed("length runs past the end of the message"))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    /// The next field number and wire type, None at the end of the message.
+    pub fn field(&mut self) -> Result<Option<(u32, u8)>, Error> {
+        if self.at_end() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        Ok(Some(((key >> 3) as u32, (key & 7) as u8)))
+    }
+
+    pub fn skip(&mut self, wire: u8) -> Result<(), Error> {
+        let n = match wire {
+            VARINT => return self.varint().map(drop),
+            LEN => return self.bytes().map(drop),
+            1 => 8,
+            5 => 4,
+            _ => return Err(malformed(&format!("unsupported wire type {}", wire))),
+        };
+        if self.data.len() - self.pos < n {
+            return Err(malformed("truncated fixed width field"));
+        }
+        self.pos += n;
+        Ok(())
+    }
+
+    /// One element of a repeated int32, which may or may not be packed.
+    pub fn ints(&mut self, wire: u8, out: &mut Vec<i32>) -> Result<(), Error> {
+        if wire == LEN {
+            let mut packed = Reader::new(self.bytes()?);
+            while !packed.at_end() {
+                out.push(packed.int()?);
+            }
+        } else {
+            out.push(self.int()?);
+        }
+        Ok(())
+    }
+
+    /// A varint length prefixed message, as written by `writeDelimitedTo`.
+    pub fn delimited(&mut self) -> Result<&'a [u8], Error> {
+        self.bytes()
+    }
+
+    pub fn rest(&self) -> &'a [u8] {
+        &self.data[self.pos..]
+    }
+}
+
+/// Turns `d1` back into bytes. Newer compilers mark the strings with a
+/// leading NUL and store one byte per char; older ones packed 7 bits per char
+/// with every byte shifted by one so that none is zero, optionally behind a
+/// U+FFFF marker.
+pub(super) fn decode_bytes(d1: &[Vec<u16>]) -> Vec<u8> {
+    let mut units: Vec<u16> = d1.iter().flatten().copied().collect();
+    match units.first() {
+        Some(0) => return units[1..].iter().map(|u| *u as u8).collect(),
+        Some(0xFFFF) => {
+            units.remove(0);
+        }
+        _ => {}
+    }
+    let bytes: Vec<u8> = units
+        .iter()
+        .map(|u| (*u as u8).wrapping_add(0x7F))
+        .collect();
+
+    let len = 7 * bytes.len() / 8;
+    let mut out = Vec::with_capacity(len);
+    let at = |i: usize| bytes.get(i).copied().unwrap_or(0) as u32;
+    let (mut i, mut bit) = (0, 0);
+    for _ in 0..len {
+        let low = at(i) >> bit;
+        i += 1;
+        let high = (at(i) & ((1 << (bit + 1)) - 1)) << (7 - bit);
+        out.push((low + high) as u8);
+        if bit == 6 {
+            i += 1;
+            bit = 0;
+        } else {
+            bit += 1;
+        }
+    }
+    out
+}
+
+// JvmNameResolverBase.PREDEFINED_STRINGS
+const PREDEFINED: [&str; 44] = [
+    "kotlin/Any",
+    "kotlin/Nothing",
+    "kotlin/Unit",
+    "kotlin/Throwable",
+    "kotlin/Number",
+    "kotlin/Byte",
+    "kotlin/Double",
+    "kotlin/Float",
+    "kotlin/Int",
+    "kotlin/Long",
+    "kotlin/Short",
+    "kotlin/Boolean",
+    "kotlin/Char",
+    "kotlin/CharSequence",
+    "kotlin/String",
+    "kotlin/Comparable",
+    "kotlin/Enum",
+    "kotlin/Array",
+    "kotlin/ByteArray",
+    "kotlin/DoubleArray",
+    "kotlin/FloatArray",
+    "kotlin/IntArray",
+    "kotlin/LongArray",
+    "kotlin/ShortArray",
+    "kotlin/BooleanArray",
+    "kotlin/CharArray",
+    "kotlin/Cloneable",
+    "kotlin/Annotation",
+    "kotlin/collections/Iterable",
+    "kotlin/collections/MutableIterable",
+    "kotlin/collections/Collection",
+    "kotlin/collections/MutableCollection",
+    "kotlin/collections/List",
+    "kotlin/collections/MutableList",
+    "kotlin/collections/Set",
+    "kotlin/collections/MutableSet",
+    "kotlin/collections/Map",
+    "kotlin/collections/MutableMap",
+    "kotlin/collections/Map.Entry",
+    "kotlin/collections/MutableMap.MutableEntry",
+    "kotlin/collections/Iterator",
+    "kotlin/collections/MutableIterator",
+    "kotlin/collections/ListIterator",
+    "kotlin/collections/MutableListIterator",
+];
+
+#[derive(Clone, Default)]
+struct Record {
+    predefined_index: Option<i32>,
+    string: Option<String>,
+    operation: i32,
+    substring_index: Vec<i32>,
+    replace_char: Vec<i32>,
+}
+
+/// Resolves the string indices used throughout the metadata: an index picks
+/// a record of the `StringTableTypes` message, which says how to derive the
+/// string from `d2`, a predefined name or an inline string.
+pub(super) struct Strings {
+    d2: Vec<String>,
+    records: Vec<Record>,
+    local_names: Vec<i32>,
+}
+
+impl Strings {
+    pub fn new(d2: Vec<String>, table: &[u8]) -> Result<Self, Error> {
+        let mut records = vec![];
+        let mut local_names = vec![];
+        let mut r = Reader::new(table);
+        while let Some((field, wire)) = r.field()? {
+            match (field, wire) {
+                (1, LEN) => {
+                    let (range, record) = Self::record(r.bytes()?)?;
+                    // `range` consecutive indices share one record; every index
+                    // has a slot in d2, which bounds the table
+                    let n = (range.max(0) as usize).min(d2.len().saturating_sub(records.len()));
+                    records.extend(std::iter::repeat_n(record, n));
+                }
+                (5, _) => r.ints(wire, &mut local_names)?,
+                _ => r.skip(wire)?,
+            }
+        }
+        Ok(Self {
+            d2,
+            records,
+            local_names,
+        })
+    }
+
+    fn record(data: &[u8]) -> Result<(i32, Record), Error> {
+        let mut range = 1;
+        let mut record = Record::default();
+        let mut r = Reader::new(data);
+        while let Some((field, wire)) = r.field()? {
+            match (field, wire) {
+                (1, VARINT) => range = r.int()?,
+                (2, VARINT) => record.predefined_index = Some(r.int()?),
+                (3, VARINT) => record.operation = r.int()?,
+                (4, _) => r.ints(wire, &mut record.substring_index)?,
+                (5, _) => r.ints(wire, &mut record.replace_char)?,
+                (6, LEN) => record.string = Some(String::from_utf8_lossy(r.bytes()?).into_owned()),
+                _ => r.skip(wire)?,
+            }
+        }
+        Ok((range, record))
+    }
+
+    pub fn get(&self, index: i32) -> Result<String, Error> {
+        let i = usize::try_from(index).map_err(|_| malformed("negative string index"))?;
+        let raw = || {
+            self.d2
+                .get(i)
+                .cloned()
+                .ok_or_else(|| Error::Malformed(format!("string index {} out of range", i)))
+        };
+        let record = match self.records.get(i) {
+            Some(record) => record,
+            None => return raw(),
+        };
+
+        let mut s = match (&record.string, record.predefined_index) {
+            (Some(s), _) => s.clone(),
+            (None, Some(p)) if (0..PREDEFINED.len() as i32).contains(&p) => {
+                PREDEFINED[p as usize].to_string()
+            }
+            _ => raw()?,
+        };
+        // indices are in UTF-16 units, as in java.lang.String
+        if let [begin, end, ..] = record.substring_index[..] {
+            let units: Vec<u16> = s.encode_utf16().collect();
+            if 0 <= begin && begin <= end && end as usize <= units.len() {
+                s = String::from_utf16_lossy(&units[begin as usize..end as usize]);
+            }
+        }
+        if let [from, to, ..] = record.replace_char[..] {
+            if let (Some(from), Some(to)) = (char::from_u32(from as u32), char::from_u32(to as u32))
+            {
+                s = s.replace(from, &to.to_string());
+            }
+        }
+        match record.operation {
+            // INTERNAL_TO_CLASS_ID
+            1 => s = s.replace('$', "."),
+            // DESC_TO_CLASS_ID
+            2 => {
+                s = s
+                    .strip_prefix('L')
+                    .and_then(|s| s.strip_suffix(';'))
+                    .unwrap_or(&s)
+                    .replace('$', ".");
+            }
+            _ => {}
+        }
+        Ok(s)
+    }
+
+    /// A class name as Kotlin spells it: `kotlin/collections/Map.Entry`, with
+    /// a leading `.` for local classes.
+    pub fn class_name(&self, index: i32) -> Result<String, Error> {
+        let name = self.get(index)?;
+        if self.local_names.contains(&index) {
+            Ok(format!(".{}", name))
+        } else {
+            Ok(name)
+        }
+    }
+}
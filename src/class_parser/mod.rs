@@ -1,4 +1,11 @@
+pub mod builder;
+pub mod bytecode;
 pub mod format;
+pub mod kotlin;
 mod parse;
+pub mod smap;
+mod write;
 
-pub use parse::parse;
+pub use parse::{parse, parse_attribute, parse_with, AttributeDecoder, ParseError, ParseOptions};
+pub(crate) use write::attribute_body;
+pub use write::{refresh_raw, write};
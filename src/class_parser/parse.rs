@@ -1,5 +1,8 @@
 use nom::{
-    call, count, do_parse, named, named_args,
+    bytes::streaming::take as take_bytes,
+    call, count, do_parse,
+    error::ErrorKind,
+    named, named_args,
     number::streaming::{be_u16, be_u32, be_u8},
     peek, switch, tag, take, value,
 };
@@ -7,10 +10,10 @@ use nom::{
 use super::format::{
     attributes::{
         AnnotationElementValue, AnnotationEntry, BootstrapMethod, Code, CodeException,
-        ElementValuePair, ElementValueTag, ElementValueType, EnclosingMethod, InnerClass,
-        LineNumber, LocalVarTargetTable, LocalVariable, MethodParameter, StackMapFrame,
-        Tag as AttrTag, TargetInfo, Type as AttributeType, TypeAnnotation, TypePath,
-        VerificationTypeInfo,
+        CustomAttribute, ElementValuePair, ElementValueTag, ElementValueType, EnclosingMethod,
+        InnerClass, LineNumber, LocalVarTargetTable, LocalVariable, MethodParameter,
+        RecordComponent, StackMapFrame, Tag as AttrTag, TargetInfo, Type as AttributeType,
+        TypeAnnotation, TypePath, VerificationTypeInfo,
     },
     class_file::ClassFile,
     constant_pool,
@@ -19,6 +22,12 @@ use super::format::{
     version::Version,
 };
 
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::mem;
+use std::rc::Rc;
 use std::sync::Arc;
 
 named!(
@@ -26,20 +35,304 @@ named!(
     do_parse!(minor: be_u16 >> major: be_u16 >> (Version { minor, major }))
 );
 
+/// What the parsers need besides their input: the constant pool, to resolve
+/// names, the class file version, which decides what is valid, and the
+/// limits of the whole parse.
+#[derive(Clone)]
+struct Context {
+    cp: Arc<Vec<constant_pool::Type>>,
+    version: Version,
+    /// how many element values enclose the one being parsed
+    depth: usize,
+    limits: Rc<Limits>,
+}
+
+impl Context {
+    fn offset(&self, at: &[u8]) -> usize {
+        self.limits.offset(at)
+    }
+
+    /// Stops the parse with `e`, which is reported instead of the nom error.
+    fn fail<'a, T>(&self, at: &'a [u8], e: ParseError) -> nom::IResult<&'a [u8], T> {
+        self.limits.error.borrow_mut().get_or_insert(e);
+        Err(nom::Err::Failure((at, ErrorKind::Verify)))
+    }
+}
+
+/// Bookkeeping for `ParseOptions` over one parse.
+struct Limits {
+    options: ParseOptions,
+    // address of the first input byte, for error offsets
+    start: usize,
+    allocated: Cell<usize>,
+    error: RefCell<Option<ParseError>>,
+}
+
+impl Limits {
+    fn new(input: &[u8], options: &ParseOptions) -> Self {
+        Self {
+            options: options.clone(),
+            start: input.as_ptr() as usize,
+            allocated: Cell::new(0),
+            error: RefCell::new(None),
+        }
+    }
+
+    fn offset(&self, at: &[u8]) -> usize {
+        at.as_ptr() as usize - self.start
+    }
+
+    /// Accounts for `bytes` more, false if that exceeds the limit.
+    fn charge(&self, bytes: usize) -> bool {
+        match self.allocated.get().checked_add(bytes) {
+            Some(total) if total <= self.options.max_allocation => {
+                self.allocated.set(total);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Decodes a vendor attribute, such as Scala's `ScalaSig`, from its
+/// contents and the class's constant pool.
+pub trait AttributeDecoder: Send + Sync {
+    fn decode(
+        &self,
+        data: &[u8],
+        cp: &[constant_pool::Type],
+    ) -> Result<Box<dyn CustomAttribute>, String>;
+}
+
+impl<F> AttributeDecoder for F
+where
+    F: Fn(&[u8], &[constant_pool::Type]) -> Result<Box<dyn CustomAttribute>, String> + Send + Sync,
+{
+    fn decode(
+        &self,
+        data: &[u8],
+        cp: &[constant_pool::Type],
+    ) -> Result<Box<dyn CustomAttribute>, String> {
+        self(data, cp)
+    }
+}
+
+#[derive(Clone)]
+pub struct ParseOptions {
+    /// Newest class file version accepted. Only the major version counts,
+    /// so preview classes of that release pass too.
+    pub max_version: Version,
+    /// How deep annotation element values may nest in arrays and
+    /// annotations, and attributes in Code and Record attributes.
+    pub max_depth: usize,
+    /// Bytes the parsed tables may take in total, counting each table by
+    /// its declared length before it is read.
+    pub max_allocation: usize,
+    /// Whether an attribute must end exactly at its declared length. One
+    /// that runs past it is always an error.
+    pub exact_attribute_length: bool,
+    /// Decoders for attributes the parser doesn't know, by name. Others
+    /// are kept as `Unknown`.
+    pub decoders: HashMap<String, Arc<dyn AttributeDecoder>>,
+}
+
+impl ParseOptions {
+    pub fn register(&mut self, name: &str, decoder: impl AttributeDecoder + 'static) -> &mut Self {
+        self.decoders.insert(name.to_string(), Arc::new(decoder));
+        self
+    }
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            max_version: Version::LATEST,
+            max_depth: 64,
+            max_allocation: 64 << 20,
+            exact_attribute_length: true,
+            decoders: HashMap::new(),
+        }
+    }
+}
+
+impl fmt::Debug for ParseOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParseOptions")
+            .field("max_version", &self.max_version)
+            .field("max_depth", &self.max_depth)
+            .field("max_allocation", &self.max_allocation)
+            .field("exact_attribute_length", &self.exact_attribute_length)
+            .field("decoders", &self.decoders.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// the input ends before the class file does
+    Truncated,
+    /// bytes that can't be parsed, starting at `offset`
+    Malformed {
+        offset: usize,
+    },
+    /// older than 45 or newer than `ParseOptions::max_version`
+    UnsupportedVersion(Version),
+    UnknownConstant {
+        index: u16,
+        tag: u8,
+        offset: usize,
+    },
+    /// a constant pool tag introduced after the class file's version
+    ConstantNotAllowed {
+        index: u16,
+        tag: u8,
+        version: Version,
+        offset: usize,
+    },
+    /// element values or attributes nested deeper than
+    /// `ParseOptions::max_depth`
+    TooDeep {
+        offset: usize,
+    },
+    /// tables larger than `ParseOptions::max_allocation` in total
+    TooLarge {
+        offset: usize,
+    },
+    /// an attribute whose contents don't end at its declared length;
+    /// `offset` is where the attribute starts
+    AttributeLength {
+        name: String,
+        length: u32,
+        offset: usize,
+    },
+    /// a registered decoder rejected the attribute starting at `offset`
+    BadAttribute {
+        name: String,
+        message: String,
+        offset: usize,
+    },
+}
+
+impl ParseError {
+    /// Where in the input the problem is.
+    pub fn offset(&self, input_len: usize) -> usize {
+        match self {
+            ParseError::Truncated => input_len,
+            // right after the magic
+            ParseError::UnsupportedVersion(_) => 4,
+            ParseError::Malformed { offset }
+            | ParseError::UnknownConstant { offset, .. }
+            | ParseError::ConstantNotAllowed { offset, .. }
+            | ParseError::TooDeep { offset }
+            | ParseError::TooLarge { offset }
+            | ParseError::AttributeLength { offset, .. }
+            | ParseError::BadAttribute { offset, .. } => *offset,
+        }
+    }
+
+    fn from_nom(limits: &Limits, e: nom::Err<(&[u8], ErrorKind)>) -> Self {
+        if let Some(e) = limits.error.borrow_mut().take() {
+            return e;
+        }
+        match e {
+            nom::Err::Incomplete(_) => ParseError::Truncated,
+            nom::Err::Error((rest, _)) | nom::Err::Failure((rest, _)) => ParseError::Malformed {
+                offset: limits.offset(rest),
+            },
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Truncated => write!(f, "unexpected end of class file"),
+            ParseError::Malformed { offset } => {
+                write!(f, "malformed class file at byte {}", offset)
+            }
+            ParseError::UnsupportedVersion(version) => {
+                write!(f, "unsupported class file version {}", version)
+            }
+            ParseError::UnknownConstant { index, tag, .. } => {
+                write!(f, "constant pool entry {} has unknown tag {}", index, tag)
+            }
+            ParseError::ConstantNotAllowed {
+                index,
+                tag,
+                version,
+                ..
+            } => write!(
+                f,
+                "constant pool entry {} has tag {}, which version {} doesn't allow",
+                index, tag, version
+            ),
+            ParseError::TooDeep { offset } => {
+                write!(f, "values nested too deeply at byte {}", offset)
+            }
+            ParseError::TooLarge { offset } => {
+                write!(f, "class file tables too large at byte {}", offset)
+            }
+            ParseError::AttributeLength {
+                name,
+                length,
+                offset,
+            } => write!(
+                f,
+                "attribute {} at byte {} doesn't match its length {}",
+                name, offset, length
+            ),
+            ParseError::BadAttribute {
+                name,
+                message,
+                offset,
+            } => write!(f, "bad {} attribute at byte {}: {}", name, offset, message),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
 named!(
-    constant_tag<constant_pool::Tag>,
-    do_parse!(tag: be_u8 >> (constant_pool::Tag::from(tag)))
+    header<Version>,
+    do_parse!(_magic: tag!(b"\xCA\xFE\xBA\xBE") >> version: version >> (version))
 );
 
-fn constant_pool(input: &[u8]) -> nom::IResult<&[u8], Arc<Vec<constant_pool::Type>>> {
-    let (mut input, count) = be_u16(input)?;
+fn constant_pool<'a>(
+    input: &'a [u8],
+    version: Version,
+    limits: &Limits,
+) -> Result<(&'a [u8], Arc<Vec<constant_pool::Type>>), ParseError> {
+    let (mut input, count) = be_u16(input).map_err(|e| ParseError::from_nom(limits, e))?;
+    if !limits.charge(count as usize * mem::size_of::<constant_pool::Type>()) {
+        return Err(ParseError::TooLarge {
+            offset: limits.offset(input),
+        });
+    }
 
     let mut output = Vec::with_capacity(count as usize);
     output.push(constant_pool::Type::Nop);
 
-    let mut i = 1;
-    while i < count {
-        let (new_input, constant_type) = cp_entry(input)?;
+    // a u32, since a Long in the last slot steps past u16::MAX
+    let mut i = 1u32;
+    while i < count as u32 {
+        let offset = limits.offset(input);
+        let (&raw_tag, rest) = input.split_first().ok_or(ParseError::Truncated)?;
+        let tag =
+            constant_pool::Tag::try_from(raw_tag).map_err(|tag| ParseError::UnknownConstant {
+                index: i as u16,
+                tag,
+                offset,
+            })?;
+        if version.major < tag.since() {
+            return Err(ParseError::ConstantNotAllowed {
+                index: i as u16,
+                tag: raw_tag,
+                version,
+                offset,
+            });
+        }
+        let (new_input, constant_type) =
+            cp_entry(rest, tag).map_err(|e| ParseError::from_nom(limits, e))?;
         input = new_input;
 
         i += 1;
@@ -65,11 +358,10 @@ macro_rules! gen_take_exact {
     ($count: expr, $name: ident) => {
         fn $name(input: &[u8]) -> nom::IResult<&[u8], [u8; $count]> {
             let mut output = [0; $count];
-            // TODO: Nom error
-            assert!(input.len() >= $count);
-            for i in 0..$count {
-                output[i] = input[i];
+            if input.len() < $count {
+                return Err(nom::Err::Incomplete(nom::Needed::Size($count)));
             }
+            output.copy_from_slice(&input[..$count]);
             Ok((&input[$count..], output))
         }
     };
@@ -78,11 +370,51 @@ macro_rules! gen_take_exact {
 gen_take_exact!(4, take_exact_4);
 gen_take_exact!(8, take_exact_8);
 
-named!(
-    cp_entry<constant_pool::Type>,
+/// Charges a table of `n` values of `T` to the allocation limit before it
+/// is read. Every entry takes at least a byte, so a count past the end of
+/// the input fails right away. Tables counted by a u8 are too small to
+/// bother.
+fn reserve<'a, T>(input: &'a [u8], ctx: &Context, n: usize) -> nom::IResult<&'a [u8], ()> {
+    if n > input.len() {
+        return Err(nom::Err::Incomplete(nom::Needed::Size(n)));
+    }
+    if !ctx.limits.charge(n * mem::size_of::<T>()) {
+        return ctx.fail(
+            input,
+            ParseError::TooLarge {
+                offset: ctx.offset(input),
+            },
+        );
+    }
+    Ok((input, ()))
+}
+
+/// The context for an element value or attribute inside the current one.
+fn nested<'a>(input: &'a [u8], ctx: &Context) -> nom::IResult<&'a [u8], Context> {
+    if ctx.depth >= ctx.limits.options.max_depth {
+        return ctx.fail(
+            input,
+            ParseError::TooDeep {
+                offset: ctx.offset(input),
+            },
+        );
+    }
+    let mut inner = ctx.clone();
+    inner.depth += 1;
+    Ok((input, inner))
+}
+
+fn utf8_at<'a>(input: &'a [u8], ctx: &Context, index: u16) -> nom::IResult<&'a [u8], Arc<Vec<u8>>> {
+    match constant_pool::get_utf8(&ctx.cp, index as usize) {
+        Some(bytes) => Ok((input, bytes)),
+        None => Err(nom::Err::Error((input, ErrorKind::Verify))),
+    }
+}
+
+named_args!(
+    cp_entry(ct: constant_pool::Tag)<constant_pool::Type>,
     do_parse!(
-        ct: constant_tag
-            >> entry:
+        entry:
                 switch!(value!(ct),
                     constant_pool::Tag::Class => do_parse!(
                         name_index: be_u16 >>
@@ -146,6 +478,19 @@ named!(
                         bootstrap_method_attr_index: be_u16 >>
                         name_and_type_index: be_u16 >>
                         (constant_pool::Type::InvokeDynamic { bootstrap_method_attr_index, name_and_type_index })
+                    ) |
+                    constant_pool::Tag::Dynamic => do_parse!(
+                        bootstrap_method_attr_index: be_u16 >>
+                        name_and_type_index: be_u16 >>
+                        (constant_pool::Type::Dynamic { bootstrap_method_attr_index, name_and_type_index })
+                    ) |
+                    constant_pool::Tag::Module => do_parse!(
+                        name_index: be_u16 >>
+                        (constant_pool::Type::Module { name_index })
+                    ) |
+                    constant_pool::Tag::Package => do_parse!(
+                        name_index: be_u16 >>
+                        (constant_pool::Type::Package { name_index })
                     )
                 )
             >> (entry)
@@ -161,8 +506,8 @@ named!(
                     0 => value!(VerificationTypeInfo::Top) |
                     1 => value!(VerificationTypeInfo::Integer) |
                     2 => value!(VerificationTypeInfo::Float) |
-                    3 => value!(VerificationTypeInfo::Long) |
-                    4 => value!(VerificationTypeInfo::Double) |
+                    3 => value!(VerificationTypeInfo::Double) |
+                    4 => value!(VerificationTypeInfo::Long) |
                     5 => value!(VerificationTypeInfo::Null) |
                     6 => value!(VerificationTypeInfo::UninitializedThis) |
                     7 => do_parse!(
@@ -178,8 +523,8 @@ named!(
     )
 );
 
-named!(
-    stack_map_frame<StackMapFrame>,
+named_args!(
+    stack_map_frame(ctx: Context)<StackMapFrame>,
     do_parse!(
         frame_type: be_u8
             >> inner:
@@ -198,7 +543,7 @@ named!(
                     247 => do_parse!(
                         offset_delta: be_u16 >>
                         type_info: verification_type_info >>
-                        (StackMapFrame::SameLocals1StackItem {
+                        (StackMapFrame::SameLocals1StackItemExtended {
                             tag: frame_type,
                             offset_delta,
                             stack: [type_info],
@@ -231,8 +576,10 @@ named!(
                     255 => do_parse!(
                         offset_delta: be_u16 >>
                         locals_count: be_u16 >>
+                        call!(reserve::<VerificationTypeInfo>, &ctx, locals_count as usize) >>
                         locals: count!(verification_type_info, locals_count as usize) >>
                         stack_count: be_u16 >>
+                        call!(reserve::<VerificationTypeInfo>, &ctx, stack_count as usize) >>
                         stack: count!(verification_type_info, stack_count as usize) >>
                         (StackMapFrame::Full {
                             tag: frame_type,
@@ -303,7 +650,7 @@ named!(
 );
 
 // I didn't found a way to turn byte/char/double/float/... boilerplate into a macro(
-named_args!(element_value_type(cp: Arc<Vec<constant_pool::Type>>)<ElementValueType>, do_parse!(
+named_args!(element_value_type(ctx: Context)<ElementValueType>, do_parse!(
     tag: element_value_tag >>
     inner: switch!(value!(tag),
         ElementValueTag::Byte => do_parse!(
@@ -356,12 +703,15 @@ named_args!(element_value_type(cp: Arc<Vec<constant_pool::Type>>)<ElementValueTy
             (ElementValueType::Class {index})
         ) |
         ElementValueTag::Annotation => do_parse!(
-            value: call!(annotation_entry, cp) >>
+            inner: call!(nested, &ctx) >>
+            value: call!(annotation_entry, inner) >>
             (ElementValueType::Annotation(AnnotationElementValue {value}))
         ) |
         ElementValueTag::Array => do_parse!(
+            inner: call!(nested, &ctx) >>
             array_size: be_u16 >>
-            values: count!(call!(element_value_type, cp.clone()), array_size as usize) >>
+            call!(reserve::<ElementValueType>, &ctx, array_size as usize) >>
+            values: count!(call!(element_value_type, inner.clone()), array_size as usize) >>
             (ElementValueType::Array {
                 values,
             })
@@ -371,18 +721,26 @@ named_args!(element_value_type(cp: Arc<Vec<constant_pool::Type>>)<ElementValueTy
     (inner)
 ));
 
-named_args!(element_value_pair(cp: Arc<Vec<constant_pool::Type>>)<ElementValuePair>, do_parse!(
+named_args!(element_value_pair(ctx: Context)<ElementValuePair>, do_parse!(
     name_index: be_u16 >>
-    value: call!(element_value_type, cp) >>
+    value: call!(element_value_type, ctx) >>
     (ElementValuePair {name_index, value})
 ));
 
-named_args!(annotation_entry(cp: Arc<Vec<constant_pool::Type>>)<AnnotationEntry>, do_parse!(
+named_args!(annotation_entry(ctx: Context)<AnnotationEntry>, do_parse!(
     type_index: be_u16 >>
     pair_count: be_u16 >>
-    pairs: count!(call!(element_value_pair, cp.clone()), pair_count as usize) >>
-    type_name: value!(constant_pool::get_utf8(&cp, type_index as usize).expect("Missing type name")) >>
-    (AnnotationEntry {type_name, pairs})
+    call!(reserve::<ElementValuePair>, &ctx, pair_count as usize) >>
+    pairs: count!(call!(element_value_pair, ctx.clone()), pair_count as usize) >>
+    type_name: call!(utf8_at, &ctx, type_index) >>
+    (AnnotationEntry {type_index, type_name, pairs})
+));
+
+named_args!(annotation_vec(ctx: Context)<Vec<AnnotationEntry>>, do_parse!(
+    annotation_count: be_u16 >>
+    call!(reserve::<AnnotationEntry>, &ctx, annotation_count as usize) >>
+    annotations: count!(call!(annotation_entry, ctx.clone()), annotation_count as usize) >>
+    (annotations)
 ));
 
 named!(
@@ -399,8 +757,8 @@ named!(
     )
 );
 
-named!(
-    target_info<TargetInfo>,
+named_args!(
+    target_info(ctx: Context)<(u8, TargetInfo)>,
     do_parse!(
         target_type: be_u8
             >> inner:
@@ -429,6 +787,7 @@ named!(
                     ) |
                     0x40 | 0x41 => do_parse!(
                         item_count: be_u16 >>
+                        call!(reserve::<LocalVarTargetTable>, &ctx, item_count as usize) >>
                         items: count!(local_var_target_table, item_count as usize) >>
                         (TargetInfo::LocalVar {table: items})
                     ) |
@@ -446,7 +805,7 @@ named!(
                         (TargetInfo::TypeArgument {offset, type_argument_index})
                     )
                 )
-            >> (inner)
+            >> (target_type, inner)
     )
 );
 
@@ -462,26 +821,29 @@ named!(
     )
 );
 
-named_args!(type_annotation(cp: Arc<Vec<constant_pool::Type>>)<TypeAnnotation>, do_parse!(
-    target_info: target_info >>
+named_args!(type_annotation(ctx: Context)<TypeAnnotation>, do_parse!(
+    target: call!(target_info, ctx.clone()) >>
     target_path_part_count: be_u8 >>
     target_path: count!(type_path, target_path_part_count as usize) >>
     type_index: be_u16 >>
     pair_count: be_u16 >>
-    pairs: count!(call!(element_value_pair, cp.clone()), pair_count as usize) >>
+    call!(reserve::<ElementValuePair>, &ctx, pair_count as usize) >>
+    pairs: count!(call!(element_value_pair, ctx.clone()), pair_count as usize) >>
     (TypeAnnotation {
-        target_info,
+        target_type: target.0,
+        target_info: target.1,
         target_path,
         type_index,
         pairs,
     })
 ));
 
-named!(
-    bootstrap_method<BootstrapMethod>,
+named_args!(
+    bootstrap_method(ctx: Context)<BootstrapMethod>,
     do_parse!(
         method_ref: be_u16
             >> arg_count: be_u16
+            >> call!(reserve::<u16>, &ctx, arg_count as usize)
             >> args: count!(be_u16, arg_count as usize)
             >> (BootstrapMethod { method_ref, args })
     )
@@ -515,27 +877,90 @@ named!(
     )
 );
 
-named_args!(attr_type_vec(cp: Arc<Vec<constant_pool::Type>>)<Vec<AttributeType>>, do_parse!(
+// components carry attributes of their own, so nesting is bounded like
+// element values
+named_args!(record_component(ctx: Context)<RecordComponent>, do_parse!(
+    name_index: be_u16 >>
+    descriptor_index: be_u16 >>
+    inner: call!(nested, &ctx) >>
+    attrs: call!(attr_type_vec, inner) >>
+    (RecordComponent {
+        name_index,
+        descriptor_index,
+        attrs,
+    })
+));
+
+named_args!(attr_type_vec(ctx: Context)<Vec<AttributeType>>, do_parse!(
     attrs_count: be_u16 >>
-    attrs: count!(call!(attr_type, cp.clone()), attrs_count as usize) >>
+    call!(reserve::<AttributeType>, &ctx, attrs_count as usize) >>
+    attrs: count!(call!(attr_type, ctx.clone()), attrs_count as usize) >>
     (attrs)
 ));
 
-named_args!(attr_type(cp: Arc<Vec<constant_pool::Type>>)<AttributeType>, do_parse!(
-    tag: call!(attr_tag, cp.clone()) >>
-    length: be_u32 >>
-    attr: call!(attr_sized, tag, length as usize, cp) >>
-    (attr)
-));
+fn attr_type(input: &[u8], ctx: Context) -> nom::IResult<&[u8], AttributeType> {
+    let start = input;
+    let (input, name_index) = be_u16(input)?;
+    let (input, name) = utf8_at(input, &ctx, name_index)?;
+    let tag = AttrTag::from(name.as_slice());
+    // spec 4.7: attributes newer than the class file are unknown to it
+    let tag = if ctx.version.major < tag.since() {
+        AttrTag::Unknown
+    } else {
+        tag
+    };
+    let (input, length) = be_u32(input)?;
+    let (rest, data) = take_bytes(length)(input)?;
 
-named_args!(attr_tag(cp: Arc<Vec<constant_pool::Type>>)<AttrTag>, do_parse!(
-    name_index: be_u16 >>
-    name: value!(constant_pool::get_utf8(&cp, name_index as usize).expect("Missing name")) >>
-    inner: value!(AttrTag::from(name.as_slice())) >>
-    (inner)
-));
+    if let AttrTag::Unknown = tag {
+        let decoder = std::str::from_utf8(&name)
+            .ok()
+            .and_then(|name| ctx.limits.options.decoders.get(name));
+        let attr = match decoder {
+            None => AttributeType::Unknown {
+                name,
+                data: Arc::new(data.to_vec()),
+            },
+            Some(decoder) => match decoder.decode(data, &ctx.cp) {
+                Ok(value) => AttributeType::Custom {
+                    name,
+                    data: Arc::new(data.to_vec()),
+                    value: Arc::from(value),
+                },
+                Err(message) => {
+                    return ctx.fail(
+                        start,
+                        ParseError::BadAttribute {
+                            name: String::from_utf8_lossy(&name).into_owned(),
+                            message,
+                            offset: ctx.offset(start),
+                        },
+                    )
+                }
+            },
+        };
+        return Ok((rest, attr));
+    }
 
-named_args!(attr_sized(tag: AttrTag, self_len: usize, cp: Arc<Vec<constant_pool::Type>>)<AttributeType>, switch!(value!(tag),
+    // the contents are parsed on their own, so running out of them means
+    // the attribute is longer than it says
+    match attr_sized(data, tag, data.len(), ctx.clone()) {
+        Ok((left, attr)) if left.is_empty() || !ctx.limits.options.exact_attribute_length => {
+            Ok((rest, attr))
+        }
+        Ok(_) | Err(nom::Err::Incomplete(_)) => ctx.fail(
+            start,
+            ParseError::AttributeLength {
+                name: String::from_utf8_lossy(&name).into_owned(),
+                length,
+                offset: ctx.offset(start),
+            },
+        ),
+        Err(e) => Err(e),
+    }
+}
+
+named_args!(attr_sized(tag: AttrTag, self_len: usize, ctx: Context)<AttributeType>, switch!(value!(tag),
     AttrTag::ConstantValue => do_parse!(
         constant_value_index: be_u16 >>
         (AttributeType::ConstantValue {constant_value_index})
@@ -546,8 +971,10 @@ named_args!(attr_sized(tag: AttrTag, self_len: usize, cp: Arc<Vec<constant_pool:
         len: be_u32 >>
         code: take!(len) >> // TODO: Parse code in same time?)
         exception_count: be_u16 >>
+        call!(reserve::<CodeException>, &ctx, exception_count as usize) >>
         exceptions: count!(code_exception, exception_count as usize) >>
-        attrs: call!(attr_type_vec, cp) >>
+        inner: call!(nested, &ctx) >>
+        attrs: call!(attr_type_vec, inner) >>
         (AttributeType::Code(Code {
             max_stack,
             max_locals,
@@ -558,16 +985,19 @@ named_args!(attr_sized(tag: AttrTag, self_len: usize, cp: Arc<Vec<constant_pool:
     ) |
     AttrTag::StackMapTable => do_parse!(
         frame_count: be_u16 >>
-        frames: count!(stack_map_frame, frame_count as usize) >>
+        call!(reserve::<StackMapFrame>, &ctx, frame_count as usize) >>
+        frames: count!(call!(stack_map_frame, ctx.clone()), frame_count as usize) >>
         (AttributeType::StackMapTable { entries: frames })
     ) |
     AttrTag::Exceptions => do_parse!(
         exception_count: be_u16 >>
+        call!(reserve::<u16>, &ctx, exception_count as usize) >>
         exceptions: count!(be_u16, exception_count as usize) >>
         (AttributeType::Exceptions { exceptions })
     ) |
     AttrTag::InnerClasses => do_parse!(
         class_count: be_u16 >>
+        call!(reserve::<InnerClass>, &ctx, class_count as usize) >>
         classes: count!(inner_class, class_count as usize) >>
         (AttributeType::InnerClasses { classes })
     ) |
@@ -590,16 +1020,19 @@ named_args!(attr_sized(tag: AttrTag, self_len: usize, cp: Arc<Vec<constant_pool:
     ) |
     AttrTag::LineNumberTable => do_parse!(
         line_count: be_u16 >>
+        call!(reserve::<LineNumber>, &ctx, line_count as usize) >>
         lines: count!(line_number, line_count as usize) >>
         (AttributeType::LineNumberTable { tables: lines })
     ) |
     AttrTag::LocalVariableTable => do_parse!(
         variable_count: be_u16 >>
+        call!(reserve::<LocalVariable>, &ctx, variable_count as usize) >>
         variables: count!(local_variable, variable_count as usize) >>
         (AttributeType::LocalVariableTable { tables: variables })
     ) |
     AttrTag::LocalVariableTypeTable => do_parse!(
         variable_count: be_u16 >>
+        call!(reserve::<LocalVariable>, &ctx, variable_count as usize) >>
         variables: count!(local_variable, variable_count as usize) >>
         (AttributeType::LocalVariableTypeTable { tables: variables })
     ) |
@@ -607,47 +1040,52 @@ named_args!(attr_sized(tag: AttrTag, self_len: usize, cp: Arc<Vec<constant_pool:
     AttrTag::RuntimeVisibleAnnotations => do_parse!(
         raw: peek!(take!(self_len)) >>
         annotation_count: be_u16 >>
-        annotations: count!(call!(annotation_entry, cp.clone()), annotation_count as usize) >>
+        call!(reserve::<AnnotationEntry>, &ctx, annotation_count as usize) >>
+        annotations: count!(call!(annotation_entry, ctx.clone()), annotation_count as usize) >>
         (AttributeType::RuntimeVisibleAnnotations {raw: Arc::new(Vec::from(raw)), annotations})
     ) |
     AttrTag::RuntimeInvisibleAnnotations => do_parse!(
         raw: peek!(take!(self_len)) >>
         annotation_count: be_u16 >>
-        annotations: count!(call!(annotation_entry, cp.clone()), annotation_count as usize) >>
+        call!(reserve::<AnnotationEntry>, &ctx, annotation_count as usize) >>
+        annotations: count!(call!(annotation_entry, ctx.clone()), annotation_count as usize) >>
         (AttributeType::RuntimeInvisibleAnnotations {raw: Arc::new(Vec::from(raw)), annotations})
     ) |
     AttrTag::RuntimeVisibleParameterAnnotations => do_parse!(
         raw: peek!(take!(self_len)) >>
-        annotation_count: be_u16 >>
-        annotations: count!(call!(annotation_entry, cp.clone()), annotation_count as usize) >>
-        (AttributeType::RuntimeVisibleParameterAnnotations {raw: Arc::new(Vec::from(raw)), annotations})
+        parameter_count: be_u8 >>
+        parameters: count!(call!(annotation_vec, ctx.clone()), parameter_count as usize) >>
+        (AttributeType::RuntimeVisibleParameterAnnotations {raw: Arc::new(Vec::from(raw)), parameters})
     ) |
     AttrTag::RuntimeInvisibleParameterAnnotations => do_parse!(
         raw: peek!(take!(self_len)) >>
-        annotation_count: be_u16 >>
-        annotations: count!(call!(annotation_entry, cp.clone()), annotation_count as usize) >>
-        (AttributeType::RuntimeInvisibleParameterAnnotations {raw: Arc::new(Vec::from(raw)), annotations})
+        parameter_count: be_u8 >>
+        parameters: count!(call!(annotation_vec, ctx.clone()), parameter_count as usize) >>
+        (AttributeType::RuntimeInvisibleParameterAnnotations {raw: Arc::new(Vec::from(raw)), parameters})
     ) |
     AttrTag::RuntimeVisibleTypeAnnotations => do_parse!(
         raw: peek!(take!(self_len)) >>
         annotation_count: be_u16 >>
-        annotations: count!(call!(type_annotation, cp.clone()), annotation_count as usize) >>
+        call!(reserve::<TypeAnnotation>, &ctx, annotation_count as usize) >>
+        annotations: count!(call!(type_annotation, ctx.clone()), annotation_count as usize) >>
         (AttributeType::RuntimeVisibleTypeAnnotations {raw: Arc::new(Vec::from(raw)), annotations})
     ) |
     AttrTag::RuntimeInvisibleTypeAnnotations => do_parse!(
         raw: peek!(take!(self_len)) >>
         annotation_count: be_u16 >>
-        annotations: count!(call!(type_annotation, cp.clone()), annotation_count as usize) >>
+        call!(reserve::<TypeAnnotation>, &ctx, annotation_count as usize) >>
+        annotations: count!(call!(type_annotation, ctx.clone()), annotation_count as usize) >>
         (AttributeType::RuntimeInvisibleTypeAnnotations {raw: Arc::new(Vec::from(raw)), annotations})
     ) |
     AttrTag::AnnotationDefault => do_parse!(
         raw: peek!(take!(self_len)) >>
-        default_value: call!(element_value_type, cp) >>
+        default_value: call!(element_value_type, ctx) >>
         (AttributeType::AnnotationDefault {raw: Arc::new(Vec::from(raw)), default_value})
     ) |
     AttrTag::BootstrapMethods => do_parse!(
         method_count: be_u16 >>
-        methods: count!(bootstrap_method, method_count as usize) >>
+        call!(reserve::<BootstrapMethod>, &ctx, method_count as usize) >>
+        methods: count!(call!(bootstrap_method, ctx.clone()), method_count as usize) >>
         (AttributeType::BootstrapMethods {n:method_count, methods})
     ) |
     AttrTag::MethodParameters => do_parse!(
@@ -655,17 +1093,35 @@ named_args!(attr_sized(tag: AttrTag, self_len: usize, cp: Arc<Vec<constant_pool:
         parameters: count!(method_parameter, parameter_count as usize) >>
         (AttributeType::MethodParameters {parameters})
     ) |
-    AttrTag::Unknown => do_parse!(
-        _data: take!(self_len) >>
-        (AttributeType::Unknown)
+    AttrTag::NestHost => do_parse!(
+        host_class_index: be_u16 >>
+        (AttributeType::NestHost { host_class_index })
+    ) |
+    AttrTag::NestMembers => do_parse!(
+        class_count: be_u16 >>
+        call!(reserve::<u16>, &ctx, class_count as usize) >>
+        classes: count!(be_u16, class_count as usize) >>
+        (AttributeType::NestMembers { classes })
+    ) |
+    AttrTag::Record => do_parse!(
+        component_count: be_u16 >>
+        call!(reserve::<RecordComponent>, &ctx, component_count as usize) >>
+        components: count!(call!(record_component, ctx.clone()), component_count as usize) >>
+        (AttributeType::Record { components })
+    ) |
+    AttrTag::PermittedSubclasses => do_parse!(
+        class_count: be_u16 >>
+        call!(reserve::<u16>, &ctx, class_count as usize) >>
+        classes: count!(be_u16, class_count as usize) >>
+        (AttributeType::PermittedSubclasses { classes })
     )
 ));
 
-named_args!(field(cp: Arc<Vec<constant_pool::Type>>)<FieldInfo>, do_parse!(
+named_args!(field(ctx: Context)<FieldInfo>, do_parse!(
     acc_flags: be_u16 >>
     name_index: be_u16 >>
     desc_index: be_u16 >>
-    attrs: call!(attr_type_vec, cp) >>
+    attrs: call!(attr_type_vec, ctx) >>
     (FieldInfo {
         acc_flags,
         name_index,
@@ -674,11 +1130,11 @@ named_args!(field(cp: Arc<Vec<constant_pool::Type>>)<FieldInfo>, do_parse!(
     })
 ));
 
-named_args!(method_info(cp: Arc<Vec<constant_pool::Type>>)<MethodInfo>, do_parse!(
+named_args!(method_info(ctx: Context)<MethodInfo>, do_parse!(
     acc_flags: be_u16 >>
     name_index: be_u16 >>
     desc_index: be_u16 >>
-    attrs: call!(attr_type_vec, cp) >>
+    attrs: call!(attr_type_vec, ctx) >>
     (MethodInfo {
         acc_flags,
         name_index,
@@ -687,36 +1143,86 @@ named_args!(method_info(cp: Arc<Vec<constant_pool::Type>>)<MethodInfo>, do_parse
     })
 ));
 
-named!(
-    class_file<ClassFile>,
-    do_parse!(
-        _magic: tag!(b"\xCA\xFE\xBA\xBE")
-            >> version: version
-            >> cp: constant_pool
-            >> acc_flags: be_u16
-            >> this_class: be_u16
-            >> super_class: be_u16
-            >> interfaces_count: be_u16
-            >> interfaces: count!(be_u16, interfaces_count as usize)
-            >> fields_count: be_u16
-            >> fields: count!(call!(field, cp.clone()), fields_count as usize)
-            >> method_count: be_u16
-            >> methods: count!(call!(method_info, cp.clone()), method_count as usize)
-            >> attrs: call!(attr_type_vec, cp.clone())
-            >> (ClassFile {
-                version,
-                cp: cp.clone(),
-                acc_flags,
-                this_class,
-                super_class,
-                interfaces,
-                fields,
-                methods,
-                attrs
-            })
-    )
-);
+named_args!(class_body(ctx: Context)<ClassFile>, do_parse!(
+    acc_flags: be_u16 >>
+    this_class: be_u16 >>
+    super_class: be_u16 >>
+    interfaces_count: be_u16 >>
+    call!(reserve::<u16>, &ctx, interfaces_count as usize) >>
+    interfaces: count!(be_u16, interfaces_count as usize) >>
+    fields_count: be_u16 >>
+    call!(reserve::<FieldInfo>, &ctx, fields_count as usize) >>
+    fields: count!(call!(field, ctx.clone()), fields_count as usize) >>
+    method_count: be_u16 >>
+    call!(reserve::<MethodInfo>, &ctx, method_count as usize) >>
+    methods: count!(call!(method_info, ctx.clone()), method_count as usize) >>
+    attrs: call!(attr_type_vec, ctx.clone()) >>
+    (ClassFile {
+        version: ctx.version,
+        cp: ctx.cp.clone(),
+        acc_flags,
+        this_class,
+        super_class,
+        interfaces,
+        fields,
+        methods,
+        attrs
+    })
+));
+
+/// Parses a class file, checking its version and constant pool against
+/// `options`.
+pub fn parse_with<'a>(
+    input: &'a [u8],
+    options: &ParseOptions,
+) -> Result<(&'a [u8], ClassFile), ParseError> {
+    let limits = Rc::new(Limits::new(input, options));
+    let (rest, version) = header(input).map_err(|e| ParseError::from_nom(&limits, e))?;
+    if version.major < Version::JAVA_1_1.major || version.major > options.max_version.major {
+        return Err(ParseError::UnsupportedVersion(version));
+    }
+    let (rest, cp) = constant_pool(rest, version, &limits)?;
+    let ctx = Context {
+        cp,
+        version,
+        depth: 0,
+        limits: limits.clone(),
+    };
+    class_body(rest, ctx).map_err(|e| ParseError::from_nom(&limits, e))
+}
 
 pub fn parse(input: &[u8]) -> nom::IResult<&[u8], ClassFile> {
-    class_file(input)
+    parse_with(input, &ParseOptions::default()).map_err(|e| match e {
+        ParseError::Truncated => nom::Err::Incomplete(nom::Needed::Unknown),
+        e => nom::Err::Error((
+            &input[e.offset(input.len())..],
+            nom::error::ErrorKind::Verify,
+        )),
+    })
+}
+
+/// Decodes the body of a single attribute called `name`. Names the parser
+/// doesn't know come back as `Unknown`.
+pub fn parse_attribute(
+    name: &[u8],
+    data: &[u8],
+    cp: Arc<Vec<constant_pool::Type>>,
+) -> Option<AttributeType> {
+    let tag = AttrTag::from(name);
+    if let AttrTag::Unknown = tag {
+        return Some(AttributeType::Unknown {
+            name: Arc::new(name.to_vec()),
+            data: Arc::new(data.to_vec()),
+        });
+    }
+    let ctx = Context {
+        cp,
+        version: Version::LATEST,
+        depth: 0,
+        limits: Rc::new(Limits::new(data, &ParseOptions::default())),
+    };
+    match attr_sized(data, tag, data.len(), ctx) {
+        Ok((&[], attr)) => Some(attr),
+        _ => None,
+    }
 }
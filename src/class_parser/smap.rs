@@ -0,0 +1,351 @@
+//! Source maps (JSR-45) as found in the `SourceDebugExtension` attribute.
+//!
+//! Compilers for languages other than Java record how lines of the class
+//! file ("output" lines, the ones in LineNumberTable) relate to lines of
+//! the original sources. A map has one or more strata, e.g. `Kotlin` and
+//! `KotlinDebug`, or `JSP`:
+//!
+//! ```text
+//! SMAP
+//! Foo.kt
+//! Kotlin
+//! *S Kotlin
+//! *F
+//! + 1 Foo.kt
+//! com/example/Foo.kt
+//! + 2 Inline.kt
+//! com/example/Inline.kt
+//! *L
+//! 1#1,20:1
+//! 5#2,3:21
+//! *E
+//! ```
+
+use super::format::{
+    attributes::{Code, Type as AttributeType},
+    class_file::ClassFile,
+    constant_pool,
+};
+
+use std::fmt;
+
+/// The stratum of the class file itself, which needs no mapping.
+pub const JAVA_STRATUM: &str = "Java";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    /// 1-based line of the map
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SMAP line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Smap {
+    /// name of the generated source, usually what SourceFile says
+    pub output_file: String,
+    pub default_stratum: String,
+    pub strata: Vec<Stratum>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stratum {
+    pub name: String,
+    pub files: Vec<FileInfo>,
+    pub lines: Vec<LineInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileInfo {
+    pub id: u32,
+    pub name: String,
+    /// path relative to the source root, only present for `+` entries
+    pub path: Option<String>,
+}
+
+/// One line of the `*L` section: `repeat_count` input lines starting at
+/// `input_start` each map to `output_increment` output lines, starting at
+/// `output_start`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineInfo {
+    pub input_start: u32,
+    pub file_id: u32,
+    pub repeat_count: u32,
+    pub output_start: u32,
+    pub output_increment: u32,
+}
+
+impl LineInfo {
+    /// The input line that `output_line` comes from, if this entry covers it.
+    pub fn input_line(&self, output_line: u32) -> Option<u32> {
+        if output_line < self.output_start {
+            return None;
+        }
+        let offset = output_line - self.output_start;
+        if self.output_increment == 0 {
+            // every input line maps to the same output line
+            return if offset == 0 && self.repeat_count > 0 {
+                Some(self.input_start)
+            } else {
+                None
+            };
+        }
+        let i = offset / self.output_increment;
+        if i < self.repeat_count {
+            self.input_start.checked_add(i)
+        } else {
+            None
+        }
+    }
+}
+
+impl Stratum {
+    pub fn file(&self, id: u32) -> Option<&FileInfo> {
+        self.files.iter().find(|f| f.id == id)
+    }
+
+    /// Maps an output line to the source file and line it was generated
+    /// from. Later entries win when several cover the same line.
+    pub fn map_line(&self, output_line: u32) -> Option<(&FileInfo, u32)> {
+        self.lines.iter().rev().find_map(|l| {
+            let line = l.input_line(output_line)?;
+            Some((self.file(l.file_id)?, line))
+        })
+    }
+}
+
+impl Smap {
+    pub fn stratum(&self, name: &str) -> Option<&Stratum> {
+        self.strata.iter().find(|s| s.name == name)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        Parser::new(text).smap()
+    }
+
+    /// The map in the `SourceDebugExtension` of `cf`, if it has one.
+    pub fn from_class(cf: &ClassFile) -> Option<Result<Self, Error>> {
+        cf.attrs.iter().find_map(|a| match a {
+            AttributeType::SourceDebugExtension { debug_extension } => {
+                // the attribute holds modified UTF-8, which is plain ASCII
+                // for every map seen in practice
+                Some(Self::parse(&String::from_utf8_lossy(debug_extension)))
+            }
+            _ => None,
+        })
+    }
+}
+
+struct Parser<'a> {
+    lines: Vec<&'a str>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            lines: text.lines().map(|l| l.trim_end_matches('\r')).collect(),
+            pos: 0,
+        }
+    }
+
+    fn err(&self, message: impl Into<String>) -> Error {
+        Error {
+            line: self.pos,
+            message: message.into(),
+        }
+    }
+
+    fn next(&mut self) -> Result<&'a str, Error> {
+        let line = *self
+            .lines
+            .get(self.pos)
+            .ok_or_else(|| self.err("unexpected end of map"))?;
+        self.pos += 1;
+        Ok(line)
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.lines.get(self.pos).copied()
+    }
+
+    fn smap(&mut self) -> Result<Smap, Error> {
+        if self.next()? != "SMAP" {
+            return Err(self.err("missing SMAP header"));
+        }
+        let output_file = self.next()?.to_string();
+        let default_stratum = self.next()?.to_string();
+
+        let mut strata: Vec<Stratum> = vec![];
+        loop {
+            let line = self.next()?;
+            let (section, arg) = match line.find(' ') {
+                Some(i) => (&line[..i], line[i + 1..].trim()),
+                None => (line, ""),
+            };
+            match section {
+                "*S" => strata.push(Stratum {
+                    name: arg.to_string(),
+                    files: vec![],
+                    lines: vec![],
+                }),
+                "*F" => {
+                    let stratum = strata
+                        .last_mut()
+                        .ok_or_else(|| self.err("*F before any *S"))?;
+                    let mut files = vec![];
+                    while let Some(line) = self.peek().filter(|l| !l.starts_with('*')) {
+                        self.pos += 1;
+                        files.push(self.file_info(line)?);
+                    }
+                    stratum.files.extend(files);
+                }
+                "*L" => {
+                    let mut lines = vec![];
+                    let mut file_id = 0;
+                    while let Some(line) = self.peek().filter(|l| !l.starts_with('*')) {
+                        self.pos += 1;
+                        let info = self.line_info(line, file_id)?;
+                        file_id = info.file_id;
+                        lines.push(info);
+                    }
+                    strata
+                        .last_mut()
+                        .ok_or_else(|| self.err("*L before any *S"))?
+                        .lines
+                        .extend(lines);
+                }
+                "*E" => break,
+                "*O" | "*C" => return Err(self.err("embedded source maps are not supported")),
+                // *V vendor sections and anything unknown are skipped
+                _ if section.starts_with('*') => {
+                    while self.peek().is_some_and(|l| !l.starts_with('*')) {
+                        self.pos += 1;
+                    }
+                }
+                _ => return Err(self.err(format!("expected a section, found {}", line))),
+            }
+        }
+
+        Ok(Smap {
+            output_file,
+            default_stratum,
+            strata,
+        })
+    }
+
+    // `1 Foo.kt`, or `+ 1 Foo.kt` followed by a line with the path
+    fn file_info(&mut self, line: &str) -> Result<FileInfo, Error> {
+        let (with_path, rest) = match line.strip_prefix('+') {
+            Some(rest) => (true, rest.trim_start()),
+            None => (false, line),
+        };
+        let (id, name) = match rest.find(' ') {
+            Some(i) => (&rest[..i], &rest[i + 1..]),
+            None => return Err(self.err(format!("bad file entry {}", line))),
+        };
+        let id = id
+            .parse()
+            .map_err(|_| self.err(format!("bad file id {}", id)))?;
+        let path = if with_path {
+            Some(self.next()?.to_string())
+        } else {
+            None
+        };
+        Ok(FileInfo {
+            id,
+            name: name.to_string(),
+            path,
+        })
+    }
+
+    // InputStartLine[#LineFileID][,RepeatCount]:OutputStartLine[,OutputLineIncrement]
+    fn line_info(&self, line: &str, file_id: u32) -> Result<LineInfo, Error> {
+        let bad = || self.err(format!("bad line entry {}", line));
+        let number = |s: &str| s.trim().parse::<u32>().map_err(|_| bad());
+
+        let (input, output) = line.split_once(':').ok_or_else(bad)?;
+        let (input, repeat_count) = match input.split_once(',') {
+            Some((input, repeat)) => (input, number(repeat)?),
+            None => (input, 1),
+        };
+        let (input_start, file_id) = match input.split_once('#') {
+            Some((start, id)) => (number(start)?, number(id)?),
+            None => (number(input)?, file_id),
+        };
+        let (output_start, output_increment) = match output.split_once(',') {
+            Some((start, increment)) => (number(start)?, number(increment)?),
+            None => (number(output)?, 1),
+        };
+        Ok(LineInfo {
+            input_start,
+            file_id,
+            repeat_count,
+            output_start,
+            output_increment,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceLocation {
+    pub file: String,
+    pub path: Option<String>,
+    pub line: u32,
+}
+
+/// Where the instruction at `pc` of `code` (a method of `cf`) came from in
+/// `stratum`, or in the map's default stratum when `stratum` is None.
+///
+/// The `Java` stratum, and any class without a map, resolve through
+/// LineNumberTable and SourceFile alone.
+pub fn source_location(
+    cf: &ClassFile,
+    code: &Code,
+    pc: u16,
+    stratum: Option<&str>,
+) -> Option<SourceLocation> {
+    let line = code.line_number(pc)? as u32;
+    let smap = Smap::from_class(cf).and_then(|r| r.ok());
+    let name = match (stratum, &smap) {
+        (Some(name), _) => name,
+        (None, Some(smap)) => smap.default_stratum.as_str(),
+        (None, None) => JAVA_STRATUM,
+    };
+
+    if let Some(s) = smap.as_ref().and_then(|smap| smap.stratum(name)) {
+        let (file, line) = s.map_line(line)?;
+        return Some(SourceLocation {
+            file: file.name.clone(),
+            path: file.path.clone(),
+            line,
+        });
+    }
+    if name != JAVA_STRATUM {
+        return None;
+    }
+
+    let file = cf.attrs.iter().find_map(|a| match a {
+        AttributeType::SourceFile { source_file_index } => {
+            constant_pool::get_utf8(&cf.cp, *source_file_index as usize)
+        }
+        _ => None,
+    });
+    let file = match (file, smap) {
+        (Some(file), _) => String::from_utf8_lossy(&file).into_owned(),
+        (None, Some(smap)) => smap.output_file,
+        (None, None) => return None,
+    };
+    Some(SourceLocation {
+        file,
+        path: None,
+        line,
+    })
+}
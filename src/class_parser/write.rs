@@ -0,0 +1,635 @@
+use super::format::{
+    attributes::{
+        AnnotationEntry, ElementValuePair, ElementValueType, StackMapFrame, TargetInfo,
+        Type as AttributeType, TypeAnnotation, VerificationTypeInfo,
+    },
+    class_file::ClassFile,
+    constant_pool,
+};
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::Arc;
+
+fn put_u8(out: &mut Vec<u8>, v: u8) {
+    out.push(v);
+}
+
+fn put_u16(out: &mut Vec<u8>, v: u16) {
+    out.extend_from_slice(&v.to_be_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, v: u32) {
+    out.extend_from_slice(&v.to_be_bytes());
+}
+
+fn put_len16(out: &mut Vec<u8>, len: usize) -> io::Result<()> {
+    if len > u16::MAX as usize {
+        return Err(invalid_data(format!("table too long: {}", len)));
+    }
+    put_u16(out, len as u16);
+    Ok(())
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn constant(out: &mut Vec<u8>, c: &constant_pool::Type) -> io::Result<()> {
+    match c {
+        constant_pool::Type::Nop => (),
+        constant_pool::Type::Class { name_index } => {
+            put_u8(out, 7);
+            put_u16(out, *name_index);
+        }
+        constant_pool::Type::FieldRef {
+            class_index,
+            name_and_type_index,
+        } => {
+            put_u8(out, 9);
+            put_u16(out, *class_index);
+            put_u16(out, *name_and_type_index);
+        }
+        constant_pool::Type::MethodRef {
+            class_index,
+            name_and_type_index,
+        } => {
+            put_u8(out, 10);
+            put_u16(out, *class_index);
+            put_u16(out, *name_and_type_index);
+        }
+        constant_pool::Type::InterfaceMethodRef {
+            class_index,
+            name_and_type_index,
+        } => {
+            put_u8(out, 11);
+            put_u16(out, *class_index);
+            put_u16(out, *name_and_type_index);
+        }
+        constant_pool::Type::String { string_index } => {
+            put_u8(out, 8);
+            put_u16(out, *string_index);
+        }
+        constant_pool::Type::Integer { v } => {
+            put_u8(out, 3);
+            out.extend_from_slice(v);
+        }
+        constant_pool::Type::Float { v } => {
+            put_u8(out, 4);
+            out.extend_from_slice(v);
+        }
+        constant_pool::Type::Long { v } => {
+            put_u8(out, 5);
+            out.extend_from_slice(v);
+        }
+        constant_pool::Type::Double { v } => {
+            put_u8(out, 6);
+            out.extend_from_slice(v);
+        }
+        constant_pool::Type::NameAndType {
+            name_index,
+            desc_index,
+        } => {
+            put_u8(out, 12);
+            put_u16(out, *name_index);
+            put_u16(out, *desc_index);
+        }
+        constant_pool::Type::Utf8 { bytes } => {
+            put_u8(out, 1);
+            put_len16(out, bytes.len())?;
+            out.extend_from_slice(bytes);
+        }
+        constant_pool::Type::MethodHandle {
+            ref_kind,
+            ref_index,
+        } => {
+            put_u8(out, 15);
+            put_u8(out, *ref_kind);
+            put_u16(out, *ref_index);
+        }
+        constant_pool::Type::MethodType { desc_index } => {
+            put_u8(out, 16);
+            put_u16(out, *desc_index);
+        }
+        constant_pool::Type::InvokeDynamic {
+            bootstrap_method_attr_index,
+            name_and_type_index,
+        } => {
+            put_u8(out, 18);
+            put_u16(out, *bootstrap_method_attr_index);
+            put_u16(out, *name_and_type_index);
+        }
+        constant_pool::Type::Dynamic {
+            bootstrap_method_attr_index,
+            name_and_type_index,
+        } => {
+            put_u8(out, 17);
+            put_u16(out, *bootstrap_method_attr_index);
+            put_u16(out, *name_and_type_index);
+        }
+        constant_pool::Type::Module { name_index } => {
+            put_u8(out, 19);
+            put_u16(out, *name_index);
+        }
+        constant_pool::Type::Package { name_index } => {
+            put_u8(out, 20);
+            put_u16(out, *name_index);
+        }
+        constant_pool::Type::Unknown => {
+            return Err(invalid_data("unknown constant pool entry".to_string()))
+        }
+    }
+    Ok(())
+}
+
+fn verification_type_info(out: &mut Vec<u8>, info: &VerificationTypeInfo) {
+    match info {
+        VerificationTypeInfo::Top => put_u8(out, 0),
+        VerificationTypeInfo::Integer => put_u8(out, 1),
+        VerificationTypeInfo::Float => put_u8(out, 2),
+        VerificationTypeInfo::Double => put_u8(out, 3),
+        VerificationTypeInfo::Long => put_u8(out, 4),
+        VerificationTypeInfo::Null => put_u8(out, 5),
+        VerificationTypeInfo::UninitializedThis => put_u8(out, 6),
+        VerificationTypeInfo::Object { cpool_index } => {
+            put_u8(out, 7);
+            put_u16(out, *cpool_index);
+        }
+        VerificationTypeInfo::Uninitialized { offset } => {
+            put_u8(out, 8);
+            put_u16(out, *offset);
+        }
+    }
+}
+
+fn stack_map_frame(out: &mut Vec<u8>, frame: &StackMapFrame) -> io::Result<()> {
+    match frame {
+        StackMapFrame::Same { tag, .. } => put_u8(out, *tag),
+        StackMapFrame::SameLocals1StackItem { tag, stack, .. } => {
+            put_u8(out, *tag);
+            verification_type_info(out, &stack[0]);
+        }
+        StackMapFrame::SameLocals1StackItemExtended {
+            offset_delta,
+            stack,
+            ..
+        } => {
+            put_u8(out, 247);
+            put_u16(out, *offset_delta);
+            verification_type_info(out, &stack[0]);
+        }
+        StackMapFrame::Chop {
+            tag, offset_delta, ..
+        } => {
+            put_u8(out, *tag);
+            put_u16(out, *offset_delta);
+        }
+        StackMapFrame::SameExtended { offset_delta, .. } => {
+            put_u8(out, 251);
+            put_u16(out, *offset_delta);
+        }
+        StackMapFrame::Append {
+            tag,
+            offset_delta,
+            locals,
+        } => {
+            put_u8(out, *tag);
+            put_u16(out, *offset_delta);
+            locals.iter().for_each(|l| verification_type_info(out, l));
+        }
+        StackMapFrame::Full {
+            offset_delta,
+            locals,
+            stack,
+            ..
+        } => {
+            put_u8(out, 255);
+            put_u16(out, *offset_delta);
+            put_len16(out, locals.len())?;
+            locals.iter().for_each(|l| verification_type_info(out, l));
+            put_len16(out, stack.len())?;
+            stack.iter().for_each(|s| verification_type_info(out, s));
+        }
+        StackMapFrame::Reserved(tag) => put_u8(out, *tag),
+    }
+    Ok(())
+}
+
+fn element_value(out: &mut Vec<u8>, value: &ElementValueType) -> io::Result<()> {
+    match value {
+        ElementValueType::Byte { val_index } => {
+            put_u8(out, b'B');
+            put_u16(out, *val_index);
+        }
+        ElementValueType::Char { val_index } => {
+            put_u8(out, b'C');
+            put_u16(out, *val_index);
+        }
+        ElementValueType::Double { val_index } => {
+            put_u8(out, b'D');
+            put_u16(out, *val_index);
+        }
+        ElementValueType::Float { val_index } => {
+            put_u8(out, b'F');
+            put_u16(out, *val_index);
+        }
+        ElementValueType::Int { val_index } => {
+            put_u8(out, b'I');
+            put_u16(out, *val_index);
+        }
+        ElementValueType::Long { val_index } => {
+            put_u8(out, b'J');
+            put_u16(out, *val_index);
+        }
+        ElementValueType::Short { val_index } => {
+            put_u8(out, b'S');
+            put_u16(out, *val_index);
+        }
+        ElementValueType::Boolean { val_index } => {
+            put_u8(out, b'Z');
+            put_u16(out, *val_index);
+        }
+        ElementValueType::String { val_index } => {
+            put_u8(out, b's');
+            put_u16(out, *val_index);
+        }
+        ElementValueType::Enum {
+            type_index,
+            val_index,
+        } => {
+            put_u8(out, b'e');
+            put_u16(out, *type_index);
+            put_u16(out, *val_index);
+        }
+        ElementValueType::Class { index } => {
+            put_u8(out, b'c');
+            put_u16(out, *index);
+        }
+        ElementValueType::Annotation(a) => {
+            put_u8(out, b'@');
+            annotation(out, &a.value)?;
+        }
+        ElementValueType::Array { values } => {
+            put_u8(out, b'[');
+            put_len16(out, values.len())?;
+            for v in values {
+                element_value(out, v)?;
+            }
+        }
+        ElementValueType::Unknown => return Err(invalid_data("unknown element value".to_string())),
+    }
+    Ok(())
+}
+
+fn element_value_pairs(out: &mut Vec<u8>, pairs: &[ElementValuePair]) -> io::Result<()> {
+    put_len16(out, pairs.len())?;
+    for pair in pairs {
+        put_u16(out, pair.name_index);
+        element_value(out, &pair.value)?;
+    }
+    Ok(())
+}
+
+fn annotation(out: &mut Vec<u8>, a: &AnnotationEntry) -> io::Result<()> {
+    put_u16(out, a.type_index);
+    element_value_pairs(out, &a.pairs)
+}
+
+fn annotations(out: &mut Vec<u8>, annotations: &[AnnotationEntry]) -> io::Result<()> {
+    put_len16(out, annotations.len())?;
+    for a in annotations {
+        annotation(out, a)?;
+    }
+    Ok(())
+}
+
+fn parameter_annotations(out: &mut Vec<u8>, parameters: &[Vec<AnnotationEntry>]) -> io::Result<()> {
+    if parameters.len() > u8::MAX as usize {
+        return Err(invalid_data(format!(
+            "too many annotated parameters: {}",
+            parameters.len()
+        )));
+    }
+    put_u8(out, parameters.len() as u8);
+    for p in parameters {
+        annotations(out, p)?;
+    }
+    Ok(())
+}
+
+fn target_info(out: &mut Vec<u8>, info: &TargetInfo) -> io::Result<()> {
+    match info {
+        TargetInfo::TypeParameter {
+            type_parameter_index,
+        } => put_u8(out, *type_parameter_index),
+        TargetInfo::SuperType { supertype_index } => put_u16(out, *supertype_index),
+        TargetInfo::TypeParameterBound {
+            type_parameter_index,
+            bound_index,
+        } => {
+            put_u8(out, *type_parameter_index);
+            put_u8(out, *bound_index);
+        }
+        TargetInfo::Empty => (),
+        TargetInfo::FormalParameter {
+            formal_parameter_index,
+        } => put_u8(out, *formal_parameter_index),
+        TargetInfo::Throws { throws_type_index } => put_u16(out, *throws_type_index),
+        TargetInfo::LocalVar { table } => {
+            put_len16(out, table.len())?;
+            for t in table {
+                put_u16(out, t.start_pc);
+                put_u16(out, t.length);
+                put_u16(out, t.index);
+            }
+        }
+        TargetInfo::Catch {
+            exception_table_index,
+        } => put_u16(out, *exception_table_index),
+        TargetInfo::Offset { offset } => put_u16(out, *offset),
+        TargetInfo::TypeArgument {
+            offset,
+            type_argument_index,
+        } => {
+            put_u16(out, *offset);
+            put_u8(out, *type_argument_index);
+        }
+    }
+    Ok(())
+}
+
+fn type_annotations(out: &mut Vec<u8>, annotations: &[TypeAnnotation]) -> io::Result<()> {
+    put_len16(out, annotations.len())?;
+    for a in annotations {
+        put_u8(out, a.target_type);
+        target_info(out, &a.target_info)?;
+        if a.target_path.len() > u8::MAX as usize {
+            return Err(invalid_data("type path too long".to_string()));
+        }
+        put_u8(out, a.target_path.len() as u8);
+        for p in &a.target_path {
+            put_u8(out, p.type_path_kind);
+            put_u8(out, p.type_argument_index);
+        }
+        put_u16(out, a.type_index);
+        element_value_pairs(out, &a.pairs)?;
+    }
+    Ok(())
+}
+
+/// Re-encodes the `raw` copies kept next to decoded annotations so they stay
+/// in sync after the decoded form was modified (e.g. indices remapped).
+pub fn refresh_raw(attrs: &mut [AttributeType]) -> io::Result<()> {
+    for attr in attrs.iter_mut() {
+        let mut out = vec![];
+        match attr {
+            AttributeType::Code(code) => refresh_raw(&mut code.attrs)?,
+            AttributeType::Record { components } => {
+                for c in components.iter_mut() {
+                    refresh_raw(&mut c.attrs)?;
+                }
+            }
+            AttributeType::RuntimeVisibleAnnotations {
+                raw,
+                annotations: a,
+            }
+            | AttributeType::RuntimeInvisibleAnnotations {
+                raw,
+                annotations: a,
+            } => {
+                annotations(&mut out, a)?;
+                *raw = Arc::new(out);
+            }
+            AttributeType::RuntimeVisibleParameterAnnotations { raw, parameters }
+            | AttributeType::RuntimeInvisibleParameterAnnotations { raw, parameters } => {
+                parameter_annotations(&mut out, parameters)?;
+                *raw = Arc::new(out);
+            }
+            AttributeType::RuntimeVisibleTypeAnnotations {
+                raw,
+                annotations: a,
+            }
+            | AttributeType::RuntimeInvisibleTypeAnnotations {
+                raw,
+                annotations: a,
+            } => {
+                type_annotations(&mut out, a)?;
+                *raw = Arc::new(out);
+            }
+            AttributeType::AnnotationDefault { raw, default_value } => {
+                element_value(&mut out, default_value)?;
+                *raw = Arc::new(out);
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+struct Writer<'a> {
+    names: HashMap<&'a [u8], u16>,
+}
+
+impl<'a> Writer<'a> {
+    fn new(cp: &'a [constant_pool::Type]) -> Self {
+        let mut names = HashMap::new();
+        for (i, c) in cp.iter().enumerate() {
+            if let constant_pool::Type::Utf8 { bytes } = c {
+                names.entry(bytes.as_slice()).or_insert(i as u16);
+            }
+        }
+        Self { names }
+    }
+
+    fn attrs(&self, out: &mut Vec<u8>, attrs: &[AttributeType]) -> io::Result<()> {
+        put_len16(out, attrs.len())?;
+        for attr in attrs {
+            let name = attr.name();
+            let name_index = *self.names.get(name).ok_or_else(|| {
+                invalid_data(format!(
+                    "missing attribute name in constant pool: {}",
+                    String::from_utf8_lossy(name)
+                ))
+            })?;
+            put_u16(out, name_index);
+
+            let mut body = vec![];
+            self.attr_body(&mut body, attr)?;
+            put_u32(out, body.len() as u32);
+            out.extend_from_slice(&body);
+        }
+        Ok(())
+    }
+
+    fn attr_body(&self, out: &mut Vec<u8>, attr: &AttributeType) -> io::Result<()> {
+        match attr {
+            AttributeType::ConstantValue {
+                constant_value_index,
+            } => put_u16(out, *constant_value_index),
+            AttributeType::Code(code) => {
+                put_u16(out, code.max_stack);
+                put_u16(out, code.max_locals);
+                put_u32(out, code.code.len() as u32);
+                out.extend_from_slice(&code.code);
+                put_len16(out, code.exceptions.len())?;
+                for e in &code.exceptions {
+                    put_u16(out, e.start_pc);
+                    put_u16(out, e.end_pc);
+                    put_u16(out, e.handler_pc);
+                    put_u16(out, e.catch_type);
+                }
+                self.attrs(out, &code.attrs)?;
+            }
+            AttributeType::StackMapTable { entries } => {
+                put_len16(out, entries.len())?;
+                for frame in entries {
+                    stack_map_frame(out, frame)?;
+                }
+            }
+            AttributeType::Exceptions { exceptions } => {
+                put_len16(out, exceptions.len())?;
+                exceptions.iter().for_each(|e| put_u16(out, *e));
+            }
+            AttributeType::InnerClasses { classes } => {
+                put_len16(out, classes.len())?;
+                for c in classes {
+                    put_u16(out, c.inner_class_info_index);
+                    put_u16(out, c.outer_class_info_index);
+                    put_u16(out, c.inner_name_index);
+                    put_u16(out, c.inner_class_access_flags);
+                }
+            }
+            AttributeType::EnclosingMethod { em } => {
+                put_u16(out, em.class_index);
+                put_u16(out, em.method_index);
+            }
+            AttributeType::Synthetic | AttributeType::Deprecated => (),
+            AttributeType::Signature { signature_index } => put_u16(out, *signature_index),
+            AttributeType::SourceFile { source_file_index } => put_u16(out, *source_file_index),
+            AttributeType::SourceDebugExtension { debug_extension } => {
+                out.extend_from_slice(debug_extension)
+            }
+            AttributeType::LineNumberTable { tables } => {
+                put_len16(out, tables.len())?;
+                for t in tables {
+                    put_u16(out, t.start_pc);
+                    put_u16(out, t.number);
+                }
+            }
+            AttributeType::LocalVariableTable { tables }
+            | AttributeType::LocalVariableTypeTable { tables } => {
+                put_len16(out, tables.len())?;
+                for t in tables {
+                    put_u16(out, t.start_pc);
+                    put_u16(out, t.length);
+                    put_u16(out, t.name_index);
+                    put_u16(out, t.signature_index);
+                    put_u16(out, t.index);
+                }
+            }
+            AttributeType::RuntimeVisibleAnnotations { annotations: a, .. }
+            | AttributeType::RuntimeInvisibleAnnotations { annotations: a, .. } => {
+                annotations(out, a)?
+            }
+            AttributeType::RuntimeVisibleParameterAnnotations { parameters, .. }
+            | AttributeType::RuntimeInvisibleParameterAnnotations { parameters, .. } => {
+                parameter_annotations(out, parameters)?
+            }
+            AttributeType::RuntimeVisibleTypeAnnotations { annotations: a, .. }
+            | AttributeType::RuntimeInvisibleTypeAnnotations { annotations: a, .. } => {
+                type_annotations(out, a)?
+            }
+            AttributeType::AnnotationDefault { default_value, .. } => {
+                element_value(out, default_value)?
+            }
+            AttributeType::BootstrapMethods { methods, .. } => {
+                put_len16(out, methods.len())?;
+                for m in methods {
+                    put_u16(out, m.method_ref);
+                    put_len16(out, m.args.len())?;
+                    m.args.iter().for_each(|a| put_u16(out, *a));
+                }
+            }
+            AttributeType::MethodParameters { parameters } => {
+                if parameters.len() > u8::MAX as usize {
+                    return Err(invalid_data("too many method parameters".to_string()));
+                }
+                put_u8(out, parameters.len() as u8);
+                for p in parameters {
+                    put_u16(out, p.name_index);
+                    put_u16(out, p.acc_flags);
+                }
+            }
+            AttributeType::NestHost { host_class_index } => put_u16(out, *host_class_index),
+            AttributeType::NestMembers { classes }
+            | AttributeType::PermittedSubclasses { classes } => {
+                put_len16(out, classes.len())?;
+                classes.iter().for_each(|c| put_u16(out, *c));
+            }
+            AttributeType::Record { components } => {
+                put_len16(out, components.len())?;
+                for c in components {
+                    put_u16(out, c.name_index);
+                    put_u16(out, c.descriptor_index);
+                    self.attrs(out, &c.attrs)?;
+                }
+            }
+            AttributeType::Unknown { data, .. } | AttributeType::Custom { data, .. } => {
+                out.extend_from_slice(data)
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The contents of `attr` as stored in a class with constant pool `cp`,
+/// without the name and length in front.
+pub(crate) fn attribute_body(
+    cp: &[constant_pool::Type],
+    attr: &AttributeType,
+) -> io::Result<Vec<u8>> {
+    let mut out = vec![];
+    Writer::new(cp).attr_body(&mut out, attr)?;
+    Ok(out)
+}
+
+/// Serializes a class back into the class file format (spec 4.1).
+pub fn write(cf: &ClassFile) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    out.extend_from_slice(b"\xCA\xFE\xBA\xBE");
+    put_u16(&mut out, cf.version.minor);
+    put_u16(&mut out, cf.version.major);
+
+    put_len16(&mut out, cf.cp.len())?;
+    for c in cf.cp.iter() {
+        constant(&mut out, c)?;
+    }
+
+    put_u16(&mut out, cf.acc_flags);
+    put_u16(&mut out, cf.this_class);
+    put_u16(&mut out, cf.super_class);
+    put_len16(&mut out, cf.interfaces.len())?;
+    cf.interfaces.iter().for_each(|i| put_u16(&mut out, *i));
+
+    let w = Writer::new(&cf.cp);
+
+    put_len16(&mut out, cf.fields.len())?;
+    for f in &cf.fields {
+        put_u16(&mut out, f.acc_flags);
+        put_u16(&mut out, f.name_index);
+        put_u16(&mut out, f.desc_index);
+        w.attrs(&mut out, &f.attrs)?;
+    }
+
+    put_len16(&mut out, cf.methods.len())?;
+    for m in &cf.methods {
+        put_u16(&mut out, m.acc_flags);
+        put_u16(&mut out, m.name_index);
+        put_u16(&mut out, m.desc_index);
+        w.attrs(&mut out, &m.attrs)?;
+    }
+
+    w.attrs(&mut out, &cf.attrs)?;
+
+    Ok(out)
+}
@@ -0,0 +1,159 @@
+//! An on-disk index of the jars of a classpath: the files of each jar and
+//! what its manifest says, keyed by the jar's path and checked against its
+//! size and modification time. With a fresh record `ClassPathManager` needn't
+//! open a jar until a class is read from it.
+
+use super::jar::JarFile;
+use super::util;
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, Metadata};
+use std::io;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+const FORMAT_VERSION: u32 = 1;
+
+pub(crate) const MANIFEST: &str = "META-INF/MANIFEST.MF";
+pub(crate) const VERSIONS: &str = "META-INF/versions/";
+
+/// What the index knows of one jar.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedJar {
+    pub size: u64,
+    /// seconds and nanoseconds since the epoch
+    pub modified: (u64, u32),
+    /// the manifest's `Class-Path`
+    pub class_path: Option<String>,
+    /// the releases of a multi-release jar's versioned directories, newest
+    /// first
+    pub versions: Vec<u16>,
+    /// every file of the jar, directories left out
+    pub names: Vec<String>,
+}
+
+fn modified(metadata: &Metadata) -> (u64, u32) {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or((0, 0), |d| (d.as_secs(), d.subsec_nanos()))
+}
+
+// the manifest of `jar`, empty if it hasn't one
+pub(crate) fn read_manifest(jar: &JarFile) -> io::Result<Vec<u8>> {
+    match jar.by_name(MANIFEST) {
+        Some(entry) => jar.read(entry),
+        None => Ok(vec![]),
+    }
+}
+
+// the releases of the versioned directories of `jar` with `manifest`, newest
+// first, none unless it's a multi-release jar
+pub(crate) fn versions(jar: &JarFile, manifest: &[u8]) -> Vec<u16> {
+    let multi_release = util::manifest_attribute(manifest, "Multi-Release")
+        .is_some_and(|v| v.eq_ignore_ascii_case("true"));
+    if !multi_release {
+        return vec![];
+    }
+    // versioned directories only count from Java 9 on
+    let found: BTreeSet<u16> = jar
+        .names()
+        .filter_map(|n| n.strip_prefix(VERSIONS)?.split('/').next()?.parse().ok())
+        .filter(|&v| v >= 9)
+        .collect();
+    found.into_iter().rev().collect()
+}
+
+impl IndexedJar {
+    /// Reads the record of `jar`, whose file has `metadata`.
+    pub fn read(jar: &JarFile, metadata: &Metadata) -> io::Result<IndexedJar> {
+        let manifest = read_manifest(jar)?;
+        Ok(IndexedJar {
+            size: metadata.len(),
+            modified: modified(metadata),
+            class_path: util::manifest_attribute(&manifest, "Class-Path"),
+            versions: versions(jar, &manifest),
+            names: jar
+                .entries()
+                .iter()
+                .filter(|e| !e.is_dir())
+                .map(|e| e.name.clone())
+                .collect(),
+        })
+    }
+
+    /// Whether the record still describes a file with `metadata`.
+    pub fn is_fresh(&self, metadata: &Metadata) -> bool {
+        self.size == metadata.len() && self.modified == modified(metadata)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ClassPathIndex {
+    version: u32,
+    // by canonical path
+    jars: BTreeMap<String, IndexedJar>,
+}
+
+impl ClassPathIndex {
+    pub fn new() -> Self {
+        ClassPathIndex {
+            version: FORMAT_VERSION,
+            jars: BTreeMap::new(),
+        }
+    }
+
+    /// Reads an index `save` wrote. Indexes of another format version are
+    /// rejected.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let index: Self = serde_json::from_slice(&fs::read(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if index.version != FORMAT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("classpath index format {}", index.version),
+            ));
+        }
+        Ok(index)
+    }
+
+    /// Writes the index to a temporary file renamed to `path`, so a reader
+    /// never sees half of it.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(format!(".{}.tmp", std::process::id()));
+        let data = serde_json::to_vec(self).map_err(io::Error::from)?;
+        fs::write(&tmp, data)?;
+        fs::rename(&tmp, path)
+    }
+
+    pub fn len(&self) -> usize {
+        self.jars.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jars.is_empty()
+    }
+
+    /// The record of the jar at canonical path `path`, if it still matches
+    /// the file's `metadata`.
+    pub fn get(&self, path: &str, metadata: &Metadata) -> Option<&IndexedJar> {
+        self.jars.get(path).filter(|jar| jar.is_fresh(metadata))
+    }
+
+    pub fn insert(&mut self, path: String, jar: IndexedJar) {
+        self.jars.insert(path, jar);
+    }
+
+    /// Drops the records of jars that no longer exist.
+    pub fn prune(&mut self) {
+        self.jars.retain(|path, _| Path::new(path).is_file());
+    }
+
+    pub fn jars(&self) -> impl Iterator<Item = (&str, &IndexedJar)> {
+        self.jars.iter().map(|(path, jar)| (path.as_str(), jar))
+    }
+}
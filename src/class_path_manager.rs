@@ -1,96 +1,1519 @@
-use std::fs::{read, File};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::fs::{metadata, read, read_dir, File};
 use std::io::{self, Read};
-use std::path::Path;
-use std::sync::{Arc, Mutex, RwLock};
-use tracing::info;
-use zip::ZipArchive;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, RwLock, RwLockReadGuard};
+use tracing::{info, warn};
 
+use super::class_path_index::{self, ClassPathIndex, IndexedJar, VERSIONS};
+use super::jar::{self, JarFile};
+use super::jimage::{self, JImage};
 use super::util;
 
+/// A classpath entry besides directories, jars and images, such as classes
+/// generated in memory or fetched from a store. Files are named by `/`
+/// separated paths, classes as `java/lang/Object.class`.
+pub trait ClassSource: Send + Sync {
+    /// Identifies the source in results and messages, as a path does a jar.
+    fn name(&self) -> &str;
+
+    /// The contents of file `name`, or None if the source hasn't it.
+    fn read(&self, name: &str) -> Result<Option<Vec<u8>>, io::Error>;
+
+    /// Every file of the source, for listing and visiting the classpath.
+    fn names(&self) -> Result<Vec<String>, io::Error>;
+
+    /// Whether the source has file `name`.
+    fn contains(&self, name: &str) -> bool {
+        matches!(self.read(name), Ok(Some(_)))
+    }
+
+    /// Opens file `name` for reading in pieces; by default it's read whole
+    /// first.
+    fn open(&self, name: &str) -> Result<Option<ResourceReader>, io::Error> {
+        Ok(self.read(name)?.map(|data| {
+            let size = data.len() as u64;
+            ResourceReader::new(self.name(), size, Box::new(io::Cursor::new(data)))
+        }))
+    }
+}
+
+/// A `ClassSource` of files held in memory.
+#[derive(Debug, Clone, Default)]
+pub struct MemorySource {
+    name: String,
+    files: BTreeMap<String, Arc<Vec<u8>>>,
+}
+
+impl MemorySource {
+    pub fn new(name: &str) -> Self {
+        MemorySource {
+            name: name.to_string(),
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Adds file `name`, replacing one of the same name.
+    pub fn insert(&mut self, name: &str, data: Vec<u8>) {
+        self.files
+            .insert(name.trim_start_matches('/').to_string(), Arc::new(data));
+    }
+
+    /// Adds class `name`, `.` or `/` separated.
+    pub fn insert_class(&mut self, name: &str, data: Vec<u8>) {
+        self.insert(&format!("{}.class", name.replace('.', "/")), data);
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Vec<u8>> {
+        self.files
+            .remove(name.trim_start_matches('/'))
+            .map(|data| Arc::try_unwrap(data).unwrap_or_else(|data| (*data).clone()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+impl ClassSource for MemorySource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn read(&self, name: &str) -> Result<Option<Vec<u8>>, io::Error> {
+        Ok(self.files.get(name).map(|data| data.to_vec()))
+    }
+
+    fn names(&self) -> Result<Vec<String>, io::Error> {
+        Ok(self.files.keys().cloned().collect())
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.files.contains_key(name)
+    }
+}
+
 enum ClassPathEntry {
     Dir(String),
-    Jar(ZipRef, String),
+    Jar(JarEntry),
+    Image(Arc<JImage>, String),
+    Source(Arc<dyn ClassSource>),
+}
+
+struct JarEntry {
+    path: String,
+    // the directory of the jar the entry is, like `BOOT-INF/classes/`, or
+    // empty for all of it
+    prefix: String,
+    // the releases of a multi-release jar's versioned directories, newest
+    // first
+    versions: Vec<u16>,
+    // opened on first use when the index vouched for the jar
+    file: OnceLock<Arc<JarFile>>,
+    // the files of the jar, from the index
+    names: Option<HashSet<String>>,
+}
+
+impl JarEntry {
+    // a jar, or directory of one, that's open already
+    fn opened(path: String, prefix: &str, file: Arc<JarFile>, versions: Vec<u16>) -> Self {
+        JarEntry {
+            path,
+            prefix: prefix.to_string(),
+            versions,
+            file: OnceLock::from(file),
+            names: None,
+        }
+    }
+
+    fn file(&self) -> Result<&Arc<JarFile>, io::Error> {
+        if let Some(file) = self.file.get() {
+            return Ok(file);
+        }
+        let file = Arc::new(JarFile::open(&self.path)?);
+        Ok(self.file.get_or_init(|| file))
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        match (&self.names, self.file.get()) {
+            (Some(names), _) => names.contains(name),
+            (None, Some(file)) => file.by_name(&self.entry_name(name)).is_some(),
+            (None, None) => false,
+        }
+    }
+
+    fn entry_name(&self, name: &str) -> String {
+        format!("{}{}", self.prefix, name)
+    }
+
+    // the entry `name` is read from and the release it's for, see
+    // `versioned_names`; the jar is open after
+    fn lookup(
+        &self,
+        name: &str,
+        release: Option<u16>,
+    ) -> Result<Option<(&jar::Entry, Option<u16>)>, io::Error> {
+        let (name, version) = match versioned_names(&self.versions, release, name)
+            .into_iter()
+            .find(|(name, _)| self.contains(name))
+        {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+        let file = self.file()?;
+        Ok(file
+            .by_name(&self.entry_name(&name))
+            .map(|entry| (entry, version)))
+    }
+
+    // the files of the entry, as named on the classpath
+    fn names(&self) -> Result<Box<dyn Iterator<Item = &str> + '_>, io::Error> {
+        match &self.names {
+            Some(names) => Ok(Box::new(names.iter().map(|n| n.as_str()))),
+            None => Ok(Box::new(self.files()?.map(|(name, _)| name))),
+        }
+    }
+
+    // the files of the jar in the entry, with their names on the classpath
+    fn files(&self) -> Result<impl Iterator<Item = (&str, &jar::Entry)>, io::Error> {
+        Ok(self.file()?.entries().iter().filter_map(move |e| {
+            let name = e.name.strip_prefix(self.prefix.as_str())?;
+            (!e.is_dir()).then_some((name, e))
+        }))
+    }
+}
+
+/// The classpath entry a file was found in, the path of the file itself for
+/// directories, its contents and, when a multi-release jar overrides it, the
+/// release of the `META-INF/versions` directory it came from.
+#[derive(Debug)]
+pub struct ClassPathResult(pub String, pub Vec<u8>, pub Option<u16>);
+
+/// A classpath file opened by `ClassPathManager::open_resource`.
+pub struct ResourceReader {
+    /// as in `ClassPathResult`
+    pub source: String,
+    /// the uncompressed length
+    pub size: u64,
+    /// as in `ClassPathResult`
+    pub release: Option<u16>,
+    inner: Box<dyn Read + Send>,
+}
+
+impl ResourceReader {
+    /// A reader of `size` bytes from `source`, for `ClassSource::open`.
+    pub fn new(source: &str, size: u64, inner: Box<dyn Read + Send>) -> Self {
+        ResourceReader {
+            source: source.to_string(),
+            size,
+            release: None,
+            inner,
+        }
+    }
+}
+
+impl Read for ResourceReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+/// Why a class or resource couldn't be read from the classpath. But for
+/// `NotFound`, each names the classpath entry and the file that failed, or
+/// no file when listing the entry failed.
+#[derive(Debug)]
+pub enum ClassPathError {
+    /// no classpath entry has the class or resource
+    NotFound(String),
+    /// the jar or image is damaged
+    Corrupt {
+        entry: String,
+        name: String,
+        source: io::Error,
+    },
+    PermissionDenied {
+        entry: String,
+        name: String,
+        source: io::Error,
+    },
+    /// the file is there but doesn't decompress, or doesn't match its
+    /// checksum
+    Decompress {
+        entry: String,
+        name: String,
+        source: io::Error,
+    },
+    /// other I/O failures
+    Io {
+        entry: String,
+        name: String,
+        source: io::Error,
+    },
+}
+
+impl ClassPathError {
+    // by the kind of `source`
+    fn new(entry: &str, name: &str, source: io::Error) -> Self {
+        let (entry, name) = (entry.to_string(), name.to_string());
+        match source.kind() {
+            io::ErrorKind::PermissionDenied => ClassPathError::PermissionDenied {
+                entry,
+                name,
+                source,
+            },
+            io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => ClassPathError::Corrupt {
+                entry,
+                name,
+                source,
+            },
+            _ => ClassPathError::Io {
+                entry,
+                name,
+                source,
+            },
+        }
+    }
+
+    fn from_jimage(entry: &str, name: &str, e: jimage::Error) -> Self {
+        match e {
+            jimage::Error::Io(e) => Self::new(entry, name, e),
+            jimage::Error::Format(_) => ClassPathError::Corrupt {
+                entry: entry.to_string(),
+                name: name.to_string(),
+                source: e.into(),
+            },
+            jimage::Error::Decompress(_) => ClassPathError::Decompress {
+                entry: entry.to_string(),
+                name: name.to_string(),
+                source: e.into(),
+            },
+        }
+    }
+
+    /// The classpath entry involved, None for `NotFound`.
+    pub fn entry(&self) -> Option<&str> {
+        match self {
+            ClassPathError::NotFound(_) => None,
+            ClassPathError::Corrupt { entry, .. }
+            | ClassPathError::PermissionDenied { entry, .. }
+            | ClassPathError::Decompress { entry, .. }
+            | ClassPathError::Io { entry, .. } => Some(entry),
+        }
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, ClassPathError::NotFound(_))
+    }
+}
+
+impl fmt::Display for ClassPathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (entry, name, source, problem) = match self {
+            ClassPathError::NotFound(name) => {
+                return write!(f, "not found on the classpath: {}", name)
+            }
+            ClassPathError::Corrupt {
+                entry,
+                name,
+                source,
+            } => (entry, name, source, "corrupt"),
+            ClassPathError::PermissionDenied {
+                entry,
+                name,
+                source,
+            } => (entry, name, source, "permission denied"),
+            ClassPathError::Decompress {
+                entry,
+                name,
+                source,
+            } => (entry, name, source, "can't decompress"),
+            ClassPathError::Io {
+                entry,
+                name,
+                source,
+            } => (entry, name, source, "can't read"),
+        };
+        if name.is_empty() {
+            write!(f, "{}: {}: {}", entry, problem, source)
+        } else {
+            write!(f, "{}: {}: {}: {}", entry, name, problem, source)
+        }
+    }
+}
+
+impl std::error::Error for ClassPathError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClassPathError::NotFound(_) => None,
+            ClassPathError::Corrupt { source, .. }
+            | ClassPathError::PermissionDenied { source, .. }
+            | ClassPathError::Decompress { source, .. }
+            | ClassPathError::Io { source, .. } => Some(source),
+        }
+    }
+}
+
+impl From<ClassPathError> for io::Error {
+    fn from(e: ClassPathError) -> Self {
+        let kind = match &e {
+            ClassPathError::NotFound(_) => io::ErrorKind::NotFound,
+            ClassPathError::PermissionDenied { .. } => io::ErrorKind::PermissionDenied,
+            ClassPathError::Corrupt { .. } | ClassPathError::Decompress { .. } => {
+                io::ErrorKind::InvalidData
+            }
+            ClassPathError::Io { source, .. } => source.kind(),
+        };
+        io::Error::new(kind, e)
+    }
+}
+
+/// What a lookup does when an entry it searches fails to read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorPolicy {
+    /// warn and go on with the next entry, as if the file weren't there
+    #[default]
+    Skip,
+    /// fail the lookup with the error
+    Abort,
+}
+
+// the path of resource `name` in directory entry `path`
+fn dir_file(path: &str, name: &str) -> String {
+    format!(
+        "{}{}{}",
+        path,
+        util::FILE_SEP,
+        name.replace('/', util::FILE_SEP)
+    )
+}
+
+// adds what's directly in `package` to `children`, given every file name of
+// an entry; jars and images needn't list their directories
+fn add_children<'a>(
+    children: &mut BTreeSet<String>,
+    package: &str,
+    names: impl Iterator<Item = &'a str>,
+) {
+    let prefix = if package.is_empty() {
+        String::new()
+    } else {
+        format!("{}/", package)
+    };
+    for name in names {
+        let rest = match name.strip_prefix(&prefix) {
+            Some(rest) if !rest.is_empty() => rest,
+            _ => continue,
+        };
+        let child = match rest.find('/') {
+            Some(i) => &rest[..=i],
+            None => rest,
+        };
+        children.insert(child.to_string());
+    }
+}
+
+// the entries `name` may be stored as in a jar with `versions`, preferred
+// first: the newest versioned one up to `release`, then the base one.
+// META-INF isn't versioned.
+fn versioned_names(
+    versions: &[u16],
+    release: Option<u16>,
+    name: &str,
+) -> Vec<(String, Option<u16>)> {
+    let mut names = vec![];
+    if !name.starts_with("META-INF/") {
+        for &v in versions.iter().filter(|&&v| release.is_none_or(|r| v <= r)) {
+            names.push((format!("{}{}/{}", VERSIONS, v, name), Some(v)));
+        }
+    }
+    names.push((name.to_string(), None));
+    names
+}
+
+impl ClassPathEntry {
+    fn read(
+        &self,
+        name: &str,
+        release: Option<u16>,
+    ) -> Result<Option<ClassPathResult>, ClassPathError> {
+        match self {
+            ClassPathEntry::Dir(path) => {
+                let p = dir_file(path, name);
+                match read(&p) {
+                    Ok(data) => Ok(Some(ClassPathResult(p, data, None))),
+                    Err(_) if !Path::new(&p).is_file() => Ok(None),
+                    Err(e) => Err(ClassPathError::new(path, name, e)),
+                }
+            }
+            ClassPathEntry::Jar(jar) => {
+                let error = |e| ClassPathError::new(&jar.path, name, e);
+                let (entry, version) = match jar.lookup(name, release).map_err(error)? {
+                    Some(found) => found,
+                    None => return Ok(None),
+                };
+                let file = jar.file().map_err(error)?;
+                file.raw(entry).map_err(error)?;
+                let data = file.read(entry).map_err(|e| ClassPathError::Decompress {
+                    entry: jar.path.clone(),
+                    name: name.to_string(),
+                    source: e,
+                })?;
+                Ok(Some(ClassPathResult(jar.path.clone(), data, version)))
+            }
+            ClassPathEntry::Image(image, path) => {
+                let loc = match image.resolve(name) {
+                    Some(loc) => loc,
+                    None => return Ok(None),
+                };
+                let data = image
+                    .read(&loc)
+                    .map_err(|e| ClassPathError::from_jimage(path, name, e))?;
+                Ok(Some(ClassPathResult(path.clone(), data, None)))
+            }
+            ClassPathEntry::Source(source) => Ok(source
+                .read(name)
+                .map_err(|e| ClassPathError::new(source.name(), name, e))?
+                .map(|data| ClassPathResult(source.name().to_string(), data, None))),
+        }
+    }
+
+    fn open(
+        &self,
+        name: &str,
+        release: Option<u16>,
+    ) -> Result<Option<ResourceReader>, ClassPathError> {
+        match self {
+            ClassPathEntry::Dir(path) => {
+                let p = dir_file(path, name);
+                let opened = File::open(&p).and_then(|f| Ok((f.metadata()?, f)));
+                let (metadata, f) = match opened {
+                    Ok(opened) if opened.0.is_file() => opened,
+                    Ok(_) => return Ok(None),
+                    Err(_) if !Path::new(&p).is_file() => return Ok(None),
+                    Err(e) => return Err(ClassPathError::new(path, name, e)),
+                };
+                Ok(Some(ResourceReader {
+                    source: p,
+                    size: metadata.len(),
+                    release: None,
+                    inner: Box::new(f),
+                }))
+            }
+            ClassPathEntry::Jar(jar) => {
+                let error = |e| ClassPathError::new(&jar.path, name, e);
+                let (entry, version) = match jar.lookup(name, release).map_err(error)? {
+                    Some(found) => found,
+                    None => return Ok(None),
+                };
+                Ok(Some(ResourceReader {
+                    source: jar.path.clone(),
+                    size: entry.size,
+                    release: version,
+                    inner: JarFile::reader(jar.file().map_err(error)?, entry).map_err(error)?,
+                }))
+            }
+            ClassPathEntry::Image(image, path) => {
+                let loc = match image.resolve(name) {
+                    Some(loc) => loc,
+                    None => return Ok(None),
+                };
+                let data = image
+                    .read(&loc)
+                    .map_err(|e| ClassPathError::from_jimage(path, name, e))?;
+                // compressed resources can only be restored whole
+                Ok(Some(ResourceReader {
+                    source: path.clone(),
+                    size: loc.uncompressed_size,
+                    release: None,
+                    inner: Box::new(io::Cursor::new(data)),
+                }))
+            }
+            ClassPathEntry::Source(source) => source
+                .open(name)
+                .map_err(|e| ClassPathError::new(source.name(), name, e)),
+        }
+    }
+
+    // adds what's directly in `package` to `names`, see `list_package`
+    fn package_children(
+        &self,
+        package: &str,
+        names: &mut BTreeSet<String>,
+    ) -> Result<(), ClassPathError> {
+        match self {
+            ClassPathEntry::Dir(path) => {
+                let dir = Path::new(path).join(package);
+                if !dir.is_dir() {
+                    return Ok(());
+                }
+                let error = |e| ClassPathError::new(path, package, e);
+                for e in read_dir(&dir).map_err(error)? {
+                    let e = e.map_err(error)?;
+                    let mut name = e.file_name().to_string_lossy().into_owned();
+                    if e.path().is_dir() {
+                        name.push('/');
+                    }
+                    names.insert(name);
+                }
+            }
+            ClassPathEntry::Jar(jar) => {
+                let files = jar
+                    .names()
+                    .map_err(|e| ClassPathError::new(&jar.path, "", e))?;
+                add_children(names, package, files);
+            }
+            ClassPathEntry::Image(image, path) => {
+                let resources: Vec<_> = image
+                    .resources()
+                    .map_err(|e| ClassPathError::from_jimage(path, "", e))?
+                    .iter()
+                    .map(|l| l.name())
+                    .collect();
+                add_children(names, package, resources.iter().map(|n| n.as_str()));
+            }
+            ClassPathEntry::Source(source) => {
+                let files = source
+                    .names()
+                    .map_err(|e| ClassPathError::new(source.name(), "", e))?;
+                add_children(names, package, files.iter().map(|n| n.as_str()));
+            }
+        }
+        Ok(())
+    }
+
+    // the files of the entry in the order `visit_entries` goes through them
+    fn files(&self, index: usize) -> Result<Vec<EntryFile>, ClassPathError> {
+        let file = |name: String| EntryFile {
+            entry: index,
+            name,
+            location: None,
+        };
+        let files = match self {
+            ClassPathEntry::Dir(path) => {
+                dir_files(path).map(|names| names.into_iter().map(file).collect())
+            }
+            ClassPathEntry::Jar(jar) => jar
+                .files()
+                .map(|files| files.map(|(name, _)| file(name.to_string())).collect()),
+            ClassPathEntry::Image(image, _) => image
+                .resources()
+                .map(|locs| {
+                    locs.into_iter()
+                        .map(|loc| EntryFile {
+                            entry: index,
+                            name: loc.name(),
+                            location: Some(loc),
+                        })
+                        .collect()
+                })
+                .map_err(io::Error::from),
+            ClassPathEntry::Source(source) => source
+                .names()
+                .map(|names| names.into_iter().map(file).collect()),
+        };
+        files.map_err(|e| ClassPathError::new(self.path(), "", e))
+    }
+
+    // the contents of `file`, None if it's gone since it was listed
+    fn read_file(&self, file: &EntryFile) -> Result<Option<Vec<u8>>, ClassPathError> {
+        let name = file.name.as_str();
+        match (self, &file.location) {
+            (ClassPathEntry::Dir(path), _) => read(Path::new(path).join(name))
+                .map(Some)
+                .map_err(|e| ClassPathError::new(path, name, e)),
+            (ClassPathEntry::Jar(jar), _) => {
+                let error = |e| ClassPathError::new(&jar.path, name, e);
+                let jar_file = jar.file().map_err(error)?;
+                let entry = match jar_file.by_name(&jar.entry_name(name)) {
+                    Some(entry) => entry,
+                    None => return Ok(None),
+                };
+                jar_file.raw(entry).map_err(error)?;
+                jar_file
+                    .read(entry)
+                    .map(Some)
+                    .map_err(|e| ClassPathError::Decompress {
+                        entry: jar.path.clone(),
+                        name: name.to_string(),
+                        source: e,
+                    })
+            }
+            (ClassPathEntry::Image(image, path), Some(loc)) => image
+                .read(loc)
+                .map(Some)
+                .map_err(|e| ClassPathError::from_jimage(path, name, e)),
+            (ClassPathEntry::Image(..), None) => Ok(None),
+            (ClassPathEntry::Source(source), _) => source
+                .read(name)
+                .map_err(|e| ClassPathError::new(source.name(), name, e)),
+        }
+    }
+
+    // whether `name` is in the entry, without reading it
+    fn has(&self, name: &str, release: Option<u16>) -> bool {
+        match self {
+            ClassPathEntry::Dir(path) => Path::new(&dir_file(path, name)).is_file(),
+            ClassPathEntry::Jar(jar) => versioned_names(&jar.versions, release, name)
+                .iter()
+                .any(|(name, _)| jar.contains(name)),
+            ClassPathEntry::Image(image, _) => image.resolve(name).is_some(),
+            ClassPathEntry::Source(source) => source.contains(name),
+        }
+    }
+
+    fn path(&self) -> &str {
+        match self {
+            ClassPathEntry::Dir(path) | ClassPathEntry::Image(_, path) => path,
+            ClassPathEntry::Jar(jar) => &jar.path,
+            ClassPathEntry::Source(source) => source.name(),
+        }
+    }
+
+    // the files of the entry, `/` separated and sorted
+    fn file_names(&self) -> Result<Vec<String>, ClassPathError> {
+        let names = match self {
+            ClassPathEntry::Dir(path) => dir_files(path),
+            ClassPathEntry::Jar(jar) => jar.names().map(|names| names.map(String::from).collect()),
+            ClassPathEntry::Image(image, _) => image
+                .resources()
+                .map(|locs| locs.iter().map(|l| l.name()).collect())
+                .map_err(io::Error::from),
+            ClassPathEntry::Source(source) => source.names(),
+        };
+        let mut names = names.map_err(|e| ClassPathError::new(self.path(), "", e))?;
+        names.sort();
+        Ok(names)
+    }
+
+    // the classes of the entry, as `/` separated names without `.class`; a
+    // multi-release jar's versioned classes count as the base ones
+    fn class_names(&self) -> Result<BTreeSet<String>, ClassPathError> {
+        Ok(self
+            .file_names()?
+            .iter()
+            .filter_map(|name| class_name(name))
+            .collect())
+    }
+}
+
+// the class file `name` is of, if any
+fn class_name(name: &str) -> Option<String> {
+    let name = match name.strip_prefix(VERSIONS) {
+        Some(rest) => &rest[rest.find('/')? + 1..],
+        None if name.starts_with("META-INF/") => return None,
+        None => name,
+    };
+    let class = name.strip_suffix(".class")?;
+    // every modular jar has one
+    if class == "module-info" {
+        return None;
+    }
+    Some(class.to_string())
+}
+
+// the package of `/` separated class `name`, None in the unnamed package
+fn package_of(class: &str) -> Option<&str> {
+    Some(&class[..class.rfind('/')?])
+}
+
+/// Which names an enumeration of the classpath yields. Class and package
+/// names are matched `/` separated, with `.` in the filter read as `/`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum NameFilter {
+    #[default]
+    All,
+    Prefix(String),
+    /// as in `util::glob_match`, `*` matching any run of characters
+    Glob(String),
+}
+
+impl NameFilter {
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            NameFilter::All => true,
+            NameFilter::Prefix(prefix) => name.starts_with(prefix.as_str()),
+            NameFilter::Glob(pattern) => util::glob_match(pattern, name),
+        }
+    }
+
+    fn for_classes(&self) -> NameFilter {
+        match self {
+            NameFilter::All => NameFilter::All,
+            NameFilter::Prefix(prefix) => NameFilter::Prefix(prefix.replace('.', "/")),
+            NameFilter::Glob(pattern) => NameFilter::Glob(pattern.replace('.', "/")),
+        }
+    }
+}
+
+/// A class on the classpath, as `ClassPathManager::classes` finds it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassEntry {
+    /// `/` separated
+    pub name: String,
+    /// the classpath entry it's in
+    pub source: String,
+    /// the earlier entry that has the class too, whose copy is the one
+    /// loaded
+    pub shadowed_by: Option<String>,
+}
+
+impl ClassEntry {
+    pub fn is_shadowed(&self) -> bool {
+        self.shadowed_by.is_some()
+    }
+}
+
+/// The classes on a classpath, see `ClassPathManager::classes`. The
+/// classpath can't be added to while it's alive.
+pub struct Classes<'a> {
+    cpm: &'a ClassPathManager,
+    class_path: RwLockReadGuard<'a, Vec<ClassPathEntry>>,
+    filter: NameFilter,
+    // the entry `pending` is of and the one after
+    current: usize,
+    next: usize,
+    pending: std::collections::btree_set::IntoIter<String>,
+    // the entry each class was first seen in
+    first: HashMap<String, usize>,
+    failed: bool,
 }
 
-pub struct ClassPathResult(pub String, pub Vec<u8>);
+impl Iterator for Classes<'_> {
+    type Item = Result<ClassEntry, ClassPathError>;
 
-type ZipRef = Arc<Mutex<Box<ZipArchive<File>>>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            for name in self.pending.by_ref() {
+                if !self.filter.matches(&name) {
+                    continue;
+                }
+                let first = *self.first.entry(name.clone()).or_insert(self.current);
+                let shadowed_by = Some(first)
+                    .filter(|&first| first != self.current)
+                    .map(|first| self.class_path[first].path().to_string());
+                return Some(Ok(ClassEntry {
+                    name,
+                    source: self.class_path[self.current].path().to_string(),
+                    shadowed_by,
+                }));
+            }
+            if self.failed || self.next >= self.class_path.len() {
+                return None;
+            }
+            self.current = self.next;
+            self.next += 1;
+            match self.class_path[self.current].class_names() {
+                Ok(names) => self.pending = names.into_iter(),
+                Err(e) => {
+                    if let Err(e) = self.cpm.skip(e) {
+                        self.failed = true;
+                        return Some(Err(e));
+                    }
+                }
+            }
+        }
+    }
+}
 
+/// A package on the classpath and the entries with classes in it, in
+/// classpath order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Package {
+    /// `/` separated
+    pub name: String,
+    pub sources: Vec<String>,
+}
+
+// the files under directory `path`, `/` separated and relative to it; a
+// directory's files in name order, then its subdirectories'
+fn dir_files(path: &str) -> Result<Vec<String>, io::Error> {
+    let mut files = vec![];
+    let mut pending = vec![String::new()];
+    while let Some(rel) = pending.pop() {
+        let dir = Path::new(path).join(&rel);
+        let mut children = read_dir(&dir)?
+            .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+            .collect::<Result<Vec<_>, _>>()?;
+        children.sort();
+        let mut dirs = vec![];
+        for child in children {
+            let name = if rel.is_empty() {
+                child
+            } else {
+                format!("{}/{}", rel, child)
+            };
+            if Path::new(path).join(&name).is_dir() {
+                dirs.push(name);
+            } else {
+                files.push(name);
+            }
+        }
+        // reversed so the stack pops them in order
+        pending.extend(dirs.into_iter().rev());
+    }
+    Ok(files)
+}
+
+/// A file of a classpath entry, listed by `ClassPathManager::entry_files` to
+/// be read later, possibly on another thread.
+#[derive(Debug, Clone)]
+pub(crate) struct EntryFile {
+    /// the index of the classpath entry
+    pub entry: usize,
+    /// `/` separated, as `visit_entries` names it
+    pub name: String,
+    // an image may have a name in more than one module
+    location: Option<jimage::Location>,
+}
+
+/// A class, or package, that more than one classpath entry has.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    /// `/` separated
+    pub name: String,
+    /// the entries that have it, in classpath order
+    pub sources: Vec<String>,
+}
+
+#[derive(Default)]
 pub struct ClassPathManager {
     class_path: RwLock<Vec<ClassPathEntry>>,
+    // canonical paths of the jars on the classpath, manifests don't add a
+    // jar again so Class-Path cycles end
+    jars: HashSet<PathBuf>,
+    // None for the newest
+    release: Option<u16>,
+    index: Option<ClassPathIndex>,
+    // whether jars were indexed since the index was loaded
+    index_changed: bool,
+    expand_boot_jars: bool,
+    error_policy: ErrorPolicy,
+}
+
+// between a jar and the path of a jar or directory in it
+const NESTED_SEP: &str = "!/";
+
+const BOOT_INF: &str = "BOOT-INF/";
+const BOOT_CLASSES: &str = "BOOT-INF/classes/";
+const BOOT_LIB: &str = "BOOT-INF/lib/";
+const BOOT_CLASSPATH_INDEX: &str = "BOOT-INF/classpath.idx";
+
+// the directory of a `dir/*` wildcard entry
+fn wildcard_dir(path: &str) -> Option<&str> {
+    let dir = path.strip_suffix('*')?;
+    if dir.is_empty() {
+        Some(".")
+    } else if dir.ends_with('/') || dir.ends_with(util::FILE_SEP) {
+        Some(dir)
+    } else {
+        None
+    }
+}
+
+// the file that has to exist for classpath entry `path` to be read: the
+// directory of a wildcard, the outermost jar of a nested path
+fn backing_file(path: &str) -> &str {
+    if let Some(dir) = wildcard_dir(path) {
+        return dir;
+    }
+    match path.split_once(NESTED_SEP) {
+        Some((outer, _)) if !Path::new(path).exists() => outer,
+        _ => path,
+    }
+}
+
+fn nested_not_found(path: &str, name: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{}: no jar or directory {}", path, name),
+    )
+}
+
+fn is_jar(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("jar"))
+}
+
+// undoes the %XX escapes of a Class-Path URL
+fn decode_url_path(url: &str) -> String {
+    let bytes = url.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                out.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn conflicts(sources: BTreeMap<String, Vec<String>>) -> Vec<Conflict> {
+    sources
+        .into_iter()
+        .filter(|(_, sources)| sources.len() > 1)
+        .map(|(name, sources)| Conflict { name, sources })
+        .collect()
 }
 
 impl ClassPathManager {
     pub fn new() -> Self {
-        Self {
-            class_path: RwLock::new(vec![]),
-        }
+        Self::default()
+    }
+
+    /// The Java release multi-release jars are read for: a class or resource
+    /// comes from the newest `META-INF/versions/N` directory with N no
+    /// greater than `release`, else from the base of the jar. By default
+    /// the newest directory is used.
+    pub fn set_release(&mut self, release: u16) {
+        self.release = Some(release);
     }
 
+    pub fn release(&self) -> Option<u16> {
+        self.release
+    }
+
+    /// Has jars in the Spring Boot layout added as the classpath they launch
+    /// with: `BOOT-INF/classes/`, then the jars of `BOOT-INF/lib/` in the
+    /// order of `BOOT-INF/classpath.idx`, read from within the jar. Such
+    /// jars are opened when added even if indexed.
+    pub fn set_expand_boot_jars(&mut self, expand: bool) {
+        self.expand_boot_jars = expand;
+    }
+
+    /// Uses the index at `path` for the jars added from now on: a jar whose
+    /// size and modification time match its record isn't opened until a
+    /// class or resource is read from it. Jars without a fresh record are
+    /// indexed as they're added, for `save_index`. A missing or unreadable
+    /// index is started over.
+    pub fn load_index<P: AsRef<Path>>(&mut self, path: P) {
+        let path = path.as_ref();
+        let index = match ClassPathIndex::load(path) {
+            Ok(index) => index,
+            Err(e) => {
+                if e.kind() != io::ErrorKind::NotFound {
+                    warn!("{}: starting a new classpath index: {}", path.display(), e);
+                }
+                ClassPathIndex::new()
+            }
+        };
+        self.index = Some(index);
+        self.index_changed = false;
+    }
+
+    /// Writes the index if a jar was indexed since `load_index`, dropping the
+    /// records of jars that no longer exist. Returns whether it was written.
+    pub fn save_index<P: AsRef<Path>>(&mut self, path: P) -> Result<bool, io::Error> {
+        let index = match &mut self.index {
+            Some(index) if self.index_changed => index,
+            _ => return Ok(false),
+        };
+        index.prune();
+        index.save(path)?;
+        self.index_changed = false;
+        Ok(true)
+    }
+
+    pub fn index(&self) -> Option<&ClassPathIndex> {
+        self.index.as_ref()
+    }
+
+    /// Adds a directory, a jar, or with `dir/*` every jar in `dir`, like
+    /// `java -cp`. The jars a jar's manifest lists in `Class-Path` follow it;
+    /// those that don't exist are skipped with a warning. A jar or directory
+    /// inside a jar is added as `app.jar!/BOOT-INF/lib/lib.jar` or
+    /// `app.jar!/BOOT-INF/classes/`, without extracting it.
     pub fn add_class_path(&mut self, path: &str) -> Result<(), io::Error> {
+        self.add(path, false)
+    }
+
+    fn add(&mut self, path: &str, from_manifest: bool) -> Result<(), io::Error> {
+        if let Some(dir) = wildcard_dir(path) {
+            let mut jars = read_dir(dir)?
+                .map(|e| e.map(|e| e.path()))
+                .collect::<Result<Vec<_>, _>>()?;
+            jars.retain(|p| is_jar(p));
+            jars.sort();
+            return jars
+                .iter()
+                .try_for_each(|jar| self.add_jar(jar, &jar.to_string_lossy(), from_manifest));
+        }
+
         let p = Path::new(path);
+        if path.contains(NESTED_SEP) && !p.exists() {
+            return self.add_nested(path);
+        }
         if p.is_dir() {
             self.class_path
                 .write()
                 .unwrap()
                 .push(ClassPathEntry::Dir(path.to_string()));
+            Ok(())
+        } else if jimage::is_jimage(p) {
+            let image = JImage::open(p)?;
+            self.class_path
+                .write()
+                .unwrap()
+                .push(ClassPathEntry::Image(Arc::new(image), path.to_string()));
+            Ok(())
+        } else {
+            self.add_jar(p, path, from_manifest)
+        }
+    }
+
+    fn add_jar(&mut self, p: &Path, path: &str, from_manifest: bool) -> Result<(), io::Error> {
+        let canonical = p.canonicalize()?;
+        if !self.jars.insert(canonical.clone()) && from_manifest {
+            return Ok(());
+        }
+        let key = canonical.to_string_lossy().into_owned();
+        let meta = metadata(p)?;
+        let indexed = self
+            .index
+            .as_ref()
+            .and_then(|i| i.get(&key, &meta))
+            .cloned();
+        let (entry, class_path) = match indexed {
+            Some(record) => (
+                JarEntry {
+                    path: path.to_string(),
+                    prefix: String::new(),
+                    versions: record.versions,
+                    file: OnceLock::new(),
+                    names: Some(record.names.into_iter().collect()),
+                },
+                record.class_path,
+            ),
+            None => {
+                let file = JarFile::open(p)?;
+                let record = IndexedJar::read(&file, &meta)?;
+                let entry = JarEntry::opened(
+                    path.to_string(),
+                    "",
+                    Arc::new(file),
+                    record.versions.clone(),
+                );
+                let class_path = record.class_path.clone();
+                if let Some(index) = &mut self.index {
+                    index.insert(key, record);
+                    self.index_changed = true;
+                }
+                (entry, class_path)
+            }
+        };
+        if self.expand_boot_jars && entry.names()?.any(|n| n.starts_with(BOOT_INF)) {
+            self.add_boot_jar(&entry)?;
         } else {
-            let f = File::open(p)?;
-            let z = ZipArchive::new(f)?;
-            let handle = Arc::new(Mutex::new(Box::new(z)));
             self.class_path
                 .write()
                 .unwrap()
-                .push(ClassPathEntry::Jar(handle, path.to_string()));
+                .push(ClassPathEntry::Jar(entry));
+        }
+
+        // relative URLs, resolved against the directory of the jar
+        let base = p.parent().unwrap_or_else(|| Path::new(""));
+        for url in class_path.iter().flat_map(|cp| cp.split_whitespace()) {
+            let rel = url.strip_prefix("file:").unwrap_or(url);
+            if rel.contains("://") {
+                warn!("{}: ignoring Class-Path entry {}", path, url);
+                continue;
+            }
+            let dep = base.join(decode_url_path(rel));
+            let dep_path = dep.to_string_lossy().into_owned();
+            if !dep.exists() {
+                warn!("{}: skipping missing Class-Path entry {}", path, dep_path);
+            } else if let Err(e) = self.add(&dep_path, true) {
+                warn!("{}: skipping Class-Path entry {}: {}", path, dep_path, e);
+            }
         }
 
         Ok(())
     }
 
+    // `outer.jar!/lib/inner.jar`, any number deep, or `outer.jar!/dir/` for a
+    // directory of a jar
+    fn add_nested(&mut self, path: &str) -> Result<(), io::Error> {
+        let depth = path.matches(NESTED_SEP).count();
+        let mut parts = path.split(NESTED_SEP);
+        let mut file = Arc::new(JarFile::open(parts.next().unwrap_or_default())?);
+        let mut prefix = "";
+        for (i, part) in parts.enumerate() {
+            match file.by_name(part) {
+                Some(entry) if !entry.is_dir() => {
+                    file = Arc::new(JarFile::open_nested(&file, entry)?);
+                }
+                // the last part may name a directory, or be empty for the
+                // whole jar
+                _ if i + 1 == depth
+                    && (part.is_empty()
+                        || part.ends_with('/') && file.names().any(|n| n.starts_with(part))) =>
+                {
+                    prefix = part;
+                }
+                _ => return Err(nested_not_found(path, part)),
+            }
+        }
+        let versions = if prefix.is_empty() {
+            class_path_index::versions(&file, &class_path_index::read_manifest(&file)?)
+        } else {
+            vec![]
+        };
+        self.class_path
+            .write()
+            .unwrap()
+            .push(ClassPathEntry::Jar(JarEntry::opened(
+                path.to_string(),
+                prefix,
+                file,
+                versions,
+            )));
+        Ok(())
+    }
+
+    // adds the classes and libraries of a Spring Boot jar in place of the jar
+    fn add_boot_jar(&mut self, outer: &JarEntry) -> Result<(), io::Error> {
+        let file = outer.file()?.clone();
+        let manifest = class_path_index::read_manifest(&file)?;
+        let attribute = |key: &str, default: &str| {
+            util::manifest_attribute(&manifest, key).unwrap_or_else(|| default.to_string())
+        };
+        let classes = attribute("Spring-Boot-Classes", BOOT_CLASSES);
+        let lib = attribute("Spring-Boot-Lib", BOOT_LIB);
+        let index = attribute("Spring-Boot-Classpath-Index", BOOT_CLASSPATH_INDEX);
+
+        // the libraries the classpath index lists, in its order, then the
+        // others in the order they're stored
+        let mut libs: Vec<String> = match file.by_name(&index) {
+            Some(entry) => String::from_utf8_lossy(&file.read(entry)?)
+                .lines()
+                .filter_map(|line| {
+                    let name = line.strip_prefix("- ")?.trim().trim_matches('"');
+                    Some(name.to_string()).filter(|name| !name.is_empty())
+                })
+                .collect(),
+            None => vec![],
+        };
+        let indexed: HashSet<String> = libs.iter().cloned().collect();
+        libs.extend(
+            file.names()
+                .filter(|n| n.starts_with(&lib) && n.ends_with(".jar") && !indexed.contains(*n))
+                .map(|n| n.to_string()),
+        );
+
+        let mut entries = vec![ClassPathEntry::Jar(JarEntry::opened(
+            format!("{}{}{}", outer.path, NESTED_SEP, classes),
+            &classes,
+            file.clone(),
+            vec![],
+        ))];
+        for name in libs {
+            let nested = file
+                .by_name(&name)
+                .ok_or_else(|| nested_not_found(&outer.path, &name))
+                .and_then(|entry| JarFile::open_nested(&file, entry))
+                .and_then(|jar| {
+                    let versions =
+                        class_path_index::versions(&jar, &class_path_index::read_manifest(&jar)?);
+                    Ok((Arc::new(jar), versions))
+                });
+            let path = format!("{}{}{}", outer.path, NESTED_SEP, name);
+            match nested {
+                Ok((jar, versions)) => entries.push(ClassPathEntry::Jar(JarEntry::opened(
+                    path, "", jar, versions,
+                ))),
+                Err(e) => warn!("skipping {}: {}", path, e),
+            }
+        }
+        self.class_path.write().unwrap().extend(entries);
+        Ok(())
+    }
+
+    /// Adds `source` after the entries added so far; it's searched in turn
+    /// like a directory or jar.
+    pub fn add_source(&mut self, source: Arc<dyn ClassSource>) {
+        self.class_path
+            .write()
+            .unwrap()
+            .push(ClassPathEntry::Source(source));
+    }
+
+    /// Adds the entries of a `PATH_SEP` separated classpath in order. Entries
+    /// that don't exist are skipped with a warning, as `java` does.
     pub fn add_class_paths(&mut self, path: &str) -> Result<(), io::Error> {
-        path.split(util::PATH_SEP)
-            .try_for_each(|p| -> Result<(), io::Error> { self.add_class_path(p) })
+        for p in path.split(util::PATH_SEP).filter(|p| !p.is_empty()) {
+            if !Path::new(backing_file(p)).exists() {
+                warn!("skipping missing classpath entry {}", p);
+                continue;
+            }
+            self.add_class_path(p)?;
+        }
+        Ok(())
     }
 
-    pub fn search_class(&self, name: &str) -> Result<ClassPathResult, io::Error> {
-        let name = name.replace(".", util::FILE_SEP);
+    /// Whether lookups skip entries that fail to read, the default, or fail.
+    pub fn set_error_policy(&mut self, policy: ErrorPolicy) {
+        self.error_policy = policy;
+    }
+
+    pub fn error_policy(&self) -> ErrorPolicy {
+        self.error_policy
+    }
+
+    // `e`, unless the policy is to skip it
+    pub(crate) fn skip(&self, e: ClassPathError) -> Result<(), ClassPathError> {
+        match self.error_policy {
+            ErrorPolicy::Skip => {
+                warn!("skipping {}", e);
+                Ok(())
+            }
+            ErrorPolicy::Abort => Err(e),
+        }
+    }
+
+    // what `f` finds in the first entry it finds anything in
+    fn find_first<T, F>(&self, name: &str, mut f: F) -> Result<T, ClassPathError>
+    where
+        F: FnMut(&ClassPathEntry) -> Result<Option<T>, ClassPathError>,
+    {
+        for it in self.class_path.read().unwrap().iter() {
+            match f(it) {
+                Ok(Some(found)) => return Ok(found),
+                Ok(None) => {}
+                Err(e) => self.skip(e)?,
+            }
+        }
+        Err(ClassPathError::NotFound(name.to_string()))
+    }
 
+    pub fn search_class(&self, name: &str) -> Result<ClassPathResult, ClassPathError> {
         info!("search_class: {}", name);
 
+        let resource = format!("{}.class", name.replace(".", "/"));
+        self.find_first(name, |it| it.read(&resource, self.release))
+    }
+
+    /// The first file named `name` on the classpath, a `/` separated path like
+    /// `META-INF/services/java.sql.Driver`.
+    pub fn find_resource(&self, name: &str) -> Result<ClassPathResult, ClassPathError> {
+        let name = name.trim_start_matches('/');
+        self.find_first(name, |it| it.read(name, self.release))
+    }
+
+    /// Every file named `name` on the classpath, in classpath order, like
+    /// `ClassLoader.getResources`.
+    pub fn find_resources(&self, name: &str) -> Result<Vec<ClassPathResult>, ClassPathError> {
+        let name = name.trim_start_matches('/');
+        let mut found = vec![];
         for it in self.class_path.read().unwrap().iter() {
-            match it {
-                ClassPathEntry::Dir(path) => {
-                    let mut p = String::from(path);
-                    p.push_str(util::FILE_SEP);
-                    p.push_str(&name);
-                    p.push_str(".class");
-                    if let Ok(data) = read(&p) {
-                        return Ok(ClassPathResult(p, data));
-                    }
+            match it.read(name, self.release) {
+                Ok(result) => found.extend(result),
+                Err(e) => self.skip(e)?,
+            }
+        }
+        Ok(found)
+    }
+
+    /// Opens the first file named `name` on the classpath for reading in
+    /// pieces.
+    pub fn open_resource(&self, name: &str) -> Result<ResourceReader, ClassPathError> {
+        let name = name.trim_start_matches('/');
+        self.find_first(name, |it| it.open(name, self.release))
+    }
+
+    /// The files and subdirectories directly in `package` (`/` or `.`
+    /// separated) across the classpath, sorted and without duplicates.
+    /// Subdirectories end with `/`. An entry that can't be listed is skipped
+    /// or fails the call, as the error policy says.
+    pub fn list_package(&self, package: &str) -> Result<Vec<String>, ClassPathError> {
+        let package = package.replace('.', "/");
+        let package = package.trim_matches('/');
+        let mut names = BTreeSet::new();
+        for it in self.class_path.read().unwrap().iter() {
+            if let Err(e) = it.package_children(package, &mut names) {
+                self.skip(e)?;
+            }
+        }
+        Ok(names.into_iter().collect())
+    }
+
+    /// Calls `f` with the classpath entry, the `/` separated entry name and
+    /// the contents of every file on the classpath, in classpath order. A
+    /// file or entry that can't be read is skipped or ends the walk with its
+    /// `ClassPathError`, as the error policy says; `f` fails it with an error
+    /// of its own.
+    pub fn visit_entries<F, E>(&self, mut f: F) -> Result<(), E>
+    where
+        F: FnMut(&str, &str, Vec<u8>) -> Result<(), E>,
+        E: From<ClassPathError>,
+    {
+        for (index, source) in self.entries().iter().enumerate() {
+            let files = match self.entry_files(index) {
+                Ok(files) => files,
+                Err(e) => {
+                    self.skip(e)?;
+                    continue;
+                }
+            };
+            for file in files {
+                match self.read_entry_file(&file) {
+                    Ok(Some(data)) => f(source, &file.name, data)?,
+                    Ok(None) => {}
+                    Err(e) => self.skip(e)?,
                 }
-                ClassPathEntry::Jar(handle, path) => {
-                    let mut p = String::from(&name);
-                    p.push_str(".class");
+            }
+        }
+        Ok(())
+    }
 
-                    let mut handle = handle.lock().unwrap();
-                    let zf = handle.by_name(&p);
+    // the files of the `index`th entry, in the order `visit_entries` reads
+    // them
+    pub(crate) fn entry_files(&self, index: usize) -> Result<Vec<EntryFile>, ClassPathError> {
+        match self.class_path.read().unwrap().get(index) {
+            Some(it) => it.files(index),
+            None => Ok(vec![]),
+        }
+    }
+
+    // the contents of a file `entry_files` listed, None if it's gone since
+    pub(crate) fn read_entry_file(
+        &self,
+        file: &EntryFile,
+    ) -> Result<Option<Vec<u8>>, ClassPathError> {
+        match self.class_path.read().unwrap().get(file.entry) {
+            Some(it) => it.read_file(file),
+            None => Ok(None),
+        }
+    }
 
-                    if let Ok(mut zf) = zf {
-                        let mut v = Vec::with_capacity(zf.size() as usize);
-                        let r = zf.read_to_end(&mut v);
+    /// Every classpath entry that has class `name`, in classpath order; the
+    /// first is the one `search_class` reads it from. Indexed jars answer
+    /// without being opened.
+    pub fn class_sources(&self, name: &str) -> Vec<String> {
+        let resource = format!("{}.class", name.replace('.', "/"));
+        self.class_path
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|it| it.has(&resource, self.release))
+            .map(|it| it.path().to_string())
+            .collect()
+    }
 
-                        debug_assert!(r.is_ok());
+    /// Every class on the classpath that `filter` matches, entry by entry
+    /// in classpath order and by name within an entry. A class in more than
+    /// one entry is yielded for each, marked shadowed after the first. An
+    /// entry that can't be listed is skipped or ends the iteration with the
+    /// error, as the error policy says.
+    pub fn classes(&self, filter: &NameFilter) -> Classes<'_> {
+        Classes {
+            cpm: self,
+            class_path: self.class_path.read().unwrap(),
+            filter: filter.for_classes(),
+            current: 0,
+            next: 0,
+            pending: BTreeSet::new().into_iter(),
+            first: HashMap::new(),
+            failed: false,
+        }
+    }
 
-                        return Ok(ClassPathResult(path.clone(), v));
-                    }
+    /// The packages with classes on the classpath that `filter` matches, by
+    /// name. The unnamed package is left out.
+    pub fn packages(
+        &self,
+        filter: &NameFilter,
+    ) -> Result<impl Iterator<Item = Package>, ClassPathError> {
+        let filter = filter.for_classes();
+        let mut sources: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for it in self.class_path.read().unwrap().iter() {
+            let classes = match it.class_names() {
+                Ok(classes) => classes,
+                Err(e) => {
+                    self.skip(e)?;
+                    continue;
                 }
+            };
+            let packages: BTreeSet<&str> = classes.iter().filter_map(|c| package_of(c)).collect();
+            for package in packages.into_iter().filter(|p| filter.matches(p)) {
+                sources
+                    .entry(package.to_string())
+                    .or_default()
+                    .push(it.path().to_string());
             }
         }
+        Ok(sources
+            .into_iter()
+            .map(|(name, sources)| Package { name, sources }))
+    }
+
+    /// The classpath entries in order: the paths of directories, jars and
+    /// images, and the names of `ClassSource`s.
+    pub fn entries(&self) -> Vec<String> {
+        self.class_path
+            .read()
+            .unwrap()
+            .iter()
+            .map(|it| it.path().to_string())
+            .collect()
+    }
+
+    /// The files of the `index`th classpath entry that `filter` matches,
+    /// `/` separated and sorted.
+    pub fn entry_contents(
+        &self,
+        index: usize,
+        filter: &NameFilter,
+    ) -> Result<impl Iterator<Item = String>, ClassPathError> {
+        let class_path = self.class_path.read().unwrap();
+        let it = class_path
+            .get(index)
+            .ok_or_else(|| ClassPathError::NotFound(format!("classpath entry {}", index)))?;
+        let filter = filter.clone();
+        Ok(it
+            .file_names()?
+            .into_iter()
+            .filter(move |name| filter.matches(name)))
+    }
+
+    /// The classes more than one classpath entry has, by name. Only the
+    /// first of each is ever loaded.
+    pub fn duplicate_classes(&self) -> Result<Vec<Conflict>, ClassPathError> {
+        let mut sources: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for class in self.classes(&NameFilter::All) {
+            let class = class?;
+            sources.entry(class.name).or_default().push(class.source);
+        }
+        Ok(conflicts(sources))
+    }
 
-        Err(io::Error::new(
-            io::ErrorKind::NotFound,
-            format!("Search class failed: {}", name),
-        ))
+    /// The packages whose classes come from more than one classpath entry,
+    /// by name. Classes of the unnamed package are left out.
+    pub fn split_packages(&self) -> Result<Vec<Conflict>, ClassPathError> {
+        Ok(self
+            .packages(&NameFilter::All)?
+            .filter(|p| p.sources.len() > 1)
+            .map(|p| Conflict {
+                name: p.name,
+                sources: p.sources,
+            })
+            .collect())
     }
 
     pub fn size(&self) -> usize {
@@ -0,0 +1,358 @@
+//! A read-only zip reader for classpath jars. The file is memory mapped and
+//! the central directory indexed by name once when opening, after which
+//! lookups and reads only borrow the jar, so any number of threads can
+//! inflate entries of one jar at the same time. A jar stored in another one
+//! is read in place, a compressed one inflated into memory.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::{Deref, Range};
+use std::path::Path;
+use std::sync::Arc;
+
+use flate2::read::DeflateDecoder;
+use memmap2::Mmap;
+
+const LOCAL_HEADER_SIG: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIG: u32 = 0x0201_4b50;
+const END_SIG: u32 = 0x0605_4b50;
+const ZIP64_END_SIG: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR_SIG: u32 = 0x0706_4b50;
+
+const LOCAL_HEADER_SIZE: usize = 30;
+const CENTRAL_HEADER_SIZE: usize = 46;
+const END_SIZE: usize = 22;
+const ZIP64_END_SIZE: usize = 56;
+const ZIP64_LOCATOR_SIZE: usize = 20;
+const ZIP64_EXTRA_ID: u16 = 0x0001;
+
+const FLAG_ENCRYPTED: u16 = 1;
+
+// the most a deflate stream can expand
+const MAX_DEFLATE_RATIO: u64 = 1032;
+
+pub const STORED: u16 = 0;
+pub const DEFLATED: u16 = 8;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn u16_at(data: &[u8], pos: usize) -> u16 {
+    u16::from_le_bytes([data[pos], data[pos + 1]])
+}
+
+fn u32_at(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+}
+
+fn u64_at(data: &[u8], pos: usize) -> u64 {
+    u32_at(data, pos) as u64 | (u32_at(data, pos + 4) as u64) << 32
+}
+
+/// A file of the jar, as the central directory describes it.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub name: String,
+    /// `STORED` or `DEFLATED`, others can't be read
+    pub method: u16,
+    pub crc32: u32,
+    pub compressed_size: u64,
+    pub size: u64,
+    flags: u16,
+    // of the local header, from the start of the file
+    header_offset: u64,
+}
+
+impl Entry {
+    pub fn is_dir(&self) -> bool {
+        self.name.ends_with('/')
+    }
+}
+
+enum Data {
+    Map(Mmap),
+    // a stored entry of the outer jar
+    Nested(Arc<JarFile>, Range<usize>),
+    Owned(Vec<u8>),
+}
+
+impl Deref for Data {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Data::Map(map) => map,
+            Data::Nested(outer, range) => &outer.data[range.clone()],
+            Data::Owned(data) => data,
+        }
+    }
+}
+
+pub struct JarFile {
+    data: Data,
+    entries: Vec<Entry>,
+    index: HashMap<String, usize>,
+}
+
+impl JarFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<JarFile> {
+        let file = File::open(path)?;
+        // the jar mustn't be truncated while it's open, as with any reader
+        // that keeps the file open
+        let map = unsafe { Mmap::map(&file)? };
+        Self::with_data(Data::Map(map))
+    }
+
+    /// A jar held in memory.
+    pub fn from_bytes(data: Vec<u8>) -> io::Result<JarFile> {
+        Self::with_data(Data::Owned(data))
+    }
+
+    /// The jar stored as `entry` of `outer`, read where it is when stored
+    /// and inflated into memory when deflated.
+    pub fn open_nested(outer: &Arc<JarFile>, entry: &Entry) -> io::Result<JarFile> {
+        if entry.method != STORED {
+            return Self::from_bytes(outer.read(entry)?);
+        }
+        let range = outer.data_range(entry)?;
+        if crc32fast::hash(&outer.data[range.clone()]) != entry.crc32 {
+            return Err(invalid(format!("{}: CRC mismatch", entry.name)));
+        }
+        Self::with_data(Data::Nested(outer.clone(), range))
+    }
+
+    fn with_data(data: Data) -> io::Result<JarFile> {
+        let (entries, archive_offset) = read_central_directory(&data)?;
+
+        let mut index = HashMap::with_capacity(entries.len());
+        let mut entries = entries;
+        for (i, entry) in entries.iter_mut().enumerate() {
+            entry.header_offset = entry
+                .header_offset
+                .checked_add(archive_offset)
+                .ok_or_else(|| invalid(format!("{}: header offset out of range", entry.name)))?;
+            // the first of duplicate names wins, like ZipFile
+            index.entry(entry.name.clone()).or_insert(i);
+        }
+        Ok(JarFile {
+            data,
+            entries,
+            index,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// In central directory order.
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<&Entry> {
+        self.index.get(name).map(|&i| &self.entries[i])
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|e| e.name.as_str())
+    }
+
+    // where the stored or compressed bytes of `entry` are in the file
+    fn data_range(&self, entry: &Entry) -> io::Result<Range<usize>> {
+        if entry.flags & FLAG_ENCRYPTED != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{} is encrypted", entry.name),
+            ));
+        }
+        let header = entry.header_offset as usize;
+        let local = self
+            .data
+            .get(header..header.saturating_add(LOCAL_HEADER_SIZE))
+            .filter(|local| u32_at(local, 0) == LOCAL_HEADER_SIG)
+            .ok_or_else(|| invalid(format!("{}: bad local header", entry.name)))?;
+        // the local extra field needn't match the central one
+        let start =
+            header + LOCAL_HEADER_SIZE + u16_at(local, 26) as usize + u16_at(local, 28) as usize;
+        match start.checked_add(entry.compressed_size as usize) {
+            Some(end) if end <= self.data.len() => Ok(start..end),
+            _ => Err(invalid(format!(
+                "{}: data past the end of the jar",
+                entry.name
+            ))),
+        }
+    }
+
+    /// The bytes of `entry` as stored, compressed or not.
+    pub fn raw(&self, entry: &Entry) -> io::Result<&[u8]> {
+        let range = self.data_range(entry)?;
+        Ok(&self.data[range])
+    }
+
+    /// Reads and checks the whole of `entry`.
+    pub fn read(&self, entry: &Entry) -> io::Result<Vec<u8>> {
+        let raw = self.raw(entry)?;
+        // the size comes from the directory and may be a lie
+        let capacity = entry.size.min(raw.len() as u64 * MAX_DEFLATE_RATIO);
+        let mut data = Vec::with_capacity(capacity as usize);
+        match entry.method {
+            STORED => data.extend_from_slice(raw),
+            DEFLATED => {
+                // one byte past the size is enough to tell it was wrong
+                DeflateDecoder::new(raw)
+                    .take(entry.size.saturating_add(1))
+                    .read_to_end(&mut data)?;
+            }
+            method => return Err(unsupported_method(entry, method)),
+        }
+        if data.len() as u64 != entry.size || crc32fast::hash(&data) != entry.crc32 {
+            return Err(invalid(format!("{}: size or CRC mismatch", entry.name)));
+        }
+        Ok(data)
+    }
+
+    /// Streams `entry`, holding on to the jar rather than borrowing it.
+    pub fn reader(jar: &Arc<JarFile>, entry: &Entry) -> io::Result<Box<dyn Read + Send>> {
+        let raw = RawReader {
+            jar: jar.clone(),
+            range: jar.data_range(entry)?,
+        };
+        match entry.method {
+            STORED => Ok(Box::new(raw)),
+            DEFLATED => Ok(Box::new(DeflateDecoder::new(raw))),
+            method => Err(unsupported_method(entry, method)),
+        }
+    }
+}
+
+fn unsupported_method(entry: &Entry, method: u16) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("{}: compression method {}", entry.name, method),
+    )
+}
+
+struct RawReader {
+    jar: Arc<JarFile>,
+    range: Range<usize>,
+}
+
+impl Read for RawReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.range.len());
+        buf[..n].copy_from_slice(&self.jar.data[self.range.start..self.range.start + n]);
+        self.range.start += n;
+        Ok(n)
+    }
+}
+
+// the entries, and how far the archive starts into the file, non-zero when
+// something like a launcher script is prepended
+fn read_central_directory(data: &[u8]) -> io::Result<(Vec<Entry>, u64)> {
+    if data.len() < END_SIZE {
+        return Err(invalid("not a zip file".into()));
+    }
+    // the end record is followed by a comment of up to 64k
+    let lowest = data.len().saturating_sub(END_SIZE + u16::MAX as usize);
+    let end = (lowest..=data.len() - END_SIZE)
+        .rev()
+        .find(|&pos| u32_at(data, pos) == END_SIG)
+        .ok_or_else(|| invalid("no end of central directory".into()))?;
+
+    let mut count = u16_at(data, end + 10) as u64;
+    let mut cd_size = u32_at(data, end + 12) as u64;
+    let mut cd_offset = u32_at(data, end + 16) as u64;
+    // where the central directory really is, to find the archive offset
+    let mut cd_end = end as u64;
+    if end >= ZIP64_LOCATOR_SIZE && u32_at(data, end - ZIP64_LOCATOR_SIZE) == ZIP64_LOCATOR_SIG {
+        let locator = end - ZIP64_LOCATOR_SIZE;
+        // the record usually sits right before the locator, whose offset
+        // doesn't account for a prefix
+        let zip64_end = [
+            locator.saturating_sub(ZIP64_END_SIZE),
+            u64_at(data, locator + 8) as usize,
+        ]
+        .iter()
+        .copied()
+        .find(|&pos| {
+            locator >= ZIP64_END_SIZE
+                && pos <= locator - ZIP64_END_SIZE
+                && u32_at(data, pos) == ZIP64_END_SIG
+        })
+        .ok_or_else(|| invalid("no zip64 end of central directory".into()))?;
+        count = u64_at(data, zip64_end + 32);
+        cd_size = u64_at(data, zip64_end + 40);
+        cd_offset = u64_at(data, zip64_end + 48);
+        cd_end = zip64_end as u64;
+    }
+    let cd_start = cd_end
+        .checked_sub(cd_size)
+        .ok_or_else(|| invalid("central directory larger than the file".into()))?;
+    let archive_offset = cd_start
+        .checked_sub(cd_offset)
+        .ok_or_else(|| invalid("bad central directory offset".into()))?;
+
+    let mut entries = Vec::with_capacity(count.min(1 << 20) as usize);
+    let mut pos = cd_start as usize;
+    for _ in 0..count {
+        let header = data
+            .get(pos..pos + CENTRAL_HEADER_SIZE)
+            .filter(|h| u32_at(h, 0) == CENTRAL_HEADER_SIG)
+            .ok_or_else(|| invalid(format!("bad central directory header at {}", pos)))?;
+        let name_len = u16_at(header, 28) as usize;
+        let extra_len = u16_at(header, 30) as usize;
+        let comment_len = u16_at(header, 32) as usize;
+        let name_start = pos + CENTRAL_HEADER_SIZE;
+        let extra_start = name_start + name_len;
+        let next = extra_start + extra_len + comment_len;
+        if next > data.len() {
+            return Err(invalid(format!("truncated central directory at {}", pos)));
+        }
+
+        let mut entry = Entry {
+            name: String::from_utf8_lossy(&data[name_start..extra_start]).into_owned(),
+            method: u16_at(header, 10),
+            crc32: u32_at(header, 16),
+            compressed_size: u32_at(header, 20) as u64,
+            size: u32_at(header, 24) as u64,
+            flags: u16_at(header, 8),
+            header_offset: u32_at(header, 42) as u64,
+        };
+        read_zip64_extra(&mut entry, &data[extra_start..extra_start + extra_len]);
+        entries.push(entry);
+        pos = next;
+    }
+    Ok((entries, archive_offset))
+}
+
+// the zip64 extra field holds the values the header has as all ones, in
+// this order
+fn read_zip64_extra(entry: &mut Entry, mut extra: &[u8]) {
+    while extra.len() >= 4 {
+        let id = u16_at(extra, 0);
+        let len = (u16_at(extra, 2) as usize).min(extra.len() - 4);
+        if id == ZIP64_EXTRA_ID {
+            let mut values = extra[4..4 + len].chunks_exact(8).map(|v| u64_at(v, 0));
+            for field in [
+                &mut entry.size,
+                &mut entry.compressed_size,
+                &mut entry.header_offset,
+            ] {
+                if *field == u32::MAX as u64 {
+                    if let Some(v) = values.next() {
+                        *field = v;
+                    }
+                }
+            }
+            return;
+        }
+        extra = &extra[4 + len..];
+    }
+}
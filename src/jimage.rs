@@ -0,0 +1,573 @@
+//! Reads the jimage container the JDK keeps its modules in (`lib/modules`
+//! since JDK 9), following the layout of `jdk.internal.jimage`: a header, a
+//! perfect hash over the resource names (the redirect and offsets tables),
+//! the location attributes, a string table and then the resources, some of
+//! them compressed.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Mutex;
+
+use flate2::read::ZlibDecoder;
+
+pub const MAGIC: u32 = 0xCAFE_DADA;
+pub const MAJOR_VERSION: u16 = 1;
+const HEADER_SIZE: usize = 7 * 4;
+
+pub const COMPRESSED_MAGIC: u32 = 0xCAFE_FAFA;
+const COMPRESSED_HEADER_SIZE: usize = 29;
+
+// the hash of jdk.internal.jimage.ImageStringsReader
+pub const HASH_MULTIPLIER: i32 = 0x0100_0193;
+
+const ATTRIBUTE_END: u8 = 0;
+const ATTRIBUTE_MODULE: u8 = 1;
+const ATTRIBUTE_PARENT: u8 = 2;
+const ATTRIBUTE_BASE: u8 = 3;
+const ATTRIBUTE_EXTENSION: u8 = 4;
+const ATTRIBUTE_OFFSET: u8 = 5;
+const ATTRIBUTE_COMPRESSED: u8 = 6;
+const ATTRIBUTE_UNCOMPRESSED: u8 = 7;
+
+// the directory trees the image lists its modules and packages in, under
+// `/modules` and `/packages`; they aren't resources of a module
+const PSEUDO_MODULES: [&str; 2] = ["modules", "packages"];
+
+// constant pool tags the string sharing compressor adds
+const EXTERNALIZED_STRING: u8 = 23;
+const EXTERNALIZED_STRING_DESCRIPTOR: u8 = 25;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// the file isn't a jimage or is damaged
+    Format(String),
+    /// a compressed resource can't be restored
+    Decompress(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Format(message) => write!(f, "bad jimage: {}", message),
+            Error::Decompress(message) => write!(f, "can't decompress resource: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+/// The hash the image's tables are built with, masked to be positive.
+pub fn hash_code(name: &str, seed: i32) -> i32 {
+    name.bytes()
+        .fold(seed, |h, b| h.wrapping_mul(HASH_MULTIPLIER) ^ b as i32)
+        & 0x7FFF_FFFF
+}
+
+/// Where a resource is and what it's called.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub module: String,
+    /// the `/` separated package
+    pub parent: String,
+    pub base: String,
+    pub extension: String,
+    /// from the end of the index
+    pub offset: u64,
+    /// 0 when the resource is stored as is
+    pub compressed_size: u64,
+    pub uncompressed_size: u64,
+}
+
+impl Location {
+    /// The name the image is looked up by, `/java.base/java/lang/Object.class`.
+    pub fn full_name(&self) -> String {
+        let mut name = String::new();
+        if !self.module.is_empty() {
+            name.push('/');
+            name.push_str(&self.module);
+            name.push('/');
+        }
+        name.push_str(&self.name());
+        name
+    }
+
+    /// The name without the module, as on a classpath.
+    pub fn name(&self) -> String {
+        let mut name = String::new();
+        if !self.parent.is_empty() {
+            name.push_str(&self.parent);
+            name.push('/');
+        }
+        name.push_str(&self.base);
+        if !self.extension.is_empty() {
+            name.push('.');
+            name.push_str(&self.extension);
+        }
+        name
+    }
+
+    fn is_resource(&self) -> bool {
+        !self.module.is_empty() && !PSEUDO_MODULES.contains(&self.module.as_str())
+    }
+}
+
+pub struct JImage {
+    big_endian: bool,
+    resource_count: u32,
+    table_length: usize,
+    locations_size: usize,
+    // the header, tables, locations and strings
+    index: Vec<u8>,
+    file: Mutex<File>,
+    file_len: u64,
+    // package to the module it's in
+    packages: HashMap<String, String>,
+}
+
+impl fmt::Debug for JImage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("JImage")
+            .field("big_endian", &self.big_endian)
+            .field("resource_count", &self.resource_count)
+            .field("packages", &self.packages.len())
+            .finish()
+    }
+}
+
+/// Whether `path` starts like a jimage.
+pub fn is_jimage(path: &Path) -> bool {
+    let mut magic = [0; 4];
+    File::open(path)
+        .and_then(|mut f| f.read_exact(&mut magic))
+        .is_ok()
+        && (u32::from_le_bytes(magic) == MAGIC || u32::from_be_bytes(magic) == MAGIC)
+}
+
+impl JImage {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<JImage, Error> {
+        let mut file = File::open(path)?;
+        let mut header = [0; HEADER_SIZE];
+        file.read_exact(&mut header)?;
+        // written in the byte order of the platform that built it
+        let big_endian = match header[..4].try_into().unwrap() {
+            b if u32::from_le_bytes(b) == MAGIC => false,
+            b if u32::from_be_bytes(b) == MAGIC => true,
+            _ => return Err(Error::Format("no jimage magic".into())),
+        };
+        let u4 = |i: usize| {
+            let b = header[i * 4..i * 4 + 4].try_into().unwrap();
+            if big_endian {
+                u32::from_be_bytes(b)
+            } else {
+                u32::from_le_bytes(b)
+            }
+        };
+        let version = u4(1);
+        if (version >> 16) as u16 != MAJOR_VERSION {
+            return Err(Error::Format(format!(
+                "unsupported version {}.{}",
+                version >> 16,
+                version & 0xFFFF
+            )));
+        }
+        let table_length = u4(4) as usize;
+        let locations_size = u4(5) as usize;
+        let strings_size = u4(6) as usize;
+        let index_size = HEADER_SIZE + table_length * 8 + locations_size + strings_size;
+        let file_len = file.metadata()?.len();
+        if index_size as u64 > file_len {
+            return Err(Error::Format("index is longer than the file".into()));
+        }
+
+        let mut index = header.to_vec();
+        index.resize(index_size, 0);
+        file.read_exact(&mut index[HEADER_SIZE..])?;
+        let mut image = JImage {
+            big_endian,
+            resource_count: u4(3),
+            table_length,
+            locations_size,
+            index,
+            file: Mutex::new(file),
+            file_len,
+            packages: HashMap::new(),
+        };
+
+        // modules don't share packages, if they did the first by name wins
+        let mut packages = HashMap::new();
+        for loc in image.resources()? {
+            if !loc.parent.is_empty() {
+                packages.entry(loc.parent).or_insert(loc.module);
+            }
+        }
+        image.packages = packages;
+        Ok(image)
+    }
+
+    fn u4(&self, pos: usize) -> u32 {
+        self.u4_of(&self.index, pos)
+    }
+
+    fn redirect(&self, i: usize) -> i32 {
+        self.u4(HEADER_SIZE + i * 4) as i32
+    }
+
+    fn offset(&self, i: usize) -> u32 {
+        self.u4(HEADER_SIZE + (self.table_length + i) * 4)
+    }
+
+    fn locations_start(&self) -> usize {
+        HEADER_SIZE + self.table_length * 8
+    }
+
+    fn strings_start(&self) -> usize {
+        self.locations_start() + self.locations_size
+    }
+
+    /// Where the resources start in the file.
+    pub fn index_size(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn resource_count(&self) -> u32 {
+        self.resource_count
+    }
+
+    fn string_bytes(&self, offset: u64) -> Result<&[u8], Error> {
+        let strings = &self.index[self.strings_start()..];
+        let rest = strings
+            .get(offset as usize..)
+            .ok_or_else(|| Error::Format(format!("string offset {} out of range", offset)))?;
+        let end = rest
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| Error::Format(format!("unterminated string at {}", offset)))?;
+        Ok(&rest[..end])
+    }
+
+    /// The string at `offset` of the string table.
+    pub fn string(&self, offset: u64) -> Result<String, Error> {
+        self.string_bytes(offset)
+            .map(|b| String::from_utf8_lossy(b).into_owned())
+    }
+
+    fn location_at(&self, offset: u32) -> Result<Location, Error> {
+        let attrs = self.index[self.locations_start()..self.strings_start()]
+            .get(offset as usize..)
+            .ok_or_else(|| Error::Format(format!("location offset {} out of range", offset)))?;
+        let mut values = [0u64; 8];
+        let mut pos = 0;
+        while let Some(&byte) = attrs.get(pos) {
+            let kind = byte >> 3;
+            if kind == ATTRIBUTE_END {
+                break;
+            }
+            let len = (byte & 7) as usize + 1;
+            let value = attrs
+                .get(pos + 1..pos + 1 + len)
+                .ok_or_else(|| Error::Format(format!("truncated location at {}", offset)))?;
+            let value = value.iter().fold(0u64, |v, &b| v << 8 | b as u64);
+            if let Some(slot) = values.get_mut(kind as usize) {
+                *slot = value;
+            }
+            pos += 1 + len;
+        }
+        Ok(Location {
+            module: self.string(values[ATTRIBUTE_MODULE as usize])?,
+            parent: self.string(values[ATTRIBUTE_PARENT as usize])?,
+            base: self.string(values[ATTRIBUTE_BASE as usize])?,
+            extension: self.string(values[ATTRIBUTE_EXTENSION as usize])?,
+            offset: values[ATTRIBUTE_OFFSET as usize],
+            compressed_size: values[ATTRIBUTE_COMPRESSED as usize],
+            uncompressed_size: values[ATTRIBUTE_UNCOMPRESSED as usize],
+        })
+    }
+
+    /// Looks up a full name like `/java.base/java/lang/Object.class`.
+    pub fn find(&self, full_name: &str) -> Option<Location> {
+        let len = self.table_length as i32;
+        if len == 0 {
+            return None;
+        }
+        let index = match self.redirect((hash_code(full_name, HASH_MULTIPLIER) % len) as usize) {
+            0 => return None,
+            r if r < 0 => !r,
+            seed => hash_code(full_name, seed) % len,
+        };
+        if index < 0 || index >= len {
+            return None;
+        }
+        // the hash is perfect for the names in the image only
+        self.location_at(self.offset(index as usize))
+            .ok()
+            .filter(|loc| loc.full_name() == full_name)
+    }
+
+    /// Looks up a classpath style name like `java/lang/Object.class` in the
+    /// module of its package, or a name that starts with its module like
+    /// `java.base/java/lang/Object.class`.
+    pub fn resolve(&self, name: &str) -> Option<Location> {
+        let name = name.trim_start_matches('/');
+        if let Some(loc) = self.find(&format!("/{}", name)) {
+            if loc.is_resource() {
+                return Some(loc);
+            }
+        }
+        let package = &name[..name.rfind('/')?];
+        let module = self.packages.get(package)?;
+        self.find(&format!("/{}/{}", module, name))
+    }
+
+    /// The module `package` (`/` separated) is in.
+    pub fn module_of(&self, package: &str) -> Option<&str> {
+        self.packages.get(package).map(|m| m.as_str())
+    }
+
+    /// Every location in the image, the `/modules` and `/packages` trees
+    /// included, in table order.
+    pub fn locations(&self) -> Result<Vec<Location>, Error> {
+        (0..self.table_length)
+            .map(|i| self.location_at(self.offset(i)))
+            .collect()
+    }
+
+    /// The resources of the modules, sorted by full name.
+    pub fn resources(&self) -> Result<Vec<Location>, Error> {
+        let mut locations = self.locations()?;
+        locations.retain(Location::is_resource);
+        locations.sort_by_cached_key(Location::full_name);
+        Ok(locations)
+    }
+
+    /// The uncompressed contents of a resource.
+    pub fn read(&self, loc: &Location) -> Result<Vec<u8>, Error> {
+        let size = if loc.compressed_size != 0 {
+            loc.compressed_size
+        } else {
+            loc.uncompressed_size
+        };
+        // the location is checked before it sizes a buffer
+        let start = self.index.len() as u64 + loc.offset.min(self.file_len);
+        if start
+            .checked_add(size)
+            .is_none_or(|end| end > self.file_len)
+        {
+            return Err(Error::Format(format!(
+                "{} ends past the end of the file",
+                loc.full_name()
+            )));
+        }
+        let mut data = vec![0; size as usize];
+        {
+            let mut file = self.file.lock().unwrap();
+            file.seek(SeekFrom::Start(start))?;
+            file.read_exact(&mut data)?;
+        }
+        if loc.compressed_size != 0 {
+            data = self.decompress(data)?;
+        }
+        Ok(data)
+    }
+
+    // undoes the compressors in turn, each leaves a header in front
+    fn decompress(&self, mut data: Vec<u8>) -> Result<Vec<u8>, Error> {
+        while data.len() >= COMPRESSED_HEADER_SIZE && self.u4_of(&data, 0) == COMPRESSED_MAGIC {
+            let u8_at = |pos: usize| {
+                let b = data[pos..pos + 8].try_into().unwrap();
+                if self.big_endian {
+                    u64::from_be_bytes(b)
+                } else {
+                    u64::from_le_bytes(b)
+                }
+            };
+            let uncompressed_size = u8_at(12);
+            let name = self.string(self.u4_of(&data, 20) as u64)?;
+            let content = &data[COMPRESSED_HEADER_SIZE..];
+            let out = match name.as_str() {
+                "zip" => {
+                    // the header's size only bounds the output, it may be
+                    // far larger than any real resource
+                    let mut out = vec![];
+                    ZlibDecoder::new(content)
+                        .take(uncompressed_size.saturating_add(1))
+                        .read_to_end(&mut out)
+                        .map_err(|e| Error::Decompress(format!("zip: {}", e)))?;
+                    out
+                }
+                "compact-cp" => self.unshare_strings(content)?,
+                _ => return Err(Error::Decompress(format!("unknown decompressor {}", name))),
+            };
+            if out.len() as u64 != uncompressed_size {
+                return Err(Error::Decompress(format!(
+                    "{}: expected {} bytes, got {}",
+                    name,
+                    uncompressed_size,
+                    out.len()
+                )));
+            }
+            data = out;
+        }
+        Ok(data)
+    }
+
+    fn u4_of(&self, data: &[u8], pos: usize) -> u32 {
+        let b = data[pos..pos + 4].try_into().unwrap();
+        if self.big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        }
+    }
+
+    // the string sharing compressor moves constant pool strings to the string
+    // table, descriptors split into the descriptor with bare `L;` class
+    // types and the package and simple name of each class
+    fn unshare_strings(&self, content: &[u8]) -> Result<Vec<u8>, Error> {
+        let truncated = || Error::Decompress("compact-cp: truncated class".into());
+        let mut r = SharedReader {
+            data: content,
+            pos: 0,
+        };
+        let mut out = Vec::with_capacity(content.len() * 2);
+        // magic and version
+        out.extend_from_slice(r.take(8).ok_or_else(truncated)?);
+        let count = r.take(2).ok_or_else(truncated)?;
+        out.extend_from_slice(count);
+        let count = u16::from_be_bytes([count[0], count[1]]);
+
+        let mut i = 1;
+        while i < count {
+            let tag = r.take(1).ok_or_else(truncated)?[0];
+            let size = match tag {
+                1 => {
+                    let len = r.take(2).ok_or_else(truncated)?;
+                    let len = u16::from_be_bytes([len[0], len[1]]) as usize;
+                    out.push(tag);
+                    write_utf(&mut out, r.take(len).ok_or_else(truncated)?)?;
+                    i += 1;
+                    continue;
+                }
+                EXTERNALIZED_STRING => {
+                    let index = r.compressed_int().ok_or_else(truncated)?;
+                    out.push(1);
+                    write_utf(&mut out, self.string_bytes(index as u64)?)?;
+                    i += 1;
+                    continue;
+                }
+                EXTERNALIZED_STRING_DESCRIPTOR => {
+                    let desc = self.unshare_descriptor(&mut r)?;
+                    out.push(1);
+                    write_utf(&mut out, &desc)?;
+                    i += 1;
+                    continue;
+                }
+                7 | 8 | 16 | 19 | 20 => 2,
+                15 => 3,
+                3 | 4 | 9 | 10 | 11 | 12 | 17 | 18 => 4,
+                5 | 6 => {
+                    i += 1;
+                    8
+                }
+                _ => {
+                    return Err(Error::Decompress(format!(
+                        "compact-cp: bad constant tag {}",
+                        tag
+                    )))
+                }
+            };
+            out.push(tag);
+            out.extend_from_slice(r.take(size).ok_or_else(truncated)?);
+            i += 1;
+        }
+        out.extend_from_slice(&content[r.pos..]);
+        Ok(out)
+    }
+
+    fn unshare_descriptor(&self, r: &mut SharedReader) -> Result<Vec<u8>, Error> {
+        let truncated = || Error::Decompress("compact-cp: truncated descriptor".into());
+        let desc = self.string_bytes(r.compressed_int().ok_or_else(truncated)? as u64)?;
+        let len = r.compressed_int().ok_or_else(truncated)? as usize;
+        let mut indexes = SharedReader {
+            data: r.take(len).ok_or_else(truncated)?,
+            pos: 0,
+        };
+        let mut out = Vec::with_capacity(desc.len() * 2);
+        for &c in desc {
+            out.push(c);
+            if c == b'L' {
+                let package = indexes.compressed_int().ok_or_else(truncated)?;
+                let package = self.string_bytes(package as u64)?;
+                if !package.is_empty() {
+                    out.extend_from_slice(package);
+                    out.push(b'/');
+                }
+                let class = indexes.compressed_int().ok_or_else(truncated)?;
+                out.extend_from_slice(self.string_bytes(class as u64)?);
+            }
+        }
+        Ok(out)
+    }
+}
+
+fn write_utf(out: &mut Vec<u8>, bytes: &[u8]) -> Result<(), Error> {
+    if bytes.len() > u16::MAX as usize {
+        return Err(Error::Decompress("compact-cp: string too long".into()));
+    }
+    out.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+    out.extend_from_slice(bytes);
+    Ok(())
+}
+
+struct SharedReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> SharedReader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos + n)?;
+        self.pos += n;
+        Some(bytes)
+    }
+
+    // CompressIndexes: a set top bit means bits 5-6 hold the length, 1 to
+    // 3 bytes, and the low 5 bits start the value; else it's 4 bytes
+    fn compressed_int(&mut self) -> Option<u32> {
+        let header = *self.data.get(self.pos)?;
+        let (len, first) = if header & 0x80 != 0 {
+            ((header >> 5) as usize & 3, header & 0x1F)
+        } else {
+            (4, header)
+        };
+        if len == 0 {
+            return None;
+        }
+        let rest = self.take(len)?;
+        Some(
+            rest[1..]
+                .iter()
+                .fold(first as u32, |v, &b| v << 8 | b as u32),
+        )
+    }
+}
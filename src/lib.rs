@@ -3,9 +3,21 @@
 #[macro_use]
 pub mod util;
 
+pub mod asm;
+pub mod audit;
 pub mod class_loader;
 pub mod class_parser;
+pub mod class_path_index;
 pub mod class_path_manager;
+pub mod jar;
+pub mod jimage;
+pub mod metrics;
+pub mod remapper;
+pub mod scan;
+pub mod serialization;
+pub mod shade;
+pub mod shrink;
+pub mod symbol;
 
 #[cfg(test)]
 mod tests {
@@ -21,21 +33,15 @@ mod tests {
 
     #[test]
     fn test_class_path_manager_and_class_loader() {
-        let cargo_dir = env!("CARGO_MANIFEST_DIR");
-        let cp = format!(
-            "{}{}{}{}{}",
-            cargo_dir,
-            util::FILE_SEP,
-            "resource",
-            util::FILE_SEP,
-            "test",
-        );
+        let cp = scratch_dir("cpm");
+        std::fs::write(cp.join("HelloWorld.class"), hello_world_bytes()).unwrap();
+        let cp = cp.to_str().unwrap();
 
         println!("cp: {}", cp);
         let mut cpm = class_path_manager::ClassPathManager::new();
 
         {
-            let result = cpm.add_class_path(&cp);
+            let result = cpm.add_class_path(cp);
             assert!(result.is_ok(), "error: {}", result.err().unwrap());
         }
 
@@ -49,7 +55,2289 @@ mod tests {
         let cl = class_loader::ClassLoader::new(cpm_ref, None);
         assert!(cl.load_class(hello_world).is_some());
         assert!(cl.load_class(hello_world2).is_none());
+
+        std::fs::remove_dir_all(cp).unwrap();
+    }
+
+    #[test]
+    fn test_class_path_wildcard_and_manifest() {
+        let dir = scratch_dir("cp-wildcard");
+        let lib = dir.join("lib");
+        std::fs::create_dir_all(lib.join("classes dir/pkg")).unwrap();
+        std::fs::create_dir_all(dir.join("ext")).unwrap();
+        std::fs::write(lib.join("classes dir/pkg/C.class"), b"c").unwrap();
+        std::fs::write(lib.join("notes.txt"), b"not a jar").unwrap();
+        let jar = |path: std::path::PathBuf, class: &str, class_path: &str| {
+            let manifest = format!(
+                "Manifest-Version: 1.0\r\nClass-Path: {}\r\n\r\nName: x\r\nClass-Path: ignored.jar\r\n",
+                class_path
+            );
+            write_jar(
+                &path,
+                &[
+                    ("META-INF/MANIFEST.MF".to_string(), manifest),
+                    (format!("{}.class", class), class.to_string()),
+                ],
+            );
+        };
+        // a and b refer to each other, c is only reachable from b
+        jar(lib.join("a.jar"), "A", "b.JAR classes%20dir/ missing.jar");
+        jar(lib.join("b.JAR"), "B", "a.jar ../ext/c.jar");
+        jar(dir.join("ext/c.jar"), "C", "");
+
+        let mut cpm = class_path_manager::ClassPathManager::new();
+        let lib_wildcard = format!("{}{}*", lib.to_str().unwrap(), util::FILE_SEP);
+        let missing = dir.join("missing");
+        cpm.add_class_paths(&format!(
+            "{}{}{}",
+            lib_wildcard,
+            util::PATH_SEP,
+            missing.to_str().unwrap()
+        ))
+        .unwrap();
+        assert!(cpm.add_class_path(missing.to_str().unwrap()).is_err());
+
+        // a, its manifest's b (with b's c) and directory, then b from the
+        // wildcard again
+        assert_eq!(cpm.size(), 5);
+        for class in ["A", "B", "C"].iter() {
+            let found = cpm.search_class(class).unwrap();
+            assert_eq!(found.1, class.as_bytes());
+        }
+        assert!(cpm.search_class("C").unwrap().0.ends_with("c.jar"));
+        assert_eq!(cpm.search_class("pkg.C").unwrap().1, b"c");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_class_path_index() {
+        use class_path_manager::{ClassPathManager, Conflict};
+        use std::io::Write;
+
+        let dir = scratch_dir("cp-index");
+        std::fs::create_dir_all(dir.join("classes/y")).unwrap();
+        std::fs::write(dir.join("classes/y/D.class"), b"d").unwrap();
+        let jar = |name: &str, class_path: &str, classes: &[&str]| {
+            let mut files = vec![(
+                "META-INF/MANIFEST.MF".to_string(),
+                format!("Manifest-Version: 1.0\r\nClass-Path: {}\r\n", class_path),
+            )];
+            for class in classes {
+                files.push((format!("{}.class", class), class.to_string()));
+            }
+            write_jar(&dir.join(name), &files)
+        };
+        let a = jar("a.jar", "b.jar", &["x/A", "x/B"]);
+        let b = jar("b.jar", "", &["x/B", "y/C"]);
+        let classes = dir.join("classes").to_str().unwrap().to_string();
+        let b_path = dir.join("b.jar").to_str().unwrap().to_string();
+        let index = dir.join("index.json");
+        let cpm = |with_index: bool| {
+            let mut cpm = ClassPathManager::new();
+            if with_index {
+                cpm.load_index(&index);
+            }
+            cpm.add_class_path(&a).unwrap();
+            cpm.add_class_path(&classes).unwrap();
+            cpm
+        };
+
+        let mut first = cpm(true);
+        assert!(first.save_index(&index).unwrap());
+        assert_eq!(first.index().unwrap().len(), 2);
+        assert_eq!(first.class_sources("x.B"), vec![a.clone(), b_path.clone()]);
+        assert_eq!(
+            first.duplicate_classes().unwrap(),
+            vec![Conflict {
+                name: "x/B".into(),
+                sources: vec![a.clone(), b_path.clone()],
+            }]
+        );
+        let split: Vec<_> = first
+            .split_packages()
+            .unwrap()
+            .into_iter()
+            .map(|c| (c.name, c.sources))
+            .collect();
+        assert_eq!(
+            split,
+            vec![
+                ("x".to_string(), vec![a.clone(), b_path.clone()]),
+                ("y".to_string(), vec![b_path.clone(), classes.clone()]),
+            ]
+        );
+        assert!(!cpm(true).save_index(&index).unwrap());
+
+        // b.jar garbled without changing its size or time: the index still
+        // vouches for it until it's read
+        let b_file = std::fs::OpenOptions::new().write(true).open(&b).unwrap();
+        let modified = b_file.metadata().unwrap().modified().unwrap();
+        let len = b_file.metadata().unwrap().len() as usize;
+        (&b_file).write_all(&vec![0; len]).unwrap();
+        b_file.set_modified(modified).unwrap();
+        let indexed = cpm(true);
+        assert_eq!(indexed.size(), 3);
+        assert_eq!(indexed.class_sources("y/C"), vec![b_path.clone()]);
+        assert_eq!(indexed.search_class("x/A").unwrap().1, b"x/A");
+        assert!(indexed.search_class("y/C").is_err());
+        assert_eq!(cpm(false).size(), 2);
+
+        // a changed jar is indexed again
+        jar("a.jar", "b.jar", &["x/A", "x/B", "x/E"]);
+        let mut reindexed = cpm(true);
+        assert_eq!(reindexed.class_sources("x.E"), vec![a.clone()]);
+        assert!(reindexed.save_index(&index).unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_class_sources() {
+        use class_path_manager::{ClassPathManager, ClassSource, MemorySource};
+        use std::io::Read;
+
+        // a stand-in for a remote store, recording what it's asked for
+        struct Remote {
+            files: Vec<(String, Vec<u8>)>,
+            requests: Mutex<Vec<String>>,
+        }
+        impl ClassSource for Remote {
+            fn name(&self) -> &str {
+                "remote"
+            }
+            fn read(&self, name: &str) -> std::io::Result<Option<Vec<u8>>> {
+                self.requests.lock().unwrap().push(name.to_string());
+                Ok(self
+                    .files
+                    .iter()
+                    .find(|(n, _)| n == name)
+                    .map(|(_, data)| data.clone()))
+            }
+            fn names(&self) -> std::io::Result<Vec<String>> {
+                Ok(self.files.iter().map(|(n, _)| n.clone()).collect())
+            }
+        }
+
+        let dir = scratch_dir("cp-sources");
+        std::fs::create_dir_all(dir.join("pkg")).unwrap();
+        std::fs::write(dir.join("pkg/A.class"), b"dir").unwrap();
+        let dir_path = dir.to_str().unwrap();
+        let mut generated = MemorySource::new("generated");
+        generated.insert_class("pkg.A", b"generated".to_vec());
+        generated.insert_class("HelloWorld", hello_world_bytes());
+        generated.insert("/META-INF/notes.txt", b"notes".to_vec());
+        assert_eq!(generated.len(), 3);
+        let remote = Arc::new(Remote {
+            files: vec![("pkg/R.class".into(), b"remote".to_vec())],
+            requests: Mutex::new(vec![]),
+        });
+
+        let mut cpm = ClassPathManager::new();
+        cpm.add_class_path(dir_path).unwrap();
+        cpm.add_source(Arc::new(generated));
+        cpm.add_source(remote.clone());
+        assert_eq!(cpm.size(), 3);
+
+        // searched in order, later sources only when earlier ones miss
+        assert_eq!(cpm.search_class("pkg.A").unwrap().1, b"dir");
+        assert_eq!(cpm.search_class("pkg/R").unwrap().0, "remote");
+        assert!(cpm.search_class("pkg/Missing").is_err());
+        assert_eq!(
+            *remote.requests.lock().unwrap(),
+            vec!["pkg/R.class", "pkg/Missing.class"]
+        );
+        let found: Vec<_> = cpm
+            .find_resources("pkg/A.class")
+            .unwrap()
+            .into_iter()
+            .map(|r| r.1)
+            .collect();
+        assert_eq!(found, vec![b"dir".to_vec(), b"generated".to_vec()]);
+        let mut notes = String::new();
+        let mut reader = cpm.open_resource("META-INF/notes.txt").unwrap();
+        reader.read_to_string(&mut notes).unwrap();
+        assert_eq!((reader.source.as_str(), reader.size), ("generated", 5));
+        assert_eq!(notes, "notes");
+        assert_eq!(cpm.list_package("pkg").unwrap(), vec!["A.class", "R.class"]);
+        assert_eq!(
+            cpm.class_sources("pkg.A"),
+            vec![dir_path.to_string(), "generated".to_string()]
+        );
+        assert_eq!(cpm.duplicate_classes().unwrap().len(), 1);
+        let mut visited = vec![];
+        cpm.visit_entries(
+            |source, name, _| -> Result<(), class_path_manager::ClassPathError> {
+                visited.push(format!("{}:{}", source, name));
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            visited[1..],
+            [
+                "generated:HelloWorld.class",
+                "generated:META-INF/notes.txt",
+                "generated:pkg/A.class",
+                "remote:pkg/R.class",
+            ]
+        );
+
+        let cl = class_loader::ClassLoader::new(Arc::new(cpm), None);
+        assert!(cl.load_class("HelloWorld").is_some());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_nested_jars() {
+        use class_path_manager::ClassPathManager;
+        use std::io::{Read, Write};
+        use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+        let a = zip_bytes(
+            &[("lib/A.class", "a"), ("lib/Dup.class", "from a")],
+            CompressionMethod::Deflated,
+        );
+        let b = zip_bytes(
+            &[("lib/B.class", "b"), ("lib/Dup.class", "from b")],
+            CompressionMethod::Deflated,
+        );
+        let hello = hello_world_bytes();
+        let mut fat = ZipWriter::new(std::io::Cursor::new(vec![]));
+        let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+        fat.start_file("META-INF/MANIFEST.MF", FileOptions::default())
+            .unwrap();
+        fat.write_all(b"Manifest-Version: 1.0\r\nMain-Class: org.springframework.boot.loader.JarLauncher\r\nStart-Class: app.Main\r\n")
+            .unwrap();
+        fat.start_file("org/springframework/boot/loader/JarLauncher.class", stored)
+            .unwrap();
+        fat.write_all(b"launcher").unwrap();
+        fat.add_directory("BOOT-INF/classes/app/", FileOptions::default())
+            .unwrap();
+        fat.start_file("BOOT-INF/classes/app/Main.class", FileOptions::default())
+            .unwrap();
+        fat.write_all(b"main").unwrap();
+        fat.start_file("BOOT-INF/classes/HelloWorld.class", FileOptions::default())
+            .unwrap();
+        fat.write_all(&hello).unwrap();
+        // a stored library is read in place, a deflated one inflated
+        fat.start_file("BOOT-INF/lib/a.jar", stored).unwrap();
+        fat.write_all(&a).unwrap();
+        fat.start_file("BOOT-INF/lib/b.jar", FileOptions::default())
+            .unwrap();
+        fat.write_all(&b).unwrap();
+        fat.start_file("BOOT-INF/classpath.idx", FileOptions::default())
+            .unwrap();
+        fat.write_all(b"- \"BOOT-INF/lib/b.jar\"\n").unwrap();
+        let dir = scratch_dir("nested");
+        let app = dir.join("app.jar");
+        std::fs::write(&app, fat.finish().unwrap().into_inner()).unwrap();
+        let app = app.to_str().unwrap();
+
+        // the jar itself
+        let mut plain = ClassPathManager::new();
+        plain.add_class_path(app).unwrap();
+        assert!(plain.search_class("app.Main").is_err());
+        assert!(plain
+            .search_class("org.springframework.boot.loader.JarLauncher")
+            .is_ok());
+
+        let mut boot = ClassPathManager::new();
+        boot.set_expand_boot_jars(true);
+        boot.add_class_path(app).unwrap();
+        assert_eq!(boot.size(), 3);
+        let main = boot.search_class("app.Main").unwrap();
+        assert_eq!(main.0, format!("{}!/BOOT-INF/classes/", app));
+        assert_eq!(main.1, b"main");
+        assert!(boot
+            .search_class("org.springframework.boot.loader.JarLauncher")
+            .is_err());
+        assert_eq!(boot.search_class("lib.A").unwrap().1, b"a");
+        // b is first in the classpath index
+        let dup = boot.search_class("lib.Dup").unwrap();
+        assert_eq!(
+            (dup.0, dup.1),
+            (format!("{}!/BOOT-INF/lib/b.jar", app), b"from b".to_vec())
+        );
+        assert_eq!(
+            boot.list_package("").unwrap(),
+            vec!["HelloWorld.class", "app/", "lib/"]
+        );
+        let mut a_class = String::new();
+        boot.open_resource("lib/A.class")
+            .unwrap()
+            .read_to_string(&mut a_class)
+            .unwrap();
+        assert_eq!(a_class, "a");
+        let mut visited = vec![];
+        boot.visit_entries(
+            |_, name, _| -> Result<(), class_path_manager::ClassPathError> {
+                visited.push(name.to_string());
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            visited,
+            vec![
+                "app/Main.class",
+                "HelloWorld.class",
+                "lib/B.class",
+                "lib/Dup.class",
+                "lib/A.class",
+                "lib/Dup.class",
+            ]
+        );
+        let cl = class_loader::ClassLoader::new(Arc::new(boot), None);
+        assert!(cl.load_class("HelloWorld").is_some());
+
+        // nested paths given directly
+        let mut cpm = ClassPathManager::new();
+        cpm.add_class_path(&format!("{}!/BOOT-INF/lib/a.jar", app))
+            .unwrap();
+        cpm.add_class_path(&format!("{}!/BOOT-INF/classes/", app))
+            .unwrap();
+        assert_eq!(cpm.search_class("lib.Dup").unwrap().1, b"from a");
+        assert_eq!(cpm.search_class("app/Main").unwrap().1, b"main");
+        for missing in [
+            "BOOT-INF/lib/c.jar",
+            "BOOT-INF/missing/",
+            "BOOT-INF/classes/!/x",
+        ]
+        .iter()
+        {
+            assert!(cpm
+                .add_class_path(&format!("{}!/{}", app, missing))
+                .is_err());
+        }
+
+        // and in a classpath list, where only the outer jar has to exist
+        let mut cpm = ClassPathManager::new();
+        let missing = dir.join("missing.jar");
+        cpm.add_class_paths(&format!(
+            "{}!/BOOT-INF/lib/b.jar{}{}!/BOOT-INF/lib/a.jar",
+            app,
+            util::PATH_SEP,
+            missing.to_str().unwrap()
+        ))
+        .unwrap();
+        assert_eq!(cpm.size(), 1);
+        assert_eq!(cpm.search_class("lib.Dup").unwrap().1, b"from b");
+        std::fs::remove_dir_all(&dir).unwrap();
     }
+
+    #[test]
+    fn test_class_path_errors() {
+        use class_path_manager::{
+            ClassPathError, ClassPathManager, ClassSource, ErrorPolicy, MemorySource,
+        };
+
+        struct Locked;
+        impl ClassSource for Locked {
+            fn name(&self) -> &str {
+                "locked"
+            }
+            fn read(&self, name: &str) -> std::io::Result<Option<Vec<u8>>> {
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::PermissionDenied,
+                    name.to_string(),
+                ))
+            }
+            fn names(&self) -> std::io::Result<Vec<String>> {
+                Err(std::io::ErrorKind::PermissionDenied.into())
+            }
+        }
+
+        let mut data = zip_bytes(
+            &[("A.class", "AAAAAAAA"), ("B.class", "BBBBBBBB")],
+            zip::CompressionMethod::Stored,
+        );
+        // A's data no longer matches its CRC, B's local header is gone
+        let at = data.windows(8).position(|w| w == b"AAAAAAAA").unwrap();
+        data[at] = b'X';
+        let at = data
+            .windows(4)
+            .enumerate()
+            .position(|(i, w)| w == b"PK\x03\x04" && data[i + 30..].starts_with(b"B.class"))
+            .unwrap();
+        data[at] = 0;
+        let dir = scratch_dir("cp-errors");
+        let jar = dir.join("broken.jar");
+        std::fs::write(&jar, &data).unwrap();
+        let jar = jar.to_str().unwrap();
+        let mut fallback = MemorySource::new("fallback");
+        fallback.insert_class("A", b"a".to_vec());
+        fallback.insert_class("B", b"b".to_vec());
+        fallback.insert_class("C", b"c".to_vec());
+
+        let mut cpm = ClassPathManager::new();
+        cpm.add_class_path(jar).unwrap();
+        cpm.add_source(Arc::new(fallback));
+        assert_eq!(cpm.error_policy(), ErrorPolicy::Skip);
+        assert_eq!(cpm.search_class("A").unwrap().1, b"a");
+        assert_eq!(cpm.find_resources("B.class").unwrap().len(), 1);
+        let err = cpm.search_class("Missing").unwrap_err();
+        assert!(err.is_not_found() && err.entry().is_none());
+        assert_eq!(
+            std::io::Error::from(err).kind(),
+            std::io::ErrorKind::NotFound
+        );
+        let mut visited = vec![];
+        cpm.visit_entries(|source, name, _| -> Result<(), ClassPathError> {
+            visited.push(format!("{}:{}", source, name));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(
+            visited,
+            vec!["fallback:A.class", "fallback:B.class", "fallback:C.class"]
+        );
+
+        cpm.set_error_policy(ErrorPolicy::Abort);
+        match cpm.search_class("A").unwrap_err() {
+            ClassPathError::Decompress { entry, name, .. } => {
+                assert_eq!((entry.as_str(), name.as_str()), (jar, "A.class"))
+            }
+            e => panic!("{}", e),
+        }
+        let err = cpm.find_resources("B.class").unwrap_err();
+        assert!(matches!(err, ClassPathError::Corrupt { .. }));
+        assert_eq!(err.entry(), Some(jar));
+        assert!(err.to_string().contains("corrupt"));
+        assert!(std::error::Error::source(&err).is_some());
+        assert!(cpm.open_resource("B.class").is_err());
+        // only the damaged files fail
+        assert_eq!(cpm.search_class("C").unwrap().1, b"c");
+        let err = cpm
+            .visit_entries(|_, _, _| -> Result<(), ClassPathError> { Ok(()) })
+            .unwrap_err();
+        assert!(matches!(err, ClassPathError::Decompress { .. }));
+
+        let mut locked = ClassPathManager::new();
+        locked.set_error_policy(ErrorPolicy::Abort);
+        locked.add_source(Arc::new(Locked));
+        let err = locked.search_class("C").unwrap_err();
+        assert!(matches!(err, ClassPathError::PermissionDenied { .. }));
+        assert_eq!(
+            std::io::Error::from(err).kind(),
+            std::io::ErrorKind::PermissionDenied
+        );
+        let err = locked.list_package("").unwrap_err();
+        assert!(matches!(err, ClassPathError::PermissionDenied { .. }));
+        locked.set_error_policy(ErrorPolicy::Skip);
+        assert!(locked.list_package("").unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_class_path_enumeration() {
+        use class_path_manager::{
+            ClassEntry, ClassPathManager, ClassSource, ErrorPolicy, MemorySource, NameFilter,
+        };
+
+        struct Unlistable;
+        impl ClassSource for Unlistable {
+            fn name(&self) -> &str {
+                "unlistable"
+            }
+            fn read(&self, _: &str) -> std::io::Result<Option<Vec<u8>>> {
+                Ok(None)
+            }
+            fn names(&self) -> std::io::Result<Vec<String>> {
+                Err(std::io::Error::other("offline"))
+            }
+        }
+
+        let dir = scratch_dir("cp-enum");
+        let classes = dir.join("classes");
+        std::fs::create_dir_all(classes.join("com/a")).unwrap();
+        for name in ["com/a/A.class", "com/a/B.class", "Top.class", "res.txt"].iter() {
+            std::fs::write(classes.join(name), b"").unwrap();
+        }
+        let mut memory = MemorySource::new("memory");
+        memory.insert_class("com.a.A", vec![]);
+        memory.insert_class("com.b.C", vec![]);
+        let jar = write_jar(
+            &dir.join("lib.jar"),
+            &[
+                ("META-INF/MANIFEST.MF", ""),
+                ("com/b/D.class", ""),
+                ("com/b/C.class", ""),
+            ],
+        );
+        let (classes, jar) = (classes.to_str().unwrap(), jar.as_str());
+
+        let mut cpm = ClassPathManager::new();
+        cpm.add_class_path(classes).unwrap();
+        cpm.add_source(Arc::new(memory));
+        cpm.add_source(Arc::new(Unlistable));
+        cpm.add_class_path(jar).unwrap();
+        assert_eq!(cpm.entries(), vec![classes, "memory", "unlistable", jar]);
+
+        let class = |name: &str, source: &str, shadowed_by: Option<&str>| ClassEntry {
+            name: name.to_string(),
+            source: source.to_string(),
+            shadowed_by: shadowed_by.map(String::from),
+        };
+        let found = |filter: NameFilter| -> Vec<ClassEntry> {
+            cpm.classes(&filter).map(Result::unwrap).collect()
+        };
+        assert_eq!(
+            found(NameFilter::All),
+            vec![
+                class("Top", classes, None),
+                class("com/a/A", classes, None),
+                class("com/a/B", classes, None),
+                class("com/a/A", "memory", Some(classes)),
+                class("com/b/C", "memory", None),
+                class("com/b/C", jar, Some("memory")),
+                class("com/b/D", jar, None),
+            ]
+        );
+        assert_eq!(
+            found(NameFilter::Prefix("com.b.".into())),
+            vec![
+                class("com/b/C", "memory", None),
+                class("com/b/C", jar, Some("memory")),
+                class("com/b/D", jar, None),
+            ]
+        );
+        let shadowed: Vec<_> = found(NameFilter::Glob("*/A".into()))
+            .into_iter()
+            .filter(ClassEntry::is_shadowed)
+            .collect();
+        assert_eq!(shadowed, vec![class("com/a/A", "memory", Some(classes))]);
+
+        let packages: Vec<_> = cpm
+            .packages(&NameFilter::All)
+            .unwrap()
+            .map(|p| (p.name, p.sources))
+            .collect();
+        assert_eq!(
+            packages,
+            vec![
+                (
+                    "com/a".to_string(),
+                    vec![classes.to_string(), "memory".to_string()]
+                ),
+                (
+                    "com/b".to_string(),
+                    vec!["memory".to_string(), jar.to_string()]
+                ),
+            ]
+        );
+        assert_eq!(
+            cpm.packages(&NameFilter::Glob("*.b".into()))
+                .unwrap()
+                .count(),
+            1
+        );
+        assert_eq!(cpm.split_packages().unwrap().len(), 2);
+        assert_eq!(cpm.duplicate_classes().unwrap().len(), 2);
+
+        let contents = |i: usize, filter: NameFilter| -> Vec<String> {
+            cpm.entry_contents(i, &filter).unwrap().collect()
+        };
+        assert_eq!(
+            contents(0, NameFilter::All),
+            vec!["Top.class", "com/a/A.class", "com/a/B.class", "res.txt"]
+        );
+        assert_eq!(
+            contents(0, NameFilter::Glob("*.txt".into())),
+            vec!["res.txt"]
+        );
+        assert_eq!(
+            contents(3, NameFilter::Prefix("META-INF/".into())),
+            vec!["META-INF/MANIFEST.MF"]
+        );
+        assert!(cpm.entry_contents(2, &NameFilter::All).is_err());
+        assert!(cpm.entry_contents(4, &NameFilter::All).is_err());
+
+        // the unlistable source ends the iteration
+        cpm.set_error_policy(ErrorPolicy::Abort);
+        let results: Vec<_> = cpm.classes(&NameFilter::All).collect();
+        assert_eq!(results.len(), 6);
+        assert_eq!(results[5].as_ref().unwrap_err().entry(), Some("unlistable"));
+        assert!(cpm.packages(&NameFilter::All).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_class_path_resources() {
+        use std::io::Read;
+
+        let dir = scratch_dir("cp-resources");
+        let classes = dir.join("classes");
+        std::fs::create_dir_all(classes.join("META-INF/services")).unwrap();
+        std::fs::create_dir_all(classes.join("pkg/sub")).unwrap();
+        let service = "META-INF/services/java.lang.Runnable";
+        std::fs::write(classes.join(service), b"pkg.A\n").unwrap();
+        std::fs::write(classes.join("pkg/A.class"), b"a").unwrap();
+
+        let big: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let jar = write_jar(
+            &dir.join("lib.jar"),
+            &[
+                (service, b"pkg.B\n".to_vec()),
+                ("pkg/B.class", b"b".to_vec()),
+                ("pkg/messages.properties", b"hello=hi".to_vec()),
+                ("pkg/other/C.class", b"c".to_vec()),
+                ("pkg/big.bin", big.clone()),
+            ],
+        );
+
+        let mut cpm = class_path_manager::ClassPathManager::new();
+        cpm.add_class_paths(&format!(
+            "{}{}{}",
+            classes.to_str().unwrap(),
+            util::PATH_SEP,
+            jar
+        ))
+        .unwrap();
+
+        let services: Vec<_> = cpm
+            .find_resources(service)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.1)
+            .collect();
+        assert_eq!(services, vec![b"pkg.A\n".to_vec(), b"pkg.B\n".to_vec()]);
+        assert_eq!(
+            cpm.find_resource("/pkg/messages.properties").unwrap().1,
+            b"hello=hi"
+        );
+        assert!(cpm.find_resource("pkg/missing.properties").is_err());
+        assert!(cpm.find_resources("pkg").unwrap().is_empty());
+        assert!(cpm.search_class("pkg.B").is_ok());
+
+        let mut reader = cpm.open_resource("pkg/big.bin").unwrap();
+        assert_eq!(reader.size, big.len() as u64);
+        let mut streamed = vec![];
+        let mut buf = [0; 4096];
+        loop {
+            let n = reader.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            // lookups aren't blocked while the entry is open
+            assert!(cpm.find_resource("pkg/B.class").is_ok());
+            streamed.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(streamed, big);
+        let mut small = String::new();
+        cpm.open_resource(service)
+            .unwrap()
+            .read_to_string(&mut small)
+            .unwrap();
+        assert_eq!(small, "pkg.A\n");
+
+        assert_eq!(
+            cpm.list_package("pkg").unwrap(),
+            vec![
+                "A.class",
+                "B.class",
+                "big.bin",
+                "messages.properties",
+                "other/",
+                "sub/"
+            ]
+        );
+        assert_eq!(cpm.list_package("pkg.other").unwrap(), vec!["C.class"]);
+        assert_eq!(cpm.list_package("").unwrap(), vec!["META-INF/", "pkg/"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_multi_release_jar() {
+        use std::io::Read;
+
+        let dir = scratch_dir("multi-release");
+        let jar = |name: &str, manifest: &str| {
+            write_jar(
+                &dir.join(name),
+                &[
+                    ("META-INF/MANIFEST.MF", manifest),
+                    ("META-INF/versions/9/META-INF/notes.txt", "9"),
+                    ("META-INF/notes.txt", "base"),
+                    ("pkg/A.class", "base"),
+                    ("META-INF/versions/9/pkg/A.class", "9"),
+                    ("META-INF/versions/11/pkg/A.class", "11"),
+                    ("META-INF/versions/17/pkg/B.properties", "17"),
+                    ("META-INF/versions/8/pkg/B.properties", "8"),
+                ],
+            )
+        };
+        let multi = jar(
+            "multi.jar",
+            "Manifest-Version: 1.0\r\nMulti-Release: true\r\n",
+        );
+        let plain = jar("plain.jar", "Manifest-Version: 1.0\r\n");
+
+        let found = |cpm: &class_path_manager::ClassPathManager, name: &str| {
+            cpm.find_resource(name)
+                .ok()
+                .map(|r| (String::from_utf8(r.1).unwrap(), r.2))
+        };
+        let mut cpm = class_path_manager::ClassPathManager::new();
+        cpm.add_class_path(&multi).unwrap();
+        assert_eq!(cpm.release(), None);
+        assert_eq!(found(&cpm, "pkg/A.class"), Some(("11".into(), Some(11))));
+        assert_eq!(
+            found(&cpm, "pkg/B.properties"),
+            Some(("17".into(), Some(17)))
+        );
+        assert_eq!(
+            found(&cpm, "META-INF/notes.txt"),
+            Some(("base".into(), None))
+        );
+
+        cpm.set_release(10);
+        assert_eq!(cpm.search_class("pkg.A").unwrap().2, Some(9));
+        assert_eq!(found(&cpm, "pkg/B.properties"), None);
+        let mut reader = cpm.open_resource("pkg/A.class").unwrap();
+        assert_eq!(reader.release, Some(9));
+        let mut data = String::new();
+        reader.read_to_string(&mut data).unwrap();
+        assert_eq!(data, "9");
+
+        cpm.set_release(8);
+        assert_eq!(found(&cpm, "pkg/A.class"), Some(("base".into(), None)));
+
+        let mut cpm = class_path_manager::ClassPathManager::new();
+        cpm.add_class_path(&plain).unwrap();
+        assert_eq!(found(&cpm, "pkg/A.class"), Some(("base".into(), None)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // a jimage holding `resources` as (full name, stored bytes, compressed
+    // length, uncompressed length), with the strings the compressors refer to
+    fn jimage_bytes(
+        big_endian: bool,
+        resources: &[(&str, Vec<u8>, u64, u64)],
+        strings: &mut Vec<u8>,
+    ) -> Vec<u8> {
+        use jimage::{hash_code, HASH_MULTIPLIER, MAGIC};
+
+        let u4 = |v: u32| {
+            if big_endian {
+                v.to_be_bytes()
+            } else {
+                v.to_le_bytes()
+            }
+        };
+        let n = resources.len();
+        let mut locations = vec![0];
+        let mut offsets = vec![];
+        let mut content = vec![];
+        for (name, data, compressed, uncompressed) in resources.iter() {
+            let (module, rest) = name[1..].split_once('/').unwrap();
+            let (parent, file) = rest.rsplit_once('/').unwrap_or(("", rest));
+            let (base, ext) = file.rsplit_once('.').unwrap_or((file, ""));
+            offsets.push(locations.len() as u32);
+            let attrs = [
+                jimage_string(strings, module),
+                jimage_string(strings, parent),
+                jimage_string(strings, base),
+                jimage_string(strings, ext),
+                content.len() as u64,
+                *compressed,
+                *uncompressed,
+            ];
+            for (kind, value) in attrs.iter().enumerate() {
+                if *value != 0 {
+                    let len = (8 - value.leading_zeros() as usize / 8).max(1);
+                    locations.push(((kind as u8 + 1) << 3) | (len as u8 - 1));
+                    locations.extend_from_slice(&value.to_be_bytes()[8 - len..]);
+                }
+            }
+            locations.push(0);
+            content.extend_from_slice(data);
+        }
+
+        // the perfect hash, fullest buckets first
+        let mut buckets = vec![vec![]; n];
+        for (i, (name, ..)) in resources.iter().enumerate() {
+            buckets[hash_code(name, HASH_MULTIPLIER) as usize % n].push(i);
+        }
+        let mut order: Vec<_> = (0..n).collect();
+        order.sort_by_key(|&b| std::cmp::Reverse(buckets[b].len()));
+        let mut redirect = vec![0i32; n];
+        let mut slots = vec![None; n];
+        for b in order {
+            match buckets[b].len() {
+                0 => {}
+                1 => {
+                    let slot = slots.iter().position(|s| s.is_none()).unwrap();
+                    slots[slot] = Some(buckets[b][0]);
+                    redirect[b] = -(slot as i32) - 1;
+                }
+                _ => {
+                    let seed = (1..)
+                        .find(|&seed| {
+                            let mut taken: Vec<_> = buckets[b]
+                                .iter()
+                                .map(|&i| hash_code(resources[i].0, seed) as usize % n)
+                                .collect();
+                            let free = taken.iter().all(|&s| slots[s].is_none());
+                            taken.sort_unstable();
+                            taken.dedup();
+                            free && taken.len() == buckets[b].len()
+                        })
+                        .unwrap();
+                    for &i in buckets[b].iter() {
+                        slots[hash_code(resources[i].0, seed) as usize % n] = Some(i);
+                    }
+                    redirect[b] = seed;
+                }
+            }
+        }
+
+        let mut out = vec![];
+        for v in [
+            MAGIC,
+            1 << 16,
+            0,
+            n as u32,
+            n as u32,
+            locations.len() as u32,
+            strings.len() as u32,
+        ]
+        .iter()
+        {
+            out.extend_from_slice(&u4(*v));
+        }
+        for r in redirect.iter() {
+            out.extend_from_slice(&u4(*r as u32));
+        }
+        for slot in slots.iter() {
+            out.extend_from_slice(&u4(offsets[slot.unwrap()]));
+        }
+        out.extend_from_slice(&locations);
+        out.extend_from_slice(strings);
+        out.extend_from_slice(&content);
+        out
+    }
+
+    fn jimage_string(strings: &mut Vec<u8>, s: &str) -> u64 {
+        if s.is_empty() {
+            return 0;
+        }
+        let mut needle = s.as_bytes().to_vec();
+        needle.push(0);
+        if let Some(i) = strings.windows(needle.len()).position(|w| w == &needle[..]) {
+            if i == 0 || strings[i - 1] == 0 {
+                return i as u64;
+            }
+        }
+        strings.extend_from_slice(&needle);
+        (strings.len() - needle.len()) as u64
+    }
+
+    #[test]
+    fn test_jimage() {
+        use flate2::{write::ZlibEncoder, Compression};
+        use std::io::Write;
+
+        // 3 byte CompressIndexes ints
+        let index = |out: &mut Vec<u8>, v: u64| {
+            out.extend_from_slice(&[0xE0 | (v >> 16) as u8, (v >> 8) as u8, v as u8])
+        };
+        let class = hello_world_bytes();
+        let text = "greeting=hello\n".repeat(200);
+        for &big_endian in [false, true].iter() {
+            let mut strings = vec![0];
+            let compressed_header =
+                |strings: &mut Vec<u8>, name: &str, content: &[u8], size: usize| {
+                    let mut out = vec![];
+                    let u4 = |v: u32| {
+                        if big_endian {
+                            v.to_be_bytes()
+                        } else {
+                            v.to_le_bytes()
+                        }
+                    };
+                    let u8_ = |v: u64| {
+                        if big_endian {
+                            v.to_be_bytes()
+                        } else {
+                            v.to_le_bytes()
+                        }
+                    };
+                    out.extend_from_slice(&u4(jimage::COMPRESSED_MAGIC));
+                    out.extend_from_slice(&u8_(content.len() as u64));
+                    out.extend_from_slice(&u8_(size as u64));
+                    out.extend_from_slice(&u4(jimage_string(strings, name) as u32));
+                    out.extend_from_slice(&u4(0));
+                    out.push(1);
+                    out.extend_from_slice(content);
+                    out
+                };
+            let zip = |data: &[u8]| {
+                let mut z = ZlibEncoder::new(vec![], Compression::default());
+                z.write_all(data).unwrap();
+                z.finish().unwrap()
+            };
+
+            // the class with its superclass name and main's descriptor
+            // moved to the string table
+            let mut shared = class[..10].to_vec();
+            let count = u16::from_be_bytes([class[8], class[9]]);
+            let mut pos = 10;
+            let mut i = 1;
+            while i < count {
+                let tag = class[pos];
+                let size = match tag {
+                    1 => 2 + u16::from_be_bytes([class[pos + 1], class[pos + 2]]) as usize,
+                    7 | 8 | 16 => 2,
+                    5 | 6 => 8,
+                    _ => 4,
+                };
+                let constant = &class[pos..pos + 1 + size];
+                match &constant[constant.len().min(3)..] {
+                    b"java/lang/Object" => {
+                        shared.push(23);
+                        index(&mut shared, jimage_string(&mut strings, "java/lang/Object"));
+                    }
+                    b"([Ljava/lang/String;)V" => {
+                        shared.push(25);
+                        index(&mut shared, jimage_string(&mut strings, "([L;)V"));
+                        index(&mut shared, 6);
+                        index(&mut shared, jimage_string(&mut strings, "java/lang"));
+                        index(&mut shared, jimage_string(&mut strings, "String"));
+                    }
+                    _ => shared.extend_from_slice(constant),
+                }
+                if tag == 5 || tag == 6 {
+                    i += 1;
+                }
+                pos += 1 + size;
+                i += 1;
+            }
+            shared.extend_from_slice(&class[pos..]);
+            assert!(shared.len() < class.len());
+            let shared = compressed_header(&mut strings, "compact-cp", &shared, class.len());
+            let stacked = compressed_header(&mut strings, "zip", &zip(&shared), shared.len());
+            let text_zip =
+                compressed_header(&mut strings, "zip", &zip(text.as_bytes()), text.len());
+            // claims far more than it inflates to
+            let huge = compressed_header(&mut strings, "zip", &zip(b"huge"), usize::MAX);
+
+            let resources = [
+                (
+                    "/app/hello/HelloWorld.class",
+                    class.clone(),
+                    0,
+                    class.len() as u64,
+                ),
+                (
+                    "/app/hello/Shared.class",
+                    stacked.clone(),
+                    stacked.len() as u64,
+                    class.len() as u64,
+                ),
+                (
+                    "/app/hello/messages.properties",
+                    text_zip.clone(),
+                    text_zip.len() as u64,
+                    text.len() as u64,
+                ),
+                ("/app/huge/data.bin", huge.clone(), huge.len() as u64, 4),
+                ("/app/module-info.class", b"m".to_vec(), 0, 1),
+                ("/other/hello/HelloWorld.class", b"shadowed".to_vec(), 0, 8),
+                ("/packages/hello/app", vec![0; 8], 0, 8),
+            ];
+            let dir = scratch_dir(&format!("jimage-{}", big_endian));
+            let path = dir.join("modules");
+            std::fs::write(&path, jimage_bytes(big_endian, &resources, &mut strings)).unwrap();
+
+            let image = jimage::JImage::open(&path).unwrap();
+            let loc = image.find("/app/hello/Shared.class").unwrap();
+            assert_eq!(
+                (loc.module.as_str(), loc.parent.as_str(), loc.base.as_str()),
+                ("app", "hello", "Shared")
+            );
+            assert!(image.find("/app/hello/Missing.class").is_none());
+            assert!(image.find("/app/hello/Shared").is_none());
+            assert_eq!(image.read(&loc).unwrap(), class);
+            let loc = image.find("/app/huge/data.bin").unwrap();
+            assert!(matches!(
+                image.read(&loc),
+                Err(jimage::Error::Decompress(_))
+            ));
+
+            let mut cpm = class_path_manager::ClassPathManager::new();
+            cpm.add_class_path(path.to_str().unwrap()).unwrap();
+            let found = cpm.search_class("hello.HelloWorld").unwrap();
+            assert_eq!(found.1, class);
+            assert_eq!(
+                cpm.find_resource("other/hello/HelloWorld.class").unwrap().1,
+                b"shadowed"
+            );
+            assert_eq!(
+                cpm.find_resource("/hello/messages.properties").unwrap().1,
+                text.as_bytes()
+            );
+            assert!(cpm.find_resource("hello/app").is_err());
+            assert_eq!(
+                cpm.list_package("hello").unwrap(),
+                vec!["HelloWorld.class", "Shared.class", "messages.properties"]
+            );
+            let mut names = vec![];
+            cpm.visit_entries(
+                |_, name, data| -> Result<(), class_path_manager::ClassPathError> {
+                    if name.ends_with(".class") && data.len() > 1 {
+                        assert!(class_parser::parse(&data).is_ok() || data == b"shadowed");
+                    }
+                    names.push(name.to_string());
+                    Ok(())
+                },
+            )
+            .unwrap();
+            assert_eq!(
+                names,
+                vec![
+                    "hello/HelloWorld.class",
+                    "hello/Shared.class",
+                    "hello/messages.properties",
+                    "module-info.class",
+                    "hello/HelloWorld.class"
+                ]
+            );
+
+            // locations past the end of a truncated image fail before any
+            // buffer is sized from them
+            let mut bytes = std::fs::read(&path).unwrap();
+            bytes.truncate(bytes.len() - 12);
+            std::fs::write(&path, bytes).unwrap();
+            let image = jimage::JImage::open(&path).unwrap();
+            let loc = image.find("/other/hello/HelloWorld.class").unwrap();
+            assert!(matches!(image.read(&loc), Err(jimage::Error::Format(_))));
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    fn test_resource(name: &str) -> String {
+        let cargo_dir = env!("CARGO_MANIFEST_DIR");
+        let path = format!(
+            "{}{}{}{}{}{}{}",
+            cargo_dir,
+            util::FILE_SEP,
+            "resource",
+            util::FILE_SEP,
+            "test",
+            util::FILE_SEP,
+            name,
+        );
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    fn hello_world_source() -> String {
+        test_resource("HelloWorld.j")
+    }
+
+    fn hello_world_bytes() -> Vec<u8> {
+        let cf = asm::assemble(&hello_world_source()).unwrap();
+        class_parser::write(&cf).unwrap()
+    }
+
+    // an empty directory of this process for the test `tag`
+    fn scratch_dir(tag: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("jvm-{}-{}", tag, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // a zip of `files` in order, each compressed with `method`
+    fn zip_bytes(
+        files: &[(impl AsRef<str>, impl AsRef<[u8]>)],
+        method: zip::CompressionMethod,
+    ) -> Vec<u8> {
+        use std::io::Write;
+        use zip::{write::FileOptions, ZipWriter};
+
+        let mut zip = ZipWriter::new(std::io::Cursor::new(vec![]));
+        for (name, data) in files {
+            zip.start_file(
+                name.as_ref(),
+                FileOptions::default().compression_method(method),
+            )
+            .unwrap();
+            zip.write_all(data.as_ref()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    // writes a jar of deflated `files` to `path`, returning the path
+    fn write_jar(path: &std::path::Path, files: &[(impl AsRef<str>, impl AsRef<[u8]>)]) -> String {
+        std::fs::write(path, zip_bytes(files, zip::CompressionMethod::Deflated)).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_write_round_trip() {
+        let bytes = hello_world_bytes();
+        let (_, cf) = class_parser::parse(&bytes).unwrap();
+        assert_eq!(class_parser::write(&cf).unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_constant_pool_builder() {
+        use class_parser::builder::{ConstantPoolBuilder, Error, MAX_CONSTANT_POOL_COUNT};
+        use class_parser::format::constant_pool::Type;
+
+        let mut b = ConstantPoolBuilder::new();
+        let m1 = b.method_ref("java/lang/Object", "<init>", "()V").unwrap();
+        let m2 = b.method_ref("java/lang/Object", "<init>", "()V").unwrap();
+        assert_eq!(m1, m2);
+
+        let l = b.long(42).unwrap();
+        assert!(matches!(b.get(l + 1), Some(Type::Nop)));
+        assert_eq!(b.integer(1).unwrap(), l + 2);
+
+        while b.len() < MAX_CONSTANT_POOL_COUNT - 1 {
+            b.integer(b.len() as i32).unwrap();
+        }
+        assert!(matches!(b.double(1.0), Err(Error::TooManyConstants { .. })));
+        assert!(b.integer(-1).is_ok());
+        assert!(b.integer(-2).is_err());
+    }
+
+    #[test]
+    fn test_constant_pool_compact() {
+        use class_parser::builder::{compact, Compaction, ConstantPoolBuilder};
+        use class_parser::format::attributes::Type;
+
+        let bytes = hello_world_bytes();
+        let (_, mut cf) = class_parser::parse(&bytes).unwrap();
+        let count = cf.cp.len();
+
+        let mut b = ConstantPoolBuilder::from_pool(&cf.cp);
+        b.method_ref("Unused", "unused", "()V").unwrap();
+        b.double(3.0).unwrap();
+        cf.cp = b.build();
+
+        assert_eq!(compact(&mut cf).unwrap(), Compaction::Removed(7));
+        assert_eq!(cf.cp.len(), count);
+
+        // a nest host is followed like any other reference
+        let mut b = ConstantPoolBuilder::from_pool(&cf.cp);
+        let host_class_index = b.class("Outer").unwrap();
+        b.utf8("NestHost").unwrap();
+        b.class("Unused").unwrap();
+        cf.cp = b.build();
+        cf.attrs.push(Type::NestHost { host_class_index });
+        assert_eq!(compact(&mut cf).unwrap(), Compaction::Removed(2));
+        assert_eq!(cf.cp.len(), count + 3);
+
+        // attributes nobody decoded may hold indices, so nothing is touched
+        let vendor = Type::Unknown {
+            name: std::sync::Arc::new(b"Vendor".to_vec()),
+            data: std::sync::Arc::new(vec![0, 1]),
+        };
+        cf.attrs.push(vendor);
+        let mut b = ConstantPoolBuilder::from_pool(&cf.cp);
+        b.class("Unused").unwrap();
+        cf.cp = b.build();
+        assert_eq!(
+            compact(&mut cf).unwrap(),
+            Compaction::Undecoded(vec!["Vendor".to_string()])
+        );
+        assert_eq!(cf.cp.len(), count + 5);
+        cf.attrs.pop();
+        assert_eq!(compact(&mut cf).unwrap(), Compaction::Removed(2));
+
+        // code cut off inside an instruction has operands that can't be found
+        let mut broken = class_parser::parse(&bytes).unwrap().1;
+        let pool = broken.cp.clone();
+        let code = broken
+            .methods
+            .iter_mut()
+            .flat_map(|m| m.attrs.iter_mut())
+            .find_map(|a| match a {
+                Type::Code(code) if code.code.len() > 2 => Some(code),
+                _ => None,
+            })
+            .unwrap();
+        code.code = std::sync::Arc::new(code.code[..code.code.len() - 2].to_vec());
+        let err = compact(&mut broken).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(std::sync::Arc::ptr_eq(&broken.cp, &pool));
+
+        let written = class_parser::write(&cf).unwrap();
+        let (_, reparsed) = class_parser::parse(&written).unwrap();
+        assert_eq!(reparsed.cp.len(), cf.cp.len());
+        assert_eq!(
+            class_parser::format::constant_pool::get_class_name(
+                &reparsed.cp,
+                reparsed.this_class as usize
+            )
+            .unwrap()
+            .as_slice(),
+            b"HelloWorld"
+        );
+    }
+
+    #[test]
+    fn test_remapper() {
+        use class_parser::format::constant_pool::{get_class_name, get_utf8};
+        use remapper::{remap_signature, Mapper, Mapping, Remapper};
+
+        let proguard = "\
+HelloWorld -> a:
+    int v_int -> b
+    void public_method() -> c
+    1:3:void main(java.lang.String[]):16:18 -> main
+";
+        let mapping = Mapping::from_proguard(proguard).unwrap();
+        assert_eq!(mapping.map_class("HelloWorld").unwrap(), "a");
+        assert_eq!(
+            mapping
+                .map_method("HelloWorld", "main", "([Ljava/lang/String;)V")
+                .unwrap(),
+            "main"
+        );
+        let reversed = mapping.reversed();
+        assert_eq!(reversed.map_field("a", "b", "I").unwrap(), "v_int");
+
+        assert_eq!(
+            remap_signature(
+                "<T:LHelloWorld;>(TT;)LHelloWorld$Inner<LHelloWorld;>.Deep;",
+                &|c| { mapping.map_class(c) }
+            ),
+            "<T:La;>(TT;)La$Inner<La;>.Deep;"
+        );
+
+        let bytes = hello_world_bytes();
+        let (_, mut cf) = class_parser::parse(&bytes).unwrap();
+        let mut remapper = Remapper::new(mapping);
+        remapper.add_class(&cf);
+        remapper.remap(&mut cf).unwrap();
+
+        let name = |i: u16| get_utf8(&cf.cp, i as usize).unwrap().to_vec();
+        assert_eq!(
+            get_class_name(&cf.cp, cf.this_class as usize)
+                .unwrap()
+                .as_slice(),
+            b"a"
+        );
+        assert!(cf.fields.iter().any(|f| name(f.name_index) == b"b"));
+        assert!(cf.methods.iter().any(|m| name(m.name_index) == b"c"));
+        assert!(!cf
+            .methods
+            .iter()
+            .any(|m| name(m.name_index) == b"public_method"));
+
+        let written = class_parser::write(&cf).unwrap();
+        assert!(class_parser::parse(&written).is_ok());
+    }
+
+    #[test]
+    fn test_shade() {
+        use shade::{MergeStrategy, Relocation, Relocator, Shader};
+
+        assert!(util::glob_match("META-INF/*.txt", "META-INF/a/b.txt"));
+        assert!(!util::glob_match("META-INF/*.txt", "META-INF/b.md"));
+        let relocator = Relocator::new(vec![Relocation::new("com.google", "shaded.com.google")]);
+        assert_eq!(
+            relocator.relocate_dotted("com.google.A"),
+            "shaded.com.google.A"
+        );
+        assert_eq!(relocator.relocate_dotted("com.googlex.A"), "com.googlex.A");
+
+        let dir = scratch_dir("shade");
+        let output = dir.join("out.jar");
+        let input = write_jar(
+            &dir.join("in.jar"),
+            &[
+                (
+                    "META-INF/MANIFEST.MF",
+                    b"Manifest-Version: 1.0\r\nMulti-Release: true\r\n".to_vec(),
+                ),
+                ("HelloWorld.class", hello_world_bytes()),
+                ("META-INF/versions/9/HelloWorld.class", hello_world_bytes()),
+                (
+                    "META-INF/services/java.lang.Runnable",
+                    b"HelloWorld\n".to_vec(),
+                ),
+                ("META-INF/NOTICE.txt", b"a".to_vec()),
+                ("META-INF/SIGNER.SF", b"".to_vec()),
+            ],
+        );
+
+        let mut cpm = class_path_manager::ClassPathManager::new();
+        cpm.add_class_paths(&format!("{}{}{}", input, util::PATH_SEP, input))
+            .unwrap();
+
+        let mut shader = Shader::new(vec![Relocation::new("HelloWorld", "shaded/HelloWorld")]);
+        shader.merge_rule("META-INF/*.txt", MergeStrategy::Concat);
+        let report = shader
+            .shade(&cpm, std::fs::File::create(&output).unwrap())
+            .unwrap();
+        assert_eq!(report.relocated_classes, 4);
+        assert_eq!(report.duplicates.len(), 4);
+
+        // the versioned class stays in its directory
+        let mut shaded = class_path_manager::ClassPathManager::new();
+        shaded.add_class_path(output.to_str().unwrap()).unwrap();
+        let found = shaded.search_class("shaded.HelloWorld").unwrap();
+        assert!(class_parser::parse(&found.1).is_ok());
+        assert_eq!(found.2, Some(9));
+        shaded.set_release(8);
+        assert_eq!(shaded.search_class("shaded.HelloWorld").unwrap().2, None);
+
+        let mut resources = std::collections::HashMap::new();
+        shaded
+            .visit_entries(
+                |_, name, data| -> Result<(), class_path_manager::ClassPathError> {
+                    resources.insert(name.to_string(), data);
+                    Ok(())
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            resources["META-INF/services/java.lang.Runnable"],
+            b"shaded.HelloWorld\nshaded.HelloWorld\n"
+        );
+        assert_eq!(resources["META-INF/NOTICE.txt"], b"a\na");
+        assert!(!resources.contains_key("META-INF/SIGNER.SF"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_shrink() {
+        use class_parser::format::attributes::Type;
+        use shrink::{shrink_class, StripOptions};
+
+        let bytes = hello_world_bytes();
+        let options = StripOptions {
+            line_numbers: true,
+            ..Default::default()
+        };
+        let (shrunk, report) = shrink_class(&bytes, &options).unwrap();
+        assert_eq!(report.name, "HelloWorld");
+        assert_eq!(report.before, bytes.len());
+        assert!(report.saved() > 0);
+
+        let (_, cf) = class_parser::parse(&shrunk).unwrap();
+        let code = cf
+            .methods
+            .iter()
+            .flat_map(|m| m.attrs.iter())
+            .find_map(|a| match a {
+                Type::Code(code) => Some(code),
+                _ => None,
+            });
+        assert!(!code
+            .unwrap()
+            .attrs
+            .iter()
+            .any(|a| matches!(a, Type::LineNumberTable { .. })));
+        assert!(cf
+            .attrs
+            .iter()
+            .any(|a| matches!(a, Type::SourceFile { .. })));
+
+        let (all, _) = shrink_class(&bytes, &StripOptions::all()).unwrap();
+        assert!(all.len() < shrunk.len());
+    }
+
+    #[test]
+    fn test_asm() {
+        // the fixture is printer output, so printing it again changes nothing
+        let (_, cf) = class_parser::parse(&hello_world_bytes()).unwrap();
+        assert_eq!(asm::print(&cf).unwrap(), hello_world_source());
+
+        let source = r#"
+.class public super Counter
+.super java/lang/Object
+
+.method public static count (I)I
+    .code stack 2 locals 2
+        iconst_0
+        istore_1
+    loop:
+        iload_0
+        ifle done
+        iinc 1 1
+        iinc 0 -1
+        goto loop
+    done:
+        iload_1
+        tableswitch 0 zero one default other
+    zero:
+        ldc String "zero" ; comment
+        pop
+    one:
+    other:
+        iload_1
+        ireturn
+        .stackmaptable
+            append loop Integer
+            same done
+            same zero
+            same one
+        .end stackmaptable
+    .end code
+.end method
+"#;
+        let cf = asm::assemble(source).unwrap();
+        let bytes = class_parser::write(&cf).unwrap();
+        let (_, cf) = class_parser::parse(&bytes).unwrap();
+        let code = match &cf.methods[0].attrs[0] {
+            class_parser::format::attributes::Type::Code(code) => code,
+            _ => panic!("expected Code"),
+        };
+        assert_eq!(code.code.len(), 45);
+        assert_eq!(code.code[16], class_parser::bytecode::TABLESWITCH);
+
+        let printed = asm::print(&cf).unwrap();
+        let again = class_parser::write(&asm::assemble(&printed).unwrap()).unwrap();
+        assert_eq!(again, bytes);
+
+        let broken = ".class A\n.method m ()V\n.code stack 0 locals 0\ngoto nowhere\n.end code\n.end method\n";
+        let err = asm::assemble(broken).unwrap_err();
+        assert_eq!(err.line, 4);
+
+        let overflow = ".class A\n.method m ()V\n.code stack 1 locals 0\niconst_0\ntableswitch 2147483647 a a default a\na:\nreturn\n.end code\n.end method\n";
+        let err = asm::assemble(overflow).unwrap_err();
+        assert_eq!(err.line, 5);
+    }
+
+    #[test]
+    fn test_smap() {
+        use class_parser::format::attributes::Type;
+        use class_parser::smap::{self, Smap};
+
+        let map = "SMAP\nHelloWorld.kt\nKotlin\n*S Kotlin\n*F\n+ 1 HelloWorld.kt\nHelloWorld.kt\n+ 2 Inline.kt\nlib/Inline.kt\n*L\n1#1,30:1\n10#2,5:32\n*S KotlinDebug\n*F\n+ 1 HelloWorld.kt\nHelloWorld.kt\n*L\n1#1,5:32\n*E\n";
+        let parsed = Smap::parse(map).unwrap();
+        assert_eq!(parsed.default_stratum, "Kotlin");
+        assert_eq!(parsed.strata.len(), 2);
+        let kotlin = parsed.stratum("Kotlin").unwrap();
+        assert_eq!(kotlin.lines[1].file_id, 2);
+        assert_eq!(
+            kotlin.map_line(33).map(|(f, l)| (f.name.as_str(), l)),
+            Some(("Inline.kt", 11))
+        );
+        assert!(kotlin.map_line(31).is_none());
+        assert!(Smap::parse("SMAP\nA.kt\nKotlin\n*S Kotlin\n*L\nbad\n*E\n").is_err());
+        // input lines that would run past u32::MAX map to nothing
+        let high =
+            Smap::parse("SMAP\nA.kt\nKotlin\n*S Kotlin\n*F\n1 A.kt\n*L\n4294967290,10:1\n*E\n")
+                .unwrap();
+        let line = &high.stratum("Kotlin").unwrap().lines[0];
+        assert_eq!(line.input_line(6), Some(u32::MAX));
+        assert_eq!(line.input_line(7), None);
+
+        // private_method starts at line 32, package_method at line 40
+        let source = hello_world_source().replace(
+            ".end class",
+            &format!(".sourcedebugextension {:?}\n.end class", map),
+        );
+        let (_, cf) =
+            class_parser::parse(&class_parser::write(&asm::assemble(&source).unwrap()).unwrap())
+                .unwrap();
+        let code = |name: &[u8]| {
+            let method = cf
+                .methods
+                .iter()
+                .find(|m| {
+                    class_parser::format::constant_pool::get_utf8(&cf.cp, m.name_index as usize)
+                        .is_some_and(|n| n.as_slice() == name)
+                })
+                .unwrap();
+            method
+                .attrs
+                .iter()
+                .find_map(|a| match a {
+                    Type::Code(code) => Some(code),
+                    _ => None,
+                })
+                .unwrap()
+        };
+
+        let private_method = code(b"private_method");
+        let location = smap::source_location(&cf, private_method, 8, None).unwrap();
+        assert_eq!(location.file, "Inline.kt");
+        assert_eq!(location.path.as_deref(), Some("lib/Inline.kt"));
+        assert_eq!(location.line, 11);
+        let location =
+            smap::source_location(&cf, private_method, 0, Some(smap::JAVA_STRATUM)).unwrap();
+        assert_eq!(
+            (location.file.as_str(), location.line),
+            ("HelloWorld.java", 32)
+        );
+        let location = smap::source_location(&cf, private_method, 0, Some("KotlinDebug")).unwrap();
+        assert_eq!(location.line, 1);
+        assert!(smap::source_location(&cf, code(b"package_method"), 0, None).is_none());
+    }
+
+    #[test]
+    fn test_kotlin_metadata() {
+        use class_parser::kotlin::*;
+
+        let fixture = test_resource("KotlinMetadata.j");
+        let metadata: Vec<KotlinMetadata> = fixture
+            .split("\n\n")
+            .filter(|block| block.starts_with(".annotation"))
+            .map(|block| {
+                let source =
+                    hello_world_source().replace(".end class", &format!("{}\n.end class", block));
+                let (_, cf) = class_parser::parse(
+                    &class_parser::write(&asm::assemble(&source).unwrap()).unwrap(),
+                )
+                .unwrap();
+                KotlinMetadata::from_class(&cf).unwrap().unwrap()
+            })
+            .collect();
+        assert_eq!(metadata.len(), 2);
+        assert_eq!(metadata[0].version, vec![1, 8, 0]);
+
+        let pair = match &metadata[0].content {
+            Content::Class(class) => class,
+            other => panic!("not a class: {:?}", other),
+        };
+        assert_eq!(pair.name, "kotlin/Pair");
+        assert!(pair.is_data());
+        assert_eq!(pair.kind(), ClassKind::Class);
+        assert_eq!(pair.modality(), Modality::Final);
+        assert_eq!(pair.type_parameters[1].variance, Variance::Out);
+        let first = pair.properties.iter().find(|p| p.name == "first").unwrap();
+        assert!(!first.is_var());
+        assert_eq!(
+            first.return_type.classifier,
+            KmClassifier::TypeParameter("A".to_string())
+        );
+        let equals = pair.functions.iter().find(|f| f.name == "equals").unwrap();
+        assert!(equals.parameters[0].ty.nullable);
+        assert!(!equals.return_type.nullable);
+        let api = metadata[0].public_api();
+        assert_eq!(
+            api[0],
+            "public data class kotlin.Pair<out A, out B> : java.io.Serializable"
+        );
+        assert!(api.contains(
+            &"    public fun copy(first: A = ..., second: B = ...): kotlin.Pair<A, B>".to_string()
+        ));
+
+        let tuples = match &metadata[1].content {
+            Content::File(package) => package,
+            other => panic!("not a file facade: {:?}", other),
+        };
+        let to = tuples.functions.iter().find(|f| f.name == "to").unwrap();
+        assert!(to.is_extension() && to.is_infix());
+        assert_eq!(
+            metadata[1].public_api(),
+            vec![
+                "public infix fun <A, B> A.to(that: B): kotlin.Pair<A, B>",
+                "public fun <T> kotlin.Pair<T, T>.toList(): kotlin.collections.List<T>",
+                "public fun <T> kotlin.Triple<T, T, T>.toList(): kotlin.collections.List<T>",
+            ]
+        );
+
+        let (_, cf) = class_parser::parse(&hello_world_bytes()).unwrap();
+        assert!(KotlinMetadata::from_class(&cf).is_none());
+    }
+
+    #[test]
+    fn test_versions() {
+        use class_parser::format::attributes::Type;
+        use class_parser::format::version::Version;
+        use class_parser::{ParseError, ParseOptions};
+
+        assert_eq!(Version::JAVA_8.to_string(), "52.0 (Java 8)");
+        assert_eq!(Version::JAVA_1_4.release_name().as_deref(), Some("1.4"));
+        assert_eq!(Version::of_release(21), Some(Version::JAVA_21));
+        assert_eq!(
+            Version::new(65, 0xFFFF).to_string(),
+            "65.65535 (Java 21 preview)"
+        );
+        assert!(!Version::new(50, 0xFFFF).is_preview());
+
+        // HelloWorld is compiled for 17
+        let bytes = hello_world_bytes();
+        let options = ParseOptions {
+            max_version: Version::JAVA_11,
+            ..ParseOptions::default()
+        };
+        match class_parser::parse_with(&bytes, &options) {
+            Err(ParseError::UnsupportedVersion(v)) => assert_eq!(v, Version::JAVA_17),
+            other => panic!("expected UnsupportedVersion, got {:?}", other.map(|_| ())),
+        }
+        let (_, cf) = class_parser::parse_with(&bytes, &ParseOptions::default()).unwrap();
+        assert_eq!(cf.version, Version::JAVA_17);
+
+        // method handles came with 51
+        let source = ".version 50 0\n.class A\n.const [1] = MethodHandle invokeStatic Method A m ()V\n.end class\n";
+        let bytes = class_parser::write(&asm::assemble(source).unwrap()).unwrap();
+        match class_parser::parse_with(&bytes, &ParseOptions::default()) {
+            Err(ParseError::ConstantNotAllowed { index: 1, .. }) => {}
+            other => panic!("expected ConstantNotAllowed, got {:?}", other.map(|_| ())),
+        }
+        assert!(class_parser::parse(&bytes).is_err());
+
+        // a StackMapTable before 50 is not the attribute and is ignored
+        let source = r#"
+.version 49 0
+.class A
+.method static m (I)V
+    .code stack 1 locals 1
+        iload_0
+        ifle done
+    done:
+        return
+        .stackmaptable
+            same done
+        .end stackmaptable
+    .end code
+.end method
+.end class
+"#;
+        let bytes = class_parser::write(&asm::assemble(source).unwrap()).unwrap();
+        let (_, cf) = class_parser::parse(&bytes).unwrap();
+        let code = match &cf.methods[0].attrs[0] {
+            Type::Code(code) => code,
+            _ => panic!("expected Code"),
+        };
+        assert!(matches!(
+            &code.attrs[..],
+            [Type::Unknown { name, .. }] if name.as_slice() == b"StackMapTable"
+        ));
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        use class_parser::builder::ConstantPoolBuilder;
+        use class_parser::format::attributes::{Code, ElementValueType, Type};
+        use class_parser::{ParseError, ParseOptions};
+
+        let bytes = hello_world_bytes();
+        let small = ParseOptions {
+            max_allocation: 1024,
+            ..ParseOptions::default()
+        };
+        assert!(matches!(
+            class_parser::parse_with(&bytes, &small),
+            Err(ParseError::TooLarge { .. })
+        ));
+
+        // InnerClasses comes last: claim a byte more than it has
+        let mut padded = bytes.clone();
+        let n = padded.len();
+        padded[n - 11] += 1;
+        padded.push(0);
+        match class_parser::parse_with(&padded, &ParseOptions::default()) {
+            Err(ParseError::AttributeLength {
+                name,
+                length,
+                offset,
+            }) => assert_eq!(
+                (name.as_str(), length, offset),
+                ("InnerClasses", 11, n - 16)
+            ),
+            other => panic!("expected AttributeLength, got {:?}", other.map(|_| ())),
+        }
+        let lenient = ParseOptions {
+            exact_attribute_length: false,
+            ..ParseOptions::default()
+        };
+        assert!(class_parser::parse_with(&padded, &lenient).is_ok());
+        // a byte less than it needs is never fine
+        padded[n - 11] -= 2;
+        assert!(matches!(
+            class_parser::parse_with(&padded, &lenient),
+            Err(ParseError::AttributeLength { length: 9, .. })
+        ));
+
+        let (_, mut cf) = class_parser::parse(&bytes).unwrap();
+        let mut b = ConstantPoolBuilder::from_pool(&cf.cp);
+        let val_index = b.integer(1).unwrap();
+        b.utf8("AnnotationDefault").unwrap();
+        cf.cp = b.build();
+        let mut default_value = ElementValueType::Int { val_index };
+        for _ in 0..100 {
+            default_value = ElementValueType::Array {
+                values: vec![default_value],
+            };
+        }
+        cf.methods[0].attrs.push(Type::AnnotationDefault {
+            raw: Arc::new(vec![]),
+            default_value,
+        });
+        let deep = class_parser::write(&cf).unwrap();
+        assert!(matches!(
+            class_parser::parse_with(&deep, &ParseOptions::default()),
+            Err(ParseError::TooDeep { .. })
+        ));
+        let options = ParseOptions {
+            max_depth: 100,
+            ..ParseOptions::default()
+        };
+        assert!(class_parser::parse_with(&deep, &options).is_ok());
+
+        // Code attributes inside each other's attributes count too
+        let (_, mut cf) = class_parser::parse(&bytes).unwrap();
+        let mut code = match cf.methods[0].attrs.remove(0) {
+            Type::Code(code) => code,
+            _ => panic!("expected Code"),
+        };
+        for _ in 0..70 {
+            code = Code {
+                max_stack: code.max_stack,
+                max_locals: code.max_locals,
+                code: code.code.clone(),
+                exceptions: vec![],
+                attrs: vec![Type::Code(code)],
+            };
+        }
+        cf.methods[0].attrs.insert(0, Type::Code(code));
+        let deep = class_parser::write(&cf).unwrap();
+        assert!(matches!(
+            class_parser::parse_with(&deep, &ParseOptions::default()),
+            Err(ParseError::TooDeep { .. })
+        ));
+        assert!(class_parser::parse_with(&deep, &options).is_ok());
+    }
+
+    #[test]
+    fn test_custom_attributes() {
+        use class_parser::format::attributes::{CustomAttribute, Type};
+        use class_parser::format::constant_pool::Type as ConstantType;
+        use class_parser::{ParseError, ParseOptions};
+
+        #[derive(Debug, PartialEq)]
+        struct ScalaSig {
+            major: u8,
+            minor: u8,
+        }
+
+        fn scala_sig(data: &[u8], _: &[ConstantType]) -> Result<Box<dyn CustomAttribute>, String> {
+            match data {
+                [major, minor, ..] => Ok(Box::new(ScalaSig {
+                    major: *major,
+                    minor: *minor,
+                })),
+                _ => Err("too short".to_string()),
+            }
+        }
+
+        fn reject(_: &[u8], _: &[ConstantType]) -> Result<Box<dyn CustomAttribute>, String> {
+            Err("not today".to_string())
+        }
+
+        let source = hello_world_source().replace(
+            ".end class",
+            ".attribute ScalaSig \"\\x05\\x00\\x00\"\n.attribute Vendor \"data\"\n.end class",
+        );
+        let bytes = class_parser::write(&asm::assemble(&source).unwrap()).unwrap();
+
+        let (_, cf) = class_parser::parse(&bytes).unwrap();
+        match &cf.attrs[cf.attrs.len() - 2..] {
+            [Type::Unknown { name, data }, Type::Unknown { .. }] => {
+                assert_eq!(name.as_slice(), b"ScalaSig");
+                assert_eq!(data.as_slice(), b"\x05\x00\x00");
+            }
+            other => panic!("expected unknown attributes, got {:?}", other),
+        }
+        assert_eq!(class_parser::write(&cf).unwrap(), bytes);
+        assert!(asm::print(&cf)
+            .unwrap()
+            .contains(".attribute ScalaSig \"\\x05\\x00\\x00\"\n"));
+
+        let mut options = ParseOptions::default();
+        options.register("ScalaSig", scala_sig);
+        let (_, cf) = class_parser::parse_with(&bytes, &options).unwrap();
+        let sig = cf.attrs.iter().find_map(|a| a.custom::<ScalaSig>());
+        assert_eq!(sig, Some(&ScalaSig { major: 5, minor: 0 }));
+        assert!(matches!(cf.attrs.last(), Some(Type::Unknown { .. })));
+        assert_eq!(class_parser::write(&cf).unwrap(), bytes);
+
+        options.register("Vendor", reject);
+        match class_parser::parse_with(&bytes, &options) {
+            Err(ParseError::BadAttribute { name, message, .. }) => {
+                assert_eq!((name.as_str(), message.as_str()), ("Vendor", "not today"))
+            }
+            other => panic!("expected BadAttribute, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_metrics() {
+        use metrics::{class_metrics, Limit, MetricsOptions};
+
+        let source = r#"
+.class public super abstract Metrics
+.super java/lang/Object
+
+.method public static sign (I)I
+    .code stack 1 locals 1
+        iload_0
+        ifle notpositive
+        iconst_1
+        ireturn
+    notpositive:
+        iload_0
+        ifge zero
+        iconst_m1
+        ireturn
+    zero:
+        iconst_0
+        ireturn
+    .end code
+.end method
+
+.method public static safe (I)I
+    .code stack 2 locals 1
+    start:
+        iconst_1
+        iload_0
+        idiv
+        invokestatic Method java/lang/Math abs (I)I
+        ireturn
+    handler:
+        pop
+        iconst_0
+        ireturn
+        .catch java/lang/ArithmeticException from start to handler using handler
+    .end code
+.end method
+
+.method public abstract run ()V
+.end method
+"#;
+        let bytes = class_parser::write(&asm::assemble(source).unwrap()).unwrap();
+        let (_, cf) = class_parser::parse(&bytes).unwrap();
+
+        let class = class_metrics(&cf, &MetricsOptions::default()).unwrap();
+        assert_eq!(class.name, "Metrics");
+        assert_eq!(class.methods.len(), 2);
+        let (sign, safe) = (&class.methods[0], &class.methods[1]);
+        assert_eq!((sign.code_length, sign.instructions), (14, 10));
+        assert_eq!(sign.complexity, 3);
+        assert_eq!(sign.calls(), 0);
+        assert_eq!((safe.complexity, safe.handlers), (2, 1));
+        assert_eq!((safe.max_stack, safe.max_locals), (2, 1));
+        assert_eq!(safe.invocations.get("invokestatic"), Some(&1));
+        assert!(sign.near.is_empty());
+        assert_eq!(
+            (class.code_length, class.complexity, class.max_complexity),
+            (24, 5, 3)
+        );
+
+        let wide = MetricsOptions { margin: 80 };
+        let class = class_metrics(&cf, &wide).unwrap();
+        assert_eq!(class.methods[0].near, vec![Limit::MaxInlineSize]);
+    }
+
+    #[test]
+    fn test_audit() {
+        use audit::{RuleError, RuleSet};
+
+        let source = r#"
+.class public super Exec
+.super java/lang/Object
+
+.method public static run (Ljava/lang/String;)V
+    .code stack 2 locals 1
+    L0:
+        invokestatic Method java/lang/Runtime getRuntime ()Ljava/lang/Runtime;
+        aload_0
+        invokevirtual Method java/lang/Runtime exec (Ljava/lang/String;)Ljava/lang/Process;
+        pop
+    L8:
+        ldc MethodHandle invokeVirtual Method java/lang/reflect/Field setAccessible (Z)V
+        pop
+        return
+        .linenumbertable
+            L0 7
+            L8 9
+        .end linenumbertable
+    .end code
+.end method
+.sourcefile "Exec.java"
+"#;
+        let bytes = class_parser::write(&asm::assemble(source).unwrap()).unwrap();
+        let (_, cf) = class_parser::parse(&bytes).unwrap();
+
+        let findings = RuleSet::builtin().check(&cf);
+        assert_eq!(findings.len(), 2);
+        assert_eq!(
+            findings[0].to_string(),
+            "command-exec: Exec.run(Exec.java:7) -> java/lang/Runtime.exec:(Ljava/lang/String;)Ljava/lang/Process;"
+        );
+        assert_eq!(
+            (findings[1].rule.as_str(), findings[1].line, findings[1].pc),
+            ("reflection-access", Some(9), 8)
+        );
+
+        let json = r#"{"rule": [
+            {"id": "runtime", "class": "java.lang.Runtime", "member": "*", "descriptor": "()*"},
+            {"id": "fields", "class": "java/lang/*", "member": "*", "kind": "field"}
+        ]}"#;
+        let findings = RuleSet::from_json(json).unwrap().check(&cf);
+        assert_eq!(findings.len(), 1);
+        assert_eq!((findings[0].rule.as_str(), findings[0].pc), ("runtime", 0));
+
+        let incomplete = "[[rule]]\nid = \"a\"\nclass = \"A\"\nmember = \"\"\n";
+        assert!(matches!(
+            RuleSet::from_toml(incomplete),
+            Err(RuleError::Incomplete(0))
+        ));
+    }
+
+    #[test]
+    fn test_serialization() {
+        use serialization::{
+            checks_serial_version_uid, decode, resolve, serial_version_uid, Content, Object, Value,
+        };
+
+        let source = r#"
+.class public super Point
+.super java/lang/Object
+.implements java/io/Serializable
+
+.field private x I
+.end field
+.field private static transient cache Ljava/lang/Object;
+.end field
+.field protected label Ljava/lang/String;
+.end field
+
+.method public <init> ()V
+    .code stack 1 locals 1
+        aload_0
+        invokespecial Method java/lang/Object <init> ()V
+        return
+    .end code
+.end method
+
+.method public getX ()I
+    .code stack 1 locals 1
+        aload_0
+        getfield Field Point x I
+        ireturn
+    .end code
+.end method
+
+.method private secret ()V
+    .code stack 0 locals 1
+        return
+    .end code
+.end method
+"#;
+        let bytes = class_parser::write(&asm::assemble(source).unwrap()).unwrap();
+        let (_, cf) = class_parser::parse(&bytes).unwrap();
+        // what ObjectStreamClass.lookup reports for the same class
+        assert_eq!(serial_version_uid(&cf), -6341567644351303388);
+
+        let mut stream = vec![0xac, 0xed, 0, 5, 0x73, 0x72, 0, 5];
+        stream.extend_from_slice(b"Point");
+        stream.extend_from_slice(&1i64.to_be_bytes());
+        stream.extend_from_slice(&[0x02, 0, 2, b'I', 0, 1, b'x', b'L', 0, 5]);
+        stream.extend_from_slice(b"label");
+        stream.extend_from_slice(&[0x74, 0, 18]);
+        stream.extend_from_slice(b"Ljava/lang/String;");
+        stream.extend_from_slice(&[0x78, 0x70, 0, 0, 0, 7, 0x74, 0, 2, b'h', b'i']);
+        stream.extend_from_slice(&[0x71, 0, 0x7e, 0, 2, 0x77, 3, 1, 2, 3]);
+
+        let decoded = decode(&stream).unwrap();
+        assert_eq!(
+            decoded.contents,
+            vec![
+                Content::Value(Value::Object(2)),
+                Content::Value(Value::Object(2)),
+                Content::BlockData(vec![1, 2, 3]),
+            ]
+        );
+        match &decoded.objects[2] {
+            Object::Instance { class: 0, data } => assert_eq!(
+                data[0].values,
+                vec![
+                    ("x".to_string(), Value::Int(7)),
+                    ("label".to_string(), Value::Object(3))
+                ]
+            ),
+            other => panic!("expected an instance, got {:?}", other),
+        }
+        assert_eq!(decoded.objects[3], Object::String("hi".to_string()));
+
+        let dir = scratch_dir("serial");
+        std::fs::write(dir.join("Point.class"), &bytes).unwrap();
+        let mut cpm = class_path_manager::ClassPathManager::new();
+        cpm.add_class_path(dir.to_str().unwrap()).unwrap();
+        let resolved = resolve(&decoded, &cpm);
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(resolved[0].local_uid, Some(-6341567644351303388));
+        assert!(resolved[0].is_mismatch());
+
+        // record R(int a) implements Serializable with serialVersionUID 42L
+        let source = r#"
+.class public final super R
+.super java/lang/Record
+.implements java/io/Serializable
+
+.field private static final serialVersionUID J
+    .constantvalue Long 42
+.end field
+"#;
+        let record = class_parser::write(&asm::assemble(source).unwrap()).unwrap();
+        let (_, cf) = class_parser::parse(&record).unwrap();
+        assert_eq!(serial_version_uid(&cf), 42);
+        assert!(!checks_serial_version_uid(&cf));
+        std::fs::write(dir.join("R.class"), &record).unwrap();
+        // a TC_CLASS of R with serialVersionUID 1
+        let mut stream = vec![0xac, 0xed, 0, 5, 0x76, 0x72, 0, 1, b'R'];
+        stream.extend_from_slice(&1i64.to_be_bytes());
+        stream.extend_from_slice(&[0x02, 0, 0, 0x78, 0x70]);
+        let resolved = resolve(&decode(&stream).unwrap(), &cpm);
+        assert_eq!(resolved[0].local_uid, Some(42));
+        assert!(!resolved[0].is_mismatch());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let err = decode(&stream[..20]).unwrap_err();
+        assert_eq!(err.offset, 20);
+    }
+
+    #[test]
+    fn test_scan() {
+        use class_parser::{format::version::Version, ParseError};
+        use scan::{scan, ScanOptions};
+
+        let dir = scratch_dir("scan");
+        std::fs::create_dir_all(dir.join("pkg")).unwrap();
+        let bytes = hello_world_bytes();
+        std::fs::write(dir.join("HelloWorld.class"), &bytes).unwrap();
+        std::fs::write(dir.join("pkg/Broken.class"), &bytes[..100]).unwrap();
+        std::fs::write(dir.join("pkg/notes.txt"), b"not a class").unwrap();
+
+        let mut cpm = class_path_manager::ClassPathManager::new();
+        cpm.add_class_path(dir.to_str().unwrap()).unwrap();
+        let options = ScanOptions {
+            threads: 2,
+            ..ScanOptions::default()
+        };
+        let report = scan(&cpm, &options).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(report.classes, 2);
+        assert_eq!(report.bytes, bytes.len() + 100);
+        assert_eq!(report.versions.get(&Version::JAVA_17), Some(&1));
+        assert_eq!(report.attributes.get("BootstrapMethods"), Some(&1));
+        assert!(report.attributes["LineNumberTable"] >= report.attributes["Code"]);
+        assert_eq!(report.failures.len(), 1);
+        let failure = &report.failures[0];
+        assert_eq!(failure.name, "pkg/Broken.class");
+        assert_eq!(failure.error, ParseError::Truncated);
+        assert_eq!(failure.offset, 100);
+    }
+
+    #[test]
+    fn test_symbol_table() {
+        use class_parser::format::constant_pool::{get_class_name, Type};
+        use symbol::SymbolTable;
+
+        let table = SymbolTable::new();
+        let object = table.intern(b"java/lang/Object");
+        assert_eq!(table.intern(b"java/lang/Object"), object);
+        assert_ne!(table.intern(b"()V"), object);
+        assert_ne!(SymbolTable::new().intern(b"java/lang/Object"), object);
+
+        let bytes = hello_world_bytes();
+        let (_, mut first) = class_parser::parse(&bytes).unwrap();
+        let (_, mut second) = class_parser::parse(&bytes).unwrap();
+        let utf8s = first
+            .cp
+            .iter()
+            .filter(|c| matches!(c, Type::Utf8 { .. }))
+            .count();
+        let shared = table.intern_class(&mut first);
+        assert!(shared >= 1 && shared < utf8s);
+        assert_eq!(table.intern_class(&mut second), utf8s);
+        assert_eq!(table.intern_class(&mut second), 0);
+        let name = |cf: &class_parser::format::class_file::ClassFile| {
+            get_class_name(&cf.cp, cf.this_class as usize).unwrap()
+        };
+        assert!(Arc::ptr_eq(&name(&first), &name(&second)));
+        assert_eq!(table.get(b"HelloWorld").unwrap().bytes(), name(&first));
+        assert_eq!(class_parser::write(&second).unwrap(), bytes);
+
+        let stats = table.stats();
+        assert_eq!(stats.hits, 1 + shared + utf8s);
+        assert!(stats.saved_bytes() > stats.hit_bytes);
+    }
+
+    #[test]
+    fn test_class_loader() {
+        use class_parser::format::constant_pool::Type;
+
+        let dir = scratch_dir("loader");
+        std::fs::write(dir.join("HelloWorld.class"), hello_world_bytes()).unwrap();
+        let mut cpm = class_path_manager::ClassPathManager::new();
+        cpm.add_class_path(dir.to_str().unwrap()).unwrap();
+        let cpm = Arc::new(cpm);
+
+        // a class is parsed and interned once, then served from the cache
+        let cl = class_loader::ClassLoader::new(cpm.clone(), None);
+        let loaded = cl.load_class("HelloWorld").unwrap();
+        assert!(Arc::ptr_eq(&loaded, &cl.load_class("HelloWorld").unwrap()));
+        assert!(cl.load_class("Missing").is_none());
+
+        let utf8s = |cf: &class_parser::format::class_file::ClassFile| {
+            cf.cp
+                .iter()
+                .filter_map(|c| match c {
+                    Type::Utf8 { bytes } => Some(bytes.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        for bytes in utf8s(&loaded) {
+            assert!(Arc::ptr_eq(
+                &cl.symbol(std::str::from_utf8(&bytes).unwrap()).bytes(),
+                &bytes
+            ));
+        }
+
+        // another loader keeps its own copy of the class, sharing its strings
+        let other = class_loader::ClassLoader::new(cpm, None);
+        let reloaded = other.load_class("HelloWorld").unwrap();
+        assert!(!Arc::ptr_eq(&loaded, &reloaded));
+        assert!(utf8s(&loaded)
+            .iter()
+            .zip(utf8s(&reloaded).iter())
+            .all(|(a, b)| Arc::ptr_eq(a, b)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_jar_file() {
+        use std::io::{Read, Write};
+        use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+        let mut zip = ZipWriter::new(std::io::Cursor::new(vec![]));
+        let class = hello_world_bytes();
+        zip.add_directory("pkg/", FileOptions::default()).unwrap();
+        zip.start_file("pkg/A.class", FileOptions::default())
+            .unwrap();
+        zip.write_all(&class).unwrap();
+        zip.start_file(
+            "pkg/a.txt",
+            FileOptions::default().compression_method(CompressionMethod::Stored),
+        )
+        .unwrap();
+        zip.write_all(b"stored").unwrap();
+        let archive = zip.finish().unwrap().into_inner();
+
+        // a launcher script in front moves every offset
+        let mut data = b"#!/bin/sh\nexec java -jar \"$0\"\n".to_vec();
+        data.extend_from_slice(&archive);
+        let dir = scratch_dir("jar");
+        let path = dir.join("launcher.jar");
+        std::fs::write(&path, &data).unwrap();
+        let jar = Arc::new(jar::JarFile::open(&path).unwrap());
+        assert_eq!(
+            jar.names().collect::<Vec<_>>(),
+            vec!["pkg/", "pkg/A.class", "pkg/a.txt"]
+        );
+        assert!(jar.entries()[0].is_dir());
+        let entry = jar.by_name("pkg/A.class").unwrap();
+        assert_eq!(entry.method, jar::DEFLATED);
+        assert_eq!(jar.read(entry).unwrap(), class);
+        let mut streamed = vec![];
+        jar::JarFile::reader(&jar, entry)
+            .unwrap()
+            .read_to_end(&mut streamed)
+            .unwrap();
+        assert_eq!(streamed, class);
+        assert_eq!(
+            jar.raw(jar.by_name("pkg/a.txt").unwrap()).unwrap(),
+            b"stored"
+        );
+        assert!(jar.by_name("pkg/B.class").is_none());
+
+        // a directory that claims far more than the entry inflates to fails
+        // the size check rather than sizing the buffer
+        let mut bomb = data.clone();
+        let central = bomb
+            .windows(4)
+            .enumerate()
+            .filter(|(_, w)| *w == b"PK\x01\x02")
+            .map(|(i, _)| i)
+            .find(|&i| bomb[i + 46..].starts_with(b"pkg/A.class"))
+            .unwrap();
+        bomb[central + 24..central + 28].copy_from_slice(&0xFFFF_FFF0u32.to_le_bytes());
+        let bomb = jar::JarFile::from_bytes(bomb).unwrap();
+        let err = bomb.read(bomb.by_name("pkg/A.class").unwrap()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        // flip a byte of the stored entry, in a copy: the file above is
+        // still mapped
+        let at = data.windows(6).position(|w| w == b"stored").unwrap();
+        data[at] = b'S';
+        let corrupt = path.with_extension("corrupt.jar");
+        std::fs::write(&corrupt, &data).unwrap();
+        let jar = jar::JarFile::open(&corrupt).unwrap();
+        let err = jar.read(jar.by_name("pkg/a.txt").unwrap()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        std::fs::remove_file(&path).unwrap();
+        assert!(jar::JarFile::open(&path).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // a jar of deflated class-sized entries, in a scratch directory of its
+    // own, and their names
+    fn bench_jar(name: &str) -> (std::path::PathBuf, Vec<String>) {
+        let dir = scratch_dir(&format!("bench-{}", name));
+        let class = hello_world_bytes();
+        let names: Vec<_> = (0..2000)
+            .map(|i| format!("pkg{}/C{}.class", i % 20, i))
+            .collect();
+        let files: Vec<_> = names.iter().map(|n| (n.as_str(), &class[..])).collect();
+        write_jar(&dir.join("bench.jar"), &files);
+        (dir, names)
+    }
+
+    // reads every name once, split over 4 threads
+    fn read_concurrently(names: &[String], read: impl Fn(&str) -> usize + Sync) -> usize {
+        let read = &read;
+        std::thread::scope(|s| {
+            let workers: Vec<_> = names
+                .chunks(names.len() / 4)
+                .map(|chunk| s.spawn(move || chunk.iter().map(|n| read(n)).sum::<usize>()))
+                .collect();
+            workers.into_iter().map(|w| w.join().unwrap()).sum()
+        })
+    }
+
+    #[bench]
+    fn bench_jar_read_zip_mutex(b: &mut Bencher) {
+        use std::io::Read;
+
+        let (dir, names) = bench_jar("zip");
+        let file = std::fs::File::open(dir.join("bench.jar")).unwrap();
+        let zip = Mutex::new(zip::ZipArchive::new(file).unwrap());
+        b.iter(|| {
+            read_concurrently(&names, |name| {
+                let mut zip = zip.lock().unwrap();
+                let mut data = vec![];
+                zip.by_name(name).unwrap().read_to_end(&mut data).unwrap();
+                data.len()
+            })
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[bench]
+    fn bench_jar_read_indexed(b: &mut Bencher) {
+        let (dir, names) = bench_jar("indexed");
+        let jar = jar::JarFile::open(dir.join("bench.jar")).unwrap();
+        b.iter(|| {
+            read_concurrently(&names, |name| {
+                jar.read(jar.by_name(name).unwrap()).unwrap().len()
+            })
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[bench]
     fn bench_mutex(b: &mut Bencher) {
         let m = Mutex::new(0);
@@ -0,0 +1,305 @@
+//! Size and complexity of every method, computed from its bytecode, with the
+//! methods whose size is close to a limit of the class file format or of
+//! HotSpot's JIT flagged.
+
+use super::class_parser::{
+    bytecode::{self, Instruction},
+    format::{
+        attributes::{Code, Type as AttributeType},
+        class_file::ClassFile,
+        constant_pool::{self, Type as ConstantType},
+        method_info::MethodInfo,
+    },
+};
+
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::sync::Arc;
+
+/// Code sizes that change how a method is treated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Limit {
+    /// spec 4.7.3: code_length must be less than 65536
+    CodeLength,
+    /// HotSpot's HugeMethodLimit, larger methods are never compiled
+    HugeMethod,
+    /// HotSpot's FreqInlineSize, the largest hot method that is inlined
+    FreqInlineSize,
+    /// HotSpot's MaxInlineSize, the largest method inlined when not hot
+    MaxInlineSize,
+}
+
+impl Limit {
+    pub const ALL: [Limit; 4] = [
+        Limit::CodeLength,
+        Limit::HugeMethod,
+        Limit::FreqInlineSize,
+        Limit::MaxInlineSize,
+    ];
+
+    /// The limit in bytes of bytecode, for HotSpot the default value.
+    pub fn bytes(self) -> usize {
+        match self {
+            Limit::CodeLength => 65535,
+            Limit::HugeMethod => 8000,
+            Limit::FreqInlineSize => 325,
+            Limit::MaxInlineSize => 35,
+        }
+    }
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Limit::CodeLength => "CodeLength",
+            Limit::HugeMethod => "HugeMethodLimit",
+            Limit::FreqInlineSize => "FreqInlineSize",
+            Limit::MaxInlineSize => "MaxInlineSize",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MetricsOptions {
+    /// How close, in percent of the limit and on either side of it, a
+    /// method's size must be to be flagged.
+    pub margin: usize,
+}
+
+impl Default for MetricsOptions {
+    fn default() -> Self {
+        Self { margin: 10 }
+    }
+}
+
+impl MetricsOptions {
+    fn near(&self, code_length: usize) -> Vec<Limit> {
+        Limit::ALL
+            .iter()
+            .copied()
+            .filter(|limit| {
+                let margin = limit.bytes() * self.margin / 100;
+                code_length + margin >= limit.bytes() && code_length <= limit.bytes() + margin
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MethodMetrics {
+    pub name: String,
+    pub descriptor: String,
+    pub code_length: usize,
+    pub instructions: usize,
+    /// Cyclomatic complexity of the control flow graph, where every
+    /// exception handler counts as one more decision.
+    pub complexity: usize,
+    pub max_stack: u16,
+    pub max_locals: u16,
+    /// Entries in the exception table.
+    pub handlers: usize,
+    /// Number of each invoke instruction, by mnemonic.
+    pub invocations: BTreeMap<&'static str, usize>,
+    /// The limits code_length is within the margin of.
+    pub near: Vec<Limit>,
+}
+
+impl MethodMetrics {
+    pub fn calls(&self) -> usize {
+        self.invocations.values().sum()
+    }
+}
+
+/// Metrics of the methods with code and their totals.
+#[derive(Debug, Clone, Serialize)]
+pub struct ClassMetrics {
+    pub name: String,
+    pub methods: Vec<MethodMetrics>,
+    pub code_length: usize,
+    pub complexity: usize,
+    pub max_complexity: usize,
+    pub handlers: usize,
+    pub calls: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    /// name and descriptor of the method
+    pub method: String,
+    pub pc: usize,
+    pub message: &'static str,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} at pc {}", self.method, self.message, self.pc)
+    }
+}
+
+impl std::error::Error for Error {}
+
+fn utf8(cp: &Arc<Vec<ConstantType>>, idx: u16) -> String {
+    constant_pool::get_utf8(cp, idx as usize)
+        .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+        .unwrap_or_default()
+}
+
+pub fn class_metrics(cf: &ClassFile, options: &MetricsOptions) -> Result<ClassMetrics, Error> {
+    let name = constant_pool::get_class_name(&cf.cp, cf.this_class as usize)
+        .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+        .unwrap_or_default();
+    let mut class = ClassMetrics {
+        name,
+        methods: vec![],
+        code_length: 0,
+        complexity: 0,
+        max_complexity: 0,
+        handlers: 0,
+        calls: 0,
+    };
+    for method in cf.methods.iter() {
+        if let Some(m) = method_metrics(&cf.cp, method, options)? {
+            class.code_length += m.code_length;
+            class.complexity += m.complexity;
+            class.max_complexity = class.max_complexity.max(m.complexity);
+            class.handlers += m.handlers;
+            class.calls += m.calls();
+            class.methods.push(m);
+        }
+    }
+    Ok(class)
+}
+
+/// None for abstract and native methods.
+pub fn method_metrics(
+    cp: &Arc<Vec<ConstantType>>,
+    method: &MethodInfo,
+    options: &MetricsOptions,
+) -> Result<Option<MethodMetrics>, Error> {
+    let code = match method.attrs.iter().find_map(|attr| match attr {
+        AttributeType::Code(code) => Some(code),
+        _ => None,
+    }) {
+        Some(code) => code,
+        None => return Ok(None),
+    };
+
+    let name = utf8(cp, method.name_index);
+    let descriptor = utf8(cp, method.desc_index);
+    let fail = |pc, message| Error {
+        method: format!("{}{}", name, descriptor),
+        pc,
+        message,
+    };
+
+    let mut insns = vec![];
+    let mut invocations = BTreeMap::new();
+    for insn in bytecode::Instructions::new(&code.code) {
+        let insn = insn.map_err(|pc| fail(pc, "bad instruction"))?;
+        if let bytecode::INVOKEVIRTUAL..=bytecode::INVOKEDYNAMIC = insn.opcode {
+            let mnemonic = bytecode::mnemonic(insn.opcode).unwrap();
+            *invocations.entry(mnemonic).or_insert(0) += 1;
+        }
+        insns.push(insn);
+    }
+    let complexity = complexity(code, &insns).map_err(|(pc, message)| fail(pc, message))?;
+
+    Ok(Some(MethodMetrics {
+        code_length: code.code.len(),
+        instructions: insns.len(),
+        complexity,
+        max_stack: code.max_stack,
+        max_locals: code.max_locals,
+        handlers: code.exceptions.len(),
+        invocations,
+        near: options.near(code.code.len()),
+        name,
+        descriptor,
+    }))
+}
+
+fn ends_block(opcode: u8) -> bool {
+    // goto, jsr, ret, switches, returns, athrow
+    matches!(opcode, 0xa7..=0xb1 | 0xbf | 0xc8 | 0xc9)
+}
+
+// McCabe's E - N + 2 over the basic blocks, with one extra node every
+// return and athrow leads to. Each distinct handler adds one edge, from
+// the block its first range starts in.
+fn complexity(code: &Code, insns: &[Instruction]) -> Result<usize, (usize, &'static str)> {
+    let starts: BTreeSet<usize> = insns.iter().map(|insn| insn.pc).collect();
+    let check = |pc: usize, at: usize| {
+        if starts.contains(&pc) {
+            Ok(pc)
+        } else {
+            Err((at, "target is not an instruction"))
+        }
+    };
+
+    let mut leaders = BTreeSet::new();
+    leaders.insert(0);
+    for insn in insns.iter() {
+        let targets = bytecode::branch_targets(&code.code, insn);
+        for &target in targets.iter() {
+            leaders.insert(check(target, insn.pc)?);
+        }
+        if ends_block(insn.opcode) || !targets.is_empty() {
+            leaders.insert(insn.pc + insn.len);
+        }
+    }
+    let mut handlers = vec![];
+    for e in code.exceptions.iter() {
+        let start = check(e.start_pc as usize, e.start_pc as usize)?;
+        let handler = check(e.handler_pc as usize, e.handler_pc as usize)?;
+        leaders.insert(start);
+        leaders.insert(handler);
+        if !handlers.iter().any(|&(_, h)| h == handler) {
+            handlers.push((start, handler));
+        }
+    }
+    let leaders: Vec<usize> = leaders
+        .into_iter()
+        .filter(|&pc| pc < code.code.len())
+        .collect();
+    let block = |pc: usize| leaders.partition_point(|&l| l <= pc) - 1;
+    let exit = leaders.len();
+
+    let mut edges: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); leaders.len()];
+    for (i, insn) in insns.iter().enumerate() {
+        let next = insn.pc + insn.len;
+        let last = insns
+            .get(i + 1)
+            .is_none_or(|n| leaders.binary_search(&n.pc).is_ok());
+        if !last {
+            continue;
+        }
+        let from = block(insn.pc);
+        let fallthrough = if next < code.code.len() {
+            block(next)
+        } else {
+            exit
+        };
+        match insn.opcode {
+            // returns, athrow and ret, whose target isn't known statically
+            0xa9 | 0xac..=0xb1 | 0xbf => {
+                edges[from].insert(exit);
+            }
+            // goto, goto_w and the switches
+            0xa7 | 0xc8 | bytecode::TABLESWITCH | bytecode::LOOKUPSWITCH => {}
+            _ => {
+                edges[from].insert(fallthrough);
+            }
+        }
+        for target in bytecode::branch_targets(&code.code, insn) {
+            edges[from].insert(block(target));
+        }
+    }
+    for (start, handler) in handlers {
+        edges[block(start)].insert(block(handler));
+    }
+
+    let e: usize = edges.iter().map(|targets| targets.len()).sum();
+    Ok((e + 2).saturating_sub(leaders.len() + 1).max(1))
+}
@@ -0,0 +1,943 @@
+use super::class_parser::builder::{self, ConstantPoolBuilder};
+use super::class_parser::format::{
+    attributes::{AnnotationEntry, ElementValuePair, ElementValueType, Type as AttributeType},
+    class_file::ClassFile,
+    constant_pool::Type,
+};
+use super::class_parser::refresh_raw;
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io;
+use std::sync::Arc;
+
+/// Source of new names. Every name is in JVM internal form (`a/b/C`) and
+/// every lookup is keyed by the names found in the class being remapped.
+pub trait Mapper {
+    fn map_class(&self, name: &str) -> Option<String>;
+
+    fn map_field(&self, _owner: &str, _name: &str, _desc: &str) -> Option<String> {
+        None
+    }
+
+    fn map_method(&self, _owner: &str, _name: &str, _desc: &str) -> Option<String> {
+        None
+    }
+
+    /// Annotation element names are method names of the annotation type,
+    /// but the element value doesn't tell the method's return type.
+    fn map_annotation_element(&self, _owner: &str, _name: &str) -> Option<String> {
+        None
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct MemberKey {
+    owner: String,
+    name: String,
+    // empty when the mapping doesn't say, matching any descriptor
+    desc: String,
+}
+
+impl MemberKey {
+    fn new(owner: &str, name: &str, desc: &str) -> Self {
+        Self {
+            owner: owner.to_string(),
+            name: name.to_string(),
+            desc: desc.to_string(),
+        }
+    }
+}
+
+/// A rename table loaded from a ProGuard `mapping.txt`, a Tiny file or a
+/// plain `old new` list.
+#[derive(Clone, Debug, Default)]
+pub struct Mapping {
+    classes: HashMap<String, String>,
+    fields: HashMap<MemberKey, String>,
+    methods: HashMap<MemberKey, String>,
+}
+
+fn invalid_data(line: usize, msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("mapping line {}: {}", line + 1, msg),
+    )
+}
+
+// `java.lang.String[]` -> `[Ljava/lang/String;`
+fn java_type_to_desc(ty: &str) -> String {
+    let mut ty = ty.trim();
+    let mut desc = String::new();
+    while let Some(elem) = ty.strip_suffix("[]") {
+        desc.push('[');
+        ty = elem;
+    }
+    match ty {
+        "void" => desc.push('V'),
+        "boolean" => desc.push('Z'),
+        "byte" => desc.push('B'),
+        "char" => desc.push('C'),
+        "short" => desc.push('S'),
+        "int" => desc.push('I'),
+        "long" => desc.push('J'),
+        "float" => desc.push('F'),
+        "double" => desc.push('D'),
+        _ => {
+            desc.push('L');
+            desc.push_str(&ty.replace('.', "/"));
+            desc.push(';');
+        }
+    }
+    desc
+}
+
+// R8 prefixes members with `startline:endline:`
+fn strip_line_range(s: &str) -> &str {
+    let mut rest = s;
+    for _ in 0..2 {
+        match rest.find(':') {
+            Some(i) if rest[..i].bytes().all(|b| b.is_ascii_digit()) => rest = &rest[i + 1..],
+            _ => break,
+        }
+    }
+    rest
+}
+
+impl Mapping {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_class(&mut self, from: &str, to: &str) {
+        self.classes.insert(from.to_string(), to.to_string());
+    }
+
+    /// `desc` may be empty to rename the field whatever its type.
+    pub fn add_field(&mut self, owner: &str, name: &str, desc: &str, to: &str) {
+        self.fields
+            .insert(MemberKey::new(owner, name, desc), to.to_string());
+    }
+
+    /// `desc` may be empty to rename every overload.
+    pub fn add_method(&mut self, owner: &str, name: &str, desc: &str, to: &str) {
+        self.methods
+            .insert(MemberKey::new(owner, name, desc), to.to_string());
+    }
+
+    /// Parses ProGuard/R8 `mapping.txt`. The result maps original names to
+    /// obfuscated ones, use [`Mapping::reversed`] to deobfuscate.
+    pub fn from_proguard(text: &str) -> io::Result<Self> {
+        let mut mapping = Self::new();
+        let mut owner: Option<String> = None;
+
+        for (n, line) in text.lines().enumerate() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+
+            let arrow = trimmed
+                .find(" -> ")
+                .ok_or_else(|| invalid_data(n, "missing `->`"))?;
+            let (left, right) = (trimmed[..arrow].trim(), trimmed[arrow + 4..].trim());
+
+            if !line.starts_with(char::is_whitespace) {
+                let to = right
+                    .strip_suffix(':')
+                    .ok_or_else(|| invalid_data(n, "class line must end with `:`"))?;
+                let from = left.replace('.', "/");
+                mapping.add_class(&from, &to.replace('.', "/"));
+                owner = Some(from);
+                continue;
+            }
+
+            let owner = owner
+                .as_ref()
+                .ok_or_else(|| invalid_data(n, "member outside of a class"))?;
+            let member = strip_line_range(left);
+            let space = member
+                .find(' ')
+                .ok_or_else(|| invalid_data(n, "missing member type"))?;
+            let (ty, rest) = (&member[..space], member[space + 1..].trim());
+
+            match rest.find('(') {
+                Some(open) => {
+                    let close = rest
+                        .find(')')
+                        .ok_or_else(|| invalid_data(n, "missing `)`"))?;
+                    let name = &rest[..open];
+                    // R8 records inlined frames as `owner.name`, they don't
+                    // describe members of this class
+                    if name.contains('.') {
+                        continue;
+                    }
+                    let mut desc = String::from("(");
+                    rest[open + 1..close]
+                        .split(',')
+                        .filter(|a| !a.trim().is_empty())
+                        .for_each(|a| desc.push_str(&java_type_to_desc(a)));
+                    desc.push(')');
+                    desc.push_str(&java_type_to_desc(ty));
+                    mapping.add_method(owner, name, &desc, right);
+                }
+                None => mapping.add_field(owner, rest, &java_type_to_desc(ty), right),
+            }
+        }
+
+        Ok(mapping)
+    }
+
+    /// Parses Tiny v1 and v2 files, mapping the first namespace to the second.
+    pub fn from_tiny(text: &str) -> io::Result<Self> {
+        let mut lines = text.lines().enumerate();
+        let header = match lines.next() {
+            Some((_, header)) => header,
+            None => return Ok(Self::new()),
+        };
+
+        let mut mapping = Self::new();
+        if header.starts_with("v1\t") {
+            for (n, line) in lines {
+                let cols: Vec<&str> = line.split('\t').collect();
+                match cols.as_slice() {
+                    ["CLASS", from, to, ..] => mapping.add_class(from, to),
+                    ["FIELD", owner, desc, from, to, ..] => {
+                        mapping.add_field(owner, from, desc, to)
+                    }
+                    ["METHOD", owner, desc, from, to, ..] => {
+                        mapping.add_method(owner, from, desc, to)
+                    }
+                    [""] | [] => (),
+                    [kind, ..] if kind.starts_with('#') => (),
+                    _ => return Err(invalid_data(n, "malformed tiny v1 entry")),
+                }
+            }
+        } else if header.starts_with("tiny\t2\t") {
+            let mut owner: Option<String> = None;
+            for (n, line) in lines {
+                let cols: Vec<&str> = line.split('\t').collect();
+                match cols.as_slice() {
+                    ["c", from, to, ..] => {
+                        if !to.is_empty() {
+                            mapping.add_class(from, to);
+                        }
+                        owner = Some(from.to_string());
+                    }
+                    ["", kind @ "f", desc, from, to, ..] | ["", kind @ "m", desc, from, to, ..] => {
+                        let owner = owner
+                            .as_ref()
+                            .ok_or_else(|| invalid_data(n, "member outside of a class"))?;
+                        if to.is_empty() {
+                            continue;
+                        }
+                        if *kind == "f" {
+                            mapping.add_field(owner, from, desc, to);
+                        } else {
+                            mapping.add_method(owner, from, desc, to);
+                        }
+                    }
+                    // comments, parameters and local variables
+                    _ => (),
+                }
+            }
+        } else {
+            return Err(invalid_data(0, "unknown tiny header"));
+        }
+
+        Ok(mapping)
+    }
+
+    /// Parses a plain rename table, one `from to` pair per line:
+    ///
+    /// ```text
+    /// a/b/C            x/y/Z
+    /// a/b/C.field      renamedField
+    /// a/b/C.method(I)V renamedMethod
+    /// ```
+    pub fn from_rename_table(text: &str) -> io::Result<Self> {
+        let mut mapping = Self::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut cols = line.split_whitespace();
+            let (from, to) = match (cols.next(), cols.next(), cols.next()) {
+                (Some(from), Some(to), None) => (from, to),
+                _ => return Err(invalid_data(n, "expected `from to`")),
+            };
+
+            match from.rfind('.') {
+                Some(dot) => {
+                    let (owner, member) = (&from[..dot], &from[dot + 1..]);
+                    match member.find('(') {
+                        Some(open) => {
+                            mapping.add_method(owner, &member[..open], &member[open..], to)
+                        }
+                        None => mapping.add_field(owner, member, "", to),
+                    }
+                }
+                None => mapping.add_class(from, to),
+            }
+        }
+        Ok(mapping)
+    }
+
+    /// Swaps the direction of the mapping; member keys are translated into
+    /// the target namespace.
+    pub fn reversed(&self) -> Self {
+        let mut reversed = Self::new();
+        for (from, to) in self.classes.iter() {
+            reversed.add_class(to, from);
+        }
+
+        let owner = |o: &str| self.map_class(o).unwrap_or_else(|| o.to_string());
+        let desc = |d: &str| remap_signature(d, &|c| self.map_class(c));
+        for (key, to) in self.fields.iter() {
+            reversed.add_field(&owner(&key.owner), to, &desc(&key.desc), &key.name);
+        }
+        for (key, to) in self.methods.iter() {
+            reversed.add_method(&owner(&key.owner), to, &desc(&key.desc), &key.name);
+        }
+
+        reversed
+    }
+}
+
+impl Mapper for Mapping {
+    fn map_class(&self, name: &str) -> Option<String> {
+        if let Some(to) = self.classes.get(name) {
+            return Some(to.clone());
+        }
+
+        // keep inner classes next to a renamed outer class
+        let dollar = name.rfind('$')?;
+        let outer = self.map_class(&name[..dollar])?;
+        Some(format!("{}{}", outer, &name[dollar..]))
+    }
+
+    fn map_field(&self, owner: &str, name: &str, desc: &str) -> Option<String> {
+        self.fields
+            .get(&MemberKey::new(owner, name, desc))
+            .or_else(|| self.fields.get(&MemberKey::new(owner, name, "")))
+            .cloned()
+    }
+
+    fn map_method(&self, owner: &str, name: &str, desc: &str) -> Option<String> {
+        self.methods
+            .get(&MemberKey::new(owner, name, desc))
+            .or_else(|| self.methods.get(&MemberKey::new(owner, name, "")))
+            .cloned()
+    }
+
+    fn map_annotation_element(&self, owner: &str, name: &str) -> Option<String> {
+        self.methods
+            .iter()
+            .find(|(key, _)| key.owner == owner && key.name == name && key.desc.starts_with("()"))
+            .map(|(_, to)| to.clone())
+    }
+}
+
+struct SignatureRemapper<'a> {
+    sig: &'a [u8],
+    pos: usize,
+    out: String,
+    map_class: &'a dyn Fn(&str) -> Option<String>,
+}
+
+impl<'a> SignatureRemapper<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.sig.get(self.pos).copied()
+    }
+
+    fn copy_one(&mut self) {
+        self.out.push(self.sig[self.pos] as char);
+        self.pos += 1;
+    }
+
+    fn ident(&mut self, stops: &[u8]) -> &'a str {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if stops.contains(&c) {
+                break;
+            }
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.sig[start..self.pos]).unwrap_or("")
+    }
+
+    fn class_type(&mut self) {
+        // 'L' already copied
+        let mut name = self.ident(b"<.;").to_string();
+        let mut mapped = (self.map_class)(&name).unwrap_or_else(|| name.clone());
+        self.out.push_str(&mapped);
+        self.type_arguments();
+
+        while self.peek() == Some(b'.') {
+            self.copy_one();
+            let simple = self.ident(b"<.;");
+            let inner = format!("{}${}", name, simple);
+            let mapped_inner =
+                (self.map_class)(&inner).unwrap_or_else(|| format!("{}${}", mapped, simple));
+            let prefix = format!("{}$", mapped);
+            let mapped_simple = match mapped_inner.strip_prefix(&prefix) {
+                Some(s) => s.to_string(),
+                None => mapped_inner
+                    .rsplit(['$', '/'])
+                    .next()
+                    .unwrap_or(simple)
+                    .to_string(),
+            };
+            self.out.push_str(&mapped_simple);
+            self.type_arguments();
+            name = inner;
+            mapped = mapped_inner;
+        }
+
+        if self.peek() == Some(b';') {
+            self.copy_one();
+        }
+    }
+
+    fn type_arguments(&mut self) {
+        if self.peek() != Some(b'<') {
+            return;
+        }
+        self.copy_one();
+        while let Some(c) = self.peek() {
+            if c == b'>' {
+                self.copy_one();
+                break;
+            }
+            self.any_type();
+        }
+    }
+
+    fn any_type(&mut self) {
+        let c = match self.peek() {
+            Some(c) => c,
+            None => return,
+        };
+        self.copy_one();
+        match c {
+            b'L' => self.class_type(),
+            b'[' | b'+' | b'-' => self.any_type(),
+            b'T' => {
+                let var = self.ident(b";");
+                self.out.push_str(var);
+                if self.peek().is_some() {
+                    self.copy_one();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn formal_type_parameters(&mut self) {
+        // '<' already copied
+        while let Some(c) = self.peek() {
+            if c == b'>' {
+                self.copy_one();
+                return;
+            }
+            let name = self.ident(b":>");
+            self.out.push_str(name);
+            while self.peek() == Some(b':') {
+                self.copy_one();
+                if let Some(b'L') | Some(b'[') | Some(b'T') = self.peek() {
+                    self.any_type();
+                }
+            }
+        }
+    }
+
+    fn run(mut self) -> String {
+        if self.peek() == Some(b'<') {
+            self.copy_one();
+            self.formal_type_parameters();
+        }
+        while let Some(c) = self.peek() {
+            match c {
+                b'(' | b')' | b'^' => self.copy_one(),
+                _ => self.any_type(),
+            }
+        }
+        self.out
+    }
+}
+
+/// Renames every class mentioned in a descriptor or generic signature.
+pub fn remap_signature(sig: &str, map_class: &dyn Fn(&str) -> Option<String>) -> String {
+    SignatureRemapper {
+        sig: sig.as_bytes(),
+        pos: 0,
+        out: String::with_capacity(sig.len()),
+        map_class,
+    }
+    .run()
+}
+
+/// Applies a [`Mapper`] to whole classes. Classes registered with
+/// [`Remapper::add_class`] make up the hierarchy used to find the mapping of
+/// inherited and overriding members.
+pub struct Remapper<M: Mapper> {
+    mapper: M,
+    // class -> super class and interfaces
+    hierarchy: HashMap<String, Vec<String>>,
+}
+
+fn utf8(cp: &[Type], idx: u16) -> Option<String> {
+    match cp.get(idx as usize) {
+        Some(Type::Utf8 { bytes }) => String::from_utf8(bytes.to_vec()).ok(),
+        _ => None,
+    }
+}
+
+fn class_name(cp: &[Type], idx: u16) -> Option<String> {
+    match cp.get(idx as usize) {
+        Some(Type::Class { name_index }) => utf8(cp, *name_index),
+        _ => None,
+    }
+}
+
+fn name_and_type(cp: &[Type], idx: u16) -> Option<(String, String)> {
+    match cp.get(idx as usize) {
+        Some(Type::NameAndType {
+            name_index,
+            desc_index,
+        }) => Some((utf8(cp, *name_index)?, utf8(cp, *desc_index)?)),
+        _ => None,
+    }
+}
+
+fn to_io(e: builder::Error) -> io::Error {
+    io::Error::other(e)
+}
+
+struct Context<'a, M: Mapper> {
+    remapper: &'a Remapper<M>,
+    old: Arc<Vec<Type>>,
+    b: ConstantPoolBuilder,
+}
+
+impl<'a, M: Mapper> Context<'a, M> {
+    fn class(&self, name: &str) -> String {
+        self.remapper.class(name)
+    }
+
+    fn desc(&self, desc: &str) -> String {
+        self.remapper.desc(desc)
+    }
+
+    // points `idx` at a Utf8 holding `f(old value)`
+    fn utf8_with(
+        &mut self,
+        idx: &mut u16,
+        f: impl FnOnce(&Self, &str) -> String,
+    ) -> io::Result<()> {
+        if let Some(old) = utf8(&self.old, *idx) {
+            let new = f(self, &old);
+            if new != old {
+                *idx = self.b.utf8(&new).map_err(to_io)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn element_value(&mut self, v: &mut ElementValueType) -> io::Result<()> {
+        match v {
+            ElementValueType::Enum { type_index, .. } => {
+                self.utf8_with(type_index, |c, d| c.desc(d))?
+            }
+            ElementValueType::Class { index } => self.utf8_with(index, |c, d| c.desc(d))?,
+            ElementValueType::Annotation(a) => self.annotation(&mut a.value)?,
+            ElementValueType::Array { values } => {
+                for v in values {
+                    self.element_value(v)?;
+                }
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn pairs(
+        &mut self,
+        owner_desc: Option<String>,
+        pairs: &mut [ElementValuePair],
+    ) -> io::Result<()> {
+        let owner = owner_desc
+            .as_deref()
+            .and_then(|d| d.strip_prefix('L'))
+            .and_then(|d| d.strip_suffix(';'))
+            .map(|o| o.to_string());
+        for pair in pairs {
+            if let Some(owner) = &owner {
+                let remapper = self.remapper;
+                self.utf8_with(&mut pair.name_index, |_, name| {
+                    remapper
+                        .annotation_element(owner, name)
+                        .unwrap_or_else(|| name.to_string())
+                })?;
+            }
+            self.element_value(&mut pair.value)?;
+        }
+        Ok(())
+    }
+
+    fn annotation(&mut self, a: &mut AnnotationEntry) -> io::Result<()> {
+        let owner = utf8(&self.old, a.type_index);
+        self.utf8_with(&mut a.type_index, |c, d| c.desc(d))?;
+        if let Some(Type::Utf8 { bytes }) = self.b.get(a.type_index) {
+            a.type_name = bytes.clone();
+        }
+        self.pairs(owner, &mut a.pairs)
+    }
+
+    fn attrs(&mut self, attrs: &mut [AttributeType]) -> io::Result<()> {
+        for attr in attrs.iter_mut() {
+            match attr {
+                AttributeType::Code(code) => self.attrs(&mut code.attrs)?,
+                AttributeType::Record { components } => {
+                    for c in components {
+                        self.utf8_with(&mut c.descriptor_index, |c, d| c.desc(d))?;
+                        self.attrs(&mut c.attrs)?;
+                    }
+                }
+                AttributeType::Signature { signature_index } => {
+                    self.utf8_with(signature_index, |c, s| c.desc(s))?
+                }
+                AttributeType::LocalVariableTable { tables }
+                | AttributeType::LocalVariableTypeTable { tables } => {
+                    for t in tables {
+                        self.utf8_with(&mut t.signature_index, |c, s| c.desc(s))?;
+                    }
+                }
+                AttributeType::InnerClasses { classes } => {
+                    for ic in classes {
+                        if ic.inner_name_index == 0 {
+                            continue;
+                        }
+                        let inner = match class_name(&self.old, ic.inner_class_info_index) {
+                            Some(inner) => inner,
+                            None => continue,
+                        };
+                        let mapped = self.class(&inner);
+                        if mapped == inner {
+                            continue;
+                        }
+                        let outer = class_name(&self.old, ic.outer_class_info_index)
+                            .map(|o| format!("{}$", self.class(&o)));
+                        let simple = match outer.as_deref().and_then(|o| mapped.strip_prefix(o)) {
+                            Some(s) => s.to_string(),
+                            None => mapped
+                                .rsplit(['$', '/'])
+                                .next()
+                                .unwrap_or(&mapped)
+                                .to_string(),
+                        };
+                        ic.inner_name_index = self.b.utf8(&simple).map_err(to_io)?;
+                    }
+                }
+                AttributeType::EnclosingMethod { em } => {
+                    let owner = class_name(&self.old, em.class_index);
+                    let nat = name_and_type(&self.old, em.method_index);
+                    if let (Some(owner), Some((name, desc))) = (owner, nat) {
+                        let name = self.remapper.method(&owner, &name, &desc);
+                        let desc = self.desc(&desc);
+                        em.method_index = self.b.name_and_type(&name, &desc).map_err(to_io)?;
+                    }
+                }
+                AttributeType::RuntimeVisibleAnnotations { annotations, .. }
+                | AttributeType::RuntimeInvisibleAnnotations { annotations, .. } => {
+                    for a in annotations {
+                        self.annotation(a)?;
+                    }
+                }
+                AttributeType::RuntimeVisibleParameterAnnotations { parameters, .. }
+                | AttributeType::RuntimeInvisibleParameterAnnotations { parameters, .. } => {
+                    for a in parameters.iter_mut().flatten() {
+                        self.annotation(a)?;
+                    }
+                }
+                AttributeType::RuntimeVisibleTypeAnnotations { annotations, .. }
+                | AttributeType::RuntimeInvisibleTypeAnnotations { annotations, .. } => {
+                    for a in annotations {
+                        let owner = utf8(&self.old, a.type_index);
+                        self.utf8_with(&mut a.type_index, |c, d| c.desc(d))?;
+                        self.pairs(owner, &mut a.pairs)?;
+                    }
+                }
+                AttributeType::AnnotationDefault { default_value, .. } => {
+                    self.element_value(default_value)?
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    fn constants(&mut self) -> io::Result<()> {
+        let old = self.old.clone();
+        for (i, c) in old.iter().enumerate() {
+            let new = match c {
+                Type::Class { name_index } => {
+                    let name = match utf8(&old, *name_index) {
+                        Some(name) => name,
+                        None => continue,
+                    };
+                    // array classes are named by their descriptor
+                    let mapped = if name.starts_with('[') {
+                        self.desc(&name)
+                    } else {
+                        self.class(&name)
+                    };
+                    if mapped == name {
+                        continue;
+                    }
+                    Type::Class {
+                        name_index: self.b.utf8(&mapped).map_err(to_io)?,
+                    }
+                }
+                Type::FieldRef {
+                    class_index,
+                    name_and_type_index,
+                }
+                | Type::MethodRef {
+                    class_index,
+                    name_and_type_index,
+                }
+                | Type::InterfaceMethodRef {
+                    class_index,
+                    name_and_type_index,
+                } => {
+                    let owner = class_name(&old, *class_index);
+                    let nat = name_and_type(&old, *name_and_type_index);
+                    let (owner, (name, desc)) = match (owner, nat) {
+                        (Some(owner), Some(nat)) => (owner, nat),
+                        _ => continue,
+                    };
+                    let new_name = if let Type::FieldRef { .. } = c {
+                        self.remapper.field(&owner, &name, &desc)
+                    } else {
+                        self.remapper.method(&owner, &name, &desc)
+                    };
+                    let new_desc = self.desc(&desc);
+                    if new_name == name && new_desc == desc {
+                        continue;
+                    }
+                    let nat = self.b.name_and_type(&new_name, &new_desc).map_err(to_io)?;
+                    match c {
+                        Type::FieldRef { .. } => Type::FieldRef {
+                            class_index: *class_index,
+                            name_and_type_index: nat,
+                        },
+                        Type::MethodRef { .. } => Type::MethodRef {
+                            class_index: *class_index,
+                            name_and_type_index: nat,
+                        },
+                        _ => Type::InterfaceMethodRef {
+                            class_index: *class_index,
+                            name_and_type_index: nat,
+                        },
+                    }
+                }
+                Type::MethodType { desc_index } => {
+                    let mut desc_index = *desc_index;
+                    self.utf8_with(&mut desc_index, |c, d| c.desc(d))?;
+                    Type::MethodType { desc_index }
+                }
+                Type::InvokeDynamic {
+                    bootstrap_method_attr_index,
+                    name_and_type_index,
+                } => {
+                    // the name is the functional interface method, resolved
+                    // at runtime, so only the call site descriptor changes
+                    let (name, desc) = match name_and_type(&old, *name_and_type_index) {
+                        Some(nat) => nat,
+                        None => continue,
+                    };
+                    let new_desc = self.desc(&desc);
+                    if new_desc == desc {
+                        continue;
+                    }
+                    Type::InvokeDynamic {
+                        bootstrap_method_attr_index: *bootstrap_method_attr_index,
+                        name_and_type_index: self
+                            .b
+                            .name_and_type(&name, &new_desc)
+                            .map_err(to_io)?,
+                    }
+                }
+                Type::Dynamic {
+                    bootstrap_method_attr_index,
+                    name_and_type_index,
+                } => {
+                    // a dynamic constant's descriptor is a field type
+                    let (name, desc) = match name_and_type(&old, *name_and_type_index) {
+                        Some(nat) => nat,
+                        None => continue,
+                    };
+                    let new_desc = self.desc(&desc);
+                    if new_desc == desc {
+                        continue;
+                    }
+                    Type::Dynamic {
+                        bootstrap_method_attr_index: *bootstrap_method_attr_index,
+                        name_and_type_index: self
+                            .b
+                            .name_and_type(&name, &new_desc)
+                            .map_err(to_io)?,
+                    }
+                }
+                _ => continue,
+            };
+            self.b.replace(i as u16, new);
+        }
+        Ok(())
+    }
+}
+
+impl<M: Mapper> Remapper<M> {
+    pub fn new(mapper: M) -> Self {
+        Self {
+            mapper,
+            hierarchy: HashMap::new(),
+        }
+    }
+
+    pub fn mapper(&self) -> &M {
+        &self.mapper
+    }
+
+    /// Registers the super types of `cf` so members it inherits or
+    /// overrides pick up the mapping of their declaring class.
+    pub fn add_class(&mut self, cf: &ClassFile) {
+        let name = match class_name(&cf.cp, cf.this_class) {
+            Some(name) => name,
+            None => return,
+        };
+        let supers = std::iter::once(cf.super_class)
+            .chain(cf.interfaces.iter().copied())
+            .filter_map(|i| class_name(&cf.cp, i))
+            .collect();
+        self.hierarchy.insert(name, supers);
+    }
+
+    pub fn class(&self, name: &str) -> String {
+        self.mapper
+            .map_class(name)
+            .unwrap_or_else(|| name.to_string())
+    }
+
+    pub fn desc(&self, desc: &str) -> String {
+        remap_signature(desc, &|c| self.mapper.map_class(c))
+    }
+
+    // breadth-first over `owner` and its known super types
+    fn lookup(&self, owner: &str, f: impl Fn(&str) -> Option<String>) -> Option<String> {
+        let mut seen = HashSet::new();
+        let mut queue = VecDeque::new();
+        queue.push_back(owner.to_string());
+        while let Some(class) = queue.pop_front() {
+            if !seen.insert(class.clone()) {
+                continue;
+            }
+            if let Some(name) = f(&class) {
+                return Some(name);
+            }
+            if let Some(supers) = self.hierarchy.get(&class) {
+                queue.extend(supers.iter().cloned());
+            }
+        }
+        None
+    }
+
+    pub fn field(&self, owner: &str, name: &str, desc: &str) -> String {
+        self.lookup(owner, |c| self.mapper.map_field(c, name, desc))
+            .unwrap_or_else(|| name.to_string())
+    }
+
+    pub fn method(&self, owner: &str, name: &str, desc: &str) -> String {
+        if name.starts_with('<') {
+            return name.to_string();
+        }
+        self.lookup(owner, |c| self.mapper.map_method(c, name, desc))
+            .unwrap_or_else(|| name.to_string())
+    }
+
+    fn annotation_element(&self, owner: &str, name: &str) -> Option<String> {
+        self.mapper.map_annotation_element(owner, name)
+    }
+
+    /// Renames classes and members throughout `cf`, then drops the constants
+    /// that only the old names used.
+    pub fn remap(&self, cf: &mut ClassFile) -> io::Result<()> {
+        let this = class_name(&cf.cp, cf.this_class).unwrap_or_default();
+        let mut ctx = Context {
+            remapper: self,
+            old: cf.cp.clone(),
+            b: ConstantPoolBuilder::from_pool(&cf.cp),
+        };
+
+        ctx.constants()?;
+
+        for field in cf.fields.iter_mut() {
+            if let (Some(name), Some(desc)) = (
+                utf8(&ctx.old, field.name_index),
+                utf8(&ctx.old, field.desc_index),
+            ) {
+                let new = self.field(&this, &name, &desc);
+                if new != name {
+                    field.name_index = ctx.b.utf8(&new).map_err(to_io)?;
+                }
+            }
+            ctx.utf8_with(&mut field.desc_index, |c, d| c.desc(d))?;
+            ctx.attrs(&mut field.attrs)?;
+        }
+        for method in cf.methods.iter_mut() {
+            if let (Some(name), Some(desc)) = (
+                utf8(&ctx.old, method.name_index),
+                utf8(&ctx.old, method.desc_index),
+            ) {
+                let new = self.method(&this, &name, &desc);
+                if new != name {
+                    method.name_index = ctx.b.utf8(&new).map_err(to_io)?;
+                }
+            }
+            ctx.utf8_with(&mut method.desc_index, |c, d| c.desc(d))?;
+            ctx.attrs(&mut method.attrs)?;
+        }
+        // record components are named after the fields they stand for
+        for attr in cf.attrs.iter_mut() {
+            if let AttributeType::Record { components } = attr {
+                for c in components {
+                    if let (Some(name), Some(desc)) = (
+                        utf8(&ctx.old, c.name_index),
+                        utf8(&ctx.old, c.descriptor_index),
+                    ) {
+                        let new = self.field(&this, &name, &desc);
+                        if new != name {
+                            c.name_index = ctx.b.utf8(&new).map_err(to_io)?;
+                        }
+                    }
+                }
+            }
+        }
+        ctx.attrs(&mut cf.attrs)?;
+
+        cf.cp = ctx.b.build();
+        refresh_raw(&mut cf.attrs)?;
+        for field in cf.fields.iter_mut() {
+            refresh_raw(&mut field.attrs)?;
+        }
+        for method in cf.methods.iter_mut() {
+            refresh_raw(&mut method.attrs)?;
+        }
+        // classes with undecoded attributes keep their whole pool
+        builder::compact(cf)?;
+
+        Ok(())
+    }
+}
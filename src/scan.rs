@@ -0,0 +1,190 @@
+//! Parses every class on a classpath, to check the parser against whole jars
+//! (or a JDK) and to gate builds on classes that don't parse.
+
+use super::class_parser::{
+    self,
+    format::{attributes::Type as AttributeType, version::Version},
+    ParseError, ParseOptions,
+};
+use super::class_path_manager::ClassPathManager;
+use super::symbol::{SymbolStats, SymbolTable};
+
+use std::collections::BTreeMap;
+use std::io;
+use std::panic;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Default)]
+pub struct ScanOptions {
+    /// Worker threads, 0 for one per CPU.
+    pub threads: usize,
+    pub parse: ParseOptions,
+    /// Intern the Utf8 constants of all classes in one table, to report the
+    /// memory sharing them saves.
+    pub intern: bool,
+}
+
+#[derive(Debug)]
+pub struct Failure {
+    /// the classpath entry the class came from
+    pub source: String,
+    pub name: String,
+    /// where in the class file parsing stopped
+    pub offset: usize,
+    pub error: ParseError,
+}
+
+#[derive(Debug, Default)]
+pub struct ScanReport {
+    pub classes: usize,
+    pub bytes: usize,
+    pub versions: BTreeMap<Version, usize>,
+    /// How often each attribute occurs, counting those inside Code.
+    pub attributes: BTreeMap<String, usize>,
+    /// Sorted by entry and name.
+    pub failures: Vec<Failure>,
+    /// Set when interning.
+    pub symbols: Option<SymbolStats>,
+    pub elapsed: Duration,
+}
+
+impl ScanReport {
+    pub fn classes_per_sec(&self) -> f64 {
+        self.classes as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    pub fn bytes_per_sec(&self) -> f64 {
+        self.bytes as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    fn add(
+        &mut self,
+        source: String,
+        name: String,
+        data: &[u8],
+        options: &ParseOptions,
+        symbols: Option<&SymbolTable>,
+    ) {
+        self.classes += 1;
+        self.bytes += data.len();
+        match class_parser::parse_with(data, options) {
+            Ok((_, mut cf)) => {
+                if let Some(symbols) = symbols {
+                    symbols.intern_class(&mut cf);
+                }
+                *self.versions.entry(cf.version).or_insert(0) += 1;
+                count_attributes(&cf.attrs, &mut self.attributes);
+                for field in cf.fields.iter() {
+                    count_attributes(&field.attrs, &mut self.attributes);
+                }
+                for method in cf.methods.iter() {
+                    count_attributes(&method.attrs, &mut self.attributes);
+                }
+            }
+            Err(error) => self.failures.push(Failure {
+                source,
+                name,
+                offset: error.offset(data.len()),
+                error,
+            }),
+        }
+    }
+
+    fn merge(&mut self, other: ScanReport) {
+        self.classes += other.classes;
+        self.bytes += other.bytes;
+        for (version, n) in other.versions {
+            *self.versions.entry(version).or_insert(0) += n;
+        }
+        for (name, n) in other.attributes {
+            *self.attributes.entry(name).or_insert(0) += n;
+        }
+        self.failures.extend(other.failures);
+    }
+}
+
+fn count_attributes(attrs: &[AttributeType], counts: &mut BTreeMap<String, usize>) {
+    for attr in attrs {
+        let name = String::from_utf8_lossy(attr.name()).into_owned();
+        *counts.entry(name).or_insert(0) += 1;
+        if let AttributeType::Code(code) = attr {
+            count_attributes(&code.attrs, counts);
+        }
+    }
+}
+
+/// Parses every `.class` entry of `cpm` on `options.threads` workers. Only
+/// failing to read the classpath is an error, unless the error policy skips
+/// what can't be read; classes that don't parse are listed in the report.
+pub fn scan(cpm: &ClassPathManager, options: &ScanOptions) -> io::Result<ScanReport> {
+    let start = Instant::now();
+    let threads = match options.threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+
+    // only the names are listed here, the workers read and inflate the
+    // files themselves
+    let sources = cpm.entries();
+    let mut work = vec![];
+    for index in 0..sources.len() {
+        match cpm.entry_files(index) {
+            Ok(files) => work.extend(files.into_iter().filter(|f| f.name.ends_with(".class"))),
+            Err(e) => cpm.skip(e)?,
+        }
+    }
+    let next = AtomicUsize::new(0);
+    let symbols = if options.intern {
+        Some(SymbolTable::new())
+    } else {
+        None
+    };
+    let mut report = thread::scope(|s| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut report = ScanReport::default();
+                    while let Some(file) = work.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        match cpm.read_entry_file(file) {
+                            Ok(Some(data)) => report.add(
+                                sources[file.entry].clone(),
+                                file.name.clone(),
+                                &data,
+                                &options.parse,
+                                symbols.as_ref(),
+                            ),
+                            Ok(None) => {}
+                            Err(e) => {
+                                if let Err(e) = cpm.skip(e) {
+                                    // the others stop at their next file
+                                    next.store(work.len(), Ordering::Relaxed);
+                                    return Err(e);
+                                }
+                            }
+                        }
+                    }
+                    Ok(report)
+                })
+            })
+            .collect();
+
+        let mut report = ScanReport::default();
+        let mut failed = None;
+        for worker in workers {
+            match worker.join().unwrap_or_else(|e| panic::resume_unwind(e)) {
+                Ok(worker_report) => report.merge(worker_report),
+                Err(e) => failed = failed.or(Some(e)),
+            }
+        }
+        failed.map_or(Ok(report), Err)
+    })?;
+
+    report
+        .failures
+        .sort_by(|a, b| (&a.source, &a.name).cmp(&(&b.source, &b.name)));
+    report.symbols = symbols.map(|symbols| symbols.stats());
+    report.elapsed = start.elapsed();
+    Ok(report)
+}
@@ -0,0 +1,72 @@
+//! Java serialization: the default `serialVersionUID` of a class and a
+//! decoder for the `ObjectOutputStream` stream format, to inspect serialized
+//! blobs and check them against the classes on a classpath.
+
+mod stream;
+mod uid;
+
+pub use stream::{
+    decode, ClassData, ClassDesc, Content, Error, FieldDesc, Object, Stream, Value, SC_BLOCK_DATA,
+    SC_ENUM, SC_EXTERNALIZABLE, SC_SERIALIZABLE, SC_WRITE_METHOD, STREAM_MAGIC, STREAM_VERSION,
+};
+pub use uid::{
+    checks_serial_version_uid, declared_serial_version_uid, default_serial_version_uid,
+    serial_version_uid,
+};
+
+use super::class_parser::{parse_with, ParseOptions};
+use super::class_path_manager::ClassPathManager;
+
+use std::collections::HashSet;
+
+/// A class descriptor of a stream looked up on a classpath.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resolution {
+    pub name: String,
+    pub stream_uid: i64,
+    /// None when the class isn't on the classpath or doesn't parse
+    pub local_uid: Option<i64>,
+    /// false for enums and records, whose `serialVersionUID` deserializing
+    /// doesn't compare
+    pub uid_checked: bool,
+    /// the classpath entry the class was found in
+    pub source: Option<String>,
+}
+
+impl Resolution {
+    /// Deserializing fails with an `InvalidClassException` for these.
+    pub fn is_mismatch(&self) -> bool {
+        self.uid_checked && self.local_uid.is_some_and(|uid| uid != self.stream_uid)
+    }
+}
+
+/// Looks up the class of every descriptor in `stream`, once per name. Arrays,
+/// proxies and classes that aren't serializable are left out, their
+/// `serialVersionUID` isn't checked when deserializing.
+pub fn resolve(stream: &Stream, cpm: &ClassPathManager) -> Vec<Resolution> {
+    let options = ParseOptions::default();
+    let mut seen = HashSet::new();
+    stream
+        .class_descs()
+        .filter(|desc| {
+            desc.proxy_interfaces.is_none()
+                && !desc.name.starts_with('[')
+                && desc.flags & (SC_SERIALIZABLE | SC_EXTERNALIZABLE) != 0
+                && seen.insert(desc.name.as_str())
+        })
+        .map(|desc| {
+            let found = cpm.search_class(&desc.name.replace('.', "/")).ok();
+            let local = found
+                .as_ref()
+                .and_then(|found| parse_with(&found.1, &options).ok())
+                .map(|(_, cf)| (serial_version_uid(&cf), checks_serial_version_uid(&cf)));
+            Resolution {
+                name: desc.name.clone(),
+                stream_uid: desc.uid,
+                local_uid: local.map(|(uid, _)| uid),
+                uid_checked: local.is_none_or(|(_, checked)| checked),
+                source: found.map(|found| found.0),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,669 @@
+use crate::class_parser::format::constant_pool;
+
+use std::convert::TryFrom;
+use std::fmt;
+
+pub const STREAM_MAGIC: u16 = 0xaced;
+pub const STREAM_VERSION: u16 = 5;
+
+const TC_NULL: u8 = 0x70;
+const TC_REFERENCE: u8 = 0x71;
+const TC_CLASSDESC: u8 = 0x72;
+const TC_OBJECT: u8 = 0x73;
+const TC_STRING: u8 = 0x74;
+const TC_ARRAY: u8 = 0x75;
+const TC_CLASS: u8 = 0x76;
+const TC_BLOCKDATA: u8 = 0x77;
+const TC_ENDBLOCKDATA: u8 = 0x78;
+const TC_RESET: u8 = 0x79;
+const TC_BLOCKDATALONG: u8 = 0x7a;
+const TC_EXCEPTION: u8 = 0x7b;
+const TC_LONGSTRING: u8 = 0x7c;
+const TC_PROXYCLASSDESC: u8 = 0x7d;
+const TC_ENUM: u8 = 0x7e;
+
+const BASE_WIRE_HANDLE: u32 = 0x7e0000;
+
+pub const SC_WRITE_METHOD: u8 = 0x01;
+pub const SC_SERIALIZABLE: u8 = 0x02;
+pub const SC_EXTERNALIZABLE: u8 = 0x04;
+pub const SC_BLOCK_DATA: u8 = 0x08;
+pub const SC_ENUM: u8 = 0x10;
+
+// nested objects, descriptors and annotations
+const MAX_DEPTH: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    /// where in the stream decoding stopped
+    pub offset: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Byte(i8),
+    Char(u16),
+    Double(f64),
+    Float(f32),
+    Int(i32),
+    Long(i64),
+    Short(i16),
+    Boolean(bool),
+    /// index into [`Stream::objects`]
+    Object(usize),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Byte(v) => write!(f, "{}", v),
+            Value::Char(v) => match char::from_u32(*v as u32) {
+                Some(c) => write!(f, "{:?}", c),
+                None => write!(f, "'\\u{:04x}'", v),
+            },
+            Value::Double(v) => write!(f, "{}", v),
+            Value::Float(v) => write!(f, "{}", v),
+            Value::Int(v) => write!(f, "{}", v),
+            Value::Long(v) => write!(f, "{}", v),
+            Value::Short(v) => write!(f, "{}", v),
+            Value::Boolean(v) => write!(f, "{}", v),
+            Value::Object(i) => write!(f, "@{}", i),
+        }
+    }
+}
+
+/// What `writeObject` and the primitive `write*` methods put in the stream.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Content {
+    Value(Value),
+    BlockData(Vec<u8>),
+    /// the throwable that aborted writing
+    Exception(Value),
+}
+
+impl fmt::Display for Content {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Content::Value(v) => write!(f, "{}", v),
+            Content::BlockData(data) => write!(f, "<{} bytes>", data.len()),
+            Content::Exception(v) => write!(f, "exception {}", v),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldDesc {
+    /// `B`, `C`, `D`, `F`, `I`, `J`, `S`, `Z`, `L` or `[`
+    pub type_code: u8,
+    pub name: String,
+    /// field descriptor of object fields, e.g. `Ljava/lang/String;`
+    pub class_name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassDesc {
+    /// binary name, e.g. `java.util.ArrayList` or `[I`; empty for proxies
+    pub name: String,
+    pub uid: i64,
+    /// `SC_*`
+    pub flags: u8,
+    pub fields: Vec<FieldDesc>,
+    /// the interfaces of a dynamic proxy class
+    pub proxy_interfaces: Option<Vec<String>>,
+    /// what `annotateClass` wrote
+    pub annotation: Vec<Content>,
+    /// index of the superclass descriptor in [`Stream::objects`]
+    pub super_class: Option<usize>,
+}
+
+/// The part of an object one class of its hierarchy wrote.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassData {
+    /// index of the class descriptor
+    pub class: usize,
+    pub values: Vec<(String, Value)>,
+    /// what `writeObject` or `writeExternal` wrote besides the fields
+    pub annotation: Vec<Content>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Object {
+    ClassDesc(ClassDesc),
+    /// class data from the topmost serializable superclass down
+    Instance {
+        class: usize,
+        data: Vec<ClassData>,
+    },
+    Array {
+        class: usize,
+        values: Vec<Value>,
+    },
+    String(String),
+    Enum {
+        class: usize,
+        constant: String,
+    },
+    /// a `java.lang.Class`, by its descriptor
+    Class(usize),
+}
+
+/// A decoded stream. Objects refer to each other, and may do so cyclically,
+/// by index into `objects`, which lists them in the order they were
+/// written.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Stream {
+    pub contents: Vec<Content>,
+    pub objects: Vec<Object>,
+}
+
+impl Stream {
+    pub fn class_descs(&self) -> impl Iterator<Item = &ClassDesc> {
+        self.objects.iter().filter_map(|o| match o {
+            Object::ClassDesc(desc) => Some(desc),
+            _ => None,
+        })
+    }
+
+    fn class_name(&self, index: usize) -> &str {
+        match self.objects.get(index) {
+            Some(Object::ClassDesc(desc)) if desc.proxy_interfaces.is_some() => "<proxy>",
+            Some(Object::ClassDesc(desc)) => &desc.name,
+            _ => "?",
+        }
+    }
+
+    /// One line per object, for people to read.
+    pub fn dump(&self) -> String {
+        let mut out = String::new();
+        let list = |contents: &[Content]| {
+            contents
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        for (i, object) in self.objects.iter().enumerate() {
+            let line = match object {
+                Object::ClassDesc(desc) => {
+                    let mut s = match &desc.proxy_interfaces {
+                        Some(interfaces) => format!("proxy class {}", interfaces.join(", ")),
+                        None => format!(
+                            "class {} uid {} flags 0x{:02x}",
+                            desc.name, desc.uid, desc.flags
+                        ),
+                    };
+                    for field in desc.fields.iter() {
+                        let ty = match &field.class_name {
+                            Some(name) => name.clone(),
+                            None => (field.type_code as char).to_string(),
+                        };
+                        s.push_str(&format!(" {}:{}", field.name, ty));
+                    }
+                    if let Some(parent) = desc.super_class {
+                        s.push_str(&format!(" extends @{}", parent));
+                    }
+                    if !desc.annotation.is_empty() {
+                        s.push_str(&format!(" [{}]", list(&desc.annotation)));
+                    }
+                    s
+                }
+                Object::Instance { class, data } => {
+                    let mut s = format!("object {}", self.class_name(*class));
+                    for d in data.iter() {
+                        let values: Vec<_> = d
+                            .values
+                            .iter()
+                            .map(|(name, v)| format!("{}={}", name, v))
+                            .collect();
+                        s.push_str(&format!(" {{{}}}", values.join(", ")));
+                        if !d.annotation.is_empty() {
+                            s.push_str(&format!(" [{}]", list(&d.annotation)));
+                        }
+                    }
+                    s
+                }
+                Object::Array { class, values } => {
+                    let values: Vec<_> = values.iter().map(|v| v.to_string()).collect();
+                    format!(
+                        "array {} {{{}}}",
+                        self.class_name(*class),
+                        values.join(", ")
+                    )
+                }
+                Object::String(s) => format!("string {:?}", s),
+                Object::Enum { class, constant } => {
+                    format!("enum {}.{}", self.class_name(*class), constant)
+                }
+                Object::Class(class) => format!("class object {}", self.class_name(*class)),
+            };
+            out.push_str(&format!("@{} {}\n", i, line));
+        }
+        out.push_str(&format!("contents: {}\n", list(&self.contents)));
+        out
+    }
+}
+
+/// Decodes an `ObjectOutputStream` stream, everything written from the
+/// stream header to the end of `data`.
+pub fn decode(data: &[u8]) -> Result<Stream, Error> {
+    let mut d = Decoder {
+        data,
+        pos: 0,
+        objects: vec![],
+        handles: vec![],
+        depth: 0,
+    };
+    let magic = d.u16()?;
+    if magic != STREAM_MAGIC {
+        return Err(d.fail(0, format!("bad magic 0x{:04x}", magic)));
+    }
+    let version = d.u16()?;
+    if version != STREAM_VERSION {
+        return Err(d.fail(2, format!("unsupported version {}", version)));
+    }
+
+    let mut contents = vec![];
+    while d.pos < data.len() {
+        if data[d.pos] == TC_RESET {
+            d.pos += 1;
+            d.handles.clear();
+            continue;
+        }
+        contents.push(d.content()?);
+    }
+
+    let objects = d
+        .objects
+        .into_iter()
+        .map(|o| o.expect("every object is filled in once read"))
+        .collect();
+    Ok(Stream { contents, objects })
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    // None while an object is being read, which it may already refer to
+    objects: Vec<Option<Object>>,
+    // wire handle - BASE_WIRE_HANDLE -> index into objects
+    handles: Vec<usize>,
+    depth: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn fail(&self, offset: usize, message: impl Into<String>) -> Error {
+        Error {
+            offset,
+            message: message.into(),
+        }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        match self.data.get(self.pos..).filter(|rest| rest.len() >= n) {
+            Some(rest) => {
+                self.pos += n;
+                Ok(&rest[..n])
+            }
+            None => Err(self.fail(self.data.len(), "truncated stream")),
+        }
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        let b = self.take(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn i32(&mut self) -> Result<i32, Error> {
+        let b = self.take(4)?;
+        Ok(i32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn i64(&mut self) -> Result<i64, Error> {
+        let mut b = [0; 8];
+        b.copy_from_slice(self.take(8)?);
+        Ok(i64::from_be_bytes(b))
+    }
+
+    fn peek(&self) -> Result<u8, Error> {
+        self.data
+            .get(self.pos)
+            .copied()
+            .ok_or_else(|| self.fail(self.data.len(), "truncated stream"))
+    }
+
+    // modified UTF-8 with a 2 or 8 byte length
+    fn utf(&mut self, long: bool) -> Result<String, Error> {
+        let len = if long {
+            let at = self.pos;
+            usize::try_from(self.i64()?).map_err(|_| self.fail(at, "bad string length"))?
+        } else {
+            self.u16()? as usize
+        };
+        let bytes = self.take(len)?;
+        let units = constant_pool::decode_modified_utf8(bytes);
+        Ok(String::from_utf16_lossy(&units))
+    }
+
+    fn new_handle(&mut self, object: Option<Object>) -> usize {
+        self.objects.push(object);
+        let index = self.objects.len() - 1;
+        self.handles.push(index);
+        index
+    }
+
+    fn enter(&mut self) -> Result<(), Error> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(self.fail(self.pos, "nested too deeply"));
+        }
+        Ok(())
+    }
+
+    fn content(&mut self) -> Result<Content, Error> {
+        match self.peek()? {
+            TC_BLOCKDATA => {
+                self.pos += 1;
+                let len = self.u8()? as usize;
+                Ok(Content::BlockData(self.take(len)?.to_vec()))
+            }
+            TC_BLOCKDATALONG => {
+                self.pos += 1;
+                let at = self.pos;
+                let len = usize::try_from(self.i32()?)
+                    .map_err(|_| self.fail(at, "bad block data length"))?;
+                Ok(Content::BlockData(self.take(len)?.to_vec()))
+            }
+            TC_EXCEPTION => {
+                self.pos += 1;
+                self.handles.clear();
+                let throwable = self.object()?;
+                self.handles.clear();
+                Ok(Content::Exception(throwable))
+            }
+            _ => Ok(Content::Value(self.object()?)),
+        }
+    }
+
+    // contents up to and including TC_ENDBLOCKDATA
+    fn annotation(&mut self) -> Result<Vec<Content>, Error> {
+        self.enter()?;
+        let mut contents = vec![];
+        loop {
+            match self.peek()? {
+                TC_ENDBLOCKDATA => {
+                    self.pos += 1;
+                    break;
+                }
+                TC_RESET => {
+                    self.pos += 1;
+                    self.handles.clear();
+                }
+                _ => contents.push(self.content()?),
+            }
+        }
+        self.depth -= 1;
+        Ok(contents)
+    }
+
+    fn object(&mut self) -> Result<Value, Error> {
+        self.enter()?;
+        let at = self.pos;
+        let value = match self.u8()? {
+            TC_NULL => Value::Null,
+            TC_REFERENCE => Value::Object(self.reference()?),
+            TC_STRING => {
+                let index = self.new_handle(None);
+                let s = self.utf(false)?;
+                self.objects[index] = Some(Object::String(s));
+                Value::Object(index)
+            }
+            TC_LONGSTRING => {
+                let index = self.new_handle(None);
+                let s = self.utf(true)?;
+                self.objects[index] = Some(Object::String(s));
+                Value::Object(index)
+            }
+            TC_CLASSDESC | TC_PROXYCLASSDESC => {
+                self.pos = at;
+                Value::Object(self.class_desc()?.unwrap())
+            }
+            TC_OBJECT => Value::Object(self.instance(at)?),
+            TC_ARRAY => Value::Object(self.array(at)?),
+            TC_CLASS => {
+                let class = self.required_class_desc(at)?;
+                Value::Object(self.new_handle(Some(Object::Class(class))))
+            }
+            TC_ENUM => {
+                let class = self.required_class_desc(at)?;
+                let index = self.new_handle(None);
+                let name_at = self.pos;
+                let constant = match self.object()? {
+                    Value::Object(i) => match &self.objects[i] {
+                        Some(Object::String(s)) => s.clone(),
+                        _ => return Err(self.fail(name_at, "enum constant name is not a string")),
+                    },
+                    _ => return Err(self.fail(name_at, "enum constant name is not a string")),
+                };
+                self.objects[index] = Some(Object::Enum { class, constant });
+                Value::Object(index)
+            }
+            tag => return Err(self.fail(at, format!("unexpected tag 0x{:02x}", tag))),
+        };
+        self.depth -= 1;
+        Ok(value)
+    }
+
+    fn reference(&mut self) -> Result<usize, Error> {
+        let at = self.pos;
+        let handle = self.i32()? as u32;
+        handle
+            .checked_sub(BASE_WIRE_HANDLE)
+            .and_then(|h| self.handles.get(h as usize).copied())
+            .ok_or_else(|| self.fail(at, format!("bad handle 0x{:x}", handle)))
+    }
+
+    // TC_CLASSDESC, TC_PROXYCLASSDESC, TC_NULL or a reference to a descriptor
+    fn class_desc(&mut self) -> Result<Option<usize>, Error> {
+        self.enter()?;
+        let at = self.pos;
+        let index = match self.u8()? {
+            TC_NULL => None,
+            TC_REFERENCE => {
+                let index = self.reference()?;
+                match &self.objects[index] {
+                    Some(Object::ClassDesc(_)) => Some(index),
+                    _ => return Err(self.fail(at, "reference to a class descriptor expected")),
+                }
+            }
+            TC_CLASSDESC => {
+                let name = self.utf(false)?;
+                let uid = self.i64()?;
+                let index = self.new_handle(None);
+                let flags = self.u8()?;
+                let count = self.u16()?;
+                let mut fields = vec![];
+                for _ in 0..count {
+                    let field_at = self.pos;
+                    let type_code = self.u8()?;
+                    let name = self.utf(false)?;
+                    let class_name = match type_code {
+                        b'B' | b'C' | b'D' | b'F' | b'I' | b'J' | b'S' | b'Z' => None,
+                        b'L' | b'[' => Some(self.string()?),
+                        _ => return Err(self.fail(field_at, "bad field type code")),
+                    };
+                    fields.push(FieldDesc {
+                        type_code,
+                        name,
+                        class_name,
+                    });
+                }
+                let annotation = self.annotation()?;
+                let super_class = self.class_desc()?;
+                self.objects[index] = Some(Object::ClassDesc(ClassDesc {
+                    name,
+                    uid,
+                    flags,
+                    fields,
+                    proxy_interfaces: None,
+                    annotation,
+                    super_class,
+                }));
+                Some(index)
+            }
+            TC_PROXYCLASSDESC => {
+                let index = self.new_handle(None);
+                let count_at = self.pos;
+                let count = self.i32()?;
+                // each name takes at least its two length bytes
+                if count < 0 || count as usize > (self.data.len() - self.pos) / 2 {
+                    return Err(self.fail(count_at, "bad interface count"));
+                }
+                let mut interfaces = vec![];
+                for _ in 0..count {
+                    interfaces.push(self.utf(false)?);
+                }
+                let annotation = self.annotation()?;
+                let super_class = self.class_desc()?;
+                self.objects[index] = Some(Object::ClassDesc(ClassDesc {
+                    name: String::new(),
+                    uid: 0,
+                    flags: SC_SERIALIZABLE,
+                    fields: vec![],
+                    proxy_interfaces: Some(interfaces),
+                    annotation,
+                    super_class,
+                }));
+                Some(index)
+            }
+            tag => {
+                return Err(self.fail(
+                    at,
+                    format!("class descriptor expected, got tag 0x{:02x}", tag),
+                ))
+            }
+        };
+        self.depth -= 1;
+        Ok(index)
+    }
+
+    fn required_class_desc(&mut self, at: usize) -> Result<usize, Error> {
+        self.class_desc()?
+            .ok_or_else(|| self.fail(at, "missing class descriptor"))
+    }
+
+    fn desc(&self, index: usize) -> &ClassDesc {
+        match &self.objects[index] {
+            Some(Object::ClassDesc(desc)) => desc,
+            _ => unreachable!("class_desc only returns descriptors"),
+        }
+    }
+
+    // a field's class name: a string, possibly by reference
+    fn string(&mut self) -> Result<String, Error> {
+        let at = self.pos;
+        match self.object()? {
+            Value::Object(i) => match &self.objects[i] {
+                Some(Object::String(s)) => Ok(s.clone()),
+                _ => Err(self.fail(at, "string expected")),
+            },
+            _ => Err(self.fail(at, "string expected")),
+        }
+    }
+
+    fn value(&mut self, type_code: u8) -> Result<Value, Error> {
+        Ok(match type_code {
+            b'B' => Value::Byte(self.u8()? as i8),
+            b'C' => Value::Char(self.u16()?),
+            b'D' => Value::Double(f64::from_bits(self.i64()? as u64)),
+            b'F' => Value::Float(f32::from_bits(self.i32()? as u32)),
+            b'I' => Value::Int(self.i32()?),
+            b'J' => Value::Long(self.i64()?),
+            b'S' => Value::Short(self.u16()? as i16),
+            b'Z' => Value::Boolean(self.u8()? != 0),
+            _ => self.object()?,
+        })
+    }
+
+    fn instance(&mut self, at: usize) -> Result<usize, Error> {
+        let class = self.required_class_desc(at)?;
+        let index = self.new_handle(None);
+
+        let mut chain = vec![];
+        let mut next = Some(class);
+        while let Some(c) = next {
+            if chain.contains(&c) {
+                return Err(self.fail(at, "class descriptors form a cycle"));
+            }
+            chain.push(c);
+            next = self.desc(c).super_class;
+        }
+
+        let mut data = vec![];
+        for &c in chain.iter().rev() {
+            let desc = self.desc(c);
+            let (flags, fields) = (desc.flags, desc.fields.clone());
+            let mut class_data = ClassData {
+                class: c,
+                values: vec![],
+                annotation: vec![],
+            };
+            if flags & SC_EXTERNALIZABLE != 0 {
+                if flags & SC_BLOCK_DATA == 0 {
+                    let name = self.desc(c).name.clone();
+                    return Err(self.fail(
+                        self.pos,
+                        format!("{} was written with protocol version 1", name),
+                    ));
+                }
+                class_data.annotation = self.annotation()?;
+            } else if flags & SC_SERIALIZABLE != 0 {
+                for field in fields {
+                    let v = self.value(field.type_code)?;
+                    class_data.values.push((field.name, v));
+                }
+                if flags & SC_WRITE_METHOD != 0 {
+                    class_data.annotation = self.annotation()?;
+                }
+            }
+            data.push(class_data);
+        }
+        self.objects[index] = Some(Object::Instance { class, data });
+        Ok(index)
+    }
+
+    fn array(&mut self, at: usize) -> Result<usize, Error> {
+        let class = self.required_class_desc(at)?;
+        let index = self.new_handle(None);
+        let type_code = match self.desc(class).name.as_bytes() {
+            [b'[', code, ..] => *code,
+            _ => return Err(self.fail(at, "array of a class that isn't an array")),
+        };
+        let size_at = self.pos;
+        let size = self.i32()?;
+        // every element takes at least a byte
+        if size < 0 || size as usize > self.data.len() - self.pos {
+            return Err(self.fail(size_at, "bad array size"));
+        }
+        let mut values = Vec::with_capacity(size as usize);
+        for _ in 0..size {
+            values.push(self.value(type_code)?);
+        }
+        self.objects[index] = Some(Object::Array { class, values });
+        Ok(index)
+    }
+}
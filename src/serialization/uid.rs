@@ -0,0 +1,206 @@
+use crate::class_parser::format::{
+    attributes::Type as AttributeType,
+    class_file::ClassFile,
+    constant_pool::{self, Type as ConstantType},
+};
+
+use std::sync::Arc;
+
+const PUBLIC: u16 = 0x0001;
+const PRIVATE: u16 = 0x0002;
+const PROTECTED: u16 = 0x0004;
+const STATIC: u16 = 0x0008;
+const FINAL: u16 = 0x0010;
+const SYNCHRONIZED: u16 = 0x0020;
+const VOLATILE: u16 = 0x0040;
+const TRANSIENT: u16 = 0x0080;
+const NATIVE: u16 = 0x0100;
+const INTERFACE: u16 = 0x0200;
+const ABSTRACT: u16 = 0x0400;
+const STRICT: u16 = 0x0800;
+const ENUM: u16 = 0x4000;
+
+const CLASS_MODIFIERS: u16 = PUBLIC | FINAL | INTERFACE | ABSTRACT;
+const FIELD_MODIFIERS: u16 = PUBLIC | PRIVATE | PROTECTED | STATIC | FINAL | VOLATILE | TRANSIENT;
+const METHOD_MODIFIERS: u16 =
+    PUBLIC | PRIVATE | PROTECTED | STATIC | FINAL | SYNCHRONIZED | NATIVE | ABSTRACT | STRICT;
+
+fn utf8(cp: &Arc<Vec<ConstantType>>, idx: u16) -> Vec<u8> {
+    constant_pool::get_utf8(cp, idx as usize).map_or_else(Vec::new, |bytes| bytes.to_vec())
+}
+
+fn class_name(cp: &Arc<Vec<ConstantType>>, idx: u16) -> Vec<u8> {
+    let name =
+        constant_pool::get_class_name(cp, idx as usize).map_or_else(Vec::new, |n| n.to_vec());
+    dotted(&name)
+}
+
+fn dotted(bytes: &[u8]) -> Vec<u8> {
+    bytes
+        .iter()
+        .map(|&b| if b == b'/' { b'.' } else { b })
+        .collect()
+}
+
+// DataOutput.writeUTF of a string whose modified UTF-8 form is `bytes`
+fn write_utf(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+    out.extend_from_slice(bytes);
+}
+
+// String.compareTo, which compares UTF-16 code units
+fn java_order(a: &[u8], b: &[u8]) -> std::cmp::Ordering {
+    constant_pool::decode_modified_utf8(a).cmp(&constant_pool::decode_modified_utf8(b))
+}
+
+/// The `serialVersionUID` the class declares as a `static final long`.
+pub fn declared_serial_version_uid(cf: &ClassFile) -> Option<i64> {
+    cf.fields.iter().find_map(|field| {
+        let wanted = STATIC | FINAL;
+        if field.acc_flags & wanted != wanted
+            || utf8(&cf.cp, field.name_index) != b"serialVersionUID"
+            || utf8(&cf.cp, field.desc_index) != b"J"
+        {
+            return None;
+        }
+        field.attrs.iter().find_map(|attr| match attr {
+            AttributeType::ConstantValue {
+                constant_value_index,
+            } => match cf.cp.get(*constant_value_index as usize) {
+                Some(ConstantType::Long { v }) => Some(i64::from_be_bytes(*v)),
+                _ => None,
+            },
+            _ => None,
+        })
+    })
+}
+
+// ACC_ENUM marks the classes of constants with a body as well
+fn is_enum(cf: &ClassFile) -> bool {
+    cf.acc_flags & ENUM != 0
+}
+
+fn is_record(cf: &ClassFile) -> bool {
+    constant_pool::get_class_name(&cf.cp, cf.super_class as usize)
+        .is_some_and(|name| name[..] == b"java/lang/Record"[..])
+}
+
+/// The `serialVersionUID` serialization uses for `cf`: 0 for enums, which
+/// ignore a declared one, else the declared one, or without it 0 for records
+/// and the default computed from the class for anything else.
+pub fn serial_version_uid(cf: &ClassFile) -> i64 {
+    if is_enum(cf) {
+        return 0;
+    }
+    declared_serial_version_uid(cf).unwrap_or_else(|| {
+        if is_record(cf) {
+            0
+        } else {
+            default_serial_version_uid(cf)
+        }
+    })
+}
+
+/// Whether deserializing compares the stream's `serialVersionUID` with the
+/// one of `cf`, which it doesn't for enums and records.
+pub fn checks_serial_version_uid(cf: &ClassFile) -> bool {
+    !is_enum(cf) && !is_record(cf)
+}
+
+/// The default `serialVersionUID` of Java Object Serialization spec 4.6:
+/// the first 8 bytes, little endian, of the SHA-1 of the class name,
+/// modifiers, interfaces and non-private members.
+pub fn default_serial_version_uid(cf: &ClassFile) -> i64 {
+    let cp = &cf.cp;
+    let mut out = vec![];
+    write_utf(&mut out, &class_name(cp, cf.this_class));
+
+    // Class.getModifiers() reports the flags of a nested class from its
+    // InnerClasses entry
+    let mut class_mods = cf.acc_flags;
+    let this_name = constant_pool::get_class_name(cp, cf.this_class as usize);
+    for attr in cf.attrs.iter() {
+        if let AttributeType::InnerClasses { classes } = attr {
+            if let Some(inner) = classes.iter().find(|c| {
+                c.inner_class_info_index != 0
+                    && constant_pool::get_class_name(cp, c.inner_class_info_index as usize)
+                        == this_name
+            }) {
+                class_mods = inner.inner_class_access_flags;
+            }
+        }
+    }
+    let methods: Vec<_> = cf
+        .methods
+        .iter()
+        .map(|m| (utf8(cp, m.name_index), m.acc_flags, utf8(cp, m.desc_index)))
+        .collect();
+    let mut class_mods = class_mods & CLASS_MODIFIERS;
+    if class_mods & INTERFACE != 0 {
+        let declared = methods
+            .iter()
+            .any(|(name, _, _)| name != b"<init>" && name != b"<clinit>");
+        class_mods = if declared {
+            class_mods | ABSTRACT
+        } else {
+            class_mods & !ABSTRACT
+        };
+    }
+    out.extend_from_slice(&(class_mods as u32).to_be_bytes());
+
+    let mut interfaces: Vec<_> = cf.interfaces.iter().map(|&i| class_name(cp, i)).collect();
+    interfaces.sort_by(|a, b| java_order(a, b));
+    for name in interfaces.iter() {
+        write_utf(&mut out, name);
+    }
+
+    let mut fields: Vec<_> = cf
+        .fields
+        .iter()
+        .map(|f| {
+            (
+                utf8(cp, f.name_index),
+                f.acc_flags & FIELD_MODIFIERS,
+                utf8(cp, f.desc_index),
+            )
+        })
+        .collect();
+    fields.sort_by(|a, b| java_order(&a.0, &b.0));
+    for (name, mods, desc) in fields.iter() {
+        if mods & PRIVATE == 0 || mods & (STATIC | TRANSIENT) == 0 {
+            write_utf(&mut out, name);
+            out.extend_from_slice(&(*mods as u32).to_be_bytes());
+            write_utf(&mut out, desc);
+        }
+    }
+
+    if methods
+        .iter()
+        .any(|(name, _, desc)| name == b"<clinit>" && desc == b"()V")
+    {
+        write_utf(&mut out, b"<clinit>");
+        out.extend_from_slice(&(STATIC as u32).to_be_bytes());
+        write_utf(&mut out, b"()V");
+    }
+
+    let mut constructors: Vec<_> = methods.iter().filter(|m| m.0 == b"<init>").collect();
+    constructors.sort_by(|a, b| java_order(&a.2, &b.2));
+    let mut others: Vec<_> = methods
+        .iter()
+        .filter(|m| m.0 != b"<init>" && m.0 != b"<clinit>")
+        .collect();
+    others.sort_by(|a, b| java_order(&a.0, &b.0).then_with(|| java_order(&a.2, &b.2)));
+    for (name, flags, desc) in constructors.into_iter().chain(others) {
+        let mods = flags & METHOD_MODIFIERS;
+        if mods & PRIVATE == 0 {
+            write_utf(&mut out, name);
+            out.extend_from_slice(&(mods as u32).to_be_bytes());
+            write_utf(&mut out, &dotted(desc));
+        }
+    }
+
+    let hash = sha1_smol::Sha1::from(&out).digest().bytes();
+    let mut uid = [0; 8];
+    uid.copy_from_slice(&hash[..8]);
+    i64::from_le_bytes(uid)
+}
@@ -0,0 +1,337 @@
+use super::class_parser::{self, format::constant_pool};
+use super::class_path_manager::ClassPathManager;
+use super::remapper::{Mapper, Remapper};
+use super::util;
+
+use std::collections::HashMap;
+use std::io::{self, Seek, Write};
+use tracing::warn;
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+/// Moves every class under `from` to `to`. Both are `/` separated package
+/// prefixes, e.g. `com/google/` and `shaded/com/google/`, or class names.
+/// Without a trailing `/`, `from` only matches at a package boundary:
+/// `com/google` covers `com/google/Foo` but not `com/googlex/Foo`.
+#[derive(Clone, Debug)]
+pub struct Relocation {
+    pub from: String,
+    pub to: String,
+}
+
+impl Relocation {
+    /// Accepts either `.` or `/` separated packages.
+    pub fn new(from: &str, to: &str) -> Self {
+        Self {
+            from: from.replace('.', "/"),
+            to: to.replace('.', "/"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Relocator {
+    relocations: Vec<Relocation>,
+}
+
+impl Relocator {
+    pub fn new(relocations: Vec<Relocation>) -> Self {
+        Self { relocations }
+    }
+
+    fn relocate(&self, name: &str) -> Option<String> {
+        self.relocations.iter().find_map(|r| {
+            let rest = name.strip_prefix(r.from.as_str())?;
+            // a relocated class takes its nested classes along
+            let boundary = r.from.ends_with('/')
+                || rest.is_empty()
+                || rest.starts_with('/')
+                || rest.starts_with('$');
+            boundary.then(|| format!("{}{}", r.to, rest))
+        })
+    }
+
+    /// Relocates a `.` separated class name, as used in service files and
+    /// manifests.
+    pub fn relocate_dotted(&self, name: &str) -> String {
+        self.relocate(&name.replace('.', "/"))
+            .map(|n| n.replace('/', "."))
+            .unwrap_or_else(|| name.to_string())
+    }
+}
+
+impl Mapper for Relocator {
+    fn map_class(&self, name: &str) -> Option<String> {
+        self.relocate(name)
+    }
+}
+
+/// What to do when several inputs carry a resource with the same name.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MergeStrategy {
+    /// keep the resource that comes first in classpath order
+    First,
+    /// keep the resource that comes last in classpath order
+    Last,
+    /// append all of them, separated by a newline
+    Concat,
+    /// refuse to build the jar
+    Fail,
+}
+
+impl std::str::FromStr for MergeStrategy {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "first" => Ok(MergeStrategy::First),
+            "last" => Ok(MergeStrategy::Last),
+            "concat" => Ok(MergeStrategy::Concat),
+            "fail" => Ok(MergeStrategy::Fail),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown merge strategy: {}", s),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ShadeReport {
+    pub classes: usize,
+    pub relocated_classes: usize,
+    pub resources: usize,
+    /// entries found in more than one input
+    pub duplicates: Vec<String>,
+}
+
+pub struct Shader {
+    relocator: Relocator,
+    rules: Vec<(String, MergeStrategy)>,
+    default_strategy: MergeStrategy,
+    main_class: Option<String>,
+}
+
+const MANIFEST: &str = "META-INF/MANIFEST.MF";
+const SERVICES: &str = "META-INF/services/";
+
+// signatures no longer match once classes are rewritten
+pub(crate) fn is_signature_file(name: &str) -> bool {
+    let upper = name.to_ascii_uppercase();
+    upper.starts_with("META-INF/")
+        && !upper["META-INF/".len()..].contains('/')
+        && [".SF", ".DSA", ".RSA", ".EC"]
+            .iter()
+            .any(|ext| upper.ends_with(ext))
+}
+
+// spec: no line may be longer than 72 bytes, longer values continue on the
+// next line after a single space
+fn manifest_line(out: &mut Vec<u8>, key: &str, value: &str) {
+    let line = format!("{}: {}", key, value);
+    let bytes = line.as_bytes();
+    let mut start = 0;
+    let mut width = 72;
+    while start < bytes.len() {
+        let mut end = usize::min(start + width, bytes.len());
+        while !line.is_char_boundary(end) {
+            end -= 1;
+        }
+        if start > 0 {
+            out.push(b' ');
+        }
+        out.extend_from_slice(&bytes[start..end]);
+        out.extend_from_slice(b"\r\n");
+        start = end;
+        width = 71;
+    }
+}
+
+impl Shader {
+    pub fn new(relocations: Vec<Relocation>) -> Self {
+        Self {
+            relocator: Relocator::new(relocations),
+            rules: vec![],
+            default_strategy: MergeStrategy::First,
+            main_class: None,
+        }
+    }
+
+    /// Rules are matched in the order they were added, `pattern` may use `*`.
+    pub fn merge_rule(&mut self, pattern: &str, strategy: MergeStrategy) -> &mut Self {
+        self.rules.push((pattern.to_string(), strategy));
+        self
+    }
+
+    pub fn default_strategy(&mut self, strategy: MergeStrategy) -> &mut Self {
+        self.default_strategy = strategy;
+        self
+    }
+
+    /// Overrides the `Main-Class` otherwise taken from the first input
+    /// manifest that has one.
+    pub fn main_class(&mut self, main_class: &str) -> &mut Self {
+        self.main_class = Some(main_class.to_string());
+        self
+    }
+
+    fn strategy(&self, name: &str) -> MergeStrategy {
+        if let Some((_, s)) = self.rules.iter().find(|(p, _)| util::glob_match(p, name)) {
+            return *s;
+        }
+        if name.starts_with(SERVICES) {
+            MergeStrategy::Concat
+        } else {
+            self.default_strategy
+        }
+    }
+
+    // the entry keeps whatever directory holds the class, like the
+    // `META-INF/versions/N/` of a multi-release jar
+    fn relocate_class(&self, entry: &str, data: &[u8]) -> io::Result<(String, Vec<u8>, bool)> {
+        let (_, mut cf) = class_parser::parse(data)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e)))?;
+        let name = constant_pool::get_class_name(&cf.cp, cf.this_class as usize)
+            .map(|n| String::from_utf8_lossy(&n).into_owned())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing this_class"))?;
+
+        let relocated = self.relocator.relocate(&name);
+        Remapper::new(self.relocator.clone()).remap(&mut cf)?;
+        let prefix = entry.strip_suffix(&format!("{}.class", name)).unwrap_or("");
+        let is_relocated = relocated.is_some();
+        let new_name = relocated.unwrap_or(name);
+
+        Ok((
+            format!("{}{}.class", prefix, new_name),
+            class_parser::write(&cf)?,
+            is_relocated,
+        ))
+    }
+
+    fn relocate_services(&self, data: &[u8]) -> Vec<u8> {
+        let text = String::from_utf8_lossy(data);
+        let mut out = String::new();
+        for line in text.lines() {
+            let (entry, comment) = match line.find('#') {
+                Some(i) => (&line[..i], &line[i..]),
+                None => (line, ""),
+            };
+            let entry = entry.trim();
+            if !entry.is_empty() {
+                out.push_str(&self.relocator.relocate_dotted(entry));
+            }
+            out.push_str(comment);
+            out.push('\n');
+        }
+        out.into_bytes()
+    }
+
+    /// Reads every entry of `cpm`, relocates it and writes the merged jar.
+    pub fn shade<W: Write + Seek>(
+        &self,
+        cpm: &ClassPathManager,
+        out: W,
+    ) -> io::Result<ShadeReport> {
+        let mut report = ShadeReport::default();
+        let mut entries: Vec<(String, Vec<u8>)> = vec![];
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        let mut main_class = self.main_class.clone();
+        let mut multi_release = false;
+
+        cpm.visit_entries(|source, name, data| -> io::Result<()> {
+            if name.eq_ignore_ascii_case(MANIFEST) {
+                if main_class.is_none() {
+                    main_class = util::manifest_attribute(&data, "Main-Class");
+                }
+                // the versioned classes are only used if the jar says so
+                multi_release |= util::manifest_attribute(&data, "Multi-Release")
+                    .is_some_and(|v| v.eq_ignore_ascii_case("true"));
+                return Ok(());
+            }
+            if is_signature_file(name) || name.ends_with('/') {
+                return Ok(());
+            }
+
+            let (name, data) = if name.ends_with(".class") {
+                let (new_name, bytes, relocated) = self
+                    .relocate_class(name, &data)
+                    .map_err(|e| io::Error::new(e.kind(), format!("{}!{}: {}", source, name, e)))?;
+                report.classes += 1;
+                if relocated {
+                    report.relocated_classes += 1;
+                }
+                (new_name, bytes)
+            } else if let Some(service) = name.strip_prefix(SERVICES) {
+                let service = self.relocator.relocate_dotted(service);
+                report.resources += 1;
+                (
+                    format!("{}{}", SERVICES, service),
+                    self.relocate_services(&data),
+                )
+            } else {
+                report.resources += 1;
+                (name.to_string(), data)
+            };
+
+            match positions.get(&name) {
+                None => {
+                    positions.insert(name.clone(), entries.len());
+                    entries.push((name, data));
+                }
+                Some(&i) => {
+                    report.duplicates.push(name.clone());
+                    let strategy = if name.ends_with(".class") {
+                        MergeStrategy::First
+                    } else {
+                        self.strategy(&name)
+                    };
+                    match strategy {
+                        MergeStrategy::First => warn!("duplicate entry {} in {}", name, source),
+                        MergeStrategy::Last => entries[i].1 = data,
+                        MergeStrategy::Concat => {
+                            let merged = &mut entries[i].1;
+                            if !merged.is_empty() && !merged.ends_with(b"\n") {
+                                merged.push(b'\n');
+                            }
+                            merged.extend_from_slice(&data);
+                        }
+                        MergeStrategy::Fail => {
+                            return Err(io::Error::new(
+                                io::ErrorKind::AlreadyExists,
+                                format!("duplicate entry {} in {}", name, source),
+                            ))
+                        }
+                    }
+                }
+            }
+            Ok(())
+        })?;
+
+        let mut manifest = vec![];
+        manifest_line(&mut manifest, "Manifest-Version", "1.0");
+        manifest_line(&mut manifest, "Created-By", "jvm shade");
+        if let Some(main_class) = main_class {
+            manifest_line(
+                &mut manifest,
+                "Main-Class",
+                &self.relocator.relocate_dotted(&main_class),
+            );
+        }
+        if multi_release {
+            manifest_line(&mut manifest, "Multi-Release", "true");
+        }
+        manifest.extend_from_slice(b"\r\n");
+
+        let mut zip = ZipWriter::new(out);
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        zip.add_directory("META-INF/", options)?;
+        zip.start_file(MANIFEST, options)?;
+        zip.write_all(&manifest)?;
+        for (name, data) in entries {
+            zip.start_file(name, options)?;
+            zip.write_all(&data)?;
+        }
+        zip.finish()?;
+
+        Ok(report)
+    }
+}
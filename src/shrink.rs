@@ -0,0 +1,166 @@
+use super::class_parser::{
+    self,
+    builder::{self, Compaction},
+    format::{
+        attributes::{Tag, Type as AttributeType},
+        class_file::ClassFile,
+        constant_pool,
+    },
+};
+use super::class_path_manager::ClassPathManager;
+use super::shade::is_signature_file;
+
+use std::io::{self, Seek, Write};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+/// Which attributes to drop. Everything defaults to kept.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StripOptions {
+    pub line_numbers: bool,
+    pub local_variables: bool,
+    pub local_variable_types: bool,
+    pub source_file: bool,
+    pub source_debug_extension: bool,
+    pub invisible_annotations: bool,
+}
+
+impl StripOptions {
+    pub fn all() -> Self {
+        Self {
+            line_numbers: true,
+            local_variables: true,
+            local_variable_types: true,
+            source_file: true,
+            source_debug_extension: true,
+            invisible_annotations: true,
+        }
+    }
+
+    fn strips(&self, tag: Tag) -> bool {
+        match tag {
+            Tag::LineNumberTable => self.line_numbers,
+            Tag::LocalVariableTable => self.local_variables,
+            Tag::LocalVariableTypeTable => self.local_variable_types,
+            Tag::SourceFile => self.source_file,
+            Tag::SourceDebugExtension => self.source_debug_extension,
+            Tag::RuntimeInvisibleAnnotations
+            | Tag::RuntimeInvisibleParameterAnnotations
+            | Tag::RuntimeInvisibleTypeAnnotations => self.invisible_annotations,
+            _ => false,
+        }
+    }
+
+    fn strip_attrs(&self, attrs: &mut Vec<AttributeType>) {
+        attrs.retain(|a| !self.strips(a.tag()));
+        for attr in attrs.iter_mut() {
+            match attr {
+                AttributeType::Code(code) => self.strip_attrs(&mut code.attrs),
+                AttributeType::Record { components } => components
+                    .iter_mut()
+                    .for_each(|c| self.strip_attrs(&mut c.attrs)),
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Removes the requested attributes and the constants only they used. The
+/// constants stay when the class has attributes `compact` can't look into.
+pub fn strip(cf: &mut ClassFile, options: &StripOptions) -> io::Result<Compaction> {
+    options.strip_attrs(&mut cf.attrs);
+    for field in cf.fields.iter_mut() {
+        options.strip_attrs(&mut field.attrs);
+    }
+    for method in cf.methods.iter_mut() {
+        options.strip_attrs(&mut method.attrs);
+    }
+    builder::compact(cf)
+}
+
+#[derive(Debug)]
+pub struct ClassReport {
+    pub name: String,
+    pub before: usize,
+    pub after: usize,
+    /// the undecoded attributes that kept the constant pool from being
+    /// compacted, empty if it was
+    pub undecoded: Vec<String>,
+}
+
+impl ClassReport {
+    pub fn saved(&self) -> usize {
+        self.before.saturating_sub(self.after)
+    }
+}
+
+/// Strips a serialized class, returning the new bytes with a size report.
+pub fn shrink_class(data: &[u8], options: &StripOptions) -> io::Result<(Vec<u8>, ClassReport)> {
+    let (_, mut cf) = class_parser::parse(data)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e)))?;
+    let compaction = strip(&mut cf, options)?;
+    let bytes = class_parser::write(&cf)?;
+
+    let name = constant_pool::get_class_name(&cf.cp, cf.this_class as usize)
+        .map(|n| String::from_utf8_lossy(&n).into_owned())
+        .unwrap_or_default();
+    let report = ClassReport {
+        name,
+        before: data.len(),
+        after: bytes.len(),
+        undecoded: match compaction {
+            Compaction::Removed(_) => vec![],
+            Compaction::Undecoded(names) => names,
+        },
+    };
+    Ok((bytes, report))
+}
+
+#[derive(Debug, Default)]
+pub struct JarReport {
+    pub classes: Vec<ClassReport>,
+    /// compressed size of the written jar
+    pub jar_after: u64,
+}
+
+impl JarReport {
+    pub fn before(&self) -> usize {
+        self.classes.iter().map(|c| c.before).sum()
+    }
+
+    pub fn after(&self) -> usize {
+        self.classes.iter().map(|c| c.after).sum()
+    }
+}
+
+/// Shrinks every class on `cpm` into a new jar. Other resources are copied
+/// unchanged except jar signatures, which stop matching.
+pub fn shrink_jar<W: Write + Seek>(
+    cpm: &ClassPathManager,
+    options: &StripOptions,
+    out: W,
+) -> io::Result<JarReport> {
+    let mut report = JarReport::default();
+    let mut zip = ZipWriter::new(out);
+    let zip_options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    cpm.visit_entries(|source, name, data| -> io::Result<()> {
+        if is_signature_file(name) {
+            return Ok(());
+        }
+        let data = if name.ends_with(".class") {
+            let (bytes, class_report) = shrink_class(&data, options)
+                .map_err(|e| io::Error::new(e.kind(), format!("{}!{}: {}", source, name, e)))?;
+            report.classes.push(class_report);
+            bytes
+        } else {
+            data
+        };
+        zip.start_file(name, zip_options)?;
+        zip.write_all(&data)?;
+        Ok(())
+    })?;
+
+    let mut out = zip.finish()?;
+    report.jar_after = out.seek(io::SeekFrom::End(0))?;
+    Ok(report)
+}
@@ -0,0 +1,218 @@
+//! Interning of constant pool Utf8 strings. Every class repeats names and
+//! descriptors like `java/lang/Object` and `()V`; interned, each is stored once
+//! per table however many classes refer to it.
+
+use super::class_parser::format::{class_file::ClassFile, constant_pool::Type as ConstantType};
+
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::mem;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+const SHARDS: usize = 64;
+
+// the heap block of an Arc<Vec<u8>> besides the bytes: the two reference
+// counts and the Vec
+const ENTRY_OVERHEAD: usize = 2 * mem::size_of::<usize>() + mem::size_of::<Vec<u8>>();
+
+/// An interned string. Symbols of the same table are equal exactly when they
+/// share storage, so comparing and hashing don't look at the bytes; symbols
+/// of different tables are never equal.
+#[derive(Clone)]
+pub struct Symbol(Arc<Vec<u8>>);
+
+impl Symbol {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// The shared bytes, as stored in a constant pool.
+    pub fn bytes(&self) -> Arc<Vec<u8>> {
+        self.0.clone()
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.0).hash(state)
+    }
+}
+
+impl Deref for Symbol {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", String::from_utf8_lossy(&self.0))
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", String::from_utf8_lossy(&self.0))
+    }
+}
+
+// looked up by the bytes
+#[derive(PartialEq, Eq, Hash)]
+struct Entry(Arc<Vec<u8>>);
+
+impl Borrow<[u8]> for Entry {
+    fn borrow(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Counters of a `SymbolTable`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SymbolStats {
+    /// distinct strings in the table
+    pub symbols: usize,
+    /// their total length
+    pub bytes: usize,
+    pub lookups: usize,
+    /// lookups that found the string already interned
+    pub hits: usize,
+    /// the length of the strings those lookups found
+    pub hit_bytes: usize,
+}
+
+impl SymbolStats {
+    /// Heap memory not allocated because the strings the hits found were
+    /// shared instead of kept once per class.
+    pub fn saved_bytes(&self) -> usize {
+        self.hit_bytes + self.hits * ENTRY_OVERHEAD
+    }
+}
+
+/// A thread safe set of interned strings, sharded to keep parallel loading
+/// from contending on one lock. Interned strings live as long as the table.
+pub struct SymbolTable {
+    hasher: RandomState,
+    shards: Vec<Mutex<HashSet<Entry>>>,
+    bytes: AtomicUsize,
+    lookups: AtomicUsize,
+    hits: AtomicUsize,
+    hit_bytes: AtomicUsize,
+}
+
+impl Default for SymbolTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for SymbolTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SymbolTable")
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        SymbolTable {
+            hasher: RandomState::new(),
+            shards: (0..SHARDS).map(|_| Mutex::new(HashSet::new())).collect(),
+            bytes: AtomicUsize::new(0),
+            lookups: AtomicUsize::new(0),
+            hits: AtomicUsize::new(0),
+            hit_bytes: AtomicUsize::new(0),
+        }
+    }
+
+    fn shard(&self, bytes: &[u8]) -> &Mutex<HashSet<Entry>> {
+        &self.shards[self.hasher.hash_one(bytes) as usize % SHARDS]
+    }
+
+    pub fn intern(&self, bytes: &[u8]) -> Symbol {
+        let (symbol, found) = self.lookup(bytes, || Arc::new(bytes.to_vec()));
+        self.count(&symbol, found);
+        symbol
+    }
+
+    /// Like `intern`, but keeps `bytes` itself when the string is new.
+    pub fn intern_arc(&self, bytes: Arc<Vec<u8>>) -> Symbol {
+        let (symbol, found) = self.lookup(&bytes, || bytes.clone());
+        // finding `bytes` itself, interned before, shares nothing
+        self.count(&symbol, found && !Arc::ptr_eq(&symbol.0, &bytes));
+        symbol
+    }
+
+    fn lookup(&self, bytes: &[u8], new: impl FnOnce() -> Arc<Vec<u8>>) -> (Symbol, bool) {
+        let mut shard = self.shard(bytes).lock().unwrap();
+        if let Some(entry) = shard.get(bytes) {
+            return (Symbol(entry.0.clone()), true);
+        }
+        let entry = new();
+        shard.insert(Entry(entry.clone()));
+        self.bytes.fetch_add(entry.len(), Ordering::Relaxed);
+        (Symbol(entry), false)
+    }
+
+    fn count(&self, symbol: &Symbol, hit: bool) {
+        self.lookups.fetch_add(1, Ordering::Relaxed);
+        if hit {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            self.hit_bytes.fetch_add(symbol.len(), Ordering::Relaxed);
+        }
+    }
+
+    /// The symbol for `bytes` if it was interned.
+    pub fn get(&self, bytes: &[u8]) -> Option<Symbol> {
+        let shard = self.shard(bytes).lock().unwrap();
+        shard.get(bytes).map(|entry| Symbol(entry.0.clone()))
+    }
+
+    /// Points the Utf8 constants of `cf` at the interned strings, interning
+    /// those that are new. Returns how many constants now share storage with
+    /// another class.
+    pub fn intern_class(&self, cf: &mut ClassFile) -> usize {
+        let mut shared = 0;
+        for c in Arc::make_mut(&mut cf.cp).iter_mut() {
+            if let ConstantType::Utf8 { bytes } = c {
+                let symbol = self.intern_arc(bytes.clone());
+                if !Arc::ptr_eq(&symbol.0, bytes) {
+                    *bytes = symbol.0;
+                    shared += 1;
+                }
+            }
+        }
+        shared
+    }
+
+    pub fn stats(&self) -> SymbolStats {
+        SymbolStats {
+            symbols: self.shards.iter().map(|s| s.lock().unwrap().len()).sum(),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            lookups: self.lookups.load(Ordering::Relaxed),
+            hits: self.hits.load(Ordering::Relaxed),
+            hit_bytes: self.hit_bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The table class loaders intern into.
+pub fn global() -> &'static SymbolTable {
+    static GLOBAL: OnceLock<SymbolTable> = OnceLock::new();
+    GLOBAL.get_or_init(SymbolTable::new)
+}
@@ -19,3 +19,47 @@ macro_rules! def_ref {
         pub type $name = std::sync::Arc<Box<$t>>;
     };
 }
+
+/// Matches `text` against a pattern where `*` stands for any run of
+/// characters, including `/`.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let (p, t) = (pattern.as_bytes(), text.as_bytes());
+    let (mut pi, mut ti) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while ti < t.len() {
+        if pi < p.len() && p[pi] == b'*' {
+            backtrack = Some((pi, ti));
+            pi += 1;
+        } else if pi < p.len() && p[pi] == t[ti] {
+            pi += 1;
+            ti += 1;
+        } else if let Some((star, matched)) = backtrack {
+            pi = star + 1;
+            ti = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    p[pi..].iter().all(|c| *c == b'*')
+}
+
+/// The value of `key` in the main section of a jar manifest.
+pub fn manifest_attribute(manifest: &[u8], key: &str) -> Option<String> {
+    let text = String::from_utf8_lossy(manifest);
+    // continuation lines start with a single space
+    let unfolded = text.replace("\r\n", "\n").replace("\n ", "");
+    unfolded
+        .lines()
+        .take_while(|l| !l.is_empty())
+        .find_map(|l| {
+            let (name, value) = l.split_once(':')?;
+            if name.eq_ignore_ascii_case(key) {
+                Some(value.trim().to_string())
+            } else {
+                None
+            }
+        })
+}